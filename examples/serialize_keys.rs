@@ -15,6 +15,7 @@ fn main() {
         .mode(0o640)
         .write(true)
         .create(true)
+        .truncate(true)
         .open("test-key")
         .unwrap();
     target.write_all(&key).unwrap();
@@ -0,0 +1,349 @@
+//! Signing with asymmetric keys managed by AWS KMS.
+//!
+//! This module is experimental: it is behind the `aws-kms` feature and its
+//! API may change in any release, e.g. once it is built on an AWS SDK
+//! instead of the command line tool.
+//!
+//! A [`KmsSigner`] never sees the private key: it fetches the public key
+//! once and sends every message to KMS to be signed. The public key is
+//! parsed like a local SPKI key, so its key ID, and the verification of the
+//! signatures, are the same as for a
+//! [`PrivateKey`](crate::crypto::PrivateKey) with the same key.
+//!
+//! The requests are made by a [`KmsClient`]. [`AwsCli`] runs the `aws`
+//! command line tool, which takes credentials and the region from the usual
+//! environment variables and configuration files. To exercise code without
+//! AWS, implement [`KmsClient`] over a local key, as shown below.
+//!
+//! # Trust assumptions
+//!
+//! - The program run by [`AwsCli`] is trusted with every digest to sign and
+//!   with the answers it returns. It is looked up on the `$PATH` unless
+//!   given with [`AwsCli::with_program`], and the credentials, region and
+//!   profile it picks up decide which account and key are used.
+//! - The public key is fetched from KMS once, when the [`KmsSigner`] is
+//!   created, and the key ID of the signatures is derived from it. An alias
+//!   may later be pointed at another key, so layouts should pin the public
+//!   key itself, never the alias.
+//! - Every signature returned by KMS is verified against that public key
+//!   before it is handed out, so a misbehaving client or a key changed
+//!   behind an alias fails to sign rather than producing a signature the
+//!   layout does not accept.
+//! - Digests are handed to the `aws` process in a temporary file and
+//!   signatures read from its output. They are not secret, but other users
+//!   of the machine may be able to see them.
+//!
+//!
+//! ```
+//! use in_toto::crypto::kms::{KmsClient, KmsSigner};
+//! use in_toto::crypto::{PrivateKey, SignatureScheme, Signer};
+//! use in_toto::Result;
+//!
+//! struct LocalKms(PrivateKey);
+//!
+//! impl KmsClient for LocalKms {
+//!     fn get_public_key(&self, _key_id: &str) -> Result<Vec<u8>> {
+//!         self.0.public().as_spki()
+//!     }
+//!
+//!     fn sign(
+//!         &self,
+//!         _key_id: &str,
+//!         msg: &[u8],
+//!         _scheme: &SignatureScheme,
+//!     ) -> Result<Vec<u8>> {
+//!         Ok(self.0.sign(msg)?.value().as_bytes().to_vec())
+//!     }
+//! }
+//!
+//! let der = include_bytes!("../../tests/rsa/rsa-2048.pk8.der");
+//! let key = PrivateKey::from_pkcs8(der, SignatureScheme::RsaSsaPssSha256)?;
+//! let public = key.public().clone();
+//!
+//! let signer = KmsSigner::new(
+//!     LocalKms(key),
+//!     "alias/functionary",
+//!     SignatureScheme::RsaSsaPssSha256,
+//! )?;
+//! assert_eq!(signer.public_key(), &public);
+//! public.verify(b"hello", &signer.sign(b"hello")?)?;
+//! # Ok::<(), in_toto::Error>(())
+//! ```
+
+use std::io::Write;
+use std::process::Command;
+
+use data_encoding::BASE64;
+use ring::digest::{self, SHA256, SHA512};
+use serde_derive::Deserialize;
+
+use crate::crypto::{
+    PublicKey, Signature, SignatureScheme, SignatureValue, Signer,
+};
+use crate::error::Error;
+use crate::Result;
+
+/// The requests a [`KmsSigner`] makes to KMS.
+pub trait KmsClient {
+    /// The DER encoded SubjectPublicKeyInfo of the KMS key `key_id`.
+    fn get_public_key(&self, key_id: &str) -> Result<Vec<u8>>;
+
+    /// Sign `msg` with the KMS key `key_id` using `scheme`, returning the
+    /// raw signature bytes.
+    fn sign(
+        &self,
+        key_id: &str,
+        msg: &[u8],
+        scheme: &SignatureScheme,
+    ) -> Result<Vec<u8>>;
+}
+
+/// A [`KmsClient`] running the `aws kms` command line tool.
+///
+/// Messages are hashed locally and sent as a `DIGEST`, so they are not
+/// limited to the 4096 bytes KMS accepts for raw messages.
+#[derive(Debug, Clone)]
+pub struct AwsCli {
+    program: String,
+}
+
+impl Default for AwsCli {
+    fn default() -> Self {
+        AwsCli::new()
+    }
+}
+
+impl AwsCli {
+    /// Run `aws` from the `$PATH`.
+    pub fn new() -> Self {
+        Self::with_program("aws".into())
+    }
+
+    /// Run `program` instead of `aws`, e.g. a wrapper that selects a
+    /// profile.
+    pub fn with_program(program: String) -> Self {
+        AwsCli { program }
+    }
+
+    fn run<T: serde::de::DeserializeOwned>(&self, args: &[&str]) -> Result<T> {
+        let output = Command::new(&self.program)
+            .arg("kms")
+            .args(args)
+            .args(["--output", "json"])
+            .output()
+            .map_err(|e| {
+                Error::io(format!("cannot run {}", self.program), e)
+            })?;
+        if !output.status.success() {
+            return Err(Error::Opaque(format!(
+                "aws kms {} failed: {}",
+                args[0],
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
+}
+
+#[derive(Deserialize)]
+struct GetPublicKeyOutput {
+    #[serde(rename = "PublicKey")]
+    public_key: String,
+}
+
+#[derive(Deserialize)]
+struct SignOutput {
+    #[serde(rename = "Signature")]
+    signature: String,
+}
+
+impl KmsClient for AwsCli {
+    fn get_public_key(&self, key_id: &str) -> Result<Vec<u8>> {
+        let output: GetPublicKeyOutput =
+            self.run(&["get-public-key", "--key-id", key_id])?;
+        Ok(BASE64.decode(output.public_key.as_bytes())?)
+    }
+
+    fn sign(
+        &self,
+        key_id: &str,
+        msg: &[u8],
+        scheme: &SignatureScheme,
+    ) -> Result<Vec<u8>> {
+        let (algorithm, digest) = match scheme {
+            SignatureScheme::RsaSsaPssSha256 => {
+                ("RSASSA_PSS_SHA_256", digest::digest(&SHA256, msg))
+            }
+            SignatureScheme::RsaSsaPssSha512 => {
+                ("RSASSA_PSS_SHA_512", digest::digest(&SHA512, msg))
+            }
+            SignatureScheme::EcdsaP256Sha256 => {
+                ("ECDSA_SHA_256", digest::digest(&SHA256, msg))
+            }
+            scheme => {
+                return Err(Error::IllegalArgument(format!(
+                    "AWS KMS does not support the {:?} signing scheme",
+                    scheme
+                )))
+            }
+        };
+
+        let mut file = tempfile::NamedTempFile::new()?;
+        file.write_all(digest.as_ref())?;
+        let message = format!("fileb://{}", file.path().display());
+        let output: SignOutput = self.run(&[
+            "sign",
+            "--key-id",
+            key_id,
+            "--message",
+            &message,
+            "--message-type",
+            "DIGEST",
+            "--signing-algorithm",
+            algorithm,
+        ])?;
+        Ok(BASE64.decode(output.signature.as_bytes())?)
+    }
+}
+
+/// A [`Signer`] for a KMS key. Signatures that do not verify with the
+/// public key fetched by [`KmsSigner::new`] are rejected.
+pub struct KmsSigner<C = AwsCli> {
+    client: C,
+    key_id: String,
+    public_key: PublicKey,
+}
+
+impl<C: KmsClient> KmsSigner<C> {
+    /// Create a signer for the KMS key `key_id`, which may be a key ID, ARN
+    /// or alias, signing with `scheme`.
+    pub fn new(
+        client: C,
+        key_id: &str,
+        scheme: SignatureScheme,
+    ) -> Result<Self> {
+        let spki = client.get_public_key(key_id)?;
+        let public_key = PublicKey::from_spki(&spki, scheme)?;
+        Ok(KmsSigner {
+            client,
+            key_id: key_id.to_string(),
+            public_key,
+        })
+    }
+}
+
+impl<C: KmsClient> Signer for KmsSigner<C> {
+    fn sign(&self, msg: &[u8]) -> Result<Signature> {
+        let sig =
+            self.client
+                .sign(&self.key_id, msg, self.public_key.scheme())?;
+        let sig = Signature::new(
+            self.public_key.key_id().clone(),
+            SignatureValue::new(sig),
+        );
+        self.public_key.verify(msg, &sig).map_err(|_| {
+            Error::Opaque(format!(
+                "KMS key {} returned a signature that does not verify with \
+                 its public key",
+                self.key_id
+            ))
+        })?;
+        Ok(sig)
+    }
+
+    fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::PrivateKey;
+
+    const RSA_2048_PK8: &[u8] =
+        include_bytes!("../../tests/rsa/rsa-2048.pk8.der");
+
+    struct LocalKms(PrivateKey);
+
+    impl KmsClient for LocalKms {
+        fn get_public_key(&self, _key_id: &str) -> Result<Vec<u8>> {
+            self.0.public().as_spki()
+        }
+
+        fn sign(
+            &self,
+            _key_id: &str,
+            msg: &[u8],
+            _scheme: &SignatureScheme,
+        ) -> Result<Vec<u8>> {
+            Ok(self.0.sign(msg)?.value().as_bytes().to_vec())
+        }
+    }
+
+    #[test]
+    fn kms_signer_matches_local_key() {
+        let key = PrivateKey::from_pkcs8(
+            RSA_2048_PK8,
+            SignatureScheme::RsaSsaPssSha256,
+        )
+        .unwrap();
+        let public = key.public().clone();
+        let signer = KmsSigner::new(
+            LocalKms(key),
+            "alias/test",
+            SignatureScheme::RsaSsaPssSha256,
+        )
+        .unwrap();
+        assert_eq!(signer.public_key().key_id(), public.key_id());
+
+        let sig = Signer::sign(&signer, b"hello").unwrap();
+        assert_eq!(sig.key_id(), public.key_id());
+        public.verify(b"hello", &sig).unwrap();
+    }
+
+    #[test]
+    fn kms_signer_rejects_foreign_signature() {
+        let key = PrivateKey::from_pkcs8(
+            RSA_2048_PK8,
+            SignatureScheme::RsaSsaPssSha256,
+        )
+        .unwrap();
+        let signer = KmsSigner::new(
+            LocalKms(key),
+            "alias/test",
+            SignatureScheme::RsaSsaPssSha256,
+        )
+        .unwrap();
+        // the alias now points at another key
+        let other = PrivateKey::from_pkcs8(
+            include_bytes!("../../tests/rsa/rsa-4096.pk8.der"),
+            SignatureScheme::RsaSsaPssSha256,
+        )
+        .unwrap();
+        let signer = KmsSigner {
+            client: LocalKms(other),
+            ..signer
+        };
+        assert!(Signer::sign(&signer, b"hello").is_err());
+    }
+
+    /// Signs with a real KMS key if `IN_TOTO_TEST_AWS_KMS_KEY_ID` names an
+    /// RSA key usable with RSASSA_PSS_SHA_256, and is skipped otherwise.
+    #[test]
+    fn aws_kms_sign() {
+        let key_id = match std::env::var("IN_TOTO_TEST_AWS_KMS_KEY_ID") {
+            Ok(key_id) => key_id,
+            Err(_) => return,
+        };
+        let signer = KmsSigner::new(
+            AwsCli::new(),
+            &key_id,
+            SignatureScheme::RsaSsaPssSha256,
+        )
+        .unwrap();
+        let msg = vec![b'a'; 8192];
+        let sig = Signer::sign(&signer, &msg).unwrap();
+        signer.public_key().verify(&msg, &sig).unwrap();
+    }
+}
@@ -0,0 +1,1231 @@
+//! X.509 certificates binding a signing key to an identity.
+//!
+//! Only what is needed to check such a binding is supported: the names,
+//! validity, public key and the subject alternative name, basic constraints,
+//! key usage, extended key usage and OIDC issuer extensions of a
+//! certificate, and verifying a chain of certificates up to a trusted root.
+//! Certificates with name constraints are rejected, and revocation is not
+//! checked.
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use regex::Regex;
+use ring::signature::{self, UnparsedPublicKey, VerificationAlgorithm};
+use untrusted::{Input, Reader};
+
+use crate::crypto::{PublicKey, ECC_SPKI_OID, P256_CURVE_OID};
+use crate::error::Error;
+use crate::Result;
+
+/// Pem header of an X.509 certificate
+const PEM_CERTIFICATE: &str = "CERTIFICATE";
+
+/// Longest chain of intermediates followed up to a root.
+const MAX_CHAIN_DEPTH: usize = 8;
+
+/// 1.2.840.10045.4.3.2 ecdsa-with-SHA256
+const ECDSA_SHA256_OID: &[u8] =
+    &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+/// 1.2.840.10045.4.3.3 ecdsa-with-SHA384
+const ECDSA_SHA384_OID: &[u8] =
+    &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03];
+/// 1.2.840.113549.1.1.11 sha256WithRSAEncryption
+const RSA_SHA256_OID: &[u8] =
+    &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b];
+/// 1.2.840.113549.1.1.12 sha384WithRSAEncryption
+const RSA_SHA384_OID: &[u8] =
+    &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0c];
+/// 1.2.840.113549.1.1.13 sha512WithRSAEncryption
+const RSA_SHA512_OID: &[u8] =
+    &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0d];
+/// 1.3.101.112 Ed25519
+const ED25519_OID: &[u8] = &[0x2b, 0x65, 0x70];
+/// 1.3.132.0.34 secp384r1 (the NIST P-384 curve)
+const P384_CURVE_OID: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x22];
+
+/// 2.5.29.15 keyUsage
+const KEY_USAGE_OID: &[u8] = &[0x55, 0x1d, 0x0f];
+/// 2.5.29.17 subjectAltName
+const SUBJECT_ALT_NAME_OID: &[u8] = &[0x55, 0x1d, 0x11];
+/// 2.5.29.19 basicConstraints
+const BASIC_CONSTRAINTS_OID: &[u8] = &[0x55, 0x1d, 0x13];
+/// 2.5.29.30 nameConstraints
+const NAME_CONSTRAINTS_OID: &[u8] = &[0x55, 0x1d, 0x1e];
+/// 2.5.29.37 extKeyUsage
+const EXT_KEY_USAGE_OID: &[u8] = &[0x55, 0x1d, 0x25];
+/// 1.3.6.1.5.5.7.3.3 id-kp-codeSigning
+const CODE_SIGNING_OID: &[u8] =
+    &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x03];
+/// 1.3.6.1.4.1.57264.1.1 Fulcio OIDC issuer, as raw bytes (deprecated)
+const OIDC_ISSUER_V1_OID: &[u8] =
+    &[0x2b, 0x06, 0x01, 0x04, 0x01, 0x83, 0xbf, 0x30, 0x01, 0x01];
+/// 1.3.6.1.4.1.57264.1.8 Fulcio OIDC issuer, as a DER UTF8String
+const OIDC_ISSUER_V2_OID: &[u8] =
+    &[0x2b, 0x06, 0x01, 0x04, 0x01, 0x83, 0xbf, 0x30, 0x01, 0x08];
+
+/// The digitalSignature bit of the key usage extension.
+const KEY_USAGE_DIGITAL_SIGNATURE: usize = 0;
+/// The keyCertSign bit of the key usage extension.
+const KEY_USAGE_KEY_CERT_SIGN: usize = 5;
+
+/// Extensions that may be marked critical, all of which are enforced.
+const KNOWN_CRITICAL_EXTENSIONS: &[&[u8]] = &[
+    KEY_USAGE_OID,
+    SUBJECT_ALT_NAME_OID,
+    BASIC_CONSTRAINTS_OID,
+    EXT_KEY_USAGE_OID,
+];
+
+const TAG_UTF8_STRING: u8 = 0x0c;
+const TAG_BMP_STRING: u8 = 0x1e;
+const TAG_SET: u8 = 0x31;
+
+/// An identity from the subject alternative name extension.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SubjectAltName {
+    /// An `rfc822Name`, e.g. `alice@example.com`.
+    Email(String),
+    /// A `dNSName`, e.g. `example.com`.
+    Dns(String),
+    /// A `uniformResourceIdentifier`, e.g. the workflow of a CI job.
+    Uri(String),
+}
+
+impl SubjectAltName {
+    /// The name, without its kind.
+    pub fn as_str(&self) -> &str {
+        match self {
+            SubjectAltName::Email(s)
+            | SubjectAltName::Dns(s)
+            | SubjectAltName::Uri(s) => s,
+        }
+    }
+}
+
+/// A distinguished name, kept as DER to compare and as a string to display.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Name {
+    der: Vec<u8>,
+    display: String,
+}
+
+/// A parsed X.509 certificate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Certificate {
+    der: Vec<u8>,
+    tbs: Vec<u8>,
+    signature_algorithm: Vec<u8>,
+    signature: Vec<u8>,
+    issuer: Name,
+    subject: Name,
+    not_before: DateTime<Utc>,
+    not_after: DateTime<Utc>,
+    spki: Vec<u8>,
+    key_algorithm: Vec<u8>,
+    key_curve: Option<Vec<u8>>,
+    key: Vec<u8>,
+    is_ca: bool,
+    path_len: Option<u8>,
+    /// The key usage bits, if the extension is present
+    key_usage: Option<Vec<u8>>,
+    /// The extended key usage OIDs, if the extension is present
+    ext_key_usage: Option<Vec<Vec<u8>>>,
+    subject_alt_names: Vec<SubjectAltName>,
+    oidc_issuer: Option<String>,
+}
+
+impl Certificate {
+    /// Parse a DER encoded certificate.
+    pub fn from_der(der: &[u8]) -> Result<Self> {
+        parse_certificate(der).map_err(|e| {
+            Error::Encoding(format!("Could not parse X.509 certificate: {}", e))
+        })
+    }
+
+    /// Parse a PEM encoded certificate (`-----BEGIN CERTIFICATE-----`).
+    pub fn from_pem(pem: &str) -> Result<Self> {
+        Self::from_der(&super::pem_contents(pem, PEM_CERTIFICATE)?)
+    }
+
+    /// Parse all certificates of a PEM bundle, in order.
+    pub fn from_pem_chain(pem: &str) -> Result<Vec<Self>> {
+        pem::parse_many(pem)
+            .map_err(|e| Error::Encoding(format!("Invalid PEM: {}", e)))?
+            .iter()
+            .map(|pem| {
+                if pem.tag() != PEM_CERTIFICATE {
+                    return Err(Error::Encoding(format!(
+                        "Expected PEM label {:?}, found {:?}",
+                        PEM_CERTIFICATE,
+                        pem.tag()
+                    )));
+                }
+                Self::from_der(pem.contents())
+            })
+            .collect()
+    }
+
+    /// The DER encoding of this certificate.
+    pub fn as_der(&self) -> &[u8] {
+        &self.der
+    }
+
+    /// The PEM encoding of this certificate.
+    pub fn to_pem(&self) -> String {
+        pem::encode(&pem::Pem::new(PEM_CERTIFICATE, self.der.clone()))
+            .replace("\r\n", "\n")
+    }
+
+    /// The subject, e.g. `O=in-toto, CN=alice`. Attributes are listed in
+    /// the order they are encoded in; unknown attribute types are written
+    /// as dotted OIDs. Empty for certificates that only identify their
+    /// subject by alternative names.
+    pub fn subject(&self) -> &str {
+        &self.subject.display
+    }
+
+    /// The issuer, formatted like [`Certificate::subject`].
+    pub fn issuer(&self) -> &str {
+        &self.issuer.display
+    }
+
+    /// The start of the validity period.
+    pub fn not_before(&self) -> &DateTime<Utc> {
+        &self.not_before
+    }
+
+    /// The end of the validity period.
+    pub fn not_after(&self) -> &DateTime<Utc> {
+        &self.not_after
+    }
+
+    /// Whether the basic constraints allow this certificate to issue
+    /// certificates.
+    pub fn is_ca(&self) -> bool {
+        self.is_ca
+    }
+
+    /// The email, DNS and URI subject alternative names.
+    pub fn subject_alt_names(&self) -> &[SubjectAltName] {
+        &self.subject_alt_names
+    }
+
+    /// The OIDC issuer that authenticated the subject, from the extensions
+    /// Fulcio adds to the certificates it issues.
+    pub fn oidc_issuer(&self) -> Option<&str> {
+        self.oidc_issuer.as_deref()
+    }
+
+    /// The public key of the subject. The signature scheme is inferred
+    /// like [`PublicKey::from_pem`] does, so only P-256 EC keys are
+    /// supported.
+    pub fn public_key(&self) -> Result<PublicKey> {
+        if self.key_algorithm == ECC_SPKI_OID
+            && self.key_curve.as_deref() != Some(P256_CURVE_OID)
+        {
+            return Err(Error::IllegalArgument(
+                "Only P-256 EC keys are supported".into(),
+            ));
+        }
+        PublicKey::from_spki_inferring_scheme(&self.spki)
+    }
+
+    /// Whether the key usage extension, if present, allows `bit`.
+    fn has_key_usage(&self, bit: usize) -> bool {
+        match &self.key_usage {
+            Some(bits) => bits
+                .get(bit / 8)
+                .is_some_and(|byte| byte & (0x80 >> (bit % 8)) != 0),
+            None => true,
+        }
+    }
+
+    /// Check that this certificate is meant to sign code: it is not a CA,
+    /// its key usage allows digital signatures and its extended key usage
+    /// includes code signing.
+    fn check_code_signing(&self) -> Result<()> {
+        if self.is_ca {
+            return Err(Error::VerificationFailure(format!(
+                "Certificate {:?} is a CA, not a signer",
+                self.subject()
+            )));
+        }
+        if !self.has_key_usage(KEY_USAGE_DIGITAL_SIGNATURE) {
+            return Err(Error::VerificationFailure(format!(
+                "Certificate {:?} may not make digital signatures",
+                self.subject()
+            )));
+        }
+        let ext_key_usage = self.ext_key_usage.as_deref().unwrap_or_default();
+        if !ext_key_usage.iter().any(|oid| oid == CODE_SIGNING_OID) {
+            return Err(Error::VerificationFailure(format!(
+                "Certificate {:?} is not issued for code signing",
+                self.subject()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check that this certificate was issued by `issuer`: the names
+    /// match, `issuer` is a CA whose key usage allows signing certificates,
+    /// and its key signed this certificate.
+    pub fn verify_issued_by(&self, issuer: &Certificate) -> Result<()> {
+        if self.issuer.der != issuer.subject.der {
+            return Err(Error::VerificationFailure(format!(
+                "Certificate issued by {:?}, not {:?}",
+                self.issuer(),
+                issuer.subject()
+            )));
+        }
+        if !issuer.is_ca {
+            return Err(Error::VerificationFailure(format!(
+                "Certificate {:?} is not a CA",
+                issuer.subject()
+            )));
+        }
+        if !issuer.has_key_usage(KEY_USAGE_KEY_CERT_SIGN) {
+            return Err(Error::VerificationFailure(format!(
+                "Certificate {:?} may not sign certificates",
+                issuer.subject()
+            )));
+        }
+        let alg = verification_algorithm(
+            &self.signature_algorithm,
+            &issuer.key_algorithm,
+            issuer.key_curve.as_deref(),
+        )?;
+        UnparsedPublicKey::new(alg, &issuer.key)
+            .verify(&self.tbs, &self.signature)
+            .map_err(|_| Error::BadSignature)
+    }
+
+    /// Check this certificate, that of a signer, and its issuers up to one
+    /// of `roots`, using `intermediates` to complete the chain.
+    ///
+    /// This certificate must not be a CA, and must be issued for digital
+    /// signatures and code signing. Every certificate of the chain must be
+    /// valid at `at`, usually the time of signing, and each issuer must
+    /// allow the number of intermediates below it by its path length
+    /// constraint.
+    pub fn verify_chain(
+        &self,
+        intermediates: &[Certificate],
+        roots: &[Certificate],
+        at: &DateTime<Utc>,
+    ) -> Result<()> {
+        self.check_code_signing()?;
+        let mut cert = self;
+        for depth in 0..=MAX_CHAIN_DEPTH {
+            cert.check_validity(at)?;
+            if roots.iter().any(|root| root.der == cert.der) {
+                return Ok(());
+            }
+            // `depth` intermediates are below the issuer of `cert`
+            let may_issue = |issuer: &&Certificate| {
+                issuer.path_len.is_none_or(|len| depth <= len.into())
+                    && cert.verify_issued_by(issuer).is_ok()
+            };
+            if let Some(root) = roots.iter().find(may_issue) {
+                return root.check_validity(at);
+            }
+            cert = intermediates.iter().find(may_issue).ok_or_else(|| {
+                Error::VerificationFailure(format!(
+                    "No trusted issuer found for certificate {:?} \
+                         issued by {:?}",
+                    cert.subject(),
+                    cert.issuer()
+                ))
+            })?;
+        }
+        Err(Error::VerificationFailure(
+            "Certificate chain is too long".into(),
+        ))
+    }
+
+    fn check_validity(&self, at: &DateTime<Utc>) -> Result<()> {
+        if at < &self.not_before || at > &self.not_after {
+            return Err(Error::VerificationFailure(format!(
+                "Certificate {:?} is only valid from {} to {}",
+                self.subject(),
+                self.not_before,
+                self.not_after
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// The certificates trusted to issue the certificates of signers.
+#[derive(Debug, Clone, Default)]
+pub struct TrustRoot {
+    /// Trusted root certificates.
+    pub roots: Vec<Certificate>,
+    /// Intermediate certificates, in addition to those embedded in the
+    /// signatures, used to complete the chains to `roots`.
+    pub intermediates: Vec<Certificate>,
+}
+
+impl TrustRoot {
+    /// Verify `chain`, a PEM encoded certificate chain as embedded in DSSE
+    /// signatures, at `at`. On success the leaf is returned.
+    pub fn verify(
+        &self,
+        chain: &str,
+        at: &DateTime<Utc>,
+    ) -> Result<Certificate> {
+        let mut chain = Certificate::from_pem_chain(chain)?;
+        if chain.is_empty() {
+            return Err(Error::Encoding("Empty certificate chain".into()));
+        }
+        let leaf = chain.remove(0);
+        chain.extend(self.intermediates.iter().cloned());
+        leaf.verify_chain(&chain, &self.roots, at)?;
+        Ok(leaf)
+    }
+}
+
+/// Constraints on the identity a certificate is issued to, to authorize
+/// functionaries by who they are rather than by key ID. Fields that are
+/// `None` match any certificate.
+#[derive(Debug, Clone, Default)]
+pub struct CertificateConstraint {
+    /// The OIDC issuer that authenticated the subject, see
+    /// [`Certificate::oidc_issuer`].
+    pub issuer: Option<String>,
+    /// A pattern the subject or one of the subject alternative names must
+    /// match. It is not anchored, so use `^` and `$` to match a whole name.
+    pub subject_regex: Option<Regex>,
+    /// A subject alternative name the certificate must have.
+    pub san: Option<String>,
+}
+
+impl CertificateConstraint {
+    /// Whether `cert` satisfies all the constraints.
+    pub fn matches(&self, cert: &Certificate) -> bool {
+        if let Some(issuer) = &self.issuer {
+            if cert.oidc_issuer() != Some(issuer.as_str()) {
+                return false;
+            }
+        }
+        if let Some(regex) = &self.subject_regex {
+            let mut names = cert.subject_alt_names().iter().map(|n| n.as_str());
+            if !regex.is_match(cert.subject())
+                && !names.any(|name| regex.is_match(name))
+            {
+                return false;
+            }
+        }
+        if let Some(san) = &self.san {
+            if !cert.subject_alt_names().iter().any(|n| n.as_str() == san) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn verification_algorithm(
+    signature_algorithm: &[u8],
+    key_algorithm: &[u8],
+    key_curve: Option<&[u8]>,
+) -> Result<&'static dyn VerificationAlgorithm> {
+    let curve = if key_algorithm == ECC_SPKI_OID {
+        key_curve
+    } else {
+        None
+    };
+    Ok(match (signature_algorithm, curve) {
+        (ECDSA_SHA256_OID, Some(P256_CURVE_OID)) => {
+            &signature::ECDSA_P256_SHA256_ASN1
+        }
+        (ECDSA_SHA256_OID, Some(P384_CURVE_OID)) => {
+            &signature::ECDSA_P384_SHA256_ASN1
+        }
+        (ECDSA_SHA384_OID, Some(P256_CURVE_OID)) => {
+            &signature::ECDSA_P256_SHA384_ASN1
+        }
+        (ECDSA_SHA384_OID, Some(P384_CURVE_OID)) => {
+            &signature::ECDSA_P384_SHA384_ASN1
+        }
+        (RSA_SHA256_OID, None) => &signature::RSA_PKCS1_2048_8192_SHA256,
+        (RSA_SHA384_OID, None) => &signature::RSA_PKCS1_2048_8192_SHA384,
+        (RSA_SHA512_OID, None) => &signature::RSA_PKCS1_2048_8192_SHA512,
+        (ED25519_OID, None) if key_algorithm == ED25519_OID => {
+            &signature::ED25519
+        }
+        _ => {
+            return Err(Error::IllegalArgument(format!(
+                "Unsupported certificate signature algorithm {} for a {} key",
+                oid_to_string(signature_algorithm),
+                oid_to_string(key_algorithm)
+            )))
+        }
+    })
+}
+
+type DerResult<T> = ::std::result::Result<T, String>;
+
+fn der_err(e: derp::Error) -> String {
+    format!("{:?}", e)
+}
+
+fn parse_certificate(der: &[u8]) -> DerResult<Certificate> {
+    let (tbs, signature_algorithm, signature) = Input::from(der)
+        .read_all(derp::Error::Read, |input| {
+            derp::nested(input, derp::Tag::Sequence, |input| {
+                let (tbs, _) = input.read_partial(|input| {
+                    derp::expect_tag_and_get_value(input, derp::Tag::Sequence)
+                })?;
+                let algorithm =
+                    derp::expect_tag_and_get_value(input, derp::Tag::Sequence)?;
+                let signature = derp::bit_string_with_no_unused_bits(input)?;
+                Ok((
+                    tbs.as_slice_less_safe().to_vec(),
+                    algorithm.as_slice_less_safe().to_vec(),
+                    signature.as_slice_less_safe().to_vec(),
+                ))
+            })
+        })
+        .map_err(der_err)?;
+
+    let (mut cert, tbs_signature_algorithm) =
+        Input::from(&tbs).read_all(String::from("trailing data"), |input| {
+            derp::nested(input, derp::Tag::Sequence, parse_tbs_certificate)
+                .map_err(der_err)?
+        })?;
+    // Both must be the same AlgorithmIdentifier, parameters included
+    if tbs_signature_algorithm != signature_algorithm {
+        return Err("mismatched signature algorithms".into());
+    }
+    cert.der = der.to_vec();
+    cert.tbs = tbs;
+    cert.signature = signature;
+    Ok(cert)
+}
+
+/// Parse the TBSCertificate, returning it with the contents of its
+/// `signature` AlgorithmIdentifier. The outer error is a DER error, the
+/// inner one a semantic error found after the DER was read.
+fn parse_tbs_certificate(
+    input: &mut Reader,
+) -> ::std::result::Result<DerResult<(Certificate, Vec<u8>)>, derp::Error> {
+    if input.peek(derp::Tag::ContextSpecificConstructed0 as u8) {
+        let version = derp::nested(
+            input,
+            derp::Tag::ContextSpecificConstructed0,
+            derp::small_nonnegative_integer,
+        )?;
+        if version > 2 {
+            return Err(derp::Error::WrongValue);
+        }
+    }
+    // serialNumber
+    derp::expect_tag_and_get_value(input, derp::Tag::Integer)?;
+    let algorithm_der =
+        derp::expect_tag_and_get_value(input, derp::Tag::Sequence)?;
+    let (signature_algorithm, _) =
+        algorithm_der.read_all(derp::Error::Read, read_algorithm_identifier)?;
+    let algorithm_der = algorithm_der.as_slice_less_safe().to_vec();
+    let issuer = read_name(input)?;
+    let (not_before, not_after) =
+        derp::nested(input, derp::Tag::Sequence, |input| {
+            Ok((read_time(input)?, read_time(input)?))
+        })?;
+    let subject = read_name(input)?;
+    let (spki, (key_algorithm, key_curve, key)) =
+        input.read_partial(|input| {
+            derp::nested(input, derp::Tag::Sequence, |input| {
+                let (algorithm, parameters) = derp::nested(
+                    input,
+                    derp::Tag::Sequence,
+                    read_algorithm_identifier,
+                )?;
+                let key = derp::bit_string_with_no_unused_bits(input)?;
+                Ok((algorithm, parameters, key.as_slice_less_safe().to_vec()))
+            })
+        })?;
+    // issuerUniqueID and subjectUniqueID
+    for tag in [0x81, 0x82] {
+        if input.peek(tag) {
+            derp::read_tag_and_get_value(input)?;
+        }
+    }
+
+    let mut cert = Certificate {
+        der: Vec::new(),
+        tbs: Vec::new(),
+        signature_algorithm,
+        signature: Vec::new(),
+        issuer,
+        subject,
+        not_before,
+        not_after,
+        spki: spki.as_slice_less_safe().to_vec(),
+        key_algorithm,
+        key_curve,
+        key,
+        is_ca: false,
+        path_len: None,
+        key_usage: None,
+        ext_key_usage: None,
+        subject_alt_names: Vec::new(),
+        oidc_issuer: None,
+    };
+    if input.at_end() {
+        return Ok(Ok((cert, algorithm_der)));
+    }
+
+    let extensions =
+        derp::nested(input, derp::Tag::ContextSpecificConstructed3, |input| {
+            derp::nested(input, derp::Tag::Sequence, |input| {
+                let mut extensions = Vec::new();
+                while !input.at_end() {
+                    extensions.push(derp::nested(
+                        input,
+                        derp::Tag::Sequence,
+                        read_extension,
+                    )?);
+                }
+                Ok(extensions)
+            })
+        })?;
+
+    let mut oidc_issuer_v1 = None;
+    for (oid, critical, value) in extensions {
+        let value = Input::from(&value);
+        match oid.as_slice() {
+            BASIC_CONSTRAINTS_OID => {
+                (cert.is_ca, cert.path_len) =
+                    value.read_all(derp::Error::Read, |input| {
+                        derp::nested(input, derp::Tag::Sequence, |input| {
+                            let is_ca = input.peek(derp::Tag::Boolean as u8)
+                                && read_boolean(input)?;
+                            let path_len = if input
+                                .peek(derp::Tag::Integer as u8)
+                            {
+                                Some(derp::small_nonnegative_integer(input)?)
+                            } else {
+                                None
+                            };
+                            Ok((is_ca, path_len))
+                        })
+                    })?;
+            }
+            KEY_USAGE_OID => {
+                let bits = value.read_all(derp::Error::Read, |input| {
+                    derp::expect_tag_and_get_value(input, derp::Tag::BitString)
+                })?;
+                match bits.as_slice_less_safe() {
+                    [unused_bits, bits @ ..] if *unused_bits < 8 => {
+                        cert.key_usage = Some(bits.to_vec());
+                    }
+                    _ => return Err(derp::Error::WrongValue),
+                }
+            }
+            EXT_KEY_USAGE_OID => {
+                cert.ext_key_usage =
+                    Some(value.read_all(derp::Error::Read, |input| {
+                        derp::nested(input, derp::Tag::Sequence, |input| {
+                            let mut oids = Vec::new();
+                            while !input.at_end() {
+                                let oid = derp::expect_tag_and_get_value(
+                                    input,
+                                    derp::Tag::Oid,
+                                )?;
+                                oids.push(oid.as_slice_less_safe().to_vec());
+                            }
+                            Ok(oids)
+                        })
+                    })?);
+            }
+            NAME_CONSTRAINTS_OID => {
+                return Ok(Err("name constraints are not supported".into()));
+            }
+            SUBJECT_ALT_NAME_OID => {
+                cert.subject_alt_names =
+                    value.read_all(derp::Error::Read, read_general_names)?;
+            }
+            OIDC_ISSUER_V1_OID => {
+                oidc_issuer_v1 = Some(utf8(value.as_slice_less_safe())?);
+            }
+            OIDC_ISSUER_V2_OID => {
+                let issuer = value.read_all(derp::Error::Read, |input| {
+                    match derp::read_tag_and_get_value(input)? {
+                        (TAG_UTF8_STRING, issuer) => Ok(issuer),
+                        _ => Err(derp::Error::WrongTag),
+                    }
+                })?;
+                cert.oidc_issuer = Some(utf8(issuer.as_slice_less_safe())?);
+            }
+            oid if critical && !KNOWN_CRITICAL_EXTENSIONS.contains(&oid) => {
+                return Ok(Err(format!(
+                    "unsupported critical extension {}",
+                    oid_to_string(oid)
+                )));
+            }
+            _ => {}
+        }
+    }
+    if cert.oidc_issuer.is_none() {
+        cert.oidc_issuer = oidc_issuer_v1;
+    }
+    Ok(Ok((cert, algorithm_der)))
+}
+
+/// Read an AlgorithmIdentifier, returning the algorithm and the OID
+/// parameter, if any.
+fn read_algorithm_identifier(
+    input: &mut Reader,
+) -> ::std::result::Result<(Vec<u8>, Option<Vec<u8>>), derp::Error> {
+    let algorithm = derp::expect_tag_and_get_value(input, derp::Tag::Oid)?
+        .as_slice_less_safe()
+        .to_vec();
+    let parameter = if input.peek(derp::Tag::Oid as u8) {
+        Some(
+            derp::expect_tag_and_get_value(input, derp::Tag::Oid)?
+                .as_slice_less_safe()
+                .to_vec(),
+        )
+    } else {
+        None
+    };
+    input.skip_to_end();
+    Ok((algorithm, parameter))
+}
+
+fn read_name(input: &mut Reader) -> ::std::result::Result<Name, derp::Error> {
+    let (der, display) = input.read_partial(|input| {
+        derp::nested(input, derp::Tag::Sequence, |input| {
+            let mut attributes = Vec::new();
+            while !input.at_end() {
+                let (tag, set) = derp::read_tag_and_get_value(input)?;
+                if tag != TAG_SET {
+                    return Err(derp::Error::WrongTag);
+                }
+                set.read_all(derp::Error::Read, |input| {
+                    while !input.at_end() {
+                        attributes.push(derp::nested(
+                            input,
+                            derp::Tag::Sequence,
+                            read_attribute,
+                        )?);
+                    }
+                    Ok(())
+                })?;
+            }
+            Ok(attributes.join(", "))
+        })
+    })?;
+    Ok(Name {
+        der: der.as_slice_less_safe().to_vec(),
+        display,
+    })
+}
+
+fn read_attribute(
+    input: &mut Reader,
+) -> ::std::result::Result<String, derp::Error> {
+    let oid = derp::expect_tag_and_get_value(input, derp::Tag::Oid)?;
+    let (tag, value) = derp::read_tag_and_get_value(input)?;
+    let value = value.as_slice_less_safe();
+    let value = match tag {
+        TAG_BMP_STRING => {
+            let units: Vec<u16> = value
+                .chunks(2)
+                .map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(value).into_owned(),
+    };
+    let name = match oid.as_slice_less_safe() {
+        [0x55, 0x04, 0x03] => "CN".to_string(),
+        [0x55, 0x04, 0x06] => "C".to_string(),
+        [0x55, 0x04, 0x07] => "L".to_string(),
+        [0x55, 0x04, 0x08] => "ST".to_string(),
+        [0x55, 0x04, 0x0a] => "O".to_string(),
+        [0x55, 0x04, 0x0b] => "OU".to_string(),
+        [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x01] => {
+            "emailAddress".to_string()
+        }
+        oid => oid_to_string(oid),
+    };
+    Ok(format!("{}={}", name, value))
+}
+
+fn read_time(
+    input: &mut Reader,
+) -> ::std::result::Result<DateTime<Utc>, derp::Error> {
+    let (tag, value) = derp::read_tag_and_get_value(input)?;
+    let value = std::str::from_utf8(value.as_slice_less_safe())
+        .map_err(|_| derp::Error::WrongValue)?;
+    let value = match tag {
+        // Two digit years from 50 on are in the 20th century.
+        t if t == derp::Tag::UtcTime as u8 => match value.get(..2) {
+            Some(yy) if yy >= "50" => format!("19{}", value),
+            _ => format!("20{}", value),
+        },
+        t if t == derp::Tag::GeneralizedTime as u8 => value.to_string(),
+        _ => return Err(derp::Error::WrongTag),
+    };
+    NaiveDateTime::parse_from_str(&value, "%Y%m%d%H%M%SZ")
+        .map(|time| time.and_utc())
+        .map_err(|_| derp::Error::WrongValue)
+}
+
+type Extension = (Vec<u8>, bool, Vec<u8>);
+
+fn read_extension(
+    input: &mut Reader,
+) -> ::std::result::Result<Extension, derp::Error> {
+    let oid = derp::expect_tag_and_get_value(input, derp::Tag::Oid)?;
+    let critical = input.peek(derp::Tag::Boolean as u8) && read_boolean(input)?;
+    let value = derp::expect_tag_and_get_value(input, derp::Tag::OctetString)?;
+    Ok((
+        oid.as_slice_less_safe().to_vec(),
+        critical,
+        value.as_slice_less_safe().to_vec(),
+    ))
+}
+
+fn read_general_names(
+    input: &mut Reader,
+) -> ::std::result::Result<Vec<SubjectAltName>, derp::Error> {
+    derp::nested(input, derp::Tag::Sequence, |input| {
+        let mut names = Vec::new();
+        while !input.at_end() {
+            let (tag, value) = derp::read_tag_and_get_value(input)?;
+            let value = || {
+                utf8(value.as_slice_less_safe())
+                    .map_err(|_| derp::Error::WrongValue)
+            };
+            match tag {
+                0x81 => names.push(SubjectAltName::Email(value()?)),
+                0x82 => names.push(SubjectAltName::Dns(value()?)),
+                0x86 => names.push(SubjectAltName::Uri(value()?)),
+                _ => {}
+            }
+        }
+        Ok(names)
+    })
+}
+
+/// Read a BOOLEAN. DER encodes true as 0xff, which `derp::boolean` rejects.
+fn read_boolean(
+    input: &mut Reader,
+) -> ::std::result::Result<bool, derp::Error> {
+    let value = derp::expect_tag_and_get_value(input, derp::Tag::Boolean)?;
+    match value.as_slice_less_safe() {
+        [0x00] => Ok(false),
+        [0xff] => Ok(true),
+        _ => Err(derp::Error::BadBooleanValue),
+    }
+}
+
+fn utf8(bytes: &[u8]) -> ::std::result::Result<String, derp::Error> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| derp::Error::WrongValue)
+}
+
+/// Format a DER encoded OID in dotted notation.
+fn oid_to_string(oid: &[u8]) -> String {
+    let mut arcs = Vec::new();
+    let mut arc: u64 = 0;
+    for &b in oid {
+        arc = (arc << 7) | u64::from(b & 0x7f);
+        if b & 0x80 == 0 {
+            if arcs.is_empty() {
+                let first = (arc / 40).min(2);
+                arcs.push(first);
+                arcs.push(arc - 40 * first);
+            } else {
+                arcs.push(arc);
+            }
+            arc = 0;
+        }
+    }
+    arcs.iter()
+        .map(|arc| arc.to_string())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Issue certificates the way Fulcio does, to test code verifying them.
+#[cfg(test)]
+pub(crate) mod testing {
+    use chrono::{DateTime, Utc};
+    use derp::{Der, Tag};
+
+    use super::*;
+    use crate::crypto::{PrivateKey, PublicKey};
+
+    /// Write a primitive element with a tag `derp::Tag` does not cover.
+    fn element(der: &mut Der<Vec<u8>>, tag: u8, value: &[u8]) {
+        assert!(value.len() < 0x80);
+        der.raw(&[tag, value.len() as u8]).unwrap();
+        der.raw(value).unwrap();
+    }
+
+    fn time(der: &mut Der<Vec<u8>>, time: &DateTime<Utc>) {
+        let time = time.format("%Y%m%d%H%M%SZ").to_string();
+        der.element(Tag::GeneralizedTime, time.as_bytes()).unwrap();
+    }
+
+    /// Issue a certificate for `public_key`, identified by `san` and
+    /// authenticated by `oidc_issuer`, signed by the P-256 key of `issuer`.
+    pub(crate) fn issue(
+        issuer: &Certificate,
+        issuer_key: &PrivateKey,
+        public_key: &PublicKey,
+        san: &SubjectAltName,
+        oidc_issuer: &str,
+        not_before: &DateTime<Utc>,
+        not_after: &DateTime<Utc>,
+    ) -> Certificate {
+        let mut tbs = Vec::new();
+        Der::new(&mut tbs)
+            .sequence(|der| {
+                der.nested(Tag::ContextSpecificConstructed0, |der| {
+                    der.integer(&[2])
+                })?;
+                der.integer(&[0x2a])?;
+                der.sequence(|der| der.oid(ECDSA_SHA256_OID))?;
+                der.raw(&issuer.subject.der)?;
+                der.sequence(|der| {
+                    time(der, not_before);
+                    time(der, not_after);
+                    Ok(())
+                })?;
+                der.sequence(|_| Ok(()))?;
+                der.raw(&public_key.as_spki().unwrap())?;
+                der.nested(Tag::ContextSpecificConstructed3, |der| {
+                    der.sequence(|der| {
+                        der.sequence(|der| {
+                            der.oid(KEY_USAGE_OID)?;
+                            der.element(Tag::Boolean, &[0xff])?;
+                            let mut usage = Vec::new();
+                            // digitalSignature
+                            Der::new(&mut usage).bit_string(7, &[0x80])?;
+                            der.octet_string(&usage)
+                        })?;
+                        der.sequence(|der| {
+                            der.oid(EXT_KEY_USAGE_OID)?;
+                            let mut usage = Vec::new();
+                            Der::new(&mut usage)
+                                .sequence(|der| der.oid(CODE_SIGNING_OID))?;
+                            der.octet_string(&usage)
+                        })?;
+                        der.sequence(|der| {
+                            der.oid(SUBJECT_ALT_NAME_OID)?;
+                            der.element(Tag::Boolean, &[0xff])?;
+                            let mut names = Vec::new();
+                            Der::new(&mut names).sequence(|der| {
+                                let tag = match san {
+                                    SubjectAltName::Email(_) => 0x81,
+                                    SubjectAltName::Dns(_) => 0x82,
+                                    SubjectAltName::Uri(_) => 0x86,
+                                };
+                                element(der, tag, san.as_str().as_bytes());
+                                Ok(())
+                            })?;
+                            der.octet_string(&names)
+                        })?;
+                        der.sequence(|der| {
+                            der.oid(OIDC_ISSUER_V2_OID)?;
+                            let mut value = Vec::new();
+                            element(
+                                &mut Der::new(&mut value),
+                                TAG_UTF8_STRING,
+                                oidc_issuer.as_bytes(),
+                            );
+                            der.octet_string(&value)
+                        })
+                    })
+                })
+            })
+            .unwrap();
+
+        let sig = issuer_key.sign(&tbs).unwrap();
+        let mut der = Vec::new();
+        Der::new(&mut der)
+            .sequence(|der| {
+                der.raw(&tbs)?;
+                der.sequence(|der| der.oid(ECDSA_SHA256_OID))?;
+                der.bit_string(0, sig.value().as_bytes())
+            })
+            .unwrap();
+        Certificate::from_der(&der).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+    use derp::Der;
+
+    use super::*;
+    use crate::crypto::{PrivateKey, SignatureScheme};
+
+    const ROOT: &[u8] = include_bytes!("../../tests/x509/root.der");
+    const INTERMEDIATE: &[u8] =
+        include_bytes!("../../tests/x509/intermediate.der");
+    const INTERMEDIATE_PK8: &[u8] =
+        include_bytes!("../../tests/x509/intermediate.pk8.der");
+    const LEAF: &[u8] = include_bytes!("../../tests/x509/leaf.der");
+    const LEAF_PK8: &[u8] = include_bytes!("../../tests/x509/leaf.pk8.der");
+    const CA_NO_CERT_SIGN: &[u8] =
+        include_bytes!("../../tests/x509/ca-no-cert-sign.der");
+    const LEAF_NO_CERT_SIGN: &[u8] =
+        include_bytes!("../../tests/x509/leaf-no-cert-sign.der");
+    const SUB_CA: &[u8] = include_bytes!("../../tests/x509/sub-ca.der");
+    const LEAF_SUB_CA: &[u8] =
+        include_bytes!("../../tests/x509/leaf-sub-ca.der");
+    const NAME_CONSTRAINED_CA: &[u8] =
+        include_bytes!("../../tests/x509/name-constrained-ca.der");
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn parse_certificates() {
+        let root = Certificate::from_der(ROOT).unwrap();
+        assert_eq!(root.subject(), "O=in-toto test, CN=Test Root CA");
+        assert_eq!(root.issuer(), root.subject());
+        assert!(root.is_ca());
+        assert!(root.public_key().is_err());
+
+        let leaf = Certificate::from_der(LEAF).unwrap();
+        assert_eq!(leaf.subject(), "");
+        assert_eq!(leaf.issuer(), "O=in-toto test, CN=Test Intermediate CA");
+        assert!(!leaf.is_ca());
+        assert_eq!(
+            leaf.subject_alt_names(),
+            [
+                SubjectAltName::Email("alice@example.com".into()),
+                SubjectAltName::Uri("https://example.com/alice".into()),
+            ]
+        );
+        assert_eq!(leaf.oidc_issuer(), Some("https://issuer.example.com"));
+        // The certificates expire after 2050, so as a GeneralizedTime.
+        assert!(
+            leaf.not_after()
+                > &Utc.with_ymd_and_hms(2100, 1, 1, 0, 0, 0).unwrap()
+        );
+
+        let key =
+            PrivateKey::from_pkcs8(LEAF_PK8, SignatureScheme::EcdsaP256Sha256)
+                .unwrap();
+        assert_eq!(&leaf.public_key().unwrap(), key.public());
+
+        let pem = leaf.to_pem();
+        assert!(pem.starts_with("-----BEGIN CERTIFICATE-----\n"));
+        assert_eq!(Certificate::from_pem(&pem).unwrap(), leaf);
+        let chain = format!("{}{}", pem, root.to_pem());
+        assert_eq!(
+            Certificate::from_pem_chain(&chain).unwrap(),
+            vec![leaf, root]
+        );
+        assert!(Certificate::from_der(&LEAF[1..]).is_err());
+    }
+
+    #[test]
+    fn verify_certificate_chain() {
+        let root = Certificate::from_der(ROOT).unwrap();
+        let intermediate = Certificate::from_der(INTERMEDIATE).unwrap();
+        let leaf = Certificate::from_der(LEAF).unwrap();
+        let roots = [root.clone()];
+        let intermediates = [intermediate.clone()];
+
+        intermediate.verify_issued_by(&root).unwrap();
+        leaf.verify_issued_by(&intermediate).unwrap();
+        assert!(leaf.verify_issued_by(&root).is_err());
+        // A leaf is not a CA, whatever it signs.
+        assert!(intermediate.verify_issued_by(&leaf).is_err());
+
+        leaf.verify_chain(&intermediates, &roots, &now()).unwrap();
+        // Only the certificates of signers are verified
+        assert!(root.verify_chain(&[], &roots, &now()).is_err());
+        assert!(leaf.verify_chain(&[], &roots, &now()).is_err());
+        assert!(leaf.verify_chain(&intermediates, &[], &now()).is_err());
+        assert!(leaf
+            .verify_chain(&intermediates, &intermediates, &now())
+            .is_ok());
+
+        let before = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        assert!(leaf.verify_chain(&intermediates, &roots, &before).is_err());
+
+        let mut tampered = leaf;
+        tampered.tbs[20] ^= 1;
+        assert_eq!(
+            tampered.verify_issued_by(&intermediate),
+            Err(Error::BadSignature)
+        );
+    }
+
+    #[test]
+    fn require_cert_sign_key_usage() {
+        let root = Certificate::from_der(ROOT).unwrap();
+        let ca = Certificate::from_der(CA_NO_CERT_SIGN).unwrap();
+        let leaf = Certificate::from_der(LEAF_NO_CERT_SIGN).unwrap();
+        ca.verify_issued_by(&root).unwrap();
+        assert!(ca.is_ca());
+
+        // A CA whose key usage lacks keyCertSign issues nothing
+        assert!(leaf.verify_issued_by(&ca).is_err());
+        assert!(leaf.verify_chain(&[ca], &[root], &now()).is_err());
+    }
+
+    #[test]
+    fn enforce_path_length() {
+        let root = Certificate::from_der(ROOT).unwrap();
+        let intermediate = Certificate::from_der(INTERMEDIATE).unwrap();
+        let sub_ca = Certificate::from_der(SUB_CA).unwrap();
+        let leaf = Certificate::from_der(LEAF_SUB_CA).unwrap();
+        sub_ca.verify_issued_by(&intermediate).unwrap();
+        leaf.verify_issued_by(&sub_ca).unwrap();
+
+        // The intermediate has a path length of 0, so no CA may be below it
+        let intermediates = [sub_ca.clone(), intermediate.clone()];
+        assert!(leaf.verify_chain(&intermediates, &[root], &now()).is_err());
+        let sub_cas = [sub_ca];
+        assert!(leaf
+            .verify_chain(&sub_cas, &[intermediate], &now())
+            .is_err());
+        leaf.verify_chain(&[], &sub_cas, &now()).unwrap();
+    }
+
+    #[test]
+    fn reject_name_constraints() {
+        assert!(Certificate::from_der(NAME_CONSTRAINED_CA).is_err());
+    }
+
+    #[test]
+    fn require_code_signing_leaf() {
+        let intermediates = [Certificate::from_der(INTERMEDIATE).unwrap()];
+        let roots = [Certificate::from_der(ROOT).unwrap()];
+        for der in [
+            &include_bytes!("../../tests/x509/leaf-ca.der")[..],
+            include_bytes!("../../tests/x509/leaf-no-eku.der"),
+            include_bytes!("../../tests/x509/leaf-server-auth.der"),
+            include_bytes!("../../tests/x509/leaf-key-encipherment.der"),
+        ] {
+            let leaf = Certificate::from_der(der).unwrap();
+            leaf.verify_issued_by(&intermediates[0]).unwrap();
+            assert!(leaf.verify_chain(&intermediates, &roots, &now()).is_err());
+        }
+    }
+
+    #[test]
+    fn reject_mismatched_signature_algorithm() {
+        let leaf = Certificate::from_der(LEAF).unwrap();
+        let encode = |algorithm: &dyn Fn(&mut Der<Vec<u8>>) -> _| {
+            let mut der = Vec::new();
+            Der::new(&mut der)
+                .sequence(|der| {
+                    der.raw(&leaf.tbs)?;
+                    der.sequence(algorithm)?;
+                    der.bit_string(0, &leaf.signature)
+                })
+                .unwrap();
+            der
+        };
+
+        let same = encode(&|der| der.oid(ECDSA_SHA256_OID));
+        assert_eq!(Certificate::from_der(&same).unwrap(), leaf);
+        let other = encode(&|der| der.oid(ECDSA_SHA384_OID));
+        assert!(Certificate::from_der(&other).is_err());
+        let with_parameters = encode(&|der| {
+            der.oid(ECDSA_SHA256_OID)?;
+            der.null()
+        });
+        assert!(Certificate::from_der(&with_parameters).is_err());
+    }
+
+    #[test]
+    fn issue_certificate() {
+        let intermediate = Certificate::from_der(INTERMEDIATE).unwrap();
+        let intermediate_key = PrivateKey::from_pkcs8(
+            INTERMEDIATE_PK8,
+            SignatureScheme::EcdsaP256Sha256,
+        )
+        .unwrap();
+        let key =
+            PrivateKey::from_pkcs8(LEAF_PK8, SignatureScheme::EcdsaP256Sha256)
+                .unwrap();
+        let san = SubjectAltName::Uri("https://ci.example.com/build".into());
+        let start = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let cert = testing::issue(
+            &intermediate,
+            &intermediate_key,
+            key.public(),
+            &san,
+            "https://ci.example.com",
+            &start,
+            &(start + chrono::Duration::minutes(10)),
+        );
+
+        assert_eq!(cert.subject_alt_names(), [san]);
+        assert_eq!(cert.oidc_issuer(), Some("https://ci.example.com"));
+        assert_eq!(&cert.public_key().unwrap(), key.public());
+        let roots = [Certificate::from_der(ROOT).unwrap()];
+        cert.verify_chain(&[intermediate], &roots, &start).unwrap();
+    }
+
+    #[test]
+    fn match_certificate_constraints() {
+        let leaf = Certificate::from_der(LEAF).unwrap();
+        let constraint =
+            |issuer: Option<&str>, regex: Option<&str>, san: Option<&str>| {
+                CertificateConstraint {
+                    issuer: issuer.map(String::from),
+                    subject_regex: regex.map(|r| Regex::new(r).unwrap()),
+                    san: san.map(String::from),
+                }
+            };
+
+        assert!(CertificateConstraint::default().matches(&leaf));
+        assert!(constraint(
+            Some("https://issuer.example.com"),
+            Some("^https://example.com/"),
+            Some("alice@example.com"),
+        )
+        .matches(&leaf));
+        assert!(!constraint(Some("https://other.example.com"), None, None)
+            .matches(&leaf));
+        assert!(!constraint(None, Some("^bob@"), None).matches(&leaf));
+        assert!(!constraint(None, None, Some("alice")).matches(&leaf));
+
+        let intermediate = Certificate::from_der(INTERMEDIATE).unwrap();
+        assert!(constraint(None, Some("CN=Test Intermediate CA$"), None)
+            .matches(&intermediate));
+        assert!(!constraint(Some("https://issuer.example.com"), None, None)
+            .matches(&intermediate));
+    }
+
+    #[test]
+    fn verify_with_trust_root() {
+        let leaf = Certificate::from_der(LEAF).unwrap();
+        let intermediate = Certificate::from_der(INTERMEDIATE).unwrap();
+        let trust_root = TrustRoot {
+            roots: vec![Certificate::from_der(ROOT).unwrap()],
+            intermediates: vec![],
+        };
+
+        let chain = leaf.to_pem() + &intermediate.to_pem();
+        assert_eq!(trust_root.verify(&chain, &now()).unwrap(), leaf);
+        assert!(trust_root.verify(&leaf.to_pem(), &now()).is_err());
+        assert!(trust_root.verify("", &now()).is_err());
+
+        let trust_root = TrustRoot {
+            intermediates: vec![intermediate],
+            ..trust_root
+        };
+        assert_eq!(trust_root.verify(&leaf.to_pem(), &now()).unwrap(), leaf);
+    }
+
+    #[test]
+    fn format_oids() {
+        assert_eq!(oid_to_string(OIDC_ISSUER_V2_OID), "1.3.6.1.4.1.57264.1.8");
+        assert_eq!(oid_to_string(P384_CURVE_OID), "1.3.132.0.34");
+        assert_eq!(oid_to_string(ECDSA_SHA256_OID), "1.2.840.10045.4.3.2");
+    }
+}
@@ -1,11 +1,17 @@
 //! Error types and converters.
 
+use chrono::{DateTime, Utc};
 use data_encoding::DecodeError;
+use std::fmt;
 use std::io;
 use std::path::Path;
 use std::str;
+use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
 
+use crate::crypto::KeyId;
+
 /// Error type for all in-toto related errors.
 #[derive(Error, Debug, PartialEq, Eq)]
 pub enum Error {
@@ -29,6 +35,19 @@ pub enum Error {
     #[error("not found")]
     NotFound,
 
+    /// An I/O operation failed, while doing what `context` says if given.
+    /// The underlying error is the [source](std::error::Error::source).
+    #[error("io: {}", io_message(.context, .source))]
+    Io {
+        context: Option<String>,
+        #[source]
+        source: IoError,
+    },
+
+    /// A cryptographic operation, e.g. generating a key or signing, failed.
+    #[error("crypto: {0}")]
+    Crypto(String),
+
     /// Opaque error type, to be interpreted similar to HTTP 500. Something went wrong, and you may
     /// or may not be able to do anything about it.
     #[error("opaque: {0}")]
@@ -51,10 +70,18 @@ pub enum Error {
     #[error("unknown key type: {0}")]
     UnknownKeyType(String),
 
+    /// The metadata expired at the given time.
+    #[error("metadata expired at {0}")]
+    ExpiredMetadata(DateTime<Utc>),
+
     /// The metadata or target failed to verify.
     #[error("verification failure: {0}")]
     VerificationFailure(String),
 
+    /// Not enough authorized keys produced a good signature over the metadata.
+    #[error("verification failure: {0}")]
+    SignatureThresholdNotMet(VerificationReport),
+
     #[error("prefix selection failure: {0}")]
     LinkGatheringError(String),
 
@@ -72,6 +99,97 @@ pub enum Error {
 
     #[error("artifact rule error: {0}")]
     ArtifactRuleError(String),
+
+    /// An executed command ran longer than its timeout and was killed.
+    #[error("command timed out after {0:?}")]
+    Timeout(Duration),
+}
+
+/// The [`io::Error`] an [`Error::Io`] was caused by. It compares equal to
+/// another error of the same kind and message, so that [`Error`] can be
+/// compared.
+#[derive(Debug, Clone)]
+pub struct IoError(Arc<io::Error>);
+
+impl IoError {
+    /// The underlying error.
+    pub fn get_ref(&self) -> &io::Error {
+        &self.0
+    }
+
+    /// The kind of the underlying error.
+    pub fn kind(&self) -> io::ErrorKind {
+        self.0.kind()
+    }
+}
+
+impl PartialEq for IoError {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind() == other.kind() && self.to_string() == other.to_string()
+    }
+}
+
+impl Eq for IoError {}
+
+impl fmt::Display for IoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for IoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.source()
+    }
+}
+
+fn io_message(context: &Option<String>, source: &IoError) -> String {
+    match context {
+        Some(context) => format!("{}: {}", context, source),
+        None => source.to_string(),
+    }
+}
+
+/// The outcome of checking the signatures of a piece of metadata against a set
+/// of authorized keys, carried by [`Error::SignatureThresholdNotMet`].
+///
+/// Each list is sorted, so reports compare and display deterministically.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerificationReport {
+    /// The number of good signatures that were required.
+    pub threshold: u32,
+    /// Authorized keys that produced a good signature.
+    pub good: Vec<KeyId>,
+    /// Authorized keys whose signature failed to verify.
+    pub bad: Vec<KeyId>,
+    /// Key IDs of signatures that matched no authorized key.
+    pub unknown: Vec<String>,
+}
+
+impl VerificationReport {
+    pub(crate) fn sort(&mut self) {
+        self.good.sort();
+        self.bad.sort();
+        self.unknown.sort();
+    }
+}
+
+impl fmt::Display for VerificationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn list<'a>(ids: impl Iterator<Item = &'a str>) -> String {
+            ids.collect::<Vec<_>>().join(", ")
+        }
+        write!(
+            f,
+            "Signature threshold not met: {}/{} (good: [{}], bad: [{}], \
+             unknown: [{}])",
+            self.good.len(),
+            self.threshold,
+            list(self.good.iter().map(KeyId::as_str)),
+            list(self.bad.iter().map(KeyId::as_str)),
+            list(self.unknown.iter().map(String::as_str)),
+        )
+    }
 }
 
 impl From<serde_json::error::Error> for Error {
@@ -82,8 +200,21 @@ impl From<serde_json::error::Error> for Error {
 
 impl Error {
     /// Helper to include the path that causd the error for FS I/O errors.
+    /// The source is a copy of `err` with its kind and message, see
+    /// [`Error::io`] to keep `err` itself.
     pub fn from_io(err: &io::Error, path: &Path) -> Error {
-        Error::Opaque(format!("Path {:?} : {:?}", path, err))
+        Error::io(
+            format!("Path {:?}", path),
+            io::Error::new(err.kind(), err.to_string()),
+        )
+    }
+
+    /// An [`Error::Io`] caused by `err` while doing what `context` says.
+    pub fn io(context: impl Into<String>, err: io::Error) -> Error {
+        Error::Io {
+            context: Some(context.into()),
+            source: IoError(Arc::new(err)),
+        }
     }
 }
 
@@ -91,11 +222,26 @@ impl From<io::Error> for Error {
     fn from(err: io::Error) -> Error {
         match err.kind() {
             std::io::ErrorKind::NotFound => Error::NotFound,
-            _ => Error::Opaque(format!("IO: {:?}", err)),
+            _ => Error::Io {
+                context: None,
+                source: IoError(Arc::new(err)),
+            },
         }
     }
 }
 
+impl From<ring::error::Unspecified> for Error {
+    fn from(_: ring::error::Unspecified) -> Error {
+        Error::Crypto("unspecified error".into())
+    }
+}
+
+impl From<ring::error::KeyRejected> for Error {
+    fn from(err: ring::error::KeyRejected) -> Error {
+        Error::Crypto(format!("key rejected: {}", err))
+    }
+}
+
 impl From<http::Error> for Error {
     fn from(err: http::Error) -> Error {
         Error::Opaque(format!("Http: {:?}", err))
@@ -134,6 +280,8 @@ impl From<str::Utf8Error> for Error {
 
 #[cfg(test)]
 mod tests {
+    use std::str::FromStr;
+
     use super::*;
 
     #[test]
@@ -144,6 +292,59 @@ mod tests {
 
         let err =
             Error::from(io::Error::from(std::io::ErrorKind::PermissionDenied));
-        assert_eq!(err.to_string(), "opaque: IO: Kind(PermissionDenied)");
+        assert_eq!(err.to_string(), "io: permission denied");
+    }
+
+    #[test]
+    fn io_error_source() {
+        let err = Error::io(
+            "cannot run git",
+            io::Error::new(io::ErrorKind::PermissionDenied, "denied"),
+        );
+        assert_eq!(err.to_string(), "io: cannot run git: denied");
+
+        let source = std::error::Error::source(&err).unwrap();
+        let io_err = source.downcast_ref::<IoError>().unwrap();
+        assert_eq!(io_err.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(io_err.get_ref().to_string(), "denied");
+        assert!(source.source().is_none());
+
+        let err = Error::from(io::Error::from(io::ErrorKind::BrokenPipe));
+        let source = std::error::Error::source(&err).unwrap();
+        assert_eq!(
+            source.downcast_ref::<IoError>().unwrap().kind(),
+            io::ErrorKind::BrokenPipe
+        );
+    }
+
+    #[test]
+    fn verify_crypto_error_display_string() {
+        let err = Error::from(ring::error::Unspecified);
+        assert_eq!(err.to_string(), "crypto: unspecified error");
+        let err = Error::from(
+            ring::signature::Ed25519KeyPair::from_pkcs8(&[]).unwrap_err(),
+        );
+        assert!(matches!(err, Error::Crypto(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn verification_report_display_string() {
+        let key_id = |c: &str| KeyId::from_str(&c.repeat(64)).unwrap();
+        let mut report = VerificationReport {
+            threshold: 2,
+            good: vec![key_id("b")],
+            bad: vec![key_id("c")],
+            unknown: vec!["ff".into(), "ee".into()],
+        };
+        report.sort();
+        assert_eq!(
+            Error::SignatureThresholdNotMet(report).to_string(),
+            format!(
+                "verification failure: Signature threshold not met: 1/2 \
+                 (good: [{}], bad: [{}], unknown: [ee, ff])",
+                "b".repeat(64),
+                "c".repeat(64)
+            )
+        );
     }
 }
@@ -0,0 +1,20 @@
+use data_encoding::BASE64;
+use serde::{self, Deserialize, Deserializer, Serializer};
+use std::result::Result;
+
+pub fn serialize<S>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&BASE64.encode(value))
+}
+
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    BASE64
+        .decode(s.as_bytes())
+        .map_err(serde::de::Error::custom)
+}
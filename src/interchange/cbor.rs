@@ -0,0 +1,196 @@
+use ciborium::value::Value;
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+use std::io::{Read, Write};
+
+use crate::error::Error;
+use crate::interchange::DataInterchange;
+use crate::Result;
+
+/// CBOR data interchange.
+///
+/// The schema is the same as for [Json](crate::interchange::Json), encoded as
+/// CBOR instead. Canonicalization follows the core deterministic encoding
+/// requirements of RFC 8949: integers and lengths use their shortest form,
+/// only definite-length items are emitted, and map entries are sorted by the
+/// bytewise order of their encoded keys. Like the canonical JSON used for
+/// signing, floating point numbers are rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cbor;
+
+impl DataInterchange for Cbor {
+    type RawData = Value;
+
+    /// ```
+    /// # use in_toto::interchange::{Cbor, DataInterchange};
+    /// assert_eq!(Cbor::extension(), "cbor");
+    /// ```
+    fn extension() -> &'static str {
+        "cbor"
+    }
+
+    /// ```
+    /// # use ciborium::value::Value;
+    /// # use in_toto::interchange::{Cbor, DataInterchange};
+    /// let raw = Value::Map(vec![
+    ///     (Value::Text("foo".into()), Value::Integer(1.into())),
+    ///     (Value::Text("ba".into()), Value::Integer(2.into())),
+    /// ]);
+    /// let out = Cbor::canonicalize(&raw).unwrap();
+    /// assert_eq!(out, b"\xa2\x62ba\x02\x63foo\x01");
+    /// ```
+    fn canonicalize(raw_data: &Self::RawData) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        ciborium::ser::into_writer(&canonical(raw_data)?, &mut buf)
+            .map_err(|e| Error::Encoding(format!("CBOR: {:?}", e)))?;
+        Ok(buf)
+    }
+
+    fn deserialize<T>(raw_data: &Self::RawData) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        raw_data
+            .deserialized()
+            .map_err(|e| Error::Encoding(format!("CBOR: {:?}", e)))
+    }
+
+    fn serialize<T>(data: &T) -> Result<Self::RawData>
+    where
+        T: Serialize,
+    {
+        Value::serialized(data)
+            .map_err(|e| Error::Encoding(format!("CBOR: {:?}", e)))
+    }
+
+    fn to_writer<W, T>(mut writer: W, value: &T) -> Result<()>
+    where
+        W: Write,
+        T: Serialize + Sized,
+    {
+        let bytes = Self::canonicalize(&Self::serialize(value)?)?;
+        writer.write_all(&bytes)?;
+        Ok(())
+    }
+
+    fn from_reader<R, T>(rdr: R) -> Result<T>
+    where
+        R: Read,
+        T: DeserializeOwned,
+    {
+        ciborium::de::from_reader(rdr)
+            .map_err(|e| Error::Encoding(format!("CBOR: {:?}", e)))
+    }
+
+    fn from_slice<T>(slice: &[u8]) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        Self::from_reader(slice)
+    }
+}
+
+/// Rebuild `value` with every map sorted by the encoding of its keys.
+fn canonical(value: &Value) -> Result<Value> {
+    Ok(match value {
+        Value::Float(_) => {
+            return Err(Error::Encoding(
+                "CBOR: floats cannot be canonicalized".into(),
+            ))
+        }
+        Value::Array(arr) => {
+            Value::Array(arr.iter().map(canonical).collect::<Result<_>>()?)
+        }
+        Value::Tag(tag, inner) => Value::Tag(*tag, Box::new(canonical(inner)?)),
+        Value::Map(map) => {
+            let mut entries = map
+                .iter()
+                .map(|(k, v)| {
+                    let k = canonical(k)?;
+                    let mut encoded = Vec::new();
+                    ciborium::ser::into_writer(&k, &mut encoded).map_err(
+                        |e| Error::Encoding(format!("CBOR: {:?}", e)),
+                    )?;
+                    Ok((encoded, k, canonical(v)?))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            if entries.windows(2).any(|w| w[0].0 == w[1].0) {
+                return Err(Error::Encoding("CBOR: duplicate map key".into()));
+            }
+            Value::Map(entries.into_iter().map(|(_, k, v)| (k, v)).collect())
+        }
+        other => other.clone(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use ciborium::value::Value;
+
+    use super::Cbor;
+    use crate::crypto::PrivateKey;
+    use crate::interchange::{DataInterchange, Json};
+    use crate::models::{LinkMetadata, LinkMetadataBuilder, VirtualTargetPath};
+
+    const ALICE_PRIVATE_KEY: &[u8] =
+        include_bytes!("../../tests/ed25519/ed25519-1");
+
+    fn sign_and_verify<D: DataInterchange>() {
+        let key = PrivateKey::from_ed25519(ALICE_PRIVATE_KEY).unwrap();
+        let link = LinkMetadataBuilder::new()
+            .name("write-code".into())
+            .add_product(
+                VirtualTargetPath::new("tests/test_metadata/demo.link".into())
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+
+        let raw = D::serialize(&link).unwrap();
+        let canonical = D::canonicalize(&raw).unwrap();
+        let sig = key.sign(&canonical).unwrap();
+
+        let decoded: LinkMetadata = D::from_slice(&canonical).unwrap();
+        assert_eq!(decoded, link);
+        let recanonical = D::canonicalize(&D::serialize(&decoded).unwrap());
+        key.public().verify(&recanonical.unwrap(), &sig).unwrap();
+    }
+
+    #[test]
+    fn sign_link_json() {
+        sign_and_verify::<Json>();
+    }
+
+    #[test]
+    fn sign_link_cbor() {
+        sign_and_verify::<Cbor>();
+    }
+
+    #[test]
+    fn canonical_map_order() {
+        let a = Value::Map(vec![
+            (Value::Text("bb".into()), Value::Integer(1.into())),
+            (Value::Integer(10.into()), Value::Bool(true)),
+            (Value::Text("a".into()), Value::Null),
+        ]);
+        let b = Value::Map(vec![
+            (Value::Text("a".into()), Value::Null),
+            (Value::Text("bb".into()), Value::Integer(1.into())),
+            (Value::Integer(10.into()), Value::Bool(true)),
+        ]);
+        let out = Cbor::canonicalize(&a).unwrap();
+        assert_eq!(out, Cbor::canonicalize(&b).unwrap());
+        assert_eq!(out, b"\xa3\x0a\xf5\x61a\xf6\x62bb\x01");
+    }
+
+    #[test]
+    fn canonical_rejects_floats() {
+        assert!(Cbor::canonicalize(&Value::Float(1.5)).is_err());
+        let dup = Value::Map(vec![
+            (Value::Text("a".into()), Value::Null),
+            (Value::Text("a".into()), Value::Null),
+        ]);
+        assert!(Cbor::canonicalize(&dup).is_err());
+    }
+}
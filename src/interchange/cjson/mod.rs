@@ -160,6 +160,27 @@ pub use pretty::JsonPretty;
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Json;
 
+impl Json {
+    /// Canonicalize a JSON value following the OLPC canonical JSON rules:
+    /// object keys are sorted, no insignificant whitespace is emitted and
+    /// only integer numbers are allowed.
+    ///
+    /// Note that `Metablock` signs this output after replacing escaped
+    /// newlines (`\\n`) with literal ones.
+    ///
+    /// ```
+    /// # use serde_json::json;
+    /// # use in_toto::interchange::Json;
+    /// let out = Json::canonicalize_bytes(&json!({"b": 1, "a": [true, null]}));
+    /// assert_eq!(out.unwrap(), br#"{"a":[true,null],"b":1}"#);
+    ///
+    /// assert!(Json::canonicalize_bytes(&json!({"pi": 3.14})).is_err());
+    /// ```
+    pub fn canonicalize_bytes(value: &serde_json::Value) -> Result<Vec<u8>> {
+        canonicalize(value).map_err(Error::Opaque)
+    }
+}
+
 impl DataInterchange for Json {
     type RawData = serde_json::Value;
 
@@ -180,7 +201,7 @@ impl DataInterchange for Json {
     /// assert_eq!(out, br#"{"baz":"quux","foo":"bar"}"#);
     /// ```
     fn canonicalize(raw_data: &Self::RawData) -> Result<Vec<u8>> {
-        canonicalize(raw_data).map_err(Error::Opaque)
+        Json::canonicalize_bytes(raw_data)
     }
 
     /// ```
@@ -393,6 +414,11 @@ fn convert(jsn: &serde_json::Value) -> std::result::Result<Value, String> {
     match *jsn {
         serde_json::Value::Null => Ok(Value::Null),
         serde_json::Value::Bool(b) => Ok(Value::Bool(b)),
+        // serde_json parses integers that do not fit an i64 or u64 as
+        // floats, so those are rejected too rather than rounded.
+        serde_json::Value::Number(ref n) if n.is_f64() => {
+            Err(format!("floats cannot be canonicalized: {}", n))
+        }
         serde_json::Value::Number(ref n) => n
             .as_i64()
             .map(Number::I64)
@@ -454,4 +480,54 @@ mod test {
         jsn.write(&mut out).unwrap();
         assert_eq!(&out, &b"{\"lol\":[\"haha\",\"new\\nline\"]}");
     }
+
+    #[test]
+    fn canonicalize_rejects_floats() {
+        for jsn in [
+            "1.5",
+            r#"{"a": [1, {"b": 1.5}]}"#,
+            "2.0",
+            "1e3",
+            "18446744073709551616",
+        ] {
+            let value: serde_json::Value = serde_json::from_str(jsn).unwrap();
+            let err = canonicalize(&value).unwrap_err();
+            assert!(err.contains("floats"), "{}: {}", jsn, err);
+        }
+    }
+
+    #[test]
+    fn canonicalize_keeps_large_integers() {
+        for jsn in [
+            "9007199254740993",
+            "-9007199254740993",
+            "18446744073709551615",
+            "-9223372036854775808",
+        ] {
+            let value: serde_json::Value = serde_json::from_str(jsn).unwrap();
+            assert_eq!(canonicalize(&value).unwrap(), jsn.as_bytes());
+        }
+        let value: serde_json::Value =
+            serde_json::from_str(r#"{"n": 9007199254740993}"#).unwrap();
+        assert_eq!(
+            Json::canonicalize(&value).unwrap(),
+            br#"{"n":9007199254740993}"#
+        );
+    }
+
+    #[test]
+    fn stream_link_from_file() {
+        use std::io::{BufReader, BufWriter, Seek, SeekFrom};
+
+        use crate::models::Metablock;
+
+        let raw = std::fs::read("tests/test_metadata/demo.link").unwrap();
+        let expected: Metablock = Json::from_slice(&raw).unwrap();
+
+        let mut file = tempfile::tempfile().unwrap();
+        Json::to_writer(BufWriter::new(&mut file), &expected).unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        let link: Metablock = Json::from_reader(BufReader::new(file)).unwrap();
+        assert_eq!(link, expected);
+    }
 }
@@ -53,6 +53,14 @@ impl PublicKey {
     pub fn keyid_hash_algorithms(&self) -> &Option<Vec<String>> {
         &self.keyid_hash_algorithms
     }
+
+    pub fn keyid(&self) -> Option<&str> {
+        self.keyid.as_deref()
+    }
+
+    pub fn private_key(&self) -> Option<&str> {
+        self.keyval.private.as_deref()
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -1,6 +1,10 @@
 //! Structures and functions to aid in various in-toto data interchange formats.
 
+#[cfg(feature = "cbor")]
+pub(crate) mod cbor;
 pub(crate) mod cjson;
+#[cfg(feature = "cbor")]
+pub use cbor::Cbor;
 pub use cjson::{Json, JsonPretty};
 
 use serde::de::DeserializeOwned;
@@ -31,20 +35,26 @@ pub trait DataInterchange: Debug + PartialEq + Clone {
     where
         T: Serialize;
 
-    /// Write a struct to a stream.
+    /// Write a struct to a stream, without building the whole encoding in
+    /// memory where the format allows it. Pass a buffered writer, e.g. a
+    /// `BufWriter` over a `File`, as the data is written in small pieces.
     #[allow(clippy::wrong_self_convention)]
     fn to_writer<W, T>(writer: W, value: &T) -> Result<()>
     where
         W: Write,
         T: Serialize + Sized;
 
-    /// Read a struct from a stream.
+    /// Read a struct from a stream, without reading the whole input into
+    /// memory first where the format allows it, so that large metadata such
+    /// as attestations with an SBOM can be loaded from a file directly. Pass
+    /// a buffered reader, e.g. a `BufReader` over a `File`, as the data is
+    /// read in small pieces.
     fn from_reader<R, T>(rdr: R) -> Result<T>
     where
         R: Read,
         T: DeserializeOwned;
 
-    /// Read a struct from a stream.
+    /// Read a struct from a byte slice.
     fn from_slice<T>(slice: &[u8]) -> Result<T>
     where
         T: DeserializeOwned;
@@ -18,6 +18,7 @@ mod rulelib;
 pub mod runlib;
 pub mod verifylib;
 
+mod format_base64;
 mod format_hex;
 
 pub use crate::error::*;
@@ -0,0 +1,725 @@
+//! DSSE (Dead Simple Signing Envelope) support.
+//!
+//! An [`Envelope`] signs an opaque payload together with its type using the
+//! Pre-Authentication Encoding (`DSSEv1 <len> <type> <len> <body>`), instead
+//! of signing the canonical JSON of the metadata as [`Metablock`] does. The
+//! serialized form follows the DSSE specification, so envelopes can be
+//! exchanged with cosign and other DSSE-based tooling.
+
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use serde_derive::{Deserialize, Serialize};
+
+use super::DSSEVersion;
+use crate::crypto::x509::{CertificateConstraint, TrustRoot};
+use crate::crypto::{
+    KeyId, PrivateKey, PublicKey, Signature, SignatureValue, Signer,
+};
+use crate::models::{Metablock, MetadataWrapper};
+use crate::{Error, Result, VerificationReport};
+
+/// Payload type used for in-toto metadata carried in an [`Envelope`].
+pub const PAYLOAD_TYPE_IN_TOTO: &str = "application/vnd.in-toto+json";
+
+/// A single signature of a DSSE [`Envelope`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DsseSignature {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    keyid: String,
+    #[serde(with = "crate::format_base64")]
+    sig: Vec<u8>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    cert: String,
+}
+
+impl DsseSignature {
+    /// The key id hint of the signing key. May be empty.
+    pub fn keyid(&self) -> &str {
+        &self.keyid
+    }
+
+    /// The raw signature bytes.
+    pub fn sig(&self) -> &[u8] {
+        &self.sig
+    }
+
+    /// The PEM encoded certificate chain of the signing key, leaf first.
+    /// May be empty. It is not covered by the signature, so it must be
+    /// verified against a trusted root before it is relied upon.
+    pub fn cert(&self) -> &str {
+        &self.cert
+    }
+}
+
+/// A DSSE envelope: an opaque payload, its type, and signatures over their
+/// Pre-Authentication Encoding.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Envelope {
+    #[serde(with = "crate::format_base64")]
+    payload: Vec<u8>,
+    payload_type: String,
+    signatures: Vec<DsseSignature>,
+}
+
+impl Envelope {
+    /// Create a new `Envelope` for `payload`, signed by each of
+    /// `private_keys`.
+    pub fn new(
+        payload: Vec<u8>,
+        payload_type: String,
+        private_keys: &[&PrivateKey],
+    ) -> Result<Self> {
+        let mut envelope = Envelope {
+            payload,
+            payload_type,
+            signatures: Vec::new(),
+        };
+        for key in private_keys {
+            envelope.add_signature(key)?;
+        }
+        Ok(envelope)
+    }
+
+    /// Wrap the metadata of a [`Metablock`] into an `Envelope`. The legacy
+    /// signatures cannot be carried over, so the payload is signed again
+    /// by `private_keys`.
+    pub fn from_signed_metadata(
+        metablock: &Metablock,
+        private_keys: &[&PrivateKey],
+    ) -> Result<Self> {
+        Self::new(
+            metablock.metadata.to_bytes()?,
+            PAYLOAD_TYPE_IN_TOTO.to_string(),
+            private_keys,
+        )
+    }
+
+    /// Convert the payload of this `Envelope` back into a [`Metablock`],
+    /// signed in the legacy format by `private_keys`.
+    pub fn to_signed_metadata(
+        &self,
+        private_keys: &[&PrivateKey],
+    ) -> Result<Metablock> {
+        if self.payload_type != PAYLOAD_TYPE_IN_TOTO {
+            return Err(Error::Encoding(format!(
+                "unexpected payload type {:?}, expected {:?}",
+                self.payload_type, PAYLOAD_TYPE_IN_TOTO
+            )));
+        }
+        let metadata = MetadataWrapper::try_from_bytes(&self.payload)?;
+        Metablock::new(metadata, private_keys)
+    }
+
+    /// Sign the Pre-Authentication Encoding of this envelope with
+    /// `private_key` and append the signature.
+    pub fn add_signature(&mut self, private_key: &PrivateKey) -> Result<()> {
+        self.add_signature_with(private_key)
+    }
+
+    /// Sign the Pre-Authentication Encoding of this envelope with `signer`
+    /// and append the signature, along with the certificate of the signer
+    /// if it has one.
+    pub fn add_signature_with(&mut self, signer: &dyn Signer) -> Result<()> {
+        let sig = signer.sign(&self.pae())?;
+        self.signatures.push(DsseSignature {
+            keyid: sig.key_id().as_str().to_string(),
+            sig: sig.value().as_bytes().to_vec(),
+            cert: signer.certificate().unwrap_or_default().to_string(),
+        });
+        Ok(())
+    }
+
+    /// Verify this envelope against `authorized_keys`, requiring at least
+    /// `threshold` distinct keys to have signed it. Signatures without a key
+    /// id are checked against every authorized key. On success the verified
+    /// payload is returned.
+    pub fn verify<'a, I>(
+        &self,
+        threshold: u32,
+        authorized_keys: I,
+    ) -> Result<&[u8]>
+    where
+        I: IntoIterator<Item = &'a PublicKey>,
+    {
+        if threshold < 1 {
+            return Err(Error::VerificationFailure(
+                "Threshold must be strictly greater than zero".into(),
+            ));
+        }
+
+        let authorized_keys: Vec<&PublicKey> =
+            authorized_keys.into_iter().collect();
+        let pae = self.pae();
+        let mut signed_by: HashSet<&KeyId> = HashSet::new();
+        let mut failed_by: HashSet<&KeyId> = HashSet::new();
+        let mut unknown: Vec<String> = Vec::new();
+
+        for sig in &self.signatures {
+            let candidates: Vec<&PublicKey> = if sig.keyid.is_empty() {
+                authorized_keys.clone()
+            } else {
+                match KeyId::from_str(&sig.keyid) {
+                    Ok(key_id) => authorized_keys
+                        .iter()
+                        .filter(|key| key.key_id() == &key_id)
+                        .copied()
+                        .collect(),
+                    Err(_) => Vec::new(),
+                }
+            };
+            if candidates.is_empty() {
+                warn!(
+                    "Key ID {:?} was not found in the set of authorized keys.",
+                    sig.keyid
+                );
+                unknown.push(sig.keyid.clone());
+                continue;
+            }
+
+            for pub_key in candidates {
+                if signed_by.contains(pub_key.key_id()) {
+                    continue;
+                }
+                match Self::check_signature(&pae, sig, pub_key) {
+                    Ok(()) => {
+                        debug!(
+                            "Good signature from key ID {:?}",
+                            pub_key.key_id()
+                        );
+                        signed_by.insert(pub_key.key_id());
+                        break;
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Bad signature from key ID {:?}: {:?}",
+                            pub_key.key_id(),
+                            e
+                        );
+                        failed_by.insert(pub_key.key_id());
+                    }
+                }
+            }
+        }
+
+        if (signed_by.len() as u32) < threshold {
+            let mut report = VerificationReport {
+                threshold,
+                good: signed_by.iter().map(|&k| k.clone()).collect(),
+                bad: failed_by
+                    .difference(&signed_by)
+                    .map(|&k| k.clone())
+                    .collect(),
+                unknown,
+            };
+            report.sort();
+            return Err(Error::SignatureThresholdNotMet(report));
+        }
+
+        Ok(&self.payload)
+    }
+
+    /// Verify this envelope against the identities of the signers rather
+    /// than their keys. A signature counts toward `threshold` if it
+    /// carries a certificate chain that `trust_root` verifies at `at`,
+    /// usually the time of signing, the certificate satisfies one of
+    /// `constraints`, and its key made the signature. Each constraint and
+    /// each key is counted once, and signers matching several constraints
+    /// are assigned so that as many constraints as possible are met. On
+    /// success the verified payload is returned.
+    ///
+    /// Keyless Sigstore verification is not implemented: `at` is taken as
+    /// given, and nothing here checks it against a Rekor inclusion proof,
+    /// an SCT or a timestamp authority. With short-lived certificates it
+    /// must come from a source the verifier trusts.
+    pub fn verify_certificates(
+        &self,
+        threshold: u32,
+        trust_root: &TrustRoot,
+        constraints: &[CertificateConstraint],
+        at: &DateTime<Utc>,
+    ) -> Result<&[u8]> {
+        if threshold < 1 {
+            return Err(Error::VerificationFailure(
+                "Threshold must be strictly greater than zero".into(),
+            ));
+        }
+
+        let pae = self.pae();
+        let mut report = VerificationReport {
+            threshold,
+            ..Default::default()
+        };
+        // The verified signers and the constraints each of them satisfies.
+        let mut signers: Vec<(KeyId, Vec<usize>)> = Vec::new();
+
+        for sig in &self.signatures {
+            let leaf = match trust_root.verify(&sig.cert, at) {
+                Ok(leaf) => leaf,
+                Err(e) => {
+                    warn!(
+                        "Certificate of key ID {:?} rejected: {}",
+                        sig.keyid, e
+                    );
+                    report.unknown.push(sig.keyid.clone());
+                    continue;
+                }
+            };
+            let satisfied: Vec<usize> = (0..constraints.len())
+                .filter(|&i| constraints[i].matches(&leaf))
+                .collect();
+            if satisfied.is_empty() {
+                warn!(
+                    "Certificate of key ID {:?} for {:?} matches no \
+                     constraint",
+                    sig.keyid,
+                    leaf.subject_alt_names()
+                );
+                report.unknown.push(sig.keyid.clone());
+                continue;
+            }
+
+            let key = match leaf.public_key() {
+                Ok(key) => key,
+                Err(e) => {
+                    warn!(
+                        "Certificate of key ID {:?} has an unusable key: {}",
+                        sig.keyid, e
+                    );
+                    report.unknown.push(sig.keyid.clone());
+                    continue;
+                }
+            };
+            match Self::check_signature(&pae, sig, &key) {
+                Ok(()) if signers.iter().any(|(id, _)| id == key.key_id()) => {
+                    debug!("Key ID {:?} signed more than once", key.key_id());
+                    report.unknown.push(key.key_id().as_str().to_string());
+                }
+                Ok(()) => {
+                    debug!("Good signature from key ID {:?}", key.key_id());
+                    signers.push((key.key_id().clone(), satisfied));
+                }
+                Err(e) => {
+                    warn!(
+                        "Bad signature from key ID {:?}: {:?}",
+                        key.key_id(),
+                        e
+                    );
+                    report.bad.push(key.key_id().clone());
+                }
+            }
+        }
+
+        let candidates: Vec<Vec<usize>> = signers
+            .iter()
+            .map(|(_, satisfied)| satisfied.clone())
+            .collect();
+        let owners = match_constraints(&candidates, constraints.len());
+        for (signer, (key_id, _)) in signers.into_iter().enumerate() {
+            if owners.contains(&Some(signer)) {
+                report.good.push(key_id);
+            } else {
+                debug!(
+                    "Key ID {:?} matches no constraint left by other signers",
+                    key_id
+                );
+                report.unknown.push(key_id.as_str().to_string());
+            }
+        }
+
+        if (report.good.len() as u32) < threshold {
+            report.sort();
+            return Err(Error::SignatureThresholdNotMet(report));
+        }
+
+        Ok(&self.payload)
+    }
+
+    /// The payload carried by this envelope.
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// The type of the payload.
+    pub fn payload_type(&self) -> &str {
+        &self.payload_type
+    }
+
+    /// The signatures of this envelope.
+    pub fn signatures(&self) -> &[DsseSignature] {
+        &self.signatures
+    }
+
+    /// Check `sig`, one of the signatures of an envelope with the
+    /// Pre-Authentication Encoding `pae`, against `key`, ignoring its key
+    /// id.
+    fn check_signature(
+        pae: &[u8],
+        sig: &DsseSignature,
+        key: &PublicKey,
+    ) -> Result<()> {
+        let signature = Signature::new(
+            key.key_id().clone(),
+            SignatureValue::new(sig.sig.clone()),
+        );
+        key.verify(pae, &signature)
+    }
+
+    fn pae(&self) -> Vec<u8> {
+        DSSEVersion::V1.pack(&self.payload, self.payload_type.clone())
+    }
+}
+
+/// Assign each of `constraints` constraints to at most one signer, where
+/// `candidates[signer]` lists the constraints that signer satisfies, so that
+/// as many signers as possible get one. Returns the signer assigned to each
+/// constraint.
+fn match_constraints(
+    candidates: &[Vec<usize>],
+    constraints: usize,
+) -> Vec<Option<usize>> {
+    // Find a constraint for `signer`, moving the signers already assigned
+    // along an augmenting path if needed.
+    fn assign(
+        signer: usize,
+        candidates: &[Vec<usize>],
+        seen: &mut [bool],
+        owners: &mut [Option<usize>],
+    ) -> bool {
+        for &constraint in &candidates[signer] {
+            if seen[constraint] {
+                continue;
+            }
+            seen[constraint] = true;
+            if owners[constraint]
+                .is_none_or(|owner| assign(owner, candidates, seen, owners))
+            {
+                owners[constraint] = Some(signer);
+                return true;
+            }
+        }
+        false
+    }
+
+    let mut owners = vec![None; constraints];
+    for signer in 0..candidates.len() {
+        assign(
+            signer,
+            candidates,
+            &mut vec![false; constraints],
+            &mut owners,
+        );
+    }
+    owners
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, TimeZone, Utc};
+    use data_encoding::BASE64;
+    use regex::Regex;
+
+    use super::{Envelope, PAYLOAD_TYPE_IN_TOTO};
+    use crate::crypto::x509::{
+        testing, Certificate, CertificateConstraint, SubjectAltName, TrustRoot,
+    };
+    use crate::crypto::{PrivateKey, SignatureScheme};
+    use crate::models::{LinkMetadataBuilder, MetablockBuilder};
+    use crate::{Error, VerificationReport};
+
+    const ALICE_PRIVATE_KEY: &[u8] =
+        include_bytes!("../../../tests/ed25519/ed25519-1");
+    const OWNER_PRIVATE_KEY: &[u8] =
+        include_bytes!("../../../tests/test_metadata/owner.der");
+    const ROOT: &[u8] = include_bytes!("../../../tests/x509/root.der");
+    const INTERMEDIATE: &[u8] =
+        include_bytes!("../../../tests/x509/intermediate.der");
+    const INTERMEDIATE_PRIVATE_KEY: &[u8] =
+        include_bytes!("../../../tests/x509/intermediate.pk8.der");
+    const LEAF: &[u8] = include_bytes!("../../../tests/x509/leaf.der");
+    const LEAF_PRIVATE_KEY: &[u8] =
+        include_bytes!("../../../tests/x509/leaf.pk8.der");
+    const LEAF_P384: &[u8] =
+        include_bytes!("../../../tests/x509/leaf-p384.der");
+
+    #[test]
+    fn sign_and_verify() {
+        let alice = PrivateKey::from_ed25519(ALICE_PRIVATE_KEY).unwrap();
+        let owner = PrivateKey::from_ed25519(OWNER_PRIVATE_KEY).unwrap();
+        let envelope = Envelope::new(
+            b"hello world".to_vec(),
+            "http://example.com/HelloWorld".to_string(),
+            &[&alice, &owner],
+        )
+        .unwrap();
+
+        assert_eq!(
+            envelope
+                .verify(2, [alice.public(), owner.public()])
+                .unwrap(),
+            b"hello world"
+        );
+        assert!(envelope.verify(2, [alice.public()]).is_err());
+
+        // The signature covers the payload type as well as the payload.
+        let mut tampered = envelope.clone();
+        tampered.payload_type = "http://example.com/Other".to_string();
+        assert!(tampered.verify(1, [alice.public()]).is_err());
+
+        let mut tampered = envelope;
+        tampered.payload = b"hello w0rld".to_vec();
+        let report = VerificationReport {
+            threshold: 1,
+            good: vec![],
+            bad: vec![alice.key_id().clone()],
+            unknown: vec![owner.key_id().as_str().to_string()],
+        };
+        assert_eq!(
+            tampered.verify(1, [alice.public()]),
+            Err(Error::SignatureThresholdNotMet(report))
+        );
+    }
+
+    #[test]
+    fn verify_certificates() {
+        let at = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let intermediate = Certificate::from_der(INTERMEDIATE).unwrap();
+        let trust_root = TrustRoot {
+            roots: vec![Certificate::from_der(ROOT).unwrap()],
+            intermediates: vec![],
+        };
+
+        let alice = PrivateKey::from_pkcs8(
+            LEAF_PRIVATE_KEY,
+            SignatureScheme::EcdsaP256Sha256,
+        )
+        .unwrap();
+        let alice_chain = Certificate::from_der(LEAF).unwrap().to_pem()
+            + &intermediate.to_pem();
+        let bob =
+            PrivateKey::generate(SignatureScheme::EcdsaP256Sha256).unwrap();
+        let bob_cert = testing::issue(
+            &intermediate,
+            &PrivateKey::from_pkcs8(
+                INTERMEDIATE_PRIVATE_KEY,
+                SignatureScheme::EcdsaP256Sha256,
+            )
+            .unwrap(),
+            bob.public(),
+            &SubjectAltName::Email("bob@example.com".into()),
+            "https://issuer.example.com",
+            &at,
+            &(at + Duration::minutes(10)),
+        );
+        let owner = PrivateKey::from_ed25519(OWNER_PRIVATE_KEY).unwrap();
+
+        let constraints = [
+            CertificateConstraint {
+                issuer: Some("https://issuer.example.com".into()),
+                subject_regex: None,
+                san: Some("alice@example.com".into()),
+            },
+            CertificateConstraint {
+                issuer: None,
+                subject_regex: Some(Regex::new("^bob@").unwrap()),
+                san: None,
+            },
+        ];
+
+        let mut envelope =
+            Envelope::new(b"hello".to_vec(), "text".to_string(), &[&alice])
+                .unwrap();
+        envelope.signatures[0].cert = alice_chain;
+        assert_eq!(
+            envelope
+                .verify_certificates(1, &trust_root, &constraints, &at)
+                .unwrap(),
+            b"hello"
+        );
+
+        // Signing twice does not make alice count as bob, and raw keys are
+        // not authorized.
+        let sig = envelope.signatures[0].clone();
+        envelope.signatures.push(sig);
+        envelope.add_signature(&owner).unwrap();
+        let mut report = VerificationReport {
+            threshold: 2,
+            good: vec![alice.key_id().clone()],
+            bad: vec![],
+            unknown: vec![
+                alice.key_id().as_str().to_string(),
+                owner.key_id().as_str().to_string(),
+            ],
+        };
+        report.sort();
+        assert_eq!(
+            envelope.verify_certificates(2, &trust_root, &constraints, &at),
+            Err(Error::SignatureThresholdNotMet(report))
+        );
+
+        envelope.add_signature(&bob).unwrap();
+        envelope.signatures.last_mut().unwrap().cert = bob_cert.to_pem();
+        let trust_root = TrustRoot {
+            intermediates: vec![intermediate],
+            ..trust_root
+        };
+        assert!(envelope
+            .verify_certificates(2, &trust_root, &constraints, &at)
+            .is_ok());
+
+        // Bob's certificate has expired.
+        let later = at + Duration::hours(1);
+        assert!(envelope
+            .verify_certificates(2, &trust_root, &constraints, &later)
+            .is_err());
+        assert!(envelope
+            .verify_certificates(2, &TrustRoot::default(), &constraints, &at)
+            .is_err());
+
+        let mut tampered = envelope;
+        tampered.payload = b"hullo".to_vec();
+        match tampered.verify_certificates(1, &trust_root, &constraints, &at) {
+            Err(Error::SignatureThresholdNotMet(report)) => {
+                assert!(report.good.is_empty());
+                assert!(report.bad.contains(bob.key_id()));
+            }
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn verify_certificates_overlapping_constraints() {
+        let at = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let intermediate = Certificate::from_der(INTERMEDIATE).unwrap();
+        let alice = PrivateKey::from_pkcs8(
+            LEAF_PRIVATE_KEY,
+            SignatureScheme::EcdsaP256Sha256,
+        )
+        .unwrap();
+        let bob =
+            PrivateKey::generate(SignatureScheme::EcdsaP256Sha256).unwrap();
+        let bob_cert = testing::issue(
+            &intermediate,
+            &PrivateKey::from_pkcs8(
+                INTERMEDIATE_PRIVATE_KEY,
+                SignatureScheme::EcdsaP256Sha256,
+            )
+            .unwrap(),
+            bob.public(),
+            &SubjectAltName::Email("bob@example.com".into()),
+            "https://issuer.example.com",
+            &at,
+            &(at + Duration::minutes(10)),
+        );
+        let trust_root = TrustRoot {
+            roots: vec![Certificate::from_der(ROOT).unwrap()],
+            intermediates: vec![intermediate],
+        };
+
+        // Alice satisfies both constraints and bob only the first, so they
+        // only meet the threshold if alice counts for the second.
+        let constraints = [
+            CertificateConstraint {
+                issuer: Some("https://issuer.example.com".into()),
+                subject_regex: None,
+                san: None,
+            },
+            CertificateConstraint {
+                issuer: None,
+                subject_regex: None,
+                san: Some("alice@example.com".into()),
+            },
+        ];
+        let mut envelope = Envelope::new(
+            b"hello".to_vec(),
+            "text".to_string(),
+            &[&alice, &bob],
+        )
+        .unwrap();
+        envelope.signatures[0].cert =
+            Certificate::from_der(LEAF).unwrap().to_pem();
+        envelope.signatures[1].cert = bob_cert.to_pem();
+        assert_eq!(
+            envelope
+                .verify_certificates(2, &trust_root, &constraints, &at)
+                .unwrap(),
+            b"hello"
+        );
+
+        // A certificate with a key that cannot be used only makes its own
+        // signature unknown.
+        let owner = PrivateKey::from_ed25519(OWNER_PRIVATE_KEY).unwrap();
+        envelope.add_signature(&owner).unwrap();
+        envelope.signatures[2].cert =
+            Certificate::from_der(LEAF_P384).unwrap().to_pem();
+        assert!(envelope
+            .verify_certificates(2, &trust_root, &constraints, &at)
+            .is_ok());
+        match envelope.verify_certificates(3, &trust_root, &constraints, &at) {
+            Err(Error::SignatureThresholdNotMet(report)) => {
+                assert_eq!(report.good.len(), 2);
+                assert_eq!(report.unknown, vec![owner.key_id().as_str()]);
+            }
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn verify_without_keyid() {
+        let alice = PrivateKey::from_ed25519(ALICE_PRIVATE_KEY).unwrap();
+        let mut envelope =
+            Envelope::new(b"{}".to_vec(), "text".to_string(), &[&alice])
+                .unwrap();
+        envelope.signatures[0].keyid = String::new();
+        assert!(envelope.verify(1, [alice.public()]).is_ok());
+
+        // The same key signing twice only counts once.
+        let sig = envelope.signatures[0].clone();
+        envelope.signatures.push(sig);
+        assert!(envelope.verify(2, [alice.public()]).is_err());
+    }
+
+    #[test]
+    fn serialize_envelope() {
+        let alice = PrivateKey::from_ed25519(ALICE_PRIVATE_KEY).unwrap();
+        let envelope =
+            Envelope::new(b"hello".to_vec(), "text".to_string(), &[&alice])
+                .unwrap();
+        let json = serde_json::to_value(&envelope).unwrap();
+
+        assert_eq!(json["payloadType"], "text");
+        assert_eq!(json["payload"], BASE64.encode(b"hello"));
+        assert_eq!(
+            json["signatures"][0]["keyid"],
+            alice.public().key_id().as_str()
+        );
+
+        let decoded: Envelope = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded, envelope);
+    }
+
+    #[test]
+    fn signed_metadata_round_trip() {
+        let alice = PrivateKey::from_ed25519(ALICE_PRIVATE_KEY).unwrap();
+        let link = LinkMetadataBuilder::new()
+            .name("test".into())
+            .build()
+            .unwrap();
+        let metablock = MetablockBuilder::from_metadata(Box::new(link))
+            .sign(&[&alice])
+            .unwrap()
+            .build();
+
+        let envelope =
+            Envelope::from_signed_metadata(&metablock, &[&alice]).unwrap();
+        assert_eq!(envelope.payload_type(), PAYLOAD_TYPE_IN_TOTO);
+        assert!(envelope.verify(1, [alice.public()]).is_ok());
+
+        let converted = envelope.to_signed_metadata(&[&alice]).unwrap();
+        assert_eq!(converted, metablock);
+        assert!(converted.verify(1, [alice.public()]).is_ok());
+    }
+}
@@ -4,9 +4,12 @@ use strum_macros::EnumIter;
 use self::pae_v1::PaeV1;
 use crate::{Error, Result};
 
+mod dsse;
 mod envelope_file;
 mod pae_v1;
 
+pub use dsse::{DsseSignature, Envelope, PAYLOAD_TYPE_IN_TOTO};
+
 pub trait DSSEParser {
     fn pae_pack(payload_ver: String, payload: &[u8]) -> Vec<u8>;
     fn pae_unpack(bytes: &[u8]) -> Result<(Vec<u8>, String)>;
@@ -20,8 +23,6 @@ pub enum DSSEVersion {
 
 impl DSSEVersion {
     /// Use Pre-Authentication Encoding to pack payload for any version.
-    #[allow(dead_code)]
-    // TODO: remove #[allow(dead_code)] after metadata deploy
     pub fn pack(&self, payload: &[u8], payload_ver: String) -> Vec<u8> {
         let payload = payload.to_vec();
 
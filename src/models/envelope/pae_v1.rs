@@ -47,7 +47,7 @@ impl DSSEParser for PaeV1 {
             "{prefix}{split}{payload_ver_len}{split}{payload_ver}{split}{payload_len}{split}",
             prefix = PREFIX,
             split = SPLIT,
-            payload_ver_len = payload_ver.as_bytes().len(),
+            payload_ver_len = payload_ver.len(),
             payload_ver = payload_ver.as_str(),
             payload_len = payload.len(),
         );
@@ -99,7 +99,7 @@ mod pae_test {
 
     static SERIALIZE_RESULT_DATAS: Lazy<HashMap<String, &str>> = Lazy::new(
         || {
-            let real_serialized_file = HashMap::from([
+            HashMap::from([
             ("blank_test".to_string(), "DSSEv1 4 link 0 "),
             (
                 "blank_envelope_naive_test".to_string(),
@@ -109,8 +109,7 @@ mod pae_test {
                 "blank_envelope_v01_test".to_string(),
                 "DSSEv1 33 https://in-toto.io/statement/v0.1 52 {\"payload\":[],\"payload_type\":\"link\",\"signatures\":[]}",
             ),
-        ]);
-            real_serialized_file
+        ])
         },
     );
 
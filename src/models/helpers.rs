@@ -1,9 +1,10 @@
 //! Supporting Functions and Types (VirtualTargetPath)
-use std::collections::HashMap;
-use std::fmt::Debug;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Debug};
 use std::str;
 
 use serde::de::{Deserialize, Deserializer, Error as DeserializeError};
+use serde::ser::Serializer;
 use serde_derive::Serialize;
 
 use crate::crypto::{HashAlgorithm, HashValue};
@@ -12,7 +13,85 @@ use crate::{Error, Result};
 /// Description of a target, used in verification.
 pub type TargetDescription = HashMap<HashAlgorithm, HashValue>;
 
+/// The outcome of comparing two [`TargetDescription`]s with
+/// [`TargetDescriptionExt::matches`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MatchResult {
+    /// Every supported hash algorithm the descriptions share agrees.
+    Match,
+    /// Every supported hash algorithm the descriptions share disagrees: the
+    /// artifacts differ.
+    Mismatch,
+    /// Some shared algorithms agree and the others, listed here, disagree.
+    /// Different artifacts cannot agree on any of their digests short of a
+    /// hash collision, so this indicates an attack.
+    Conflict(Vec<HashAlgorithm>),
+    /// The descriptions share no supported hash algorithm, so they cannot
+    /// be compared.
+    NoSharedAlgorithm,
+}
+
+/// Comparison of [`TargetDescription`]s, which is a type alias.
+pub trait TargetDescriptionExt {
+    /// Compare the digests of two artifacts on every supported hash
+    /// algorithm they share, so e.g. an artifact recorded with sha256 and
+    /// sha512 matches the same file recorded with sha256 only. Algorithms
+    /// this crate does not know, e.g. from links recorded by a newer tool,
+    /// are skipped.
+    fn matches(&self, other: &TargetDescription) -> MatchResult;
+}
+
+impl TargetDescriptionExt for TargetDescription {
+    fn matches(&self, other: &TargetDescription) -> MatchResult {
+        let mut agree = false;
+        let mut disagree = Vec::new();
+        for (alg, value) in self {
+            if matches!(alg, HashAlgorithm::Unknown(_)) {
+                continue;
+            }
+            match other.get(alg) {
+                Some(other) if other == value => agree = true,
+                Some(_) => disagree.push(alg.clone()),
+                None => (),
+            }
+        }
+        disagree.sort_by(|a, b| a.name().cmp(b.name()));
+        match (agree, disagree.is_empty()) {
+            (true, true) => MatchResult::Match,
+            (true, false) => MatchResult::Conflict(disagree),
+            (false, false) => MatchResult::Mismatch,
+            (false, true) => MatchResult::NoSharedAlgorithm,
+        }
+    }
+}
+
+/// Serialize artifacts with the hashes of each artifact sorted by algorithm
+/// name, as canonicalization would, so that the same artifacts are written
+/// to the same bytes by every data interchange and in every process.
+pub(crate) fn serialize_artifacts<S>(
+    artifacts: &BTreeMap<VirtualTargetPath, TargetDescription>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_map(artifacts.iter().map(|(path, hashes)| {
+        let hashes: BTreeMap<&str, &HashValue> = hashes
+            .iter()
+            .map(|(alg, value)| (alg.name(), value))
+            .collect();
+        (path, hashes)
+    }))
+}
+
 /// Wrapper for the Virtual path to a target.
+///
+/// Paths are ordered by comparing their UTF-8 bytes, i.e. by code point,
+/// which is how the keys of canonical JSON are sorted here and by Python's
+/// `sorted`, so materials and products are written in the same order as
+/// other in-toto implementations write them. Paths are neither case folded
+/// nor Unicode normalized: `A` sorts before `a`, and `é` written as one
+/// code point and as `e` with a combining accent are different paths.
 #[derive(Debug, Clone, PartialEq, Hash, Eq, PartialOrd, Ord, Serialize)]
 pub struct VirtualTargetPath(String);
 
@@ -28,21 +107,204 @@ impl VirtualTargetPath {
         &self.0
     }
 
-    /// Judge if this [`VirtualTargetPath`] matches the given pattern
-    pub(crate) fn matches(&self, pattern: &str) -> Result<bool> {
-        let matcher = glob::Pattern::new(pattern).map_err(|e| {
-            Error::IllegalArgument(format!(
-                "Pattern matcher creation failed: {}",
-                e
-            ))
-        })?;
-        Ok(matcher.matches(self.value()))
+    /// Remove the directory `prefix` from the start of this path, returning
+    /// the rest of the path, or `None` if the path is not under `prefix`.
+    /// A trailing `/` on `prefix` is optional. Both `prefix` and the rest
+    /// must pass [`safe_path`], so e.g. `..` cannot be stripped.
+    pub fn strip_prefix(&self, prefix: &str) -> Option<VirtualTargetPath> {
+        let prefix = prefix.strip_suffix('/').unwrap_or(prefix);
+        safe_path(prefix).ok()?;
+        let rest = self.0.strip_prefix(prefix)?.strip_prefix('/')?;
+        safe_path(rest).ok()?;
+        Some(VirtualTargetPath(rest.to_string()))
+    }
+
+    /// Put this path under the directory `prefix`, the reverse of
+    /// [`VirtualTargetPath::strip_prefix`]. A trailing `/` on `prefix` is
+    /// optional. Fails unless both `prefix` and this path pass
+    /// [`safe_path`].
+    pub fn with_prefix(&self, prefix: &str) -> Result<VirtualTargetPath> {
+        let prefix = prefix.strip_suffix('/').unwrap_or(prefix);
+        safe_path(prefix)?;
+        safe_path(&self.0)?;
+        Ok(VirtualTargetPath(format!("{}/{}", prefix, self.0)))
+    }
+
+    /// Judge if this [`VirtualTargetPath`] matches the shell glob `pattern`,
+    /// as used by artifact rules.
+    ///
+    /// The semantics are those of Python's `fnmatch`, which the in-toto
+    /// specification refers to. `/` is not special, so `dir/*` matches
+    /// every file below `dir`, however deep:
+    /// * `*` matches any sequence of characters, including `/`, so `**`
+    ///   is the same as `*`
+    /// * `?` matches any single character
+    /// * `[abc]`, `[a-z]` match one of the listed characters, and `[!abc]`
+    ///   one that is not listed. A `]` right after the opening bracket is a
+    ///   literal, and a `[` without a closing bracket matches itself
+    ///
+    /// There is no escape character: use `[*]` to match a literal `*`.
+    pub fn matches(&self, pattern: &str) -> bool {
+        let pattern = parse_glob(pattern);
+        let path: Vec<char> = self.0.chars().collect();
+        let mut memo = vec![None; (pattern.len() + 1) * (path.len() + 1)];
+        glob_match(&pattern, 0, &path, 0, &mut memo)
+    }
+}
+
+/// Check that `path` is a relative path that stays inside the directory
+/// it is resolved against, on any platform. Use this to validate paths
+/// taken from untrusted metadata before touching the file system.
+///
+/// A path is rejected if it:
+/// * is empty
+/// * starts with `/` or `\`, or with a drive letter like `C:`
+/// * has a `..` component, with components separated by `/` or `\`
+/// * contains NUL or another control character
+pub fn safe_path(path: &str) -> Result<()> {
+    if path.is_empty() {
+        return Err(Error::IllegalArgument("Path cannot be empty".into()));
+    }
+    if path.chars().any(char::is_control) {
+        return Err(Error::IllegalArgument(format!(
+            "Path {:?} cannot contain control characters",
+            path
+        )));
+    }
+    if path.starts_with(['/', '\\']) {
+        return Err(Error::IllegalArgument(format!(
+            "Path {:?} cannot be absolute",
+            path
+        )));
+    }
+    let mut chars = path.chars();
+    if chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.next() == Some(':')
+    {
+        return Err(Error::IllegalArgument(format!(
+            "Path {:?} cannot start with a drive letter",
+            path
+        )));
+    }
+    if path.split(['/', '\\']).any(|component| component == "..") {
+        return Err(Error::IllegalArgument(format!(
+            "Path {:?} cannot contain '..'",
+            path
+        )));
+    }
+    Ok(())
+}
+
+/// A token of a glob pattern, see [`VirtualTargetPath::matches`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum GlobToken {
+    Char(char),
+    AnyChar,
+    Star,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+fn parse_glob(pattern: &str) -> Vec<GlobToken> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            // More stars add nothing.
+            '*' if tokens.last() == Some(&GlobToken::Star) => (),
+            '*' => tokens.push(GlobToken::Star),
+            '?' => tokens.push(GlobToken::AnyChar),
+            '[' => {
+                if let Some((class, next)) = parse_glob_class(&chars, i + 1) {
+                    tokens.push(class);
+                    i = next;
+                    continue;
+                }
+                tokens.push(GlobToken::Char('['));
+            }
+            c => tokens.push(GlobToken::Char(c)),
+        }
+        i += 1;
     }
+    tokens
 }
 
-impl ToString for VirtualTargetPath {
-    fn to_string(&self) -> String {
-        self.0.clone()
+/// Parse the class starting after the `[` at `start`, returning the class
+/// and the index after its closing `]`, or `None` if it is not closed.
+fn parse_glob_class(
+    chars: &[char],
+    start: usize,
+) -> Option<(GlobToken, usize)> {
+    let mut i = start;
+    let negated = matches!(chars.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+    let mut ranges = Vec::new();
+    let first = i;
+    loop {
+        let c = *chars.get(i)?;
+        if c == ']' && i > first {
+            return Some((GlobToken::Class { negated, ranges }, i + 1));
+        }
+        match (chars.get(i + 1), chars.get(i + 2)) {
+            (Some('-'), Some(&end)) if end != ']' => {
+                ranges.push((c, end));
+                i += 3;
+            }
+            _ => {
+                ranges.push((c, c));
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Whether `pattern[ti..]` matches `path[si..]`. `memo` caches the result
+/// for every pair of offsets, to keep patterns with many stars linear.
+fn glob_match(
+    pattern: &[GlobToken],
+    ti: usize,
+    path: &[char],
+    si: usize,
+    memo: &mut [Option<bool>],
+) -> bool {
+    let key = ti * (path.len() + 1) + si;
+    if let Some(matched) = memo[key] {
+        return matched;
+    }
+    let matched = match pattern.get(ti) {
+        None => si == path.len(),
+        Some(GlobToken::Star) => (si..=path.len())
+            .any(|end| glob_match(pattern, ti + 1, path, end, memo)),
+        Some(token) => match path.get(si) {
+            Some(&c) if glob_token_matches(token, c) => {
+                glob_match(pattern, ti + 1, path, si + 1, memo)
+            }
+            _ => false,
+        },
+    };
+    memo[key] = Some(matched);
+    matched
+}
+
+fn glob_token_matches(token: &GlobToken, c: char) -> bool {
+    match token {
+        GlobToken::Char(expected) => *expected == c,
+        GlobToken::AnyChar => true,
+        GlobToken::Class { negated, ranges } => {
+            ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated
+        }
+        GlobToken::Star => false,
+    }
+}
+
+impl fmt::Display for VirtualTargetPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
     }
 }
 
@@ -70,7 +332,182 @@ impl<'de> Deserialize<'de> for VirtualTargetPath {
 
 #[cfg(test)]
 mod tests {
-    use crate::models::VirtualTargetPath;
+    use rstest::rstest;
+
+    use crate::crypto::HashAlgorithm;
+    use crate::models::helpers::safe_path;
+    use crate::models::{
+        LinkMetadataBuilder, MatchResult, Metadata, TargetDescription,
+        TargetDescriptionExt, VirtualTargetPath,
+    };
+    use std::collections::BTreeMap;
+
+    #[rstest]
+    #[case(r#"{"sha256": "aa"}"#, r#"{"sha256": "aa"}"#, MatchResult::Match)]
+    // agree on both
+    #[case(
+        r#"{"sha256": "aa", "sha512": "bb"}"#,
+        r#"{"sha256": "aa", "sha512": "bb"}"#,
+        MatchResult::Match
+    )]
+    #[case(
+        r#"{"sha256": "aa", "sha512": "bb"}"#,
+        r#"{"sha256": "aa"}"#,
+        MatchResult::Match
+    )]
+    // disagree on one
+    #[case(
+        r#"{"sha256": "aa", "sha512": "bb"}"#,
+        r#"{"sha256": "aa", "sha512": "cc"}"#,
+        MatchResult::Conflict(vec![HashAlgorithm::Sha512])
+    )]
+    #[case(
+        r#"{"sha256": "aa", "sha512": "bb"}"#,
+        r#"{"sha256": "dd", "sha512": "cc"}"#,
+        MatchResult::Mismatch
+    )]
+    #[case(r#"{"sha256": "aa"}"#, r#"{"sha256": "bb"}"#, MatchResult::Mismatch)]
+    // no shared algorithm
+    #[case(
+        r#"{"sha256": "aa"}"#,
+        r#"{"sha512": "aa"}"#,
+        MatchResult::NoSharedAlgorithm
+    )]
+    // unknown algorithms are skipped, even when they differ
+    #[case(
+        r#"{"sha256": "aa", "future-hash": "bb"}"#,
+        r#"{"sha256": "aa", "future-hash": "cc"}"#,
+        MatchResult::Match
+    )]
+    #[case(
+        r#"{"future-hash": "aa"}"#,
+        r#"{"future-hash": "aa"}"#,
+        MatchResult::NoSharedAlgorithm
+    )]
+    fn target_description_matches(
+        #[case] a: &str,
+        #[case] b: &str,
+        #[case] expected: MatchResult,
+    ) {
+        let a: TargetDescription = serde_json::from_str(a).unwrap();
+        let b: TargetDescription = serde_json::from_str(b).unwrap();
+        assert_eq!(a.matches(&b), expected);
+        assert_eq!(b.matches(&a), expected);
+    }
+
+    #[rstest]
+    #[case("foo.py", "foo.py", true)]
+    #[case("foo.py", "*.py", true)]
+    #[case("src/foo.py", "*.py", true)]
+    #[case("src/foo.py", "*/*.py", true)]
+    #[case("foo.py", "*/*.py", false)]
+    #[case("src/foo.py", "**.py", true)]
+    #[case("foo.py", "**/*.py", false)]
+    #[case("a/b/c/foo.py", "**/*.py", true)]
+    #[case("a/b/c/foo.pyc", "**/*.py", false)]
+    #[case("src/a/b/foo.py", "src/**/foo.py", true)]
+    #[case("src/foo.py", "src/**/foo.py", false)]
+    #[case("srcfoo.py", "src/**/foo.py", false)]
+    #[case("src/a/b", "src/**", true)]
+    #[case("dir/a/b.txt", "dir/*", true)]
+    #[case("dir", "dir/*", false)]
+    #[case("foo.py", "?oo.py", true)]
+    #[case("/oo.py", "?oo.py", true)]
+    #[case("foo.py", "f[aeiou]o.py", true)]
+    #[case("fxo.py", "f[aeiou]o.py", false)]
+    #[case("fxo.py", "f[!aeiou]o.py", true)]
+    #[case("f/o.py", "f[!aeiou]o.py", true)]
+    #[case("v3.tar", "v[0-9].tar", true)]
+    #[case("v-.tar", "v[0-9].tar", false)]
+    #[case("a-b", "a[x-]b", true)]
+    #[case("a]b", "a[]]b", true)]
+    #[case("a[b", "a[b", true)]
+    #[case("a*b", "a[*]b", true)]
+    #[case("axb", "a[*]b", false)]
+    #[case("", "*", true)]
+    #[case("foo", "", false)]
+    fn match_virtual_target_path(
+        #[case] path: &str,
+        #[case] pattern: &str,
+        #[case] expected: bool,
+    ) {
+        assert_eq!(
+            VirtualTargetPath::from(path).matches(pattern),
+            expected,
+            "{:?} matching {:?}",
+            path,
+            pattern
+        );
+    }
+
+    #[rstest]
+    #[case("foo.py", true)]
+    #[case("src/foo.py", true)]
+    #[case("src/./foo.py", true)]
+    #[case("src/..foo/foo..py", true)]
+    #[case("caf\u{e9}/men\u{fc}.txt", true)]
+    #[case("ab:c", true)]
+    #[case("", false)]
+    #[case("..", false)]
+    #[case("../foo.py", false)]
+    #[case("src/../../foo.py", false)]
+    #[case("src/..", false)]
+    #[case("..\\foo.py", false)]
+    #[case("src\\..\\..\\foo.py", false)]
+    #[case("src/..\\foo.py", false)]
+    #[case("/etc/passwd", false)]
+    #[case("\\etc\\passwd", false)]
+    #[case("\\\\server\\share", false)]
+    #[case("C:\\Windows", false)]
+    #[case("c:/Windows", false)]
+    #[case("C:foo", false)]
+    #[case("foo\0.py", false)]
+    #[case("foo\n.py", false)]
+    #[case("foo\u{1b}[0m.py", false)]
+    #[case("foo\u{7f}.py", false)]
+    fn check_safe_path(#[case] path: &str, #[case] safe: bool) {
+        assert_eq!(safe_path(path).is_ok(), safe, "{:?}", path);
+    }
+
+    #[test]
+    fn strip_and_add_prefix() {
+        let path = VirtualTargetPath::from("dist/app.js");
+        let stripped = VirtualTargetPath::from("app.js");
+        assert_eq!(path.strip_prefix("dist/"), Some(stripped.clone()));
+        assert_eq!(path.strip_prefix("dist"), Some(stripped.clone()));
+        assert_eq!(path.strip_prefix("src/"), None);
+        assert_eq!(path.strip_prefix("dis"), None);
+        assert_eq!(path.strip_prefix("dist/app.js"), None);
+        assert_eq!(path.strip_prefix(""), None);
+        assert_eq!(
+            VirtualTargetPath::from("dist/a/b.js").strip_prefix("dist/a/"),
+            Some(VirtualTargetPath::from("b.js"))
+        );
+        assert_eq!(
+            VirtualTargetPath::from("../dist/app.js").strip_prefix("../dist"),
+            None
+        );
+        assert_eq!(
+            VirtualTargetPath::from("dist/../app.js").strip_prefix("dist"),
+            None
+        );
+
+        assert_eq!(stripped.with_prefix("dist/").unwrap(), path);
+        assert_eq!(stripped.with_prefix("dist").unwrap(), path);
+        assert!(stripped.with_prefix("..").is_err());
+        assert!(stripped.with_prefix("/dist").is_err());
+        assert!(stripped.with_prefix("").is_err());
+        assert!(VirtualTargetPath::from("../app.js")
+            .with_prefix("dist")
+            .is_err());
+    }
+
+    #[test]
+    fn match_many_stars() {
+        let path = VirtualTargetPath::from("a".repeat(64).as_str());
+        assert!(!path.matches(&format!("{}b", "*a".repeat(32))));
+        assert!(path.matches(&"a*".repeat(32)));
+    }
 
     #[test]
     fn serialize_virtual_target_path() {
@@ -81,6 +518,38 @@ mod tests {
         assert!(serialized == expected);
     }
 
+    #[test]
+    fn virtual_target_path_order() {
+        // "é" precomposed (NFC) and decomposed (NFD)
+        let nfc = "caf\u{e9}";
+        let nfd = "cafe\u{301}";
+        let sorted = ["Cafe", "Z", "cafe", nfd, nfc, "\u{1f600}"];
+        let mut paths: Vec<_> = sorted
+            .iter()
+            .rev()
+            .map(|p| VirtualTargetPath::from(*p))
+            .collect();
+        paths.sort();
+        let values: Vec<_> = paths.iter().map(|p| p.value()).collect();
+        assert_eq!(values, sorted);
+
+        let artifacts: BTreeMap<_, _> = paths
+            .into_iter()
+            .map(|path| (path, TargetDescription::new()))
+            .collect();
+        assert_eq!(artifacts.len(), sorted.len());
+        let link = LinkMetadataBuilder::new()
+            .materials(artifacts)
+            .build()
+            .unwrap();
+        let canonical = String::from_utf8(link.to_bytes().unwrap()).unwrap();
+        let positions: Vec<_> = sorted
+            .iter()
+            .map(|path| canonical.find(&format!("\"{}\":", path)).unwrap())
+            .collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]), "{}", canonical);
+    }
+
     #[test]
     fn deserialize_virtual_target_path() {
         let path = VirtualTargetPath::from("foo.py");
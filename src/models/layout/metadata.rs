@@ -4,16 +4,20 @@ use chrono::{DateTime, Duration, Utc};
 use serde::de::{Deserialize, Deserializer, Error as DeserializeError};
 use serde::ser::{Error as SerializeError, Serialize, Serializer};
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
 use crate::crypto::KeyId;
 use crate::crypto::PublicKey;
 use crate::interchange::{DataInterchange, Json};
 use crate::models::{Metadata, MetadataType, MetadataWrapper};
-use crate::Result;
+use crate::{Error, Result};
 
 use super::Layout;
-use super::{inspection::Inspection, step::Step};
+use super::{
+    inspection::Inspection,
+    rule::ArtifactRule,
+    step::{Command, Step},
+};
 
 /// Helper to construct `LayoutMetadata`
 pub struct LayoutMetadataBuilder {
@@ -99,14 +103,65 @@ impl LayoutMetadataBuilder {
         self
     }
 
+    /// Build the layout, checking it like [`LayoutMetadata::new`] does: the
+    /// names of steps and inspections must be unique, thresholds must be
+    /// satisfiable and every key ID a step references must have been added
+    /// with [`LayoutMetadataBuilder::add_key`].
+    ///
+    /// ```
+    /// # use in_toto::crypto::PrivateKey;
+    /// # use in_toto::models::inspection::Inspection;
+    /// # use in_toto::models::rule::ArtifactRule;
+    /// # use in_toto::models::step::{Command, Step};
+    /// # use in_toto::models::{LayoutMetadataBuilder, MetablockBuilder};
+    /// # let owner = PrivateKey::from_ed25519(include_bytes!("../../../tests/ed25519/ed25519-1"))?;
+    /// # let dev = PrivateKey::from_pkcs8(
+    /// #     include_bytes!("../../../tests/ed25519/ed25519-2.pk8.der"),
+    /// #     in_toto::crypto::SignatureScheme::Ed25519,
+    /// # )?;
+    /// let dev_key = dev.public().clone();
+    /// let layout = LayoutMetadataBuilder::new()
+    ///     .readme("Build foo".into())
+    ///     .add_key(dev_key.clone())
+    ///     .add_step(
+    ///         Step::new("write-code")
+    ///             .add_key(dev_key.key_id().clone())
+    ///             .threshold(1)
+    ///             .add_expected_product(ArtifactRule::from_tokens(&["CREATE", "foo.py"])?),
+    ///     )
+    ///     .add_step(
+    ///         Step::new("package")
+    ///             .add_key(dev_key.key_id().clone())
+    ///             .threshold(1)
+    ///             .expected_command(Command::from("tar zcvf foo.tar.gz foo.py"))
+    ///             .add_expected_material(ArtifactRule::from_tokens(&[
+    ///                 "MATCH", "foo.py", "WITH", "PRODUCTS", "FROM", "write-code",
+    ///             ])?)
+    ///             .add_expected_product(ArtifactRule::from_tokens(&["CREATE", "foo.tar.gz"])?),
+    ///     )
+    ///     .add_inspect(Inspection::new("untar").run(Command::from("tar xzf foo.tar.gz")))
+    ///     .build()?;
+    ///
+    /// let metablock = MetablockBuilder::from_metadata(Box::new(layout))
+    ///     .sign(&[&owner])?
+    ///     .build();
+    /// metablock.verify(1, [owner.public()])?;
+    ///
+    /// // A step may only reference keys of the layout.
+    /// assert!(LayoutMetadataBuilder::new()
+    ///     .add_step(Step::new("build").add_key(dev_key.key_id().clone()).threshold(1))
+    ///     .build()
+    ///     .is_err());
+    /// # Ok::<(), in_toto::Error>(())
+    /// ```
     pub fn build(self) -> Result<LayoutMetadata> {
-        Ok(LayoutMetadata::new(
+        LayoutMetadata::new(
             self.expires,
             self.readme,
             self.keys,
             self.steps,
             self.inspect,
-        ))
+        )
     }
 }
 
@@ -121,21 +176,226 @@ pub struct LayoutMetadata {
 }
 
 impl LayoutMetadata {
+    /// Create a new `LayoutMetadata`. Every step and inspection must have
+    /// a unique name, every step must have a satisfiable threshold, and
+    /// every key ID referenced by a step must be present in `keys`.
     pub fn new(
         expires: DateTime<Utc>,
         readme: String,
         keys: HashMap<KeyId, PublicKey>,
         steps: Vec<Step>,
         inspect: Vec<Inspection>,
-    ) -> Self {
-        LayoutMetadata {
+    ) -> Result<Self> {
+        let mut names = HashSet::new();
+        let item_names = steps
+            .iter()
+            .map(|step| &step.name)
+            .chain(inspect.iter().map(|inspection| &inspection.name));
+        for name in item_names {
+            if !names.insert(name) {
+                return Err(Error::IllegalArgument(format!(
+                    "duplicate step or inspection name {}",
+                    name
+                )));
+            }
+        }
+
+        for step in &steps {
+            step.validate()?;
+            if let Some(key_id) =
+                step.pub_keys.iter().find(|id| !keys.contains_key(id))
+            {
+                return Err(Error::IllegalArgument(format!(
+                    "step {} references unknown key {:?}",
+                    step.name, key_id
+                )));
+            }
+        }
+
+        Ok(LayoutMetadata {
             steps,
             inspect,
             keys,
             expires,
             readme,
+        })
+    }
+
+    /// Get the steps of this layout
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// Get the inspections of this layout
+    pub fn inspect(&self) -> &[Inspection] {
+        &self.inspect
+    }
+
+    /// Get the functionary keys of this layout
+    pub fn keys(&self) -> &HashMap<KeyId, PublicKey> {
+        &self.keys
+    }
+
+    /// Get the expiration time of this layout
+    pub fn expires(&self) -> &DateTime<Utc> {
+        &self.expires
+    }
+
+    /// Get the readme of this layout
+    pub fn readme(&self) -> &str {
+        &self.readme
+    }
+
+    /// Check whether this layout has expired at the given time
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires <= now
+    }
+
+    /// List every step with the key IDs of the functionaries authorized to
+    /// sign its link, in the order of the layout. These are the links that
+    /// verification may use, named as given by
+    /// [`link_filename`](crate::runlib::link_filename), so that a driver can
+    /// fetch exactly those files instead of a whole directory. Only
+    /// `threshold` of the links of a step are needed.
+    pub fn required_links(&self) -> Vec<(String, Vec<KeyId>)> {
+        self.steps
+            .iter()
+            .map(|step| (step.name.clone(), step.pub_keys.clone()))
+            .collect()
+    }
+
+    /// Collect the key IDs of the functionaries of every step, and of the
+    /// steps of the sublayouts those steps delegate to, recursively. These
+    /// are the keys to provision up front to verify the whole supply chain.
+    ///
+    /// `resolver` returns the sublayout the step of the given name delegates
+    /// to, or `None` if the step is carried out directly. It is an error if a
+    /// sublayout delegates, directly or not, to a step it was resolved for,
+    /// as the delegation would never end.
+    pub fn transitive_keys(
+        &self,
+        resolver: impl Fn(&str) -> Option<LayoutMetadata>,
+    ) -> Result<BTreeSet<KeyId>> {
+        let mut keys = BTreeSet::new();
+        collect_transitive_keys(self, &resolver, &mut Vec::new(), &mut keys)?;
+        Ok(keys)
+    }
+
+    /// Replace the `{KEY}` parameters in the expected commands and artifact
+    /// rules of the steps, and in the commands and artifact rules of the
+    /// inspections, with their values in `params`, so that one layout can
+    /// serve many releases. A parameter name consists of ASCII letters,
+    /// digits, `_` and `-`; other braces, like those of a `{a,b}` glob, are
+    /// left as they are. Values are not substituted again.
+    ///
+    /// It is an error if `params` has no value for a parameter.
+    pub fn substitute_parameters(
+        &self,
+        params: &BTreeMap<String, String>,
+    ) -> Result<LayoutMetadata> {
+        let mut layout = self.clone();
+        for step in &mut layout.steps {
+            step.expected_command =
+                substitute_command(&step.expected_command, params)?;
+            substitute_rules(&mut step.expected_materials, params)?;
+            substitute_rules(&mut step.expected_products, params)?;
+        }
+        for inspection in &mut layout.inspect {
+            inspection.run = substitute_command(&inspection.run, params)?;
+            substitute_rules(&mut inspection.expected_materials, params)?;
+            substitute_rules(&mut inspection.expected_products, params)?;
+        }
+        Ok(layout)
+    }
+}
+
+/// Add the step keys of `layout` and its sublayouts to `keys`. `ancestors`
+/// holds the names of the steps the sublayouts being walked were resolved
+/// for.
+fn collect_transitive_keys(
+    layout: &LayoutMetadata,
+    resolver: &dyn Fn(&str) -> Option<LayoutMetadata>,
+    ancestors: &mut Vec<String>,
+    keys: &mut BTreeSet<KeyId>,
+) -> Result<()> {
+    for step in &layout.steps {
+        keys.extend(step.pub_keys.iter().cloned());
+        let Some(sublayout) = resolver(&step.name) else {
+            continue;
+        };
+        if ancestors.contains(&step.name) {
+            return Err(Error::IllegalArgument(format!(
+                "sublayouts delegate in a cycle: {} -> {}",
+                ancestors.join(" -> "),
+                step.name
+            )));
+        }
+        ancestors.push(step.name.clone());
+        collect_transitive_keys(&sublayout, resolver, ancestors, keys)?;
+        ancestors.pop();
+    }
+    Ok(())
+}
+
+fn substitute_command(
+    command: &Command,
+    params: &BTreeMap<String, String>,
+) -> Result<Command> {
+    command
+        .as_ref()
+        .iter()
+        .map(|arg| substitute(arg, params))
+        .collect::<Result<Vec<_>>>()
+        .map(Command::from)
+}
+
+fn substitute_rules(
+    rules: &mut [ArtifactRule],
+    params: &BTreeMap<String, String>,
+) -> Result<()> {
+    for rule in rules {
+        let tokens = rule
+            .to_tokens()
+            .iter()
+            .map(|token| substitute(token, params))
+            .collect::<Result<Vec<_>>>()?;
+        *rule = ArtifactRule::from_tokens(&tokens)?;
+    }
+    Ok(())
+}
+
+/// Replace the `{KEY}` parameters in `s`.
+fn substitute(s: &str, params: &BTreeMap<String, String>) -> Result<String> {
+    let mut substituted = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('{') {
+        substituted.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let name = rest[1..].find('}').map(|end| &rest[1..end + 1]);
+        match name {
+            Some(name)
+                if !name.is_empty()
+                    && name.chars().all(|c| {
+                        c.is_ascii_alphanumeric() || c == '_' || c == '-'
+                    }) =>
+            {
+                let value = params.get(name).ok_or_else(|| {
+                    Error::IllegalArgument(format!(
+                        "no value for the layout parameter {{{}}} in {:?}",
+                        name, s
+                    ))
+                })?;
+                substituted.push_str(value);
+                rest = &rest[name.len() + 2..];
+            }
+            _ => {
+                substituted.push('{');
+                rest = &rest[1..];
+            }
         }
     }
+    substituted.push_str(rest);
+    Ok(substituted)
 }
 
 impl Metadata for LayoutMetadata {
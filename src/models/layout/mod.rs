@@ -4,7 +4,6 @@ use std::collections::BTreeMap;
 
 use chrono::prelude::*;
 use chrono::{DateTime, Utc};
-use log::warn;
 use serde_derive::{Deserialize, Serialize};
 
 use crate::crypto::{KeyId, PublicKey};
@@ -48,28 +47,33 @@ impl Layout {
     }
 
     pub fn try_into(self) -> Result<LayoutMetadata> {
-        // Ignore all keys with incorrect key IDs.
-        // If a malformed key is used, there will be a warning
-        let keys_with_correct_key_id = self
-            .keys
-            .into_iter()
-            .filter(|(key_id, pkey)| {
-                if key_id == pkey.key_id() {
-                    true
-                } else {
-                    warn!("Malformed key of ID {:?}", key_id);
-                    false
-                }
-            })
-            .collect();
+        if self.typ != "layout" {
+            return Err(Error::Encoding(format!(
+                "unexpected _type {:?}, expected \"layout\"",
+                self.typ
+            )));
+        }
+
+        // Layouts may come from untrusted sources, so every key must be
+        // listed under the ID calculated from it.
+        for (key_id, pkey) in &self.keys {
+            pkey.validate_key_id()?;
+            if key_id != pkey.key_id() {
+                return Err(Error::VerificationFailure(format!(
+                    "key listed as {} has the key ID {}",
+                    key_id.as_str(),
+                    pkey.key_id().as_str(),
+                )));
+            }
+        }
 
-        Ok(LayoutMetadata::new(
+        LayoutMetadata::new(
             parse_datetime(&self.expires)?,
             self.readme,
-            keys_with_correct_key_id,
+            self.keys.into_iter().collect(),
             self.steps,
             self.inspect,
-        ))
+        )
     }
 }
 
@@ -87,39 +91,41 @@ fn format_datetime(ts: &DateTime<Utc>) -> String {
 #[cfg(test)]
 mod test {
     use assert_json_diff::assert_json_eq;
-    use chrono::{NaiveDateTime, TimeZone, Utc};
+    use chrono::DateTime;
     use serde_json::json;
 
-    use crate::{crypto::PublicKey, models::layout::format_datetime};
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::str::FromStr;
+
+    use crate::{
+        crypto::{KeyId, PublicKey},
+        models::{layout::format_datetime, Metablock, MetadataWrapper},
+    };
 
     use super::{
         inspection::Inspection,
         parse_datetime,
         rule::{Artifact, ArtifactRule},
-        step::Step,
+        step::{Command, Step},
         Layout, LayoutMetadataBuilder,
     };
 
-    const ALICE_PUB_KEY: &'static [u8] =
+    const ALICE_PUB_KEY: &[u8] =
         include_bytes!("../../../tests/ed25519/ed25519-1.pub");
-    const BOB_PUB_KEY: &'static [u8] =
+    const BOB_PUB_KEY: &[u8] =
         include_bytes!("../../../tests/rsa/rsa-4096.spki.der");
 
     #[test]
     fn parse_datetime_test() {
         let time_str = "1970-01-01T00:00:00Z".to_string();
         let parsed_dt = parse_datetime(&time_str[..]).unwrap();
-        let dt = Utc.from_utc_datetime(
-            &NaiveDateTime::from_timestamp_opt(0, 0).unwrap(),
-        );
+        let dt = DateTime::from_timestamp(0, 0).unwrap();
         assert_eq!(parsed_dt, dt);
     }
 
     #[test]
     fn format_datetime_test() {
-        let dt = Utc.from_utc_datetime(
-            &NaiveDateTime::from_timestamp_opt(0, 0).unwrap(),
-        );
+        let dt = DateTime::from_timestamp(0, 0).unwrap();
         let generated_dt_str = format_datetime(&dt);
         let dt_str = "1970-01-01T00:00:00Z".to_string();
         assert_eq!(dt_str, generated_dt_str);
@@ -133,9 +139,7 @@ mod test {
         )
         .unwrap();
         let metadata = LayoutMetadataBuilder::new()
-            .expires(Utc.from_utc_datetime(
-                &NaiveDateTime::from_timestamp_opt(0, 0).unwrap(),
-            ))
+            .expires(DateTime::from_timestamp(0, 0).unwrap())
             .add_key(alice_key.clone())
             .add_key(bob_key.clone())
             .add_step(
@@ -376,4 +380,224 @@ mod test {
         let layout_parse: Layout = serde_json::from_str(json).unwrap();
         assert_eq!(layout, layout_parse);
     }
+
+    #[test]
+    fn duplicate_item_name_rejected() {
+        let alice_key = PublicKey::from_ed25519(ALICE_PUB_KEY).unwrap();
+        let res = LayoutMetadataBuilder::new()
+            .add_key(alice_key.clone())
+            .add_step(
+                Step::new("package")
+                    .threshold(1)
+                    .add_key(alice_key.key_id().to_owned()),
+            )
+            .add_inspect(Inspection::new("package"))
+            .build();
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn unknown_step_key_rejected() {
+        let alice_key = PublicKey::from_ed25519(ALICE_PUB_KEY).unwrap();
+        let res = LayoutMetadataBuilder::new()
+            .add_step(
+                Step::new("write-code")
+                    .threshold(1)
+                    .add_key(alice_key.key_id().to_owned()),
+            )
+            .build();
+        assert!(res.is_err());
+
+        let res = LayoutMetadataBuilder::new()
+            .add_key(alice_key.clone())
+            .add_step(
+                Step::new("write-code")
+                    .threshold(1)
+                    .add_key(alice_key.key_id().to_owned()),
+            )
+            .build();
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn mismatched_key_id_rejected() {
+        let mut json =
+            serde_json::to_value(get_example_layout_metadata()).unwrap();
+        let layout: Layout = serde_json::from_value(json.clone()).unwrap();
+        assert!(layout.try_into().is_ok());
+
+        // list a key under an ID it does not hash to
+        let ed25519_key_id =
+            "e0294a3f17cc8563c3ed5fceb3bd8d3f6bfeeaca499b5c9572729ae015566554";
+        let wrong_key_id = "a".repeat(64);
+        let keys = json["keys"].as_object_mut().unwrap();
+        let key = keys.remove(ed25519_key_id).unwrap();
+        keys.insert(wrong_key_id.clone(), key);
+        let layout: Layout = serde_json::from_value(json.clone()).unwrap();
+        let err = layout.try_into().unwrap_err().to_string();
+        assert!(err.contains(&wrong_key_id), "{}", err);
+        assert!(err.contains(ed25519_key_id), "{}", err);
+
+        // list it under its own ID, but with a `keyid` it does not hash to
+        let keys = json["keys"].as_object_mut().unwrap();
+        let mut key = keys.remove(&wrong_key_id).unwrap();
+        key["keyid"] = wrong_key_id.clone().into();
+        keys.insert(ed25519_key_id.to_string(), key);
+        let layout: Layout = serde_json::from_value(json).unwrap();
+        let err = layout.try_into().unwrap_err().to_string();
+        assert!(err.contains(&wrong_key_id), "{}", err);
+        assert!(err.contains(ed25519_key_id), "{}", err);
+    }
+
+    #[test]
+    fn list_required_links() {
+        let raw = std::fs::read("tests/test_metadata/demo.layout").unwrap();
+        let metablock: Metablock = serde_json::from_slice(&raw).unwrap();
+        let layout = match metablock.metadata {
+            MetadataWrapper::Layout(layout) => layout,
+            MetadataWrapper::Link(_) => panic!("Unexpected link."),
+        };
+        let key_id = |id: &str| KeyId::from_str(id).unwrap();
+        assert_eq!(
+            layout.required_links(),
+            vec![
+                (
+                    "write-code".to_string(),
+                    vec![key_id("e0294a3f17cc8563c3ed5fceb3bd8d3f6bfeeaca499b5c9572729ae015566554")],
+                ),
+                (
+                    "package".to_string(),
+                    vec![key_id("59d12f31ee173dbb3359769414e73c120f219af551baefb70aa69414dfba4aaf")],
+                ),
+            ]
+        );
+        assert!(LayoutMetadataBuilder::new()
+            .build()
+            .unwrap()
+            .required_links()
+            .is_empty());
+    }
+
+    #[test]
+    fn transitive_keys() {
+        let alice_key = PublicKey::from_ed25519(ALICE_PUB_KEY).unwrap();
+        let bob_key = PublicKey::from_spki(
+            BOB_PUB_KEY,
+            crate::crypto::SignatureScheme::RsaSsaPssSha256,
+        )
+        .unwrap();
+        let layout = |step: &str, key: &PublicKey| {
+            LayoutMetadataBuilder::new()
+                .add_key(key.clone())
+                .add_step(
+                    Step::new(step).threshold(1).add_key(key.key_id().clone()),
+                )
+                .build()
+                .unwrap()
+        };
+        let root = layout("build", &alice_key);
+        let keys = |ids: &[&PublicKey]| {
+            ids.iter()
+                .map(|key| key.key_id().clone())
+                .collect::<BTreeSet<_>>()
+        };
+
+        assert_eq!(
+            root.transitive_keys(|_| None).unwrap(),
+            keys(&[&alice_key])
+        );
+        let resolved = root.transitive_keys(|step| match step {
+            "build" => Some(layout("compile", &bob_key)),
+            _ => None,
+        });
+        assert_eq!(resolved.unwrap(), keys(&[&alice_key, &bob_key]));
+
+        // compile delegates back to build, whose sublayout has compile.
+        let err = root
+            .transitive_keys(|step| match step {
+                "build" => Some(layout("compile", &bob_key)),
+                "compile" => Some(layout("build", &bob_key)),
+                _ => None,
+            })
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "illegal argument: sublayouts delegate in a cycle: build -> \
+             compile -> build"
+        );
+    }
+
+    #[test]
+    fn substitute_parameters() {
+        let alice_key = PublicKey::from_ed25519(ALICE_PUB_KEY).unwrap();
+        let layout = LayoutMetadataBuilder::new()
+            .add_key(alice_key.clone())
+            .add_step(
+                Step::new("build")
+                    .add_key(alice_key.key_id().clone())
+                    .threshold(1)
+                    .expected_command("make COMMIT={GITHUB_SHA}".into())
+                    .add_expected_product(
+                        ArtifactRule::from_tokens(&[
+                            "CREATE",
+                            "foo-{GITHUB_SHA}.tar.gz",
+                        ])
+                        .unwrap(),
+                    ),
+            )
+            .add_inspect(
+                Inspection::new("check")
+                    .run("tar tzf foo-{GITHUB_SHA}.tar.gz".into())
+                    .add_expected_material(
+                        ArtifactRule::from_tokens(&[
+                            "MATCH",
+                            "foo-{GITHUB_SHA}.tar.gz",
+                            "WITH",
+                            "PRODUCTS",
+                            "FROM",
+                            "build",
+                        ])
+                        .unwrap(),
+                    )
+                    .add_expected_product(
+                        ArtifactRule::from_tokens(&["ALLOW", "src/{a,b}"])
+                            .unwrap(),
+                    ),
+            )
+            .build()
+            .unwrap();
+        let params =
+            BTreeMap::from([("GITHUB_SHA".to_string(), "8f2c1e0".to_string())]);
+
+        let substituted = layout.substitute_parameters(&params).unwrap();
+        let step = &substituted.steps()[0];
+        assert_eq!(step.expected_command, Command::from("make COMMIT=8f2c1e0"));
+        assert_eq!(
+            step.expected_products,
+            [ArtifactRule::Create("foo-8f2c1e0.tar.gz".into())]
+        );
+        let inspection = &substituted.inspect()[0];
+        assert_eq!(inspection.run, Command::from("tar tzf foo-8f2c1e0.tar.gz"));
+        assert_eq!(
+            inspection.expected_materials,
+            [ArtifactRule::Match {
+                pattern: "foo-8f2c1e0.tar.gz".into(),
+                in_src: None,
+                with: Artifact::Products,
+                in_dst: None,
+                from: "build".into(),
+            }]
+        );
+        // Braces that do not hold a parameter name are kept.
+        assert_eq!(
+            inspection.expected_products,
+            [ArtifactRule::Allow("src/{a,b}".into())]
+        );
+
+        let err = layout
+            .substitute_parameters(&BTreeMap::new())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("{GITHUB_SHA}"), "{}", err);
+    }
 }
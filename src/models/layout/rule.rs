@@ -13,12 +13,12 @@
 //! Format of an Artifact Rule is the following:
 //! ```plaintext
 //! {MATCH <pattern> [IN <source-path-prefix>] WITH (MATERIALS|PRODUCTS) [IN <destination-path-prefix>] FROM <step> ||
-//! CREATE <pattern> ||
-//! DELETE <pattern> ||
-//! MODIFY <pattern> ||
-//! ALLOW <pattern> ||
-//! REQUIRE <pattern> ||
-//! DISALLOW <pattern>}
+//! CREATE <pattern> [IN <path-prefix>] ||
+//! DELETE <pattern> [IN <path-prefix>] ||
+//! MODIFY <pattern> [IN <path-prefix>] ||
+//! ALLOW <pattern> [IN <path-prefix>] ||
+//! REQUIRE <pattern> [IN <path-prefix>] ||
+//! DISALLOW <pattern> [IN <path-prefix>]}
 //! ```
 //!
 //! Please refer to [`in-toto v0.9 spec`] for concrete functions
@@ -83,6 +83,39 @@
 //! ["CREATE", "./artifact"]
 //! ```
 //!
+//! With an `IN <path-prefix>`, such a rule only applies to the artifacts
+//! under the prefix, and the pattern is matched against their path relative
+//! to it. This is the [`ArtifactRule::In`] wrapper around the rule
+//!
+//! ```
+//! # use in_toto::{models::rule::ArtifactRule, Result};
+//!
+//! # fn main() -> Result<()> {
+//!     let rule = ArtifactRule::Allow("*.py".into()).in_prefix("src/")?;
+//!     assert_eq!(rule.to_tokens(), ["ALLOW", "*.py", "IN", "src/"]);
+//!
+//!     Ok(())
+//! # }
+//!
+//! ```
+//!
+//! ## Tokens
+//!
+//! An Artifact Rule can also be converted from and to its token list
+//! form directly. Malformed token lists return `Error::Encoding`.
+//!
+//! ```
+//! # use in_toto::{models::rule::ArtifactRule, Result};
+//!
+//! # fn main() -> Result<()> {
+//! let tokens = ["MATCH", "foo.py", "WITH", "PRODUCTS", "FROM", "write-code"];
+//! let rule = ArtifactRule::from_tokens(&tokens)?;
+//! assert_eq!(rule.to_tokens(), tokens);
+//! assert!(ArtifactRule::from_tokens(&["MATCH", "foo.py"]).is_err());
+//! # Ok(())
+//! # }
+//! ```
+//!
 //! ## Deserialize and Serialize
 //!
 //! To make it easy to parse `.layout` files, format in [`in-toto v0.9 spec`]
@@ -123,13 +156,10 @@
 
 use std::result::Result as StdResult;
 
-use serde::{
-    de::{self, SeqAccess, Unexpected, Visitor},
-    ser::{Serialize, SerializeSeq},
-    Deserialize,
-};
+use serde::{de, ser::Serialize, Deserialize};
 
 use crate::models::VirtualTargetPath;
+use crate::{Error, Result};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Artifact {
@@ -148,6 +178,7 @@ impl AsRef<str> for Artifact {
 
 /// Artifact rule enum
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum ArtifactRule {
     /// indicates that products matched by the pattern must not appear
     /// as materials of this step.
@@ -177,6 +208,15 @@ pub enum ArtifactRule {
         in_dst: Option<String>,
         from: String,
     },
+    /// restricts a rule other than `MATCH` to the artifacts under `prefix`,
+    /// whose path relative to `prefix` must match the pattern of the rule.
+    /// It is built with [`ArtifactRule::in_prefix`], which rejects `MATCH`
+    /// rules and rules already restricted.
+    #[non_exhaustive]
+    In {
+        rule: Box<ArtifactRule>,
+        prefix: String,
+    },
 }
 
 impl ArtifactRule {
@@ -189,229 +229,182 @@ impl ArtifactRule {
             ArtifactRule::Require(pattern) => pattern,
             ArtifactRule::Disallow(pattern) => pattern,
             ArtifactRule::Match { pattern, .. } => pattern,
+            ArtifactRule::In { rule, .. } => rule.pattern(),
         }
     }
-}
 
-impl Serialize for ArtifactRule {
-    fn serialize<S>(&self, serializer: S) -> StdResult<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
+    /// Restrict this rule to the artifacts under `prefix`, like
+    /// `["ALLOW", "*.py", "IN", "src/"]`. Fails for `MATCH` rules, which
+    /// have their own prefixes, and for rules already restricted.
+    pub fn in_prefix(self, prefix: &str) -> Result<Self> {
         match self {
-            ArtifactRule::Create(pattern) => {
-                let mut seq = serializer.serialize_seq(Some(2))?;
-                seq.serialize_element("CREATE")?;
-                seq.serialize_element(pattern)?;
-                seq.end()
-            }
-            ArtifactRule::Delete(pattern) => {
-                let mut seq = serializer.serialize_seq(Some(2))?;
-                seq.serialize_element("DELETE")?;
-                seq.serialize_element(pattern)?;
-                seq.end()
-            }
-            ArtifactRule::Modify(pattern) => {
-                let mut seq = serializer.serialize_seq(Some(2))?;
-                seq.serialize_element("MODIFY")?;
-                seq.serialize_element(pattern)?;
-                seq.end()
-            }
-            ArtifactRule::Allow(pattern) => {
-                let mut seq = serializer.serialize_seq(Some(2))?;
-                seq.serialize_element("ALLOW")?;
-                seq.serialize_element(pattern)?;
-                seq.end()
-            }
-            ArtifactRule::Require(pattern) => {
-                let mut seq = serializer.serialize_seq(Some(2))?;
-                seq.serialize_element("REQUIRE")?;
-                seq.serialize_element(pattern)?;
-                seq.end()
-            }
-            ArtifactRule::Disallow(pattern) => {
-                let mut seq = serializer.serialize_seq(Some(2))?;
-                seq.serialize_element("DISALLOW")?;
-                seq.serialize_element(pattern)?;
-                seq.end()
-            }
-            ArtifactRule::Match {
-                pattern,
-                in_src,
-                with,
-                in_dst,
-                from,
-            } => {
-                let mut to_be_serialized = vec!["MATCH", pattern.as_ref()];
-                if let Some(src) = in_src {
-                    to_be_serialized.append(&mut vec!["IN", src]);
-                }
-                to_be_serialized.append(&mut vec!["WITH", with.as_ref()]);
-                if let Some(dst) = in_dst {
-                    to_be_serialized.append(&mut vec!["IN", dst]);
-                }
-                to_be_serialized.append(&mut vec!["FROM", from]);
-                let mut seq =
-                    serializer.serialize_seq(Some(to_be_serialized.len()))?;
-                for e in to_be_serialized {
-                    seq.serialize_element(e)?;
-                }
-                seq.end()
+            ArtifactRule::Match { .. } | ArtifactRule::In { .. } => {
+                Err(Error::IllegalArgument(format!(
+                    "{} cannot be restricted to the prefix {}",
+                    self.to_tokens().join(" "),
+                    prefix
+                )))
             }
+            rule => Ok(ArtifactRule::In {
+                rule: Box::new(rule),
+                prefix: prefix.to_string(),
+            }),
         }
     }
-}
 
-/// Visitor helps to deserialize `ArtifactRule`
-struct ArtifactRuleVisitor {}
-
-impl ArtifactRuleVisitor {
-    pub fn new() -> Self {
-        ArtifactRuleVisitor {}
+    /// The rule without its `IN <path-prefix>` restriction, and the prefix.
+    pub(crate) fn unscoped(&self) -> (&ArtifactRule, Option<&str>) {
+        match self {
+            ArtifactRule::In { rule, prefix } => (rule, Some(prefix)),
+            rule => (rule, None),
+        }
     }
-}
-
-impl<'de> Visitor<'de> for ArtifactRuleVisitor {
-    type Value = ArtifactRule;
 
-    fn expecting(
-        &self,
-        formatter: &mut std::fmt::Formatter,
-    ) -> std::fmt::Result {
-        formatter.write_str("An Artifact Rule for in-toto")
-    }
+    /// Parse an `ArtifactRule` from the token list form used in layouts,
+    /// e.g. `["MATCH", "foo", "WITH", "PRODUCTS", "FROM", "build"]`.
+    pub fn from_tokens<S: AsRef<str>>(tokens: &[S]) -> Result<Self> {
+        let mut tokens = tokens.iter().map(AsRef::as_ref);
+        let mut next = |expected: &str| {
+            tokens.next().ok_or_else(|| {
+                Error::Encoding(format!(
+                    "artifact rule ended early, expected {}",
+                    expected
+                ))
+            })
+        };
 
-    /// Deserialize a sequence to an `ArtifactRule`.
-    fn visit_seq<V>(self, mut seq: V) -> StdResult<ArtifactRule, V::Error>
-    where
-        V: SeqAccess<'de>,
-    {
-        let mut len = 0;
-        let typ: &str = seq
-            .next_element()?
-            .ok_or_else(|| de::Error::invalid_length(len, &self))?;
-        len += 1;
-
-        let pattern: VirtualTargetPath = seq
-            .next_element()?
-            .ok_or_else(|| de::Error::invalid_length(len, &self))?;
-        len += 1;
-
-        match typ {
-            "CREATE" => Ok(ArtifactRule::Create(pattern)),
-            "DELETE" => Ok(ArtifactRule::Delete(pattern)),
-            "MODIFY" => Ok(ArtifactRule::Modify(pattern)),
-            "ALLOW" => Ok(ArtifactRule::Allow(pattern)),
-            "REQUIRE" => Ok(ArtifactRule::Require(pattern)),
-            "DISALLOW" => Ok(ArtifactRule::Disallow(pattern)),
+        let typ = next("a rule type")?;
+        let pattern = VirtualTargetPath::new(next("a pattern")?.to_string())?;
+        let mut rule = match typ {
+            "CREATE" => ArtifactRule::Create(pattern),
+            "DELETE" => ArtifactRule::Delete(pattern),
+            "MODIFY" => ArtifactRule::Modify(pattern),
+            "ALLOW" => ArtifactRule::Allow(pattern),
+            "REQUIRE" => ArtifactRule::Require(pattern),
+            "DISALLOW" => ArtifactRule::Disallow(pattern),
             "MATCH" => {
-                let in_or_with: String = seq
-                    .next_element()?
-                    .ok_or_else(|| de::Error::invalid_length(len, &self))?;
-                len += 1;
-
                 let mut in_src = None;
-                let mut with = Artifact::Materials;
                 let mut in_dst = None;
 
-                match &in_or_with[..] {
-                    "IN" => {
-                        let source_path_prefix: String =
-                            seq.next_element()?.ok_or_else(|| {
-                                de::Error::invalid_length(len, &self)
-                            })?;
-                        len += 1;
-
-                        in_src = Some(source_path_prefix);
-
-                        let in_: String =
-                            seq.next_element()?.ok_or_else(|| {
-                                de::Error::invalid_length(len, &self)
-                            })?;
-                        len += 1;
-
-                        if in_ != "WITH" {
-                            Err(de::Error::invalid_value(
-                                Unexpected::Str(&in_),
-                                &"IN",
-                            ))?
-                        }
-                    }
-                    "WITH" => {}
-                    _ => {
-                        return Err(de::Error::invalid_value(
-                            Unexpected::Str(&in_or_with),
-                            &"WITH or IN",
-                        ))
-                    }
+                let mut token = next("IN or WITH")?;
+                if token == "IN" {
+                    in_src = Some(next("a source path prefix")?.to_string());
+                    token = next("WITH")?;
+                }
+                if token != "WITH" {
+                    return Err(Error::Encoding(format!(
+                        "unexpected token {} in MATCH rule, expected WITH",
+                        token
+                    )));
                 }
 
-                let target: String = seq
-                    .next_element()?
-                    .ok_or_else(|| de::Error::invalid_length(len, &self))?;
-                len += 1;
-
-                match &target[..] {
-                    "MATERIALS" => {}
-                    "PRODUCTS" => with = Artifact::Products,
-                    _ => Err(de::Error::invalid_value(
-                        Unexpected::Str(&target),
-                        &"MATERIALS or PRODUCTS",
-                    ))?,
-                };
-
-                let in_or_from: String = seq
-                    .next_element()?
-                    .ok_or_else(|| de::Error::invalid_length(len, &self))?;
-                len += 1;
-
-                match &in_or_from[..] {
-                    "IN" => {
-                        let destination_path_prefix: String =
-                            seq.next_element()?.ok_or_else(|| {
-                                de::Error::invalid_length(len, &self)
-                            })?;
-                        len += 1;
-                        in_dst = Some(destination_path_prefix);
-
-                        let from_: String =
-                            seq.next_element()?.ok_or_else(|| {
-                                de::Error::invalid_length(len, &self)
-                            })?;
-                        len += 1;
-
-                        if from_ != "FROM" {
-                            return Err(de::Error::invalid_value(
-                                Unexpected::Str(&from_),
-                                &"FROM",
-                            ));
-                        }
-                    }
-                    "FROM" => {}
-                    _ => {
-                        return Err(de::Error::invalid_value(
-                            Unexpected::Str(&in_or_from),
-                            &"IN or FROM",
-                        ));
+                let with = match next("MATERIALS or PRODUCTS")? {
+                    "MATERIALS" => Artifact::Materials,
+                    "PRODUCTS" => Artifact::Products,
+                    other => {
+                        return Err(Error::Encoding(format!(
+                            "unexpected token {} in MATCH rule, expected \
+                             MATERIALS or PRODUCTS",
+                            other
+                        )))
                     }
                 };
 
-                let from: String = seq
-                    .next_element()?
-                    .ok_or_else(|| de::Error::invalid_length(len, &self))?;
+                let mut token = next("IN or FROM")?;
+                if token == "IN" {
+                    in_dst =
+                        Some(next("a destination path prefix")?.to_string());
+                    token = next("FROM")?;
+                }
+                if token != "FROM" {
+                    return Err(Error::Encoding(format!(
+                        "unexpected token {} in MATCH rule, expected FROM",
+                        token
+                    )));
+                }
 
-                Ok(ArtifactRule::Match {
+                let from = next("a step name")?.to_string();
+                ArtifactRule::Match {
                     pattern,
                     in_src,
                     with,
                     in_dst,
                     from,
-                })
+                }
             }
             others => {
-                Err(de::Error::custom(format!("Unexpected token {}", others)))
+                return Err(Error::Encoding(format!(
+                    "unexpected artifact rule type {}",
+                    others
+                )))
             }
+        };
+
+        let mut token = tokens.next();
+        if token == Some("IN") && typ != "MATCH" {
+            let prefix = tokens.next().ok_or_else(|| {
+                Error::Encoding(
+                    "artifact rule ended early, expected a path prefix".into(),
+                )
+            })?;
+            rule = rule.in_prefix(prefix)?;
+            token = tokens.next();
+        }
+        if let Some(token) = token {
+            return Err(Error::Encoding(format!(
+                "unexpected trailing token {} in artifact rule",
+                token
+            )));
         }
+        Ok(rule)
+    }
+
+    /// Convert this `ArtifactRule` into the token list form used in
+    /// layouts. This is the inverse of [`ArtifactRule::from_tokens`].
+    pub fn to_tokens(&self) -> Vec<String> {
+        let (typ, pattern) = match self {
+            ArtifactRule::Create(pattern) => ("CREATE", pattern),
+            ArtifactRule::Delete(pattern) => ("DELETE", pattern),
+            ArtifactRule::Modify(pattern) => ("MODIFY", pattern),
+            ArtifactRule::Allow(pattern) => ("ALLOW", pattern),
+            ArtifactRule::Require(pattern) => ("REQUIRE", pattern),
+            ArtifactRule::Disallow(pattern) => ("DISALLOW", pattern),
+            ArtifactRule::Match { pattern, .. } => ("MATCH", pattern),
+            ArtifactRule::In { rule, prefix } => {
+                let mut tokens = rule.to_tokens();
+                tokens.extend(["IN".to_string(), prefix.clone()]);
+                return tokens;
+            }
+        };
+        let mut tokens = vec![typ.to_string(), pattern.value().to_string()];
+
+        if let ArtifactRule::Match {
+            in_src,
+            with,
+            in_dst,
+            from,
+            ..
+        } = self
+        {
+            if let Some(src) = in_src {
+                tokens.extend(["IN".to_string(), src.clone()]);
+            }
+            tokens.extend(["WITH".to_string(), with.as_ref().to_string()]);
+            if let Some(dst) = in_dst {
+                tokens.extend(["IN".to_string(), dst.clone()]);
+            }
+            tokens.extend(["FROM".to_string(), from.clone()]);
+        }
+        tokens
+    }
+}
+
+impl Serialize for ArtifactRule {
+    fn serialize<S>(&self, serializer: S) -> StdResult<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.to_tokens().serialize(serializer)
     }
 }
 
@@ -420,11 +413,9 @@ impl<'de> Deserialize<'de> for ArtifactRule {
     where
         D: serde::Deserializer<'de>,
     {
-        deserializer.deserialize_struct(
-            "ArtifactRule",
-            &["inner"],
-            ArtifactRuleVisitor::new(),
-        )
+        let tokens: Vec<String> = Deserialize::deserialize(deserializer)?;
+        ArtifactRule::from_tokens(&tokens)
+            .map_err(|e| de::Error::custom(format!("{:?}", e)))
     }
 }
 
@@ -433,6 +424,8 @@ pub mod test {
     use rstest::rstest;
     use serde_json::json;
 
+    use crate::Error;
+
     use super::{Artifact, ArtifactRule};
 
     /// generate a ARTIFACT_RULE as json:
@@ -657,4 +650,71 @@ pub mod test {
         let rule_parsed: ArtifactRule = serde_json::from_str(json).unwrap();
         assert_eq!(rule, rule_parsed);
     }
+
+    #[rstest]
+    #[case(ArtifactRule::Create("./artifact".into()))]
+    #[case(ArtifactRule::Delete("./artifact".into()))]
+    #[case(ArtifactRule::Modify("./artifact".into()))]
+    #[case(ArtifactRule::Allow("./artifact".into()))]
+    #[case(ArtifactRule::Require("./artifact".into()))]
+    #[case(ArtifactRule::Disallow("./artifact".into()))]
+    #[case(generate_materials_rule())]
+    #[case(generate_products_rule())]
+    #[case(ArtifactRule::Match {
+        pattern: "./".into(),
+        in_src: None,
+        with: Artifact::Products,
+        in_dst: None,
+        from: "build".into(),
+    })]
+    #[case(ArtifactRule::Allow("*.py".into()).in_prefix("src/").unwrap())]
+    #[case(ArtifactRule::Disallow("*".into()).in_prefix("build").unwrap())]
+    fn tokens_round_trip(#[case] rule: ArtifactRule) {
+        let tokens = rule.to_tokens();
+        assert_eq!(ArtifactRule::from_tokens(&tokens).unwrap(), rule);
+    }
+
+    #[test]
+    fn parse_rule_in_prefix() {
+        let rule = ArtifactRule::from_tokens(&["ALLOW", "*.py", "IN", "src/"])
+            .unwrap();
+        assert_eq!(
+            rule,
+            ArtifactRule::In {
+                rule: Box::new(ArtifactRule::Allow("*.py".into())),
+                prefix: "src/".into(),
+            }
+        );
+        assert_eq!(rule.pattern().value(), "*.py");
+        assert_eq!(rule.to_tokens(), ["ALLOW", "*.py", "IN", "src/"]);
+        assert_eq!(
+            serde_json::to_value(&rule).unwrap(),
+            json!(["ALLOW", "*.py", "IN", "src/"])
+        );
+
+        assert!(rule.clone().in_prefix("lib").is_err());
+        assert!(generate_materials_rule().in_prefix("lib").is_err());
+    }
+
+    #[rstest]
+    #[case(&[])]
+    #[case(&["CREATE"])]
+    #[case(&["CREATE", "foo", "bar"])]
+    #[case(&["RENAME", "foo"])]
+    #[case(&["MATCH", "foo", "FROM", "build"])]
+    #[case(&["MATCH", "foo", "IN", "src", "FROM", "build"])]
+    #[case(&["MATCH", "foo", "WITH", "ARTIFACTS", "FROM", "build"])]
+    #[case(&["MATCH", "foo", "WITH", "PRODUCTS", "IN", "dst"])]
+    #[case(&["MATCH", "foo", "WITH", "PRODUCTS", "FROM"])]
+    #[case(&["ALLOW", "foo", "IN"])]
+    #[case(&["ALLOW", "foo", "IN", "src", "IN", "lib"])]
+    #[case(&["ALLOW", "foo", "WITH", "src"])]
+    #[case(&["MATCH", "foo", "WITH", "PRODUCTS", "FROM", "build", "IN", "a"])]
+    fn malformed_tokens(#[case] tokens: &[&str]) {
+        assert!(matches!(
+            ArtifactRule::from_tokens(tokens),
+            Err(Error::Encoding(_))
+        ));
+        assert!(serde_json::from_value::<ArtifactRule>(json!(tokens)).is_err());
+    }
 }
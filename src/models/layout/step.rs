@@ -140,6 +140,31 @@ impl Step {
         self
     }
 
+    /// Get the key IDs of the functionaries authorized for this Step
+    pub fn pub_keys(&self) -> &[KeyId] {
+        &self.pub_keys
+    }
+
+    /// Check that the threshold of this Step can be met, i.e. that it is
+    /// not zero and not larger than the number of authorized keys.
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.threshold == 0 {
+            return Err(Error::IllegalArgument(format!(
+                "step {} has a threshold of zero",
+                self.name
+            )));
+        }
+        if self.threshold as usize > self.pub_keys.len() {
+            return Err(Error::IllegalArgument(format!(
+                "step {} has a threshold of {} but only {} pubkeys",
+                self.name,
+                self.threshold,
+                self.pub_keys.len()
+            )));
+        }
+        Ok(())
+    }
+
     // Derive operations on `materials`/`products` and `name`
     supply_chain_item_derive!();
 }
@@ -251,4 +276,27 @@ mod test {
         assert_eq!(step_parsed, step);
         Ok(())
     }
+
+    #[test]
+    fn validate_threshold() -> Result<()> {
+        let key_id = KeyId::from_str(
+            "70ca5750c2eda80b18f41f4ec5f92146789b5d68dd09577be422a0159bd13680",
+        )?;
+        assert!(Step::new("package")
+            .add_key(key_id.clone())
+            .validate()
+            .is_err());
+        assert!(Step::new("package").threshold(1).validate().is_err());
+        assert!(Step::new("package")
+            .add_key(key_id.clone())
+            .threshold(2)
+            .validate()
+            .is_err());
+        assert!(Step::new("package")
+            .add_key(key_id)
+            .threshold(1)
+            .validate()
+            .is_ok());
+        Ok(())
+    }
 }
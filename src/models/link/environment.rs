@@ -0,0 +1,84 @@
+//! in-toto link's structured environment
+//!
+use std::collections::BTreeMap;
+
+use serde_derive::{Deserialize, Serialize};
+
+/// The environment a step was run in, as newer in-toto links record it:
+/// the captured environment variables, a description of the filesystem and
+/// the working directory. Links may instead carry a flat map of strings,
+/// see [`LinkMetadata::env`](crate::models::LinkMetadata::env).
+/// # Example
+/// ```
+/// use std::collections::BTreeMap;
+/// use in_toto::models::environment::Environment;
+/// let mut variables: BTreeMap<String, String> = BTreeMap::new();
+/// variables.insert("LANG".into(), "C.UTF-8".into());
+///
+/// let environment = Environment::new()
+///     .set_variables(variables)
+///     .set_workdir("/src".into());
+/// assert_eq!(environment.workdir(), Some("/src"));
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Environment {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    variables: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filesystem: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    workdir: Option<String>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Environment::default()
+    }
+
+    /// Set the environment variables
+    pub fn set_variables(
+        mut self,
+        variables: BTreeMap<String, String>,
+    ) -> Self {
+        self.variables = Some(variables);
+        self
+    }
+
+    /// Set the description of the filesystem
+    pub fn set_filesystem(mut self, filesystem: String) -> Self {
+        self.filesystem = Some(filesystem);
+        self
+    }
+
+    /// Set the working directory
+    pub fn set_workdir(mut self, workdir: String) -> Self {
+        self.workdir = Some(workdir);
+        self
+    }
+
+    /// Get the environment variables
+    pub fn variables(&self) -> Option<&BTreeMap<String, String>> {
+        self.variables.as_ref()
+    }
+
+    /// Get the description of the filesystem
+    pub fn filesystem(&self) -> Option<&str> {
+        self.filesystem.as_deref()
+    }
+
+    /// Get the working directory
+    pub fn workdir(&self) -> Option<&str> {
+        self.workdir.as_deref()
+    }
+}
+
+/// The two shapes of a link's `environment`. A map holding only strings is
+/// read as the flat form, so a structured environment without `variables`
+/// is read as a flat map with the same keys and values.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum EnvironmentShape {
+    Flat(BTreeMap<String, String>),
+    Structured(Environment),
+}
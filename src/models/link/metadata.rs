@@ -13,11 +13,12 @@ use crate::Result;
 
 use crate::models::step::Command;
 use crate::models::{
-    Link, Metablock, Metadata, MetadataType, MetadataWrapper,
-    TargetDescription, VirtualTargetPath,
+    Link, LinkV02, Metablock, Metadata, MetadataType, MetadataWrapper,
+    ResourceDescriptorBuilder, Statement, TargetDescription, VirtualTargetPath,
 };
 
 use super::byproducts::ByProducts;
+use super::environment::Environment;
 
 /// Helper to construct `LinkMetadata`.
 pub struct LinkMetadataBuilder {
@@ -25,6 +26,7 @@ pub struct LinkMetadataBuilder {
     materials: BTreeMap<VirtualTargetPath, TargetDescription>,
     products: BTreeMap<VirtualTargetPath, TargetDescription>,
     env: Option<BTreeMap<String, String>>,
+    environment: Option<Environment>,
     byproducts: ByProducts,
     command: Command,
 }
@@ -42,6 +44,7 @@ impl LinkMetadataBuilder {
             materials: BTreeMap::new(),
             products: BTreeMap::new(),
             env: None,
+            environment: None,
             byproducts: ByProducts::new(),
             command: Command::default(),
         }
@@ -101,6 +104,13 @@ impl LinkMetadataBuilder {
         self
     }
 
+    /// Set the structured environment for this metadata, which is recorded
+    /// instead of the flat `env`
+    pub fn environment(mut self, environment: Option<Environment>) -> Self {
+        self.environment = environment;
+        self
+    }
+
     /// Set the products for this metadata
     pub fn byproducts(mut self, byproducts: ByProducts) -> Self {
         self.byproducts = byproducts;
@@ -114,14 +124,16 @@ impl LinkMetadataBuilder {
     }
 
     pub fn build(self) -> Result<LinkMetadata> {
-        LinkMetadata::new(
+        let mut link = LinkMetadata::new(
             self.name,
             self.materials,
             self.products,
             self.env,
             self.byproducts,
             self.command,
-        )
+        )?;
+        link.environment = self.environment;
+        Ok(link)
     }
 
     /// Construct a new `Metablock<D, LinkMetadata>`.
@@ -147,9 +159,14 @@ pub struct LinkMetadata {
     pub name: String,
     pub materials: BTreeMap<VirtualTargetPath, TargetDescription>,
     pub products: BTreeMap<VirtualTargetPath, TargetDescription>,
+    /// The flat `environment` of the link, a map of strings.
     pub env: Option<BTreeMap<String, String>>,
+    /// The structured `environment` of the link. When set, it is recorded
+    /// instead of `env`, which is `None` for links read in this form.
+    pub environment: Option<Environment>,
     pub byproducts: ByProducts,
     pub command: Command,
+    pub(crate) spec_version: Option<String>,
 }
 
 impl LinkMetadata {
@@ -167,10 +184,55 @@ impl LinkMetadata {
             materials,
             products,
             env,
+            environment: None,
             byproducts,
             command,
+            spec_version: None,
         })
     }
+
+    /// The `spec_version` the tool that wrote this link tagged it with, if
+    /// any. The field is not part of the in-toto link format, so it is read
+    /// but never written, and links created by this crate have none.
+    pub fn spec_version(&self) -> Option<&str> {
+        self.spec_version.as_deref()
+    }
+
+    /// Convert this link into an attestation [`Statement`] with a
+    /// [`LinkV02`] predicate.
+    ///
+    /// The products become the subjects of the statement, named by their
+    /// path and with their hashes as digests. The name, materials,
+    /// environment, command and byproducts are kept in the predicate.
+    pub fn into_statement(self) -> Result<Statement<LinkV02>> {
+        let subject = self
+            .products
+            .iter()
+            .map(|(path, hashes)| {
+                ResourceDescriptorBuilder::new()
+                    .name(path.to_string())
+                    .target_description(hashes)
+                    .build()
+            })
+            .collect::<Result<_>>()?;
+        Ok(Statement::new(subject, LinkV02::from(self)))
+    }
+
+    /// Convert a statement created by [`LinkMetadata::into_statement`] back
+    /// into a link.
+    pub fn from_statement(statement: &Statement<LinkV02>) -> Result<Self> {
+        let products = statement
+            .subject()
+            .iter()
+            .map(|subject| {
+                Ok((
+                    VirtualTargetPath::new(subject.name().to_string())?,
+                    subject.target_description()?,
+                ))
+            })
+            .collect::<Result<_>>()?;
+        statement.predicate().clone().into_link(products)
+    }
 }
 
 impl Metadata for LinkMetadata {
@@ -211,11 +273,17 @@ impl<'de> Deserialize<'de> for LinkMetadata {
 
 #[cfg(test)]
 mod test {
+    use std::collections::{BTreeMap, HashMap};
+
+    use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
     use serde_json::json;
 
+    use crate::crypto::{HashAlgorithm, HashValue};
+    use crate::interchange::{DataInterchange, Json};
     use crate::models::{
         byproducts::ByProducts, step::Command, LinkMetadata,
-        LinkMetadataBuilder, VirtualTargetPath,
+        LinkMetadataBuilder, LinkV02, MetablockBuilder, PredicateVer,
+        Statement, VirtualTargetPath,
     };
 
     #[test]
@@ -298,4 +366,259 @@ mod test {
             serde_json::from_str(json).unwrap();
         assert_eq!(link_metadata, deserialized_link_metadata);
     }
+
+    #[test]
+    fn deserialize_untagged_linkmetadata() {
+        let json = json!({
+            "_type": "link",
+            "name": "",
+            "materials": {},
+            "products": {},
+            "byproducts": {},
+            "command": [],
+            "environment": null
+        });
+
+        // Links without a spec version are accepted.
+        let link_metadata: LinkMetadata =
+            serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(link_metadata.spec_version(), None);
+        assert_eq!(serde_json::to_value(&link_metadata).unwrap(), json);
+
+        let mut tagged = json.clone();
+        tagged["spec_version"] = "1.0".into();
+        let link_metadata: LinkMetadata =
+            serde_json::from_value(tagged).unwrap();
+        assert_eq!(link_metadata.spec_version(), Some("1.0"));
+        // The tag is not written back, as it is not part of the signed
+        // link format.
+        assert_eq!(serde_json::to_value(&link_metadata).unwrap(), json);
+
+        let mut invalid = json;
+        invalid["spec_version"] = "latest".into();
+        assert!(serde_json::from_value::<LinkMetadata>(invalid).is_err());
+    }
+
+    #[test]
+    fn deserialize_flat_environment() {
+        let json = json!({
+            "_type": "link",
+            "name": "",
+            "materials": {},
+            "products": {},
+            "byproducts": {},
+            "command": [],
+            "environment": { "PATH": "/usr/bin", "workdir": "/src" }
+        });
+
+        let link_metadata: LinkMetadata =
+            serde_json::from_value(json.clone()).unwrap();
+        let env = link_metadata.env.as_ref().unwrap();
+        assert_eq!(env["PATH"], "/usr/bin");
+        assert_eq!(env["workdir"], "/src");
+        assert_eq!(link_metadata.environment, None);
+        assert_eq!(serde_json::to_value(&link_metadata).unwrap(), json);
+    }
+
+    #[test]
+    fn deserialize_structured_environment() {
+        let json = json!({
+            "_type": "link",
+            "name": "",
+            "materials": {},
+            "products": {},
+            "byproducts": {},
+            "command": [],
+            "environment": {
+                "variables": { "PATH": "/usr/bin" },
+                "filesystem": "overlay",
+                "workdir": "/src"
+            }
+        });
+
+        let link_metadata: LinkMetadata =
+            serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(link_metadata.env, None);
+        let environment = link_metadata.environment.as_ref().unwrap();
+        assert_eq!(environment.variables().unwrap()["PATH"], "/usr/bin");
+        assert_eq!(environment.filesystem(), Some("overlay"));
+        assert_eq!(environment.workdir(), Some("/src"));
+        assert_eq!(serde_json::to_value(&link_metadata).unwrap(), json);
+
+        let built = LinkMetadataBuilder::new()
+            .environment(Some(environment.clone()))
+            .build()
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(&built).unwrap()["environment"],
+            json["environment"]
+        );
+
+        let mut unknown = json;
+        unknown["environment"]["os"] = json!({ "name": "linux" });
+        assert!(serde_json::from_value::<LinkMetadata>(unknown).is_err());
+    }
+
+    #[test]
+    fn link_into_statement() {
+        let link_metadata = LinkMetadataBuilder::new()
+            .name("package".into())
+            .add_material(
+                VirtualTargetPath::new("tests/test_link/foo.tar.gz".into())
+                    .unwrap(),
+            )
+            .add_product(
+                VirtualTargetPath::new("tests/test_metadata/demo.link".into())
+                    .unwrap(),
+            )
+            .command(Command::from("tar zcvf foo.tar.gz foo.py"))
+            .build()
+            .unwrap();
+
+        let statement = link_metadata.clone().into_statement().unwrap();
+        assert_eq!(
+            statement.predicate_type(),
+            String::from(PredicateVer::LinkV0_2)
+        );
+        let subject = statement.subject();
+        assert_eq!(subject.len(), 1);
+        assert_eq!(subject[0].name(), "tests/test_metadata/demo.link");
+        assert!(subject[0].digest().contains_key("sha256"));
+
+        let json = serde_json::to_value(&statement).unwrap();
+        assert!(json["predicate"]["materials"]
+            .get("tests/test_link/foo.tar.gz")
+            .is_some());
+        assert!(json["predicate"].get("products").is_none());
+
+        let decoded: Statement<LinkV02> = serde_json::from_value(json).unwrap();
+        assert_eq!(
+            LinkMetadata::from_statement(&decoded).unwrap(),
+            link_metadata
+        );
+    }
+
+    /// A link with artifacts, environment and byproducts drawn from `seed`,
+    /// inserted into its maps in an order drawn from `order`.
+    fn random_link(seed: u64, order: u64) -> LinkMetadata {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let string = |rng: &mut StdRng| -> String {
+            let chars = ['a', 'B', '0', '/', '"', '\\', '\n', 'é', ' '];
+            (0..rng.gen_range(1..8))
+                .map(|_| *chars.choose(rng).unwrap())
+                .collect()
+        };
+        let algorithms = [
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Sha512,
+            HashAlgorithm::Sha384,
+        ];
+        let artifacts = |rng: &mut StdRng| -> Vec<_> {
+            (0..rng.gen_range(0..6))
+                .map(|i| {
+                    let mut hashes = Vec::new();
+                    for alg in &algorithms {
+                        if rng.gen_bool(0.5) {
+                            let value: [u8; 32] = rng.gen();
+                            hashes.push((
+                                alg.clone(),
+                                HashValue::new(value.into()),
+                            ));
+                        }
+                    }
+                    (format!("dir{}/{}", i, string(rng)), hashes)
+                })
+                .collect()
+        };
+        let mut materials = artifacts(&mut rng);
+        let mut products = artifacts(&mut rng);
+        let mut env: Vec<_> = (0..rng.gen_range(0..6))
+            .map(|i| (format!("{}{}", i, string(&mut rng)), string(&mut rng)))
+            .collect();
+        let mut other_fields: Vec<_> = (0..rng.gen_range(0..4))
+            .map(|i| (format!("x-{}{}", i, string(&mut rng)), string(&mut rng)))
+            .collect();
+
+        let mut order = StdRng::seed_from_u64(order);
+        let mut insert = |artifacts: &mut Vec<(String, Vec<_>)>| {
+            artifacts.shuffle(&mut order);
+            let mut map = BTreeMap::new();
+            for (path, hashes) in artifacts.iter_mut() {
+                hashes.shuffle(&mut order);
+                let hashes: HashMap<_, _> = hashes.iter().cloned().collect();
+                map.insert(
+                    VirtualTargetPath::new(path.clone()).unwrap(),
+                    hashes,
+                );
+            }
+            map
+        };
+        let materials = insert(&mut materials);
+        let products = insert(&mut products);
+        env.shuffle(&mut order);
+        other_fields.shuffle(&mut order);
+        let byproducts = other_fields.into_iter().fold(
+            ByProducts::new()
+                .set_return_value(0)
+                .set_stdout(string(&mut rng)),
+            |byproducts, (key, value)| byproducts.set_other_field(key, value),
+        );
+
+        LinkMetadataBuilder::new()
+            .name(string(&mut rng))
+            .materials(materials)
+            .products(products)
+            .env(Some(env.into_iter().collect()))
+            .byproducts(byproducts)
+            .command(Command::from(vec![string(&mut rng), string(&mut rng)]))
+            .build()
+            .unwrap()
+    }
+
+    fn canonical<D: DataInterchange>(link: &LinkMetadata) -> Vec<u8> {
+        D::canonicalize(&D::serialize(link).unwrap()).unwrap()
+    }
+
+    fn written(link: &LinkMetadata) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        Json::to_writer(&mut bytes, link).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn canonical_bytes_ignore_insertion_order() {
+        for seed in 0..64 {
+            let link = random_link(seed, 0);
+            let signable =
+                MetablockBuilder::from_metadata(Box::new(link.clone()))
+                    .signable_bytes()
+                    .unwrap();
+            for order in 1..4 {
+                let shuffled = random_link(seed, order);
+                assert_eq!(shuffled, link, "seed {}", seed);
+                assert_eq!(
+                    canonical::<Json>(&shuffled),
+                    canonical::<Json>(&link),
+                    "seed {}",
+                    seed
+                );
+                #[cfg(feature = "cbor")]
+                assert_eq!(
+                    canonical::<crate::interchange::Cbor>(&shuffled),
+                    canonical::<crate::interchange::Cbor>(&link),
+                    "seed {}",
+                    seed
+                );
+                assert_eq!(written(&shuffled), written(&link), "seed {}", seed);
+                assert_eq!(
+                    MetablockBuilder::from_metadata(Box::new(shuffled))
+                        .signable_bytes()
+                        .unwrap(),
+                    signable,
+                    "seed {}",
+                    seed
+                );
+            }
+        }
+    }
 }
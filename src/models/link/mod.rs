@@ -4,31 +4,40 @@ use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::str;
 
-use crate::Result;
+use crate::{Error, Result};
+use log::warn;
 use serde_derive::{Deserialize, Serialize};
 
 pub mod byproducts;
+pub mod environment;
 mod metadata;
 pub use metadata::{LinkMetadata, LinkMetadataBuilder};
 
+use crate::models::helpers::serialize_artifacts;
 use crate::models::{TargetDescription, VirtualTargetPath};
 
 use self::byproducts::ByProducts;
+use self::environment::EnvironmentShape;
 
 use super::step::Command;
 
-// FIXME, we need to tag a spec
-//const SPEC_VERSION: &str = "0.9-dev";
+/// Version of the in-toto specification links are read for. Links tagged
+/// with a newer `spec_version` are accepted with a warning.
+pub const SPEC_VERSION: &str = "0.9";
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Link {
     #[serde(rename = "_type")]
     typ: String,
+    #[serde(default, skip_serializing)]
+    spec_version: Option<String>,
     name: String,
+    #[serde(serialize_with = "serialize_artifacts")]
     materials: BTreeMap<VirtualTargetPath, TargetDescription>,
+    #[serde(serialize_with = "serialize_artifacts")]
     products: BTreeMap<VirtualTargetPath, TargetDescription>,
     #[serde(rename = "environment")]
-    env: Option<BTreeMap<String, String>>,
+    env: Option<EnvironmentShape>,
     byproducts: ByProducts,
     command: Command,
 }
@@ -37,23 +46,69 @@ impl Link {
     pub fn from(meta: &LinkMetadata) -> Result<Self> {
         Ok(Link {
             typ: String::from("link"),
+            spec_version: meta.spec_version.clone(),
             name: meta.name.clone(),
             materials: meta.materials.clone(),
             products: meta.products.clone(),
-            env: meta.env.clone(),
+            env: match &meta.environment {
+                Some(environment) => {
+                    Some(EnvironmentShape::Structured(environment.clone()))
+                }
+                None => meta.env.clone().map(EnvironmentShape::Flat),
+            },
             byproducts: meta.byproducts.clone(),
             command: meta.command.clone(),
         })
     }
 
     pub fn try_into(self) -> Result<LinkMetadata> {
-        LinkMetadata::new(
+        if self.typ != "link" {
+            return Err(Error::Encoding(format!(
+                "unexpected _type {:?}, expected \"link\"",
+                self.typ
+            )));
+        }
+        if let Some(version) = &self.spec_version {
+            check_spec_version(version)?;
+        }
+        let (env, environment) = match self.env {
+            Some(EnvironmentShape::Flat(env)) => (Some(env), None),
+            Some(EnvironmentShape::Structured(environment)) => {
+                (None, Some(environment))
+            }
+            None => (None, None),
+        };
+        let mut link = LinkMetadata::new(
             self.name,
             self.materials,
             self.products,
-            self.env,
+            env,
             self.byproducts,
             self.command,
-        )
+        )?;
+        link.environment = environment;
+        link.spec_version = self.spec_version;
+        Ok(link)
     }
 }
+
+/// Check that `version` is a `<major>.<minor>` spec version. Versions newer
+/// than [`SPEC_VERSION`] are accepted but warned about.
+fn check_spec_version(version: &str) -> Result<()> {
+    let parse = |version: &str| -> Option<(u32, u32)> {
+        let mut parts = version.split(['.', '-']);
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next()?.parse().ok()?;
+        Some((major, minor))
+    };
+    let found = parse(version).ok_or_else(|| {
+        Error::Encoding(format!("invalid spec_version {:?}", version))
+    })?;
+    if Some(found) > parse(SPEC_VERSION) {
+        warn!(
+            "link spec_version {} is newer than the supported {}",
+            version, SPEC_VERSION
+        );
+    }
+    Ok(())
+}
@@ -3,8 +3,9 @@
 //! Metadata is the top level abstract for both layout metadata and link
 //! metadata. Metadata it is devided into two types
 //! * enum `MetadataWrapper` is used to do serialize, deserialize and
-//! other object unsafe operations.
+//!   other object unsafe operations.
 //! * trait `Metadata` is used to work for trait object.
+//!
 //! The reason please refer to issue <https://github.com/in-toto/in-toto-rs/issues/33>
 //!
 //! # Metablock
@@ -14,20 +15,24 @@
 //! an instance of Metablock, and methods to verify signatures,
 //! create signatures.
 
-use log::{debug, warn};
 use serde_derive::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::str;
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
-use crate::crypto::{KeyId, PrivateKey, PublicKey, Signature};
+use crate::crypto::{
+    verify_threshold, KeyId, PrivateKey, PublicKey, Signature, Signer,
+};
 use crate::error::Error;
 use crate::interchange::{DataInterchange, Json};
 use crate::Result;
 
-use super::{LayoutMetadata, LinkMetadata};
+use super::{Envelope, LayoutMetadata, LinkMetadata, PAYLOAD_TYPE_IN_TOTO};
 
 pub const FILENAME_FORMAT: &str = "{step_name}.{keyid:.8}.link";
 
@@ -65,6 +70,14 @@ impl MetadataWrapper {
         }
     }
 
+    /// The type of the wrapped metadata
+    pub fn typ(&self) -> MetadataType {
+        match self {
+            MetadataWrapper::Layout(_) => MetadataType::Layout,
+            MetadataWrapper::Link(_) => MetadataType::Link,
+        }
+    }
+
     /// Standard deserialize for MetadataWrapper by its metadata
     pub fn from_bytes(
         bytes: &[u8],
@@ -112,7 +125,10 @@ pub trait Metadata {
 /// All signed files (link and layout files) have the format.
 /// * `signatures`: A pubkey => signature map. signatures are for the metadata.
 /// * `metadata`: <ROLE> dictionary. Also known as signed metadata. e.g., link
-/// or layout.
+///   or layout.
+///
+/// Fields of the signed metadata this crate does not know are dropped, see
+/// [`RawMetablock`] to keep them.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Metablock {
     pub signatures: Vec<Signature>,
@@ -120,6 +136,110 @@ pub struct Metablock {
     pub metadata: MetadataWrapper,
 }
 
+/// A signed link or layout that keeps the signed metadata as it was read.
+///
+/// Metadata written by other tools may have fields this crate does not
+/// know, which [`Metablock`] drops, so their signatures no longer verify.
+/// A RawMetablock verifies signatures over the document as read, signs it
+/// again through [`RawMetablock::into_builder`], and serializes it back
+/// unchanged. The document cannot be changed; its typed view is
+/// [`RawMetablock::metadata`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawMetablockShim")]
+pub struct RawMetablock {
+    signatures: Vec<Signature>,
+    signed: serde_json::Value,
+    #[serde(skip_serializing)]
+    metadata: MetadataWrapper,
+}
+
+#[derive(Deserialize)]
+struct RawMetablockShim {
+    signatures: Vec<Signature>,
+    signed: serde_json::Value,
+}
+
+impl TryFrom<RawMetablockShim> for RawMetablock {
+    type Error = Error;
+
+    fn try_from(shim: RawMetablockShim) -> Result<Self> {
+        Ok(RawMetablock {
+            metadata: serde_json::from_value(shim.signed.clone())?,
+            signatures: shim.signatures,
+            signed: shim.signed,
+        })
+    }
+}
+
+impl RawMetablock {
+    /// The signatures over the signed metadata.
+    pub fn signatures(&self) -> &[Signature] {
+        &self.signatures
+    }
+
+    /// The signed metadata as it was read.
+    pub fn signed(&self) -> &serde_json::Value {
+        &self.signed
+    }
+
+    /// The signed metadata parsed as a link or layout, without the fields
+    /// this crate does not know.
+    pub fn metadata(&self) -> &MetadataWrapper {
+        &self.metadata
+    }
+
+    /// Verify the signatures over the signed metadata as read, like
+    /// [`Metablock::verify`]. The returned metadata is the typed view, so
+    /// the signed fields it does not know are only in
+    /// [`RawMetablock::signed`].
+    pub fn verify<'a, I>(
+        &self,
+        threshold: u32,
+        authorized_keys: I,
+    ) -> Result<MetadataWrapper>
+    where
+        I: IntoIterator<Item = &'a PublicKey>,
+    {
+        let authorized_keys = authorized_keys
+            .into_iter()
+            .map(|k| (k.key_id(), k))
+            .collect::<HashMap<&KeyId, &PublicKey>>();
+        let metadata = signable_bytes(Json::canonicalize(&self.signed)?)?;
+        verify_threshold(
+            &authorized_keys,
+            &metadata,
+            &self.signatures,
+            threshold,
+        )?;
+        Ok(self.metadata.clone())
+    }
+
+    /// Drop the signatures and return a builder for the same document, e.g.
+    /// to sign it again with a rotated key. The new signatures are made over
+    /// the same bytes; build them with [`MetablockBuilder::build_raw`].
+    pub fn into_builder(self) -> MetablockBuilder {
+        MetablockBuilder {
+            signatures: HashMap::new(),
+            metadata: self.metadata,
+            signed: Some(self.signed),
+        }
+    }
+}
+
+/// The bytes signatures are made over, given the canonical JSON of the
+/// signed metadata.
+fn signable_bytes(canonical: Vec<u8>) -> Result<Vec<u8>> {
+    let metadata = String::from_utf8(canonical)
+        .map_err(|e| {
+            Error::Encoding(format!(
+                "Cannot convert metadata into a string: {}",
+                e
+            ))
+        })?
+        .replace("\\n", "\n");
+    Ok(metadata.into_bytes())
+}
+
 impl Metablock {
     /// Create a new Metablock, using data of metadata. And the signatures are
     /// generated by using private-keys to sign the metadata.
@@ -151,6 +271,13 @@ impl Metablock {
         })
     }
 
+    /// Drop the signatures and return a builder for the same metadata, e.g.
+    /// to sign it again with a rotated key. To keep fields this crate does
+    /// not know, see [`RawMetablock::into_builder`].
+    pub fn into_builder(self) -> MetablockBuilder {
+        MetablockBuilder::from_metadata(self.metadata.into_trait())
+    }
+
     /// Verify this metadata.
     /// Each signature in the Metablock signed by an authorized key
     /// is a legal signature. Only legal the number signatures is
@@ -163,84 +290,254 @@ impl Metablock {
     where
         I: IntoIterator<Item = &'a PublicKey>,
     {
-        if self.signatures.is_empty() {
-            return Err(Error::VerificationFailure(
-                "The metadata was not signed with any authorized keys.".into(),
-            ));
-        }
-
-        if threshold < 1 {
-            return Err(Error::VerificationFailure(
-                "Threshold must be strictly greater than zero".into(),
-            ));
-        }
-
         let authorized_keys = authorized_keys
             .into_iter()
             .map(|k| (k.key_id(), k))
             .collect::<HashMap<&KeyId, &PublicKey>>();
+        self.verify_with(threshold, &authorized_keys)
+    }
 
-        let raw = self.metadata.to_bytes()?;
-        let metadata = String::from_utf8(raw)
-            .map_err(|e| {
-                Error::Encoding(format!(
-                    "Cannot convert metadata into a string: {}",
-                    e
-                ))
-            })?
-            .replace("\\n", "\n");
-        let mut signatures_needed = threshold;
+    /// Verify many independent metablocks, each with its own threshold,
+    /// against the same set of keys, as a consumer of a transparency log
+    /// would. The results are in the order of `items`, and each is what
+    /// [`Metablock::verify`] returns for that item.
+    ///
+    /// The key set is indexed once for the whole batch, and with the `rayon`
+    /// feature the items are verified on the `rayon` global thread pool.
+    /// Each item is still canonicalized on its own, as in
+    /// [`Metablock::verify`].
+    pub fn verify_batch(
+        items: &[(Metablock, u32)],
+        keys: &[PublicKey],
+    ) -> Vec<Result<MetadataWrapper>> {
+        let authorized_keys = keys
+            .iter()
+            .map(|k| (k.key_id(), k))
+            .collect::<HashMap<&KeyId, &PublicKey>>();
+        let verify = |(metablock, threshold): &(Metablock, u32)| {
+            metablock.verify_with(*threshold, &authorized_keys)
+        };
 
-        // Create a key_id->signature map to deduplicate the key_ids.
-        let signatures = self
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+            items.par_iter().map(verify).collect()
+        }
+        #[cfg(not(feature = "rayon"))]
+        {
+            items.iter().map(verify).collect()
+        }
+    }
+
+    /// The signature check of [`Metablock::verify`], with the authorized
+    /// keys indexed by key ID.
+    fn verify_with(
+        &self,
+        threshold: u32,
+        authorized_keys: &HashMap<&KeyId, &PublicKey>,
+    ) -> Result<MetadataWrapper> {
+        let metadata = signable_bytes(self.metadata.to_bytes()?)?;
+        verify_threshold(
+            authorized_keys,
+            &metadata,
+            &self.signatures,
+            threshold,
+        )?;
+        Ok(self.metadata.clone())
+    }
+
+    /// Verify this metadata like [`Metablock::verify`], but fail if more than
+    /// one signature claims the same key ID instead of keeping only one of
+    /// them, so that padded or double-counted signatures are noticed.
+    pub fn verify_strict<'a, I>(
+        &self,
+        threshold: u32,
+        authorized_keys: I,
+    ) -> Result<MetadataWrapper>
+    where
+        I: IntoIterator<Item = &'a PublicKey>,
+    {
+        let mut key_ids = HashSet::new();
+        if let Some(sig) = self
             .signatures
             .iter()
-            .map(|sig| (sig.key_id(), sig))
-            .collect::<HashMap<&KeyId, &Signature>>();
-
-        // check the signatures, if is signed by an authorized key,
-        // signatures_needed - 1
-
-        for (key_id, sig) in signatures {
-            match authorized_keys.get(key_id) {
-                Some(pub_key) => match pub_key.verify(metadata.as_bytes(), sig)
-                {
-                    Ok(()) => {
-                        debug!(
-                            "Good signature from key ID {:?}",
-                            pub_key.key_id()
-                        );
-                        signatures_needed -= 1;
-                    }
-                    Err(e) => {
-                        warn!(
-                            "Bad signature from key ID {:?}: {:?}",
-                            pub_key.key_id(),
-                            e
-                        );
-                    }
-                },
-                None => {
-                    warn!(
-                        "Key ID {:?} was not found in the set of authorized keys.",
-                        sig.key_id()
-                    );
-                }
-            }
-            if signatures_needed == 0 {
-                break;
+            .find(|sig| !key_ids.insert(sig.key_id()))
+        {
+            return Err(Error::VerificationFailure(format!(
+                "The metadata has more than one signature from key ID {:?}",
+                sig.key_id()
+            )));
+        }
+        self.verify(threshold, authorized_keys)
+    }
+
+    /// Add the signatures of `other` to this Metablock, which must have the
+    /// same metadata. Signatures from a key that already signed are
+    /// skipped. Returns the number of signatures added.
+    pub fn merge_signatures(&mut self, other: &Self) -> Result<usize> {
+        self.merge_all(std::slice::from_ref(other))
+    }
+
+    /// Add the signatures of all of `others`, e.g. the links recorded by
+    /// each functionary of a step, to this Metablock. Nothing is merged
+    /// unless they all have the same metadata as this one. Signatures from
+    /// a key that already signed are skipped. Returns the number of
+    /// signatures added.
+    pub fn merge_all(&mut self, others: &[Self]) -> Result<usize> {
+        if let Some(index) = others
+            .iter()
+            .position(|other| other.metadata != self.metadata)
+        {
+            return Err(Error::IllegalArgument(format!(
+                "The metadata of Metablock {} differs from the metadata to \
+                 merge into",
+                index
+            )));
+        }
+
+        let before = self.signatures.len();
+        for sig in others.iter().flat_map(|other| &other.signatures) {
+            if !self.signatures.iter().any(|s| s.key_id() == sig.key_id()) {
+                self.signatures.push(sig.clone());
             }
         }
+        Ok(self.signatures.len() - before)
+    }
 
-        if signatures_needed > 0 {
-            return Err(Error::VerificationFailure(format!(
-                "Signature threshold not met: {}/{}",
-                threshold - signatures_needed,
-                threshold
+    /// Deserialize a Metablock from `bytes` encoded with the data interchange
+    /// `D`, failing unless the signed metadata is of type `expected`.
+    /// Signatures are not checked, see [`Metablock::verify`].
+    ///
+    /// With the `schema` feature, the document is first checked against
+    /// the [schema](crate::models::schema) of `expected` metadata, so that
+    /// the error names every missing or mistyped field.
+    pub fn parse_typed<D>(bytes: &[u8], expected: MetadataType) -> Result<Self>
+    where
+        D: DataInterchange,
+    {
+        #[cfg(feature = "schema")]
+        crate::models::schema::validate(
+            &D::from_slice::<serde_json::Value>(bytes)?,
+            expected,
+        )?;
+        let metablock: Self = D::from_slice(bytes)?;
+        let found = metablock.metadata.typ();
+        if found != expected {
+            return Err(Error::Encoding(format!(
+                "expected {} metadata, found {}",
+                expected, found
             )));
         }
+        Ok(metablock)
+    }
 
-        Ok(self.metadata.clone())
+    /// Write this Metablock to `dir` as `name` followed by the extension of
+    /// the data interchange `D`, e.g. `root.layout.json` for [`Json`].
+    /// Returns the path of the written file.
+    pub fn write_to_dir<D>(&self, dir: &Path, name: &str) -> Result<PathBuf>
+    where
+        D: DataInterchange,
+    {
+        let path = metadata_path::<D>(dir, name);
+        let mut writer = BufWriter::new(File::create(&path)?);
+        D::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        Ok(path)
+    }
+
+    /// Read the Metablock `name` from `dir`, appending the extension of the
+    /// data interchange `D` like [`Metablock::write_to_dir`] does.
+    pub fn read_from_dir<D>(dir: &Path, name: &str) -> Result<Self>
+    where
+        D: DataInterchange,
+    {
+        let file = File::open(metadata_path::<D>(dir, name))?;
+        D::from_reader(BufReader::new(file))
+    }
+
+    /// Format this Metablock as indented JSON with sorted keys, to inspect
+    /// it. The output is for display only: signatures are made over the
+    /// canonical JSON of the metadata, which this does not change, so
+    /// never sign or verify these bytes.
+    pub fn to_pretty_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&serde_json::to_value(self)?)?)
+    }
+}
+
+fn metadata_path<D: DataInterchange>(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.{}", name, D::extension()))
+}
+
+/// Read the type of the in-toto document `bytes` encoded with the data
+/// interchange `D`, without deserializing the metadata: the `_type` of the
+/// signed metadata of a [`Metablock`] (`link` or `layout`), the
+/// `payloadType` of an [`Envelope`], or else the `_type` of the document
+/// itself, e.g. of an unsigned statement.
+pub fn peek_type<D>(bytes: &[u8]) -> Result<String>
+where
+    D: DataInterchange,
+{
+    #[derive(Deserialize)]
+    struct Type {
+        #[serde(rename = "_type")]
+        typ: String,
+    }
+
+    #[derive(Deserialize)]
+    struct Document {
+        signed: Option<Type>,
+        #[serde(rename = "payloadType")]
+        payload_type: Option<String>,
+        #[serde(rename = "_type")]
+        typ: Option<String>,
+    }
+
+    let document: Document = D::from_slice(bytes)?;
+    document
+        .signed
+        .map(|signed| signed.typ)
+        .or(document.payload_type)
+        .or(document.typ)
+        .ok_or_else(|| {
+            Error::Encoding(
+                "The document has no `_type` or `payloadType` field".into(),
+            )
+        })
+}
+
+/// Any signed in-toto document, to ingest files whose type is not known in
+/// advance, e.g. from an artifact store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnyMetablock {
+    /// A link signed in the [`Metablock`] format.
+    Link(Metablock),
+    /// A layout signed in the [`Metablock`] format.
+    Layout(Metablock),
+    /// An [`Envelope`] with an in-toto payload, normally a statement.
+    Statement(Envelope),
+}
+
+impl AnyMetablock {
+    /// Deserialize a signed document encoded with the data interchange `D`,
+    /// dispatching on its type as given by [`peek_type`]. Signatures are not
+    /// checked.
+    pub fn from_bytes<D>(bytes: &[u8]) -> Result<Self>
+    where
+        D: DataInterchange,
+    {
+        match peek_type::<D>(bytes)?.as_str() {
+            "link" => Metablock::parse_typed::<D>(bytes, MetadataType::Link)
+                .map(Self::Link),
+            "layout" => {
+                Metablock::parse_typed::<D>(bytes, MetadataType::Layout)
+                    .map(Self::Layout)
+            }
+            PAYLOAD_TYPE_IN_TOTO => D::from_slice(bytes).map(Self::Statement),
+            typ => Err(Error::Encoding(format!(
+                "Unsupported in-toto document type {:?}",
+                typ
+            ))),
+        }
     }
 }
 
@@ -248,6 +545,7 @@ impl Metablock {
 pub struct MetablockBuilder {
     signatures: HashMap<KeyId, Signature>,
     metadata: MetadataWrapper,
+    signed: Option<serde_json::Value>,
 }
 
 impl MetablockBuilder {
@@ -256,16 +554,20 @@ impl MetablockBuilder {
         Self {
             signatures: HashMap::new(),
             metadata: metadata.into_enum(),
+            signed: None,
         }
     }
 
     /// Create a new `MetablockBuilder` from manually serialized metadata to be signed.
-    /// Returns an error if `metadata` cannot be parsed into Metadata.
+    /// Returns an error if `metadata` cannot be parsed into Metadata. The metadata is signed as
+    /// it is, with fields unknown to this crate, which only [`MetablockBuilder::build_raw`]
+    /// keeps.
     pub fn from_raw_metadata(raw_metadata: &[u8]) -> Result<Self> {
-        let metadata = MetadataWrapper::try_from_bytes(raw_metadata)?;
+        let signed: serde_json::Value = serde_json::from_slice(raw_metadata)?;
         Ok(Self {
             signatures: HashMap::new(),
-            metadata,
+            metadata: serde_json::from_value(signed.clone())?,
+            signed: Some(signed),
         })
     }
 
@@ -273,18 +575,10 @@ impl MetablockBuilder {
     /// same `KeyId`.
     pub fn sign(mut self, private_keys: &[&PrivateKey]) -> Result<Self> {
         let mut signatures = HashMap::new();
-        let raw = self.metadata.to_bytes()?;
-        let metadata = String::from_utf8(raw)
-            .map_err(|e| {
-                Error::Encoding(format!(
-                    "Cannot convert metadata into a string: {}",
-                    e
-                ))
-            })?
-            .replace("\\n", "\n");
+        let metadata = self.signable_bytes()?;
 
         private_keys.iter().try_for_each(|key| -> Result<()> {
-            let sig = key.sign(metadata.as_bytes())?;
+            let sig = key.sign(&metadata)?;
             signatures.insert(sig.key_id().clone(), sig);
             Ok(())
         })?;
@@ -293,48 +587,137 @@ impl MetablockBuilder {
         Ok(self)
     }
 
+    /// Add a signature over the metadata made by `signer`, replacing an existing signature with
+    /// the same `KeyId`. Fails if the signature does not carry the key ID of the signer's public
+    /// key. The signer's certificate chain, if any, is attached to the signature.
+    pub fn sign_with(mut self, signer: &dyn Signer) -> Result<Self> {
+        let mut sig = signer.sign(&self.signable_bytes()?)?;
+        if sig.key_id() != signer.public_key().key_id() {
+            return Err(Error::IllegalArgument(format!(
+                "Signer for key ID {:?} returned a signature for key ID {:?}",
+                signer.public_key().key_id(),
+                sig.key_id()
+            )));
+        }
+        if let (None, Some(cert)) = (sig.cert(), signer.certificate()) {
+            sig = sig.with_cert(cert.to_string());
+        }
+        self.signatures.insert(sig.key_id().clone(), sig);
+        Ok(self)
+    }
+
+    /// Add a signature made elsewhere, replacing an existing signature with the same `KeyId`.
+    /// The signature is not checked; it must be made over [`MetablockBuilder::signable_bytes`].
+    ///
+    /// This supports signing on an offline machine: export the signable bytes, sign them on the
+    /// machine holding the key, then import the signature.
+    ///
+    /// ```
+    /// # use in_toto::crypto::{PrivateKey, Signature};
+    /// # use in_toto::models::{LinkMetadataBuilder, MetablockBuilder};
+    /// # let key = PrivateKey::from_ed25519(include_bytes!("../../tests/ed25519/ed25519-1"))?;
+    /// let link = LinkMetadataBuilder::new().name("build".into()).build()?;
+    /// let builder = MetablockBuilder::from_metadata(Box::new(link));
+    /// let bytes = builder.signable_bytes()?;
+    ///
+    /// // On the offline machine, sign `bytes`, e.g. with `key.sign` or another tool.
+    /// let sig: Signature = key.sign(&bytes)?;
+    ///
+    /// let metablock = builder.add_signature(sig).build();
+    /// metablock.verify(1, [key.public()])?;
+    /// # Ok::<(), in_toto::Error>(())
+    /// ```
+    pub fn add_signature(mut self, sig: Signature) -> Self {
+        self.signatures.insert(sig.key_id().clone(), sig);
+        self
+    }
+
+    /// The canonical bytes of the metadata that signatures are made over, to sign them without
+    /// this builder, e.g. on an offline machine. See [`MetablockBuilder::add_signature`].
+    pub fn signable_bytes(&self) -> Result<Vec<u8>> {
+        match &self.signed {
+            Some(signed) => signable_bytes(Json::canonicalize(signed)?),
+            None => signable_bytes(self.metadata.to_bytes()?),
+        }
+    }
+
     /// Construct a new `Metablock` using the included signatures, sorting the signatures by
-    /// `KeyId`.
+    /// `KeyId`. Signatures over metadata with fields unknown to this crate do not verify on the
+    /// `Metablock`; use [`MetablockBuilder::build_raw`] for such metadata.
     pub fn build(self) -> Metablock {
-        let mut signatures = self.signatures.into_values().collect::<Vec<_>>();
-        signatures.sort_unstable_by(|a, b| a.key_id().cmp(b.key_id()));
-
         Metablock {
-            signatures,
+            signatures: self.sorted_signatures(),
             metadata: self.metadata,
         }
     }
+
+    /// Construct a new `RawMetablock` using the included signatures, sorting the signatures by
+    /// `KeyId`. The signed metadata is kept as given to [`MetablockBuilder::from_raw_metadata`]
+    /// or [`RawMetablock::into_builder`].
+    pub fn build_raw(self) -> Result<RawMetablock> {
+        let signatures = self.sorted_signatures();
+        let signed = match self.signed {
+            Some(signed) => signed,
+            None => serde_json::to_value(&self.metadata)?,
+        };
+        Ok(RawMetablock {
+            signatures,
+            signed,
+            metadata: self.metadata,
+        })
+    }
+
+    fn sorted_signatures(&self) -> Vec<Signature> {
+        let mut signatures =
+            self.signatures.values().cloned().collect::<Vec<_>>();
+        signatures.sort_unstable_by(|a, b| a.key_id().cmp(b.key_id()));
+        signatures
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{fs, str::FromStr};
+    use std::{cell::RefCell, fs, str::FromStr};
 
     use assert_json_diff::assert_json_eq;
-    use chrono::{NaiveDateTime, TimeZone, Utc};
+    use chrono::DateTime;
     use serde_json::json;
 
     use crate::{
-        crypto::{PrivateKey, PublicKey},
+        crypto::{
+            KeyId, PrivateKey, PublicKey, Signature, SignatureScheme,
+            SignatureValue, Signer,
+        },
         models::{
             byproducts::ByProducts,
             inspection::Inspection,
             rule::{Artifact, ArtifactRule},
             step::{Command, Step},
-            LayoutMetadataBuilder, LinkMetadataBuilder, Metablock,
-            VirtualTargetPath,
+            LayoutMetadataBuilder, LinkMetadata, LinkMetadataBuilder,
+            Metablock, VirtualTargetPath,
         },
+        Error, Result, VerificationReport,
     };
 
-    use super::MetablockBuilder;
+    use super::{
+        peek_type, AnyMetablock, MetablockBuilder, MetadataType,
+        MetadataWrapper, RawMetablock,
+    };
+    use crate::interchange::{DataInterchange, Json};
+    use crate::models::{Envelope, PAYLOAD_TYPE_IN_TOTO};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
 
-    const ALICE_PRIVATE_KEY: &'static [u8] =
+    const ALICE_PRIVATE_KEY: &[u8] =
         include_bytes!("../../tests/ed25519/ed25519-1");
-    const ALICE_PUB_KEY: &'static [u8] =
+    const ALICE_PUB_KEY: &[u8] =
         include_bytes!("../../tests/ed25519/ed25519-1.pub");
-    const BOB_PUB_KEY: &'static [u8] =
+    const BOB_PUB_KEY: &[u8] =
         include_bytes!("../../tests/rsa/rsa-4096.spki.der");
-    const OWNER_PRIVATE_KEY: &'static [u8] =
+    const RSA_2048_PK8: &[u8] =
+        include_bytes!("../../tests/rsa/rsa-2048.pk8.der");
+    const RSA_2048_SPKI: &[u8] =
+        include_bytes!("../../tests/rsa/rsa-2048.spki.der");
+    const OWNER_PRIVATE_KEY: &[u8] =
         include_bytes!("../../tests/test_metadata/owner.der");
 
     #[test]
@@ -343,6 +726,83 @@ mod tests {
         assert!(serde_json::from_slice::<Metablock>(&raw).is_ok());
     }
 
+    #[test]
+    fn dispatch_on_document_type() {
+        let layout = fs::read("tests/test_metadata/demo.layout").unwrap();
+        assert_eq!(peek_type::<Json>(&layout).unwrap(), "layout");
+        assert!(matches!(
+            AnyMetablock::from_bytes::<Json>(&layout).unwrap(),
+            AnyMetablock::Layout(m) if m.metadata.typ() == MetadataType::Layout
+        ));
+
+        let link = fs::read("tests/test_metadata/demo.link").unwrap();
+        assert_eq!(peek_type::<Json>(&link).unwrap(), "link");
+        assert!(matches!(
+            AnyMetablock::from_bytes::<Json>(&link).unwrap(),
+            AnyMetablock::Link(m) if m.metadata.typ() == MetadataType::Link
+        ));
+
+        let statement = json!({
+            "_type": "https://in-toto.io/Statement/v1",
+            "subject": [],
+            "predicateType": "https://slsa.dev/provenance/v1",
+            "predicate": {},
+        });
+        assert_eq!(
+            peek_type::<Json>(statement.to_string().as_bytes()).unwrap(),
+            "https://in-toto.io/Statement/v1"
+        );
+        let alice = PrivateKey::from_ed25519(ALICE_PRIVATE_KEY).unwrap();
+        let envelope = Envelope::new(
+            statement.to_string().into_bytes(),
+            PAYLOAD_TYPE_IN_TOTO.to_string(),
+            &[&alice],
+        )
+        .unwrap();
+        let raw = serde_json::to_vec(&envelope).unwrap();
+        assert_eq!(peek_type::<Json>(&raw).unwrap(), PAYLOAD_TYPE_IN_TOTO);
+        assert_eq!(
+            AnyMetablock::from_bytes::<Json>(&raw).unwrap(),
+            AnyMetablock::Statement(envelope)
+        );
+
+        assert!(peek_type::<Json>(br#"{"signed": {}}"#).is_err());
+        assert!(peek_type::<Json>(br#"{"name": "build"}"#).is_err());
+        assert!(AnyMetablock::from_bytes::<Json>(
+            statement.to_string().as_bytes()
+        )
+        .is_err());
+        // A document claiming to be a link has to be one.
+        assert!(AnyMetablock::from_bytes::<Json>(
+            br#"{"signed": {"_type": "link"}, "signatures": []}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parse_typed_metablock() {
+        let raw = fs::read("tests/test_metadata/demo.layout").unwrap();
+        let metablock =
+            Metablock::parse_typed::<Json>(&raw, MetadataType::Layout).unwrap();
+        assert_eq!(metablock.metadata.typ(), MetadataType::Layout);
+        assert!(
+            Metablock::parse_typed::<Json>(&raw, MetadataType::Link).is_err()
+        );
+
+        // The `_type` of the signed metadata has to match its fields.
+        let mut json: serde_json::Value = serde_json::from_slice(
+            &fs::read("tests/test_metadata/demo.link").unwrap(),
+        )
+        .unwrap();
+        assert!(serde_json::from_value::<Metablock>(json.clone()).is_ok());
+        json["signed"]["_type"] = "layout".into();
+        assert!(serde_json::from_value::<Metablock>(json.clone()).is_err());
+        assert!(
+            serde_json::from_value::<LinkMetadata>(json["signed"].clone())
+                .is_err()
+        );
+    }
+
     #[test]
     fn deserialize_link_metablock() {
         let raw = fs::read("tests/test_metadata/demo.link").unwrap();
@@ -361,9 +821,7 @@ mod tests {
             PrivateKey::from_ed25519(OWNER_PRIVATE_KEY).unwrap();
         let layout_metadata = Box::new(
             LayoutMetadataBuilder::new()
-                .expires(Utc.from_utc_datetime(
-                    &NaiveDateTime::from_timestamp_opt(0, 0).unwrap(),
-                ))
+                .expires(DateTime::from_timestamp(0, 0).unwrap())
                 .add_key(alice_public_key.clone())
                 .add_key(bob_public_key.clone())
                 .add_step(
@@ -589,4 +1047,552 @@ mod tests {
         let authorized_keys = vec![&public_key];
         assert!(metablock.verify(1, authorized_keys).is_ok());
     }
+
+    #[test]
+    fn verify_rsa_signatures_of_metablock() {
+        let link_metadata = LinkMetadataBuilder::new()
+            .name("package".into())
+            .add_product(
+                VirtualTargetPath::new("tests/test_link/foo.tar.gz".into())
+                    .unwrap(),
+            )
+            .command(Command::from("tar zcvf foo.tar.gz foo.py"))
+            .build()
+            .unwrap();
+        let rsa_private_key = PrivateKey::from_pkcs8(
+            RSA_2048_PK8,
+            crate::crypto::SignatureScheme::RsaSsaPssSha256,
+        )
+        .unwrap();
+        let metablock =
+            MetablockBuilder::from_metadata(Box::new(link_metadata))
+                .sign(&[&rsa_private_key])
+                .unwrap()
+                .build();
+
+        let public_key = PublicKey::from_spki(
+            RSA_2048_SPKI,
+            crate::crypto::SignatureScheme::RsaSsaPssSha256,
+        )
+        .unwrap();
+        assert!(metablock.verify(1, vec![&public_key]).is_ok());
+
+        // A tampered payload no longer matches the signature.
+        let mut tampered = metablock;
+        tampered.metadata = MetadataWrapper::Link(
+            LinkMetadataBuilder::new()
+                .name("package".into())
+                .command(Command::from("tar zcvf foo.tar.gz evil.py"))
+                .build()
+                .unwrap(),
+        );
+        assert!(tampered.verify(1, vec![&public_key]).is_err());
+    }
+
+    #[test]
+    fn verify_reports_signature_results() {
+        let alice = PrivateKey::from_ed25519(ALICE_PRIVATE_KEY).unwrap();
+        let owner = PrivateKey::from_ed25519(OWNER_PRIVATE_KEY).unwrap();
+        let link_metadata = LinkMetadataBuilder::new()
+            .name("package".into())
+            .command(Command::from("tar zcvf foo.tar.gz foo.py"))
+            .build()
+            .unwrap();
+        let mut metablock =
+            MetablockBuilder::from_metadata(Box::new(link_metadata))
+                .sign(&[&alice, &owner])
+                .unwrap()
+                .build();
+
+        let expected = VerificationReport {
+            threshold: 2,
+            good: vec![alice.key_id().clone()],
+            bad: vec![],
+            unknown: vec![owner.key_id().as_str().to_string()],
+        };
+        assert_eq!(
+            metablock.verify(2, [alice.public()]),
+            Err(Error::SignatureThresholdNotMet(expected))
+        );
+
+        metablock.metadata = MetadataWrapper::Link(
+            LinkMetadataBuilder::new()
+                .name("package".into())
+                .build()
+                .unwrap(),
+        );
+        let expected = VerificationReport {
+            threshold: 1,
+            good: vec![],
+            bad: vec![alice.key_id().clone()],
+            unknown: vec![owner.key_id().as_str().to_string()],
+        };
+        assert_eq!(
+            metablock.verify(1, [alice.public()]),
+            Err(Error::SignatureThresholdNotMet(expected))
+        );
+    }
+
+    /// Signs with an in-memory key, recording each message like a remote
+    /// signer would see it.
+    struct MockSigner {
+        key: PrivateKey,
+        key_id: KeyId,
+        messages: RefCell<Vec<Vec<u8>>>,
+    }
+
+    impl Signer for MockSigner {
+        fn sign(&self, msg: &[u8]) -> Result<Signature> {
+            self.messages.borrow_mut().push(msg.to_vec());
+            let sig = self.key.sign(msg)?;
+            Ok(Signature::new(self.key_id.clone(), sig.value().clone()))
+        }
+
+        fn public_key(&self) -> &PublicKey {
+            self.key.public()
+        }
+    }
+
+    #[test]
+    fn pretty_print_metablock() {
+        let alice = PrivateKey::from_ed25519(ALICE_PRIVATE_KEY).unwrap();
+        let link = LinkMetadataBuilder::new()
+            .name("pretty".into())
+            .add_product(VirtualTargetPath::new("README.md".into()).unwrap())
+            .add_product(VirtualTargetPath::new("Cargo.toml".into()).unwrap())
+            .build()
+            .unwrap();
+        let builder = MetablockBuilder::from_metadata(Box::new(link));
+        let signable = builder.signable_bytes().unwrap();
+        let metablock = builder.sign(&[&alice]).unwrap().build();
+
+        let pretty = metablock.to_pretty_json().unwrap();
+        assert!(pretty.starts_with("{\n  \"signatures\": [\n"));
+        assert!(pretty.find("\"Cargo.toml\"") < pretty.find("\"README.md\""));
+
+        let parsed: Metablock = serde_json::from_str(&pretty).unwrap();
+        assert_eq!(parsed, metablock);
+        assert_eq!(parsed.metadata.to_bytes().unwrap(), signable);
+        assert!(parsed.verify(1, [alice.public()]).is_ok());
+    }
+
+    #[test]
+    fn sign_metablock_offline() {
+        let link = LinkMetadataBuilder::new()
+            .name("offline".into())
+            .build()
+            .unwrap();
+        let builder = MetablockBuilder::from_metadata(Box::new(link));
+        let bytes = builder.signable_bytes().unwrap();
+        assert_eq!(bytes, builder.metadata.to_bytes().unwrap());
+
+        // The offline machine only sees the bytes, and returns the raw
+        // signature of its key.
+        let rsa = PrivateKey::from_pkcs8(
+            RSA_2048_PK8,
+            SignatureScheme::RsaSsaPssSha256,
+        )
+        .unwrap();
+        let raw = rsa.sign(&bytes).unwrap().value().as_bytes().to_vec();
+        let sig =
+            Signature::new(rsa.key_id().clone(), SignatureValue::new(raw));
+
+        let alice = PrivateKey::from_ed25519(ALICE_PRIVATE_KEY).unwrap();
+        let metablock = builder
+            .add_signature(alice.sign(b"something else").unwrap())
+            .add_signature(alice.sign(&bytes).unwrap())
+            .add_signature(sig)
+            .build();
+        assert_eq!(metablock.signatures.len(), 2);
+        assert!(metablock.verify(2, [alice.public(), rsa.public()]).is_ok());
+
+        let metablock =
+            MetablockBuilder::from_metadata(metablock.metadata.into_trait())
+                .add_signature(alice.sign(b"something else").unwrap())
+                .build();
+        assert!(metablock.verify(1, [alice.public()]).is_err());
+    }
+
+    #[test]
+    fn round_trip_unknown_fields() {
+        let alice = PrivateKey::from_ed25519(ALICE_PRIVATE_KEY).unwrap();
+        let link = LinkMetadataBuilder::new()
+            .name("build".into())
+            .build()
+            .unwrap();
+        let mut signed = serde_json::to_value(&link).unwrap();
+        signed["x-builder"] = json!({"id": "ci-7", "log": "a\nb"});
+
+        // signed by a tool that knows the extra field
+        let canonical = Json::canonicalize_bytes(&signed).unwrap();
+        let canonical =
+            String::from_utf8(canonical).unwrap().replace("\\n", "\n");
+        let sig = alice.sign(canonical.as_bytes()).unwrap();
+        let foreign = serde_json::to_vec(&json!({
+            "signatures": [sig],
+            "signed": signed,
+        }))
+        .unwrap();
+
+        let metablock: RawMetablock = serde_json::from_slice(&foreign).unwrap();
+        assert_eq!(metablock.metadata(), &MetadataWrapper::Link(link.clone()));
+        assert_eq!(metablock.signed(), &signed);
+        assert_eq!(
+            metablock.verify(1, [alice.public()]).unwrap(),
+            MetadataWrapper::Link(link.clone())
+        );
+        assert_eq!(serde_json::to_vec(&metablock).unwrap(), foreign);
+
+        // the builder keeps the extra field too
+        let builder = MetablockBuilder::from_raw_metadata(
+            &serde_json::to_vec(&signed).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(builder.signable_bytes().unwrap(), canonical.as_bytes());
+        let resigned = builder.sign(&[&alice]).unwrap().build_raw().unwrap();
+        assert_eq!(resigned.signed(), &signed);
+        assert!(resigned.verify(1, [alice.public()]).is_ok());
+
+        // stripped and signed again with another key
+        let owner = PrivateKey::from_ed25519(OWNER_PRIVATE_KEY).unwrap();
+        let rotated = metablock.clone().into_builder();
+        assert_eq!(rotated.signable_bytes().unwrap(), canonical.as_bytes());
+        let rotated = rotated.sign(&[&owner]).unwrap().build_raw().unwrap();
+        assert_eq!(rotated.signatures().len(), 1);
+        assert_eq!(rotated.signed(), &signed);
+        assert!(rotated.verify(1, [owner.public()]).is_ok());
+        assert!(rotated.verify(1, [alice.public()]).is_err());
+
+        // a Metablock drops the extra field, so the signature fails
+        let typed: Metablock = serde_json::from_slice(&foreign).unwrap();
+        assert_eq!(typed.metadata, MetadataWrapper::Link(link.clone()));
+        assert!(typed.verify(1, [alice.public()]).is_err());
+        let written = serde_json::to_value(&typed).unwrap();
+        assert_eq!(written["signed"].get("x-builder"), None);
+
+        // metadata without unknown fields verifies either way
+        let known = serde_json::to_vec(
+            &Metablock::new(MetadataWrapper::Link(link), &[&alice]).unwrap(),
+        )
+        .unwrap();
+        let raw: RawMetablock = serde_json::from_slice(&known).unwrap();
+        assert!(raw.verify(1, [alice.public()]).is_ok());
+        assert_eq!(
+            serde_json::to_value(&raw).unwrap(),
+            serde_json::from_slice::<serde_json::Value>(&known).unwrap()
+        );
+    }
+
+    #[test]
+    fn verify_strict_rejects_duplicate_key_ids() {
+        let alice = PrivateKey::from_ed25519(ALICE_PRIVATE_KEY).unwrap();
+        let link = MetadataWrapper::Link(
+            LinkMetadataBuilder::new()
+                .name("build".into())
+                .build()
+                .unwrap(),
+        );
+        let mut metablock = Metablock::new(link, &[&alice]).unwrap();
+        assert!(metablock.verify_strict(1, [alice.public()]).is_ok());
+
+        // Another signature claiming the key ID of alice is dropped by
+        // `verify`, but not by `verify_strict`.
+        let other = alice.sign(b"something else").unwrap();
+        metablock.signatures.insert(0, other);
+        assert!(metablock.verify(1, [alice.public()]).is_ok());
+        let err = metablock.verify_strict(1, [alice.public()]).unwrap_err();
+        assert!(matches!(err, Error::VerificationFailure(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn merge_metablock_signatures() {
+        let alice = PrivateKey::from_ed25519(ALICE_PRIVATE_KEY).unwrap();
+        let rsa = PrivateKey::from_pkcs8(
+            RSA_2048_PK8,
+            SignatureScheme::RsaSsaPssSha256,
+        )
+        .unwrap();
+        let link = MetadataWrapper::Link(
+            LinkMetadataBuilder::new()
+                .name("build".into())
+                .build()
+                .unwrap(),
+        );
+        let mut merged = Metablock::new(link.clone(), &[&alice]).unwrap();
+        let others = [
+            Metablock::new(link.clone(), &[&alice]).unwrap(),
+            Metablock::new(link.clone(), &[&rsa]).unwrap(),
+            Metablock::new(link.clone(), &[&alice, &rsa]).unwrap(),
+        ];
+        assert_eq!(merged.merge_all(&others).unwrap(), 1);
+        assert_eq!(merged.signatures.len(), 2);
+        assert!(merged.verify(2, [alice.public(), rsa.public()]).is_ok());
+        assert_eq!(merged.merge_signatures(&others[1]).unwrap(), 0);
+
+        // Nothing is merged if any of the metadata differs.
+        let mut merged = Metablock::new(link.clone(), &[&alice]).unwrap();
+        let other = MetadataWrapper::Link(
+            LinkMetadataBuilder::new()
+                .name("test".into())
+                .build()
+                .unwrap(),
+        );
+        let others = [
+            Metablock::new(link, &[&rsa]).unwrap(),
+            Metablock::new(other, &[&rsa]).unwrap(),
+        ];
+        let err = merged.merge_all(&others).unwrap_err();
+        assert!(err.to_string().contains("Metablock 1"), "{}", err);
+        assert_eq!(merged.signatures.len(), 1);
+    }
+
+    #[test]
+    fn sign_metablock_with_signer() {
+        let alice = PrivateKey::from_ed25519(ALICE_PRIVATE_KEY).unwrap();
+        let signer = MockSigner {
+            key: PrivateKey::from_ed25519(ALICE_PRIVATE_KEY).unwrap(),
+            key_id: alice.key_id().clone(),
+            messages: RefCell::new(Vec::new()),
+        };
+        let link_metadata = LinkMetadataBuilder::new()
+            .name("package".into())
+            .command(Command::from("tar zcvf foo.tar.gz foo.py"))
+            .build()
+            .unwrap();
+        let builder =
+            || MetablockBuilder::from_metadata(Box::new(link_metadata.clone()));
+
+        let metablock = builder().sign_with(&signer).unwrap().build();
+        assert_eq!(signer.messages.borrow().len(), 1);
+        assert!(metablock.verify(1, [alice.public()]).is_ok());
+        assert_eq!(metablock, builder().sign(&[&alice]).unwrap().build());
+
+        // PrivateKey is a Signer too.
+        let owner = PrivateKey::from_ed25519(OWNER_PRIVATE_KEY).unwrap();
+        let metablock = builder()
+            .sign_with(&signer)
+            .unwrap()
+            .sign_with(&owner)
+            .unwrap()
+            .build();
+        assert!(metablock
+            .verify(2, [alice.public(), owner.public()])
+            .is_ok());
+
+        let liar = MockSigner {
+            key_id: owner.key_id().clone(),
+            ..signer
+        };
+        assert!(builder().sign_with(&liar).is_err());
+    }
+
+    fn signed_link() -> Metablock {
+        let private_key = PrivateKey::from_ed25519(ALICE_PRIVATE_KEY).unwrap();
+        let link_metadata = LinkMetadataBuilder::new()
+            .name("package".into())
+            .command(Command::from("tar zcvf foo.tar.gz foo.py"))
+            .build()
+            .unwrap();
+        MetablockBuilder::from_metadata(Box::new(link_metadata))
+            .sign(&[&private_key])
+            .unwrap()
+            .build()
+    }
+
+    #[test]
+    fn verify_batch() {
+        let alice = PrivateKey::from_ed25519(ALICE_PRIVATE_KEY).unwrap();
+        let owner = PrivateKey::from_ed25519(OWNER_PRIVATE_KEY).unwrap();
+        let valid = signed_link();
+        let mut tampered = valid.clone();
+        if let MetadataWrapper::Link(link) = &mut tampered.metadata {
+            link.name = "build".into();
+        }
+        let by_owner = MetablockBuilder::from_metadata(Box::new(
+            LinkMetadataBuilder::new()
+                .name("build".into())
+                .build()
+                .unwrap(),
+        ))
+        .sign(&[&owner])
+        .unwrap()
+        .build();
+
+        let items = vec![
+            (valid.clone(), 1),
+            (tampered, 1),
+            (by_owner, 1),
+            (valid.clone(), 2),
+            (valid.clone(), 0),
+            (valid.clone(), 1),
+        ];
+        let results =
+            Metablock::verify_batch(&items, &[alice.public().clone()]);
+        assert_eq!(results.len(), items.len());
+        for (i, (result, (metablock, threshold))) in
+            results.iter().zip(&items).enumerate()
+        {
+            let expected = metablock.verify(*threshold, [alice.public()]);
+            assert_eq!(result.is_ok(), expected.is_ok(), "item {}", i);
+            assert_eq!(
+                result.as_ref().err().map(ToString::to_string),
+                expected.err().map(|e| e.to_string()),
+                "item {}",
+                i
+            );
+        }
+        assert!(results[0].is_ok() && results[5].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(Error::SignatureThresholdNotMet(_))
+        ));
+        assert!(matches!(results[4], Err(Error::VerificationFailure(_))));
+        assert_eq!(
+            results[0].as_ref().unwrap(),
+            &valid.verify(1, [alice.public()]).unwrap()
+        );
+    }
+
+    #[test]
+    fn write_and_read_json_metablock() {
+        let dir = tempfile::tempdir().unwrap();
+        let metablock = signed_link();
+
+        let path = metablock
+            .write_to_dir::<Json>(dir.path(), "package.776a00e2.link")
+            .unwrap();
+        assert_eq!(path, dir.path().join("package.776a00e2.link.json"));
+
+        let read = Metablock::read_from_dir::<Json>(
+            dir.path(),
+            "package.776a00e2.link",
+        )
+        .unwrap();
+        assert_eq!(read, metablock);
+        assert!(
+            Metablock::read_from_dir::<Json>(dir.path(), "missing").is_err()
+        );
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn write_and_read_cbor_metablock() {
+        use crate::interchange::Cbor;
+
+        let dir = tempfile::tempdir().unwrap();
+        let metablock = signed_link();
+
+        let path = metablock.write_to_dir::<Cbor>(dir.path(), "name").unwrap();
+        assert_eq!(path, dir.path().join("name.cbor"));
+        assert!(!dir.path().join("name.json").exists());
+
+        let read =
+            Metablock::read_from_dir::<Cbor>(dir.path(), "name").unwrap();
+        assert_eq!(read, metablock);
+        let public_key = PrivateKey::from_ed25519(ALICE_PRIVATE_KEY)
+            .unwrap()
+            .public()
+            .clone();
+        assert!(read.verify(1, [&public_key]).is_ok());
+    }
+
+    /// Apply a few random edits to `bytes`, favoring the characters that
+    /// structure JSON documents.
+    fn mutate(rng: &mut StdRng, bytes: &[u8]) -> Vec<u8> {
+        const TOKENS: &[&[u8]] = &[
+            b"[", b"]", b"{", b"}", b"\"", b"\\", b":", b",", b"\\u",
+            b"\\ud800", b"0", b"-1", b"1e999", b"null", b"\xff", b"\t",
+        ];
+        let mut bytes = bytes.to_vec();
+        for _ in 0..rng.gen_range(1..8) {
+            let at = rng.gen_range(0..=bytes.len());
+            match rng.gen_range(0..5) {
+                0 if at < bytes.len() => bytes[at] = rng.gen(),
+                1 => {
+                    let end = rng.gen_range(at..=bytes.len());
+                    bytes.drain(at..end);
+                }
+                2 => {
+                    let end = rng.gen_range(at..=bytes.len().min(at + 64));
+                    let copy = bytes[at..end].to_vec();
+                    let to = rng.gen_range(0..=bytes.len());
+                    bytes.splice(to..to, copy);
+                }
+                3 => bytes.truncate(at),
+                _ => {
+                    let token = TOKENS[rng.gen_range(0..TOKENS.len())];
+                    bytes.splice(at..at, token.iter().copied());
+                }
+            }
+        }
+        bytes
+    }
+
+    /// Parse `bytes` as a metablock with `D` and use whatever comes out.
+    fn parse_untrusted<D: DataInterchange>(bytes: &[u8], key: &PublicKey) {
+        if let Ok(metablock) = D::from_slice::<Metablock>(bytes) {
+            let _ = metablock.verify(1, [key]);
+            let _ = metablock.metadata.to_bytes();
+        }
+        let _ = AnyMetablock::from_bytes::<D>(bytes);
+    }
+
+    #[test]
+    fn deserialize_mutated_metadata() {
+        let key = PrivateKey::from_ed25519(ALICE_PRIVATE_KEY).unwrap();
+        let key = key.public();
+        let json = [
+            fs::read("tests/test_metadata/demo.layout").unwrap(),
+            fs::read("tests/test_metadata/demo.link").unwrap(),
+        ];
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..2000 {
+            for input in &json {
+                parse_untrusted::<Json>(&mutate(&mut rng, input), key);
+            }
+        }
+
+        #[cfg(feature = "cbor")]
+        {
+            use crate::interchange::Cbor;
+            let cbor: Vec<Vec<u8>> = json
+                .iter()
+                .map(|input| {
+                    let metablock: Metablock =
+                        serde_json::from_slice(input).unwrap();
+                    let mut bytes = Vec::new();
+                    Cbor::to_writer(&mut bytes, &metablock).unwrap();
+                    bytes
+                })
+                .collect();
+            for _ in 0..2000 {
+                for input in &cbor {
+                    parse_untrusted::<Cbor>(&mutate(&mut rng, input), key);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn deserialize_deeply_nested_metadata() {
+        let nested = "[".repeat(100_000);
+        let json = format!(r#"{{"signatures": [], "signed": {}}}"#, nested);
+        assert!(matches!(
+            Json::from_slice::<Metablock>(json.as_bytes()),
+            Err(Error::Encoding(_))
+        ));
+
+        #[cfg(feature = "cbor")]
+        {
+            let mut cbor = vec![0xa2, 0x6a];
+            cbor.extend(b"signatures");
+            cbor.extend([0x80, 0x66]);
+            cbor.extend(b"signed");
+            cbor.extend([0x81].repeat(100_000));
+            assert!(matches!(
+                crate::interchange::Cbor::from_slice::<Metablock>(&cbor),
+                Err(Error::Encoding(_))
+            ));
+        }
+    }
 }
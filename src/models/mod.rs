@@ -1,19 +1,30 @@
 //! Models used in in-toto
 
 mod envelope;
-mod helpers;
+pub mod helpers;
 mod layout;
 mod link;
 mod metadata;
 mod predicate;
+mod resource_descriptor;
+#[cfg(feature = "schema")]
+pub mod schema;
 mod statement;
 
+pub use envelope::{DsseSignature, Envelope, PAYLOAD_TYPE_IN_TOTO};
 pub use helpers::*;
 pub use layout::*;
 pub use link::*;
 pub use metadata::*;
-pub use predicate::{PredicateLayout, PredicateVer, PredicateWrapper};
-pub use statement::{StatementVer, StatementWrapper};
+pub use predicate::{
+    CycloneDxPredicate, LinkV02, Predicate, PredicateLayout, PredicateVer,
+    PredicateWrapper, SpdxPredicate, PREDICATE_TYPE_CYCLONEDX,
+    PREDICATE_TYPE_SPDX,
+};
+pub use resource_descriptor::{ResourceDescriptor, ResourceDescriptorBuilder};
+pub use statement::{
+    Statement, StatementVer, StatementWrapper, STATEMENT_TYPE_V1,
+};
 
 #[cfg(test)]
 mod test {
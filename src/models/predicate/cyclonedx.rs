@@ -0,0 +1,39 @@
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::Predicate;
+
+/// The `predicateType` of a [`CycloneDxPredicate`].
+pub const PREDICATE_TYPE_CYCLONEDX: &str = "https://cyclonedx.org/bom";
+
+/// Predicate `CycloneDxPredicate` carries a
+/// [CycloneDX](https://cyclonedx.org/) SBOM as its predicate.
+///
+/// The SBOM is kept as an opaque JSON document: it is neither parsed nor
+/// validated, and serializes to exactly the document it was created from.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(transparent)]
+pub struct CycloneDxPredicate(Value);
+
+impl CycloneDxPredicate {
+    /// Wrap the CycloneDX JSON document `sbom`.
+    pub fn new(sbom: Value) -> Self {
+        CycloneDxPredicate(sbom)
+    }
+
+    /// The CycloneDX JSON document.
+    pub fn sbom(&self) -> &Value {
+        &self.0
+    }
+
+    /// Unwrap the CycloneDX JSON document.
+    pub fn into_sbom(self) -> Value {
+        self.0
+    }
+}
+
+impl Predicate for CycloneDxPredicate {
+    fn predicate_type(&self) -> String {
+        PREDICATE_TYPE_CYCLONEDX.to_string()
+    }
+}
@@ -5,8 +5,9 @@ use serde_derive::{Deserialize, Serialize};
 use super::{PredicateLayout, PredicateVer, PredicateWrapper};
 use crate::interchange::{DataInterchange, Json};
 use crate::models::byproducts::ByProducts;
+use crate::models::helpers::serialize_artifacts;
 use crate::models::step::Command;
-use crate::models::{TargetDescription, VirtualTargetPath};
+use crate::models::{LinkMetadata, TargetDescription, VirtualTargetPath};
 use crate::Result;
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -17,6 +18,7 @@ use crate::Result;
 /// can be used together with most states.
 pub struct LinkV02 {
     name: String,
+    #[serde(serialize_with = "serialize_artifacts")]
     materials: BTreeMap<VirtualTargetPath, TargetDescription>,
     env: Option<BTreeMap<String, String>>,
     command: Command,
@@ -37,6 +39,41 @@ impl PredicateLayout for LinkV02 {
     }
 }
 
+impl LinkV02 {
+    /// Rebuild the link this predicate was created from, given its
+    /// `products`.
+    pub(crate) fn into_link(
+        self,
+        products: BTreeMap<VirtualTargetPath, TargetDescription>,
+    ) -> Result<LinkMetadata> {
+        LinkMetadata::new(
+            self.name,
+            self.materials,
+            products,
+            self.env,
+            self.byproducts,
+            self.command,
+        )
+    }
+}
+
+impl From<LinkMetadata> for LinkV02 {
+    /// Take everything but the products from `meta`; those are the subject
+    /// of the statement carrying this predicate.
+    fn from(meta: LinkMetadata) -> Self {
+        LinkV02 {
+            name: meta.name,
+            materials: meta.materials,
+            // Only the variables of a structured environment are kept.
+            env: meta.env.or_else(|| {
+                meta.environment.and_then(|env| env.variables().cloned())
+            }),
+            command: meta.command,
+            byproducts: meta.byproducts,
+        }
+    }
+}
+
 #[cfg(test)]
 pub mod test {
     use std::collections::BTreeMap;
@@ -50,8 +87,8 @@ pub mod test {
     use crate::{
         interchange::{DataInterchange, Json},
         models::{
-            byproducts::ByProducts, PredicateLayout, PredicateVer,
-            PredicateWrapper,
+            byproducts::ByProducts, LinkMetadataBuilder, PredicateLayout,
+            PredicateVer, PredicateWrapper,
         },
     };
 
@@ -95,7 +132,13 @@ pub mod test {
 
     #[test]
     fn create_predicate_from_meta() {
-        // TODO: convert from metadata is no supported recentely
+        let meta = LinkMetadataBuilder::new()
+            .byproducts(PREDICATE_LINK_V02.byproducts.clone())
+            .build()
+            .unwrap();
+        let predicate = LinkV02::from(meta);
+
+        assert_eq!(predicate, *PREDICATE_LINK_V02);
     }
 
     #[test]
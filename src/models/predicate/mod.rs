@@ -1,16 +1,22 @@
 //! in-toto link
 
+pub mod cyclonedx;
 pub mod link_v02;
 pub mod slsa_provenance_v01;
 pub mod slsa_provenance_v02;
+pub mod spdx;
 use std::convert::TryFrom;
 
+pub use cyclonedx::{CycloneDxPredicate, PREDICATE_TYPE_CYCLONEDX};
 pub use link_v02::LinkV02;
 use serde_json::Value;
 pub use slsa_provenance_v01::SLSAProvenanceV01;
 pub use slsa_provenance_v02::SLSAProvenanceV02;
+pub use spdx::{SpdxPredicate, PREDICATE_TYPE_SPDX};
 
-use serde::de::{Deserialize, Deserializer, Error as DeserializeError};
+use serde::de::{
+    Deserialize, DeserializeOwned, Deserializer, Error as DeserializeError,
+};
 use serde::ser::{Serialize, Serializer};
 use serde_derive::Serialize;
 use strum::IntoEnumIterator;
@@ -148,3 +154,44 @@ pub trait PredicateLayout {
     /// Standard serialize for PredicateLayout
     fn to_bytes(&self) -> Result<Vec<u8>>;
 }
+
+/// A typed predicate that can be carried by a
+/// [`Statement`](crate::models::Statement).
+pub trait Predicate: Serialize + DeserializeOwned {
+    /// The URI identifying the type of this predicate, used as the
+    /// `predicateType` of the statement.
+    fn predicate_type(&self) -> String;
+}
+
+impl Predicate for PredicateWrapper {
+    fn predicate_type(&self) -> String {
+        match self {
+            PredicateWrapper::LinkV0_2(_) => PredicateVer::LinkV0_2,
+            PredicateWrapper::SLSAProvenanceV0_1(_) => {
+                PredicateVer::SLSAProvenanceV0_1
+            }
+            PredicateWrapper::SLSAProvenanceV0_2(_) => {
+                PredicateVer::SLSAProvenanceV0_2
+            }
+        }
+        .into()
+    }
+}
+
+impl Predicate for LinkV02 {
+    fn predicate_type(&self) -> String {
+        PredicateVer::LinkV0_2.into()
+    }
+}
+
+impl Predicate for SLSAProvenanceV01 {
+    fn predicate_type(&self) -> String {
+        PredicateVer::SLSAProvenanceV0_1.into()
+    }
+}
+
+impl Predicate for SLSAProvenanceV02 {
+    fn predicate_type(&self) -> String {
+        PredicateVer::SLSAProvenanceV0_2.into()
+    }
+}
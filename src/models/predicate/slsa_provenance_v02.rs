@@ -1,10 +1,9 @@
 use std::collections::HashMap;
 
 use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
 
-use super::slsa_provenance_v01::{
-    Builder, Material, ProvenanceMetadata, TypeURI,
-};
+use super::slsa_provenance_v01::{Builder, Material, TimeStamp, TypeURI};
 use super::{PredicateLayout, PredicateVer, PredicateWrapper};
 use crate::interchange::{DataInterchange, Json};
 use crate::Result;
@@ -27,9 +26,40 @@ pub struct Invocation {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub config_source: Option<ConfigSource>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub parameters: Option<String>,
+    pub parameters: Option<Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub environment: Option<String>,
+    pub environment: Option<Value>,
+}
+
+/// Unlike v0.1, completeness in v0.2 is reported for the invocation
+/// `parameters` rather than the recipe `arguments`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Completeness {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub environment: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub materials: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ProvenanceMetadata {
+    #[serde(rename = "buildInvocationId")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_invocation_id: Option<String>,
+    #[serde(rename = "buildStartedOn")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_started_on: Option<TimeStamp>,
+    #[serde(rename = "buildFinishedOn")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_finished_on: Option<TimeStamp>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completeness: Option<Completeness>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reproducible: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -46,7 +76,7 @@ pub struct SLSAProvenanceV02 {
     pub invocation: Option<Invocation>,
     #[serde(rename = "buildConfig")]
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub build_config: Option<String>,
+    pub build_config: Option<Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub metadata: Option<ProvenanceMetadata>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -76,13 +106,14 @@ pub mod test {
     use serde_json::{json, Value};
     use strum::IntoEnumIterator;
 
-    use super::{ConfigSource, Invocation, SLSAProvenanceV02};
+    use super::{
+        Completeness, ConfigSource, Invocation, ProvenanceMetadata,
+        SLSAProvenanceV02,
+    };
     use crate::{
         interchange::{DataInterchange, Json},
         models::{
-            predicate::slsa_provenance_v01::{
-                Builder, Completeness, Material, ProvenanceMetadata, TypeURI,
-            },
+            predicate::slsa_provenance_v01::{Builder, Material, TypeURI},
             PredicateLayout, PredicateVer, PredicateWrapper,
         },
     };
@@ -153,7 +184,7 @@ pub mod test {
                 build_started_on: None,
                 build_finished_on: None,
                 completeness: Some(Completeness {
-                    arguments: None,
+                    parameters: None,
                     environment: Some(true),
                     materials: None,
                 }),
@@ -241,4 +272,58 @@ pub mod test {
             assert!(predicate.is_err());
         }
     }
+
+    #[test]
+    fn round_trip_slsa_sample() {
+        // Sample provenance in the shape of the v0.2 specification, with
+        // arbitrary JSON objects as parameters, environment and buildConfig.
+        let value = json!({
+            "builder": { "id": "https://github.com/Attestations/GitHubHostedActions@v1" },
+            "buildType": "https://github.com/Attestations/GitHubActionsWorkflow@v1",
+            "invocation": {
+                "configSource": {
+                    "uri": "git+https://github.com/foo/bar.git@refs/heads/main",
+                    "digest": { "sha1": "d6525c840a62b398424a78d792f457477135d0cf" },
+                    "entryPoint": ".github/workflows/build.yaml:build"
+                },
+                "parameters": { "inputs": { "release": "v1.0.0" } },
+                "environment": {
+                    "arch": "amd64",
+                    "env": { "GITHUB_RUN_ID": "1234" }
+                }
+            },
+            "buildConfig": {
+                "steps": [{ "command": ["make", "all"], "env": null }]
+            },
+            "metadata": {
+                "buildInvocationId": "1234-1",
+                "buildStartedOn": "2021-01-23T10:00:00Z",
+                "buildFinishedOn": "2021-01-23T10:05:00Z",
+                "completeness": {
+                    "parameters": true,
+                    "environment": false,
+                    "materials": false
+                },
+                "reproducible": false
+            },
+            "materials": [{
+                "uri": "git+https://github.com/foo/bar.git@refs/heads/main",
+                "digest": { "sha1": "d6525c840a62b398424a78d792f457477135d0cf" }
+            }]
+        });
+
+        let predicate =
+            PredicateWrapper::try_from_value(value.clone()).unwrap();
+        let provenance = match &predicate {
+            PredicateWrapper::SLSAProvenanceV0_2(provenance) => provenance,
+            other => panic!("expected SLSA provenance v0.2, got {:?}", other),
+        };
+        let invocation = provenance.invocation.as_ref().unwrap();
+        assert_eq!(
+            invocation.parameters,
+            Some(json!({ "inputs": { "release": "v1.0.0" } }))
+        );
+
+        assert_eq!(serde_json::to_value(&predicate).unwrap(), value);
+    }
 }
@@ -0,0 +1,99 @@
+use std::convert::TryFrom;
+
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::Predicate;
+use crate::{Error, Result};
+
+/// The `predicateType` of an [`SpdxPredicate`].
+pub const PREDICATE_TYPE_SPDX: &str = "https://spdx.dev/Document";
+
+/// Predicate `SpdxPredicate` carries an [SPDX](https://spdx.dev/) SBOM as
+/// its predicate.
+///
+/// Apart from the `spdxVersion` and `SPDXID` strings every SPDX document
+/// holds, the SBOM is kept as an opaque JSON document that serializes to
+/// exactly the document it was created from.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(try_from = "Value")]
+pub struct SpdxPredicate(Value);
+
+impl SpdxPredicate {
+    /// Wrap the SPDX JSON document `sbom`, failing if it is not an object
+    /// with string `spdxVersion` and `SPDXID` fields.
+    pub fn new(sbom: Value) -> Result<Self> {
+        for field in ["spdxVersion", "SPDXID"] {
+            if !sbom.get(field).is_some_and(Value::is_string) {
+                return Err(Error::Encoding(format!(
+                    "SPDX document has no {:?} string",
+                    field
+                )));
+            }
+        }
+        Ok(SpdxPredicate(sbom))
+    }
+
+    /// The version of the SPDX specification the document follows.
+    pub fn spdx_version(&self) -> &str {
+        self.0["spdxVersion"].as_str().unwrap_or_default()
+    }
+
+    /// The identifier of the document.
+    pub fn spdx_id(&self) -> &str {
+        self.0["SPDXID"].as_str().unwrap_or_default()
+    }
+
+    /// The SPDX JSON document.
+    pub fn sbom(&self) -> &Value {
+        &self.0
+    }
+
+    /// Unwrap the SPDX JSON document.
+    pub fn into_sbom(self) -> Value {
+        self.0
+    }
+}
+
+impl TryFrom<Value> for SpdxPredicate {
+    type Error = Error;
+
+    fn try_from(sbom: Value) -> Result<Self> {
+        SpdxPredicate::new(sbom)
+    }
+}
+
+impl Predicate for SpdxPredicate {
+    fn predicate_type(&self) -> String {
+        PREDICATE_TYPE_SPDX.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::SpdxPredicate;
+
+    #[test]
+    fn require_spdx_fields() {
+        let sbom = json!({
+            "spdxVersion": "SPDX-2.3",
+            "SPDXID": "SPDXRef-DOCUMENT",
+        });
+        let predicate = SpdxPredicate::new(sbom.clone()).unwrap();
+        assert_eq!(predicate.spdx_version(), "SPDX-2.3");
+        assert_eq!(predicate.spdx_id(), "SPDXRef-DOCUMENT");
+        assert_eq!(serde_json::to_value(&predicate).unwrap(), sbom);
+
+        for sbom in [
+            json!({ "SPDXID": "SPDXRef-DOCUMENT" }),
+            json!({ "spdxVersion": "SPDX-2.3" }),
+            json!({ "spdxVersion": 2, "SPDXID": "SPDXRef-DOCUMENT" }),
+            json!(["SPDX-2.3", "SPDXRef-DOCUMENT"]),
+        ] {
+            assert!(SpdxPredicate::new(sbom.clone()).is_err());
+            assert!(serde_json::from_value::<SpdxPredicate>(sbom).is_err());
+        }
+    }
+}
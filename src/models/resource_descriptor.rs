@@ -0,0 +1,302 @@
+//! Resource descriptors of the in-toto attestation framework.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::crypto::{HashAlgorithm, HashValue};
+use crate::models::TargetDescription;
+use crate::{Error, Result};
+
+/// A software artifact or resource referred to by an attestation, e.g. a
+/// subject of a [`Statement`](crate::models::Statement).
+///
+/// The digest maps algorithm names such as `sha256` to lowercase hex
+/// encoded values. At least one of `uri`, `digest` or `content` is always
+/// set; use [`ResourceDescriptorBuilder`] to create one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawResourceDescriptor")]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceDescriptor {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    name: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    uri: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    digest: BTreeMap<String, String>,
+    #[serde(
+        skip_serializing_if = "Vec::is_empty",
+        with = "crate::format_base64"
+    )]
+    content: Vec<u8>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    download_location: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    media_type: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    annotations: BTreeMap<String, Value>,
+}
+
+impl ResourceDescriptor {
+    /// The name of the resource. May be empty.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The URI identifying the resource. May be empty.
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    /// The digests of the resource, keyed by algorithm.
+    pub fn digest(&self) -> &BTreeMap<String, String> {
+        &self.digest
+    }
+
+    /// The contents of the resource. May be empty.
+    pub fn content(&self) -> &[u8] {
+        &self.content
+    }
+
+    /// Where the resource can be downloaded from. May be empty.
+    pub fn download_location(&self) -> &str {
+        &self.download_location
+    }
+
+    /// The media type of the resource. May be empty.
+    pub fn media_type(&self) -> &str {
+        &self.media_type
+    }
+
+    /// Additional information about the resource.
+    pub fn annotations(&self) -> &BTreeMap<String, Value> {
+        &self.annotations
+    }
+
+    /// The digests of the resource as the hashes of a target. Digests of
+    /// algorithms this crate does not know are kept as
+    /// [`HashAlgorithm::Unknown`].
+    pub(crate) fn target_description(&self) -> Result<TargetDescription> {
+        self.digest
+            .iter()
+            .map(|(alg, value)| {
+                let alg = HashAlgorithm::return_all()
+                    .remove(alg)
+                    .unwrap_or_else(|| HashAlgorithm::Unknown(alg.clone()));
+                Ok((alg, HashValue::from_hex(value)?))
+            })
+            .collect()
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.uri.is_empty()
+            && self.digest.is_empty()
+            && self.content.is_empty()
+        {
+            return Err(Error::IllegalArgument(
+                "resource descriptor needs one of uri, digest or content"
+                    .into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(rename_all = "camelCase")]
+struct RawResourceDescriptor {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    uri: String,
+    #[serde(default)]
+    digest: BTreeMap<String, String>,
+    #[serde(default, with = "crate::format_base64")]
+    content: Vec<u8>,
+    #[serde(default)]
+    download_location: String,
+    #[serde(default)]
+    media_type: String,
+    #[serde(default)]
+    annotations: BTreeMap<String, Value>,
+}
+
+impl TryFrom<RawResourceDescriptor> for ResourceDescriptor {
+    type Error = Error;
+
+    fn try_from(raw: RawResourceDescriptor) -> Result<Self> {
+        let descriptor = ResourceDescriptor {
+            name: raw.name,
+            uri: raw.uri,
+            digest: raw.digest,
+            content: raw.content,
+            download_location: raw.download_location,
+            media_type: raw.media_type,
+            annotations: raw.annotations,
+        };
+        descriptor.validate()?;
+        Ok(descriptor)
+    }
+}
+
+/// A helper to build a [`ResourceDescriptor`]
+#[derive(Debug, Clone)]
+pub struct ResourceDescriptorBuilder {
+    descriptor: ResourceDescriptor,
+}
+
+impl Default for ResourceDescriptorBuilder {
+    fn default() -> Self {
+        ResourceDescriptorBuilder::new()
+    }
+}
+
+impl ResourceDescriptorBuilder {
+    pub fn new() -> Self {
+        ResourceDescriptorBuilder {
+            descriptor: ResourceDescriptor {
+                name: String::new(),
+                uri: String::new(),
+                digest: BTreeMap::new(),
+                content: Vec::new(),
+                download_location: String::new(),
+                media_type: String::new(),
+                annotations: BTreeMap::new(),
+            },
+        }
+    }
+
+    /// Set the name of the resource
+    pub fn name(mut self, name: String) -> Self {
+        self.descriptor.name = name;
+        self
+    }
+
+    /// Set the URI of the resource
+    pub fn uri(mut self, uri: String) -> Self {
+        self.descriptor.uri = uri;
+        self
+    }
+
+    /// Set the digests of the resource
+    pub fn digest(mut self, digest: BTreeMap<String, String>) -> Self {
+        self.descriptor.digest = digest;
+        self
+    }
+
+    /// Set the digests of the resource from the hashes recorded for a
+    /// target, e.g. a product of a link
+    pub fn target_description(mut self, hashes: &TargetDescription) -> Self {
+        self.descriptor.digest = hashes
+            .iter()
+            .map(|(alg, value)| (alg.name().to_string(), value.to_hex()))
+            .collect();
+        self
+    }
+
+    /// Set the contents of the resource
+    pub fn content(mut self, content: Vec<u8>) -> Self {
+        self.descriptor.content = content;
+        self
+    }
+
+    /// Set where the resource can be downloaded from
+    pub fn download_location(mut self, download_location: String) -> Self {
+        self.descriptor.download_location = download_location;
+        self
+    }
+
+    /// Set the media type of the resource
+    pub fn media_type(mut self, media_type: String) -> Self {
+        self.descriptor.media_type = media_type;
+        self
+    }
+
+    /// Add an annotation about the resource
+    pub fn add_annotation(mut self, key: String, value: Value) -> Self {
+        self.descriptor.annotations.insert(key, value);
+        self
+    }
+
+    /// Build the `ResourceDescriptor`. Fails if none of uri, digest or
+    /// content were set.
+    pub fn build(self) -> Result<ResourceDescriptor> {
+        self.descriptor.validate()?;
+        Ok(self.descriptor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeMap, HashMap};
+
+    use serde_json::json;
+
+    use super::{ResourceDescriptor, ResourceDescriptorBuilder};
+    use crate::crypto::{HashAlgorithm, HashValue};
+
+    #[test]
+    fn serialize_resource_descriptor() {
+        let hashes = HashMap::from([(
+            HashAlgorithm::Sha256,
+            HashValue::new(vec![0x52, 0x94, 0x7c, 0xb7]),
+        )]);
+        let descriptor = ResourceDescriptorBuilder::new()
+            .name("foo.tar.gz".into())
+            .target_description(&hashes)
+            .download_location("https://example.com/foo.tar.gz".into())
+            .media_type("application/gzip".into())
+            .add_annotation("release".into(), json!({ "stable": true }))
+            .build()
+            .unwrap();
+
+        let json = serde_json::to_value(&descriptor).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "name": "foo.tar.gz",
+                "digest": { "sha256": "52947cb7" },
+                "downloadLocation": "https://example.com/foo.tar.gz",
+                "mediaType": "application/gzip",
+                "annotations": { "release": { "stable": true } }
+            })
+        );
+
+        let decoded: ResourceDescriptor = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded, descriptor);
+    }
+
+    #[test]
+    fn content_only_resource_descriptor() {
+        let descriptor = ResourceDescriptorBuilder::new()
+            .content(b"hello".to_vec())
+            .build()
+            .unwrap();
+        let json = serde_json::to_value(&descriptor).unwrap();
+        assert_eq!(json, json!({ "content": "aGVsbG8=" }));
+        assert_eq!(
+            serde_json::from_value::<ResourceDescriptor>(json).unwrap(),
+            descriptor
+        );
+    }
+
+    #[test]
+    fn reject_unidentified_resource_descriptor() {
+        assert!(ResourceDescriptorBuilder::new()
+            .name("foo".into())
+            .digest(BTreeMap::new())
+            .build()
+            .is_err());
+        assert!(serde_json::from_value::<ResourceDescriptor>(
+            json!({ "name": "foo", "mediaType": "text/plain" })
+        )
+        .is_err());
+        assert!(serde_json::from_value::<ResourceDescriptor>(
+            json!({ "name": "foo", "uri": "pkg:cargo/foo@1.0.0" })
+        )
+        .is_ok());
+    }
+}
@@ -0,0 +1,475 @@
+//! JSON schemas of signed links and layouts, to check metadata before it is
+//! deserialized and report precisely which field is missing or has the
+//! wrong type, instead of the first error serde runs into.
+//!
+//! The schemas describe what this crate accepts, which is what in-toto
+//! implementations write. They are checked by a small validator for the
+//! part of JSON Schema they use, not by a general JSON Schema
+//! implementation. It supports these keywords only:
+//! * `$ref`, to a schema under `#/definitions`
+//! * `type`, a name or a list of names
+//! * `const`
+//! * `properties`, `required` and `additionalProperties`
+//! * `items` with a single schema, and `minItems`
+//! * `minimum`
+//!
+//! Annotations such as `$schema` and `title` are ignored, as is any other
+//! keyword.
+
+use serde_json::Value;
+
+use crate::models::MetadataType;
+use crate::{Error, Result};
+
+/// The schema of a signed link.
+pub const LINK_SCHEMA: &str = r##"{
+  "$schema": "http://json-schema.org/draft-07/schema#",
+  "title": "in-toto link",
+  "type": "object",
+  "required": ["signatures", "signed"],
+  "properties": {
+    "signatures": {
+      "type": "array",
+      "items": { "$ref": "#/definitions/signature" }
+    },
+    "signed": { "$ref": "#/definitions/link" }
+  },
+  "definitions": {
+    "signature": {
+      "type": "object",
+      "required": ["keyid", "sig"],
+      "properties": {
+        "keyid": { "type": "string" },
+        "sig": { "type": "string" },
+        "cert": { "type": "string" }
+      }
+    },
+    "artifacts": {
+      "type": "object",
+      "additionalProperties": {
+        "type": "object",
+        "additionalProperties": { "type": "string" }
+      }
+    },
+    "link": {
+      "type": "object",
+      "required": [
+        "_type", "name", "materials", "products", "byproducts", "command"
+      ],
+      "properties": {
+        "_type": { "const": "link" },
+        "spec_version": { "type": "string" },
+        "name": { "type": "string" },
+        "materials": { "$ref": "#/definitions/artifacts" },
+        "products": { "$ref": "#/definitions/artifacts" },
+        "environment": {
+          "type": ["object", "null"],
+          "additionalProperties": { "type": ["string", "object"] }
+        },
+        "byproducts": {
+          "type": "object",
+          "properties": {
+            "return-value": { "type": ["integer", "null"] },
+            "stderr": { "type": ["string", "null"] },
+            "stdout": { "type": ["string", "null"] }
+          },
+          "additionalProperties": { "type": "string" }
+        },
+        "command": { "type": "array", "items": { "type": "string" } }
+      }
+    }
+  }
+}"##;
+
+/// The schema of a signed layout.
+pub const LAYOUT_SCHEMA: &str = r##"{
+  "$schema": "http://json-schema.org/draft-07/schema#",
+  "title": "in-toto layout",
+  "type": "object",
+  "required": ["signatures", "signed"],
+  "properties": {
+    "signatures": {
+      "type": "array",
+      "items": { "$ref": "#/definitions/signature" }
+    },
+    "signed": { "$ref": "#/definitions/layout" }
+  },
+  "definitions": {
+    "signature": {
+      "type": "object",
+      "required": ["keyid", "sig"],
+      "properties": {
+        "keyid": { "type": "string" },
+        "sig": { "type": "string" },
+        "cert": { "type": "string" }
+      }
+    },
+    "strings": { "type": "array", "items": { "type": "string" } },
+    "key": {
+      "type": "object",
+      "required": ["keytype", "scheme", "keyval"],
+      "properties": {
+        "keytype": { "type": "string" },
+        "scheme": { "type": "string" },
+        "keyid": { "type": "string" },
+        "keyid_hash_algorithms": { "$ref": "#/definitions/strings" },
+        "keyval": {
+          "type": "object",
+          "required": ["public"],
+          "properties": {
+            "public": { "type": "string" },
+            "private": { "type": "string" }
+          }
+        }
+      }
+    },
+    "rules": {
+      "type": "array",
+      "items": {
+        "type": "array",
+        "minItems": 2,
+        "items": { "type": "string" }
+      }
+    },
+    "step": {
+      "type": "object",
+      "required": [
+        "_type", "name", "threshold", "expected_materials",
+        "expected_products", "pubkeys", "expected_command"
+      ],
+      "properties": {
+        "_type": { "type": "string" },
+        "name": { "type": "string" },
+        "threshold": { "type": "integer", "minimum": 0 },
+        "expected_materials": { "$ref": "#/definitions/rules" },
+        "expected_products": { "$ref": "#/definitions/rules" },
+        "pubkeys": { "$ref": "#/definitions/strings" },
+        "expected_command": { "$ref": "#/definitions/strings" }
+      }
+    },
+    "inspection": {
+      "type": "object",
+      "required": [
+        "_type", "name", "expected_materials", "expected_products", "run"
+      ],
+      "properties": {
+        "_type": { "type": "string" },
+        "name": { "type": "string" },
+        "expected_materials": { "$ref": "#/definitions/rules" },
+        "expected_products": { "$ref": "#/definitions/rules" },
+        "run": { "$ref": "#/definitions/strings" }
+      }
+    },
+    "layout": {
+      "type": "object",
+      "required": ["_type", "expires", "readme", "keys", "steps", "inspect"],
+      "properties": {
+        "_type": { "const": "layout" },
+        "expires": { "type": "string" },
+        "readme": { "type": "string" },
+        "keys": {
+          "type": "object",
+          "additionalProperties": { "$ref": "#/definitions/key" }
+        },
+        "steps": { "type": "array", "items": { "$ref": "#/definitions/step" } },
+        "inspect": {
+          "type": "array",
+          "items": { "$ref": "#/definitions/inspection" }
+        }
+      }
+    }
+  }
+}"##;
+
+/// The schema of signed metadata of type `typ`.
+pub fn schema(typ: MetadataType) -> &'static str {
+    match typ {
+        MetadataType::Link => LINK_SCHEMA,
+        MetadataType::Layout => LAYOUT_SCHEMA,
+    }
+}
+
+/// Check `document`, a signed link or layout as given by `typ`, against its
+/// schema. The error lists every violation with the path of the field,
+/// e.g. `signed.steps[0].threshold: expected integer, found string`.
+pub fn validate(document: &Value, typ: MetadataType) -> Result<()> {
+    let schema: Value = serde_json::from_str(schema(typ))
+        .expect("the embedded schemas are valid JSON");
+    let mut validator = Validator {
+        root: &schema,
+        errors: Vec::new(),
+    };
+    validator.check(&schema, document, "");
+    if validator.errors.is_empty() {
+        return Ok(());
+    }
+    Err(Error::Encoding(format!(
+        "{} metadata does not match its schema: {}",
+        typ,
+        validator.errors.join("; ")
+    )))
+}
+
+struct Validator<'a> {
+    root: &'a Value,
+    errors: Vec<String>,
+}
+
+impl<'a> Validator<'a> {
+    fn error(&mut self, path: &str, message: String) {
+        let path = if path.is_empty() { "document" } else { path };
+        self.errors.push(format!("{}: {}", path, message));
+    }
+
+    fn check(&mut self, schema: &'a Value, value: &Value, path: &str) {
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            let name = reference.strip_prefix("#/definitions/");
+            match name.and_then(|name| self.root["definitions"].get(name)) {
+                Some(definition) => self.check(definition, value, path),
+                None => self.error(path, format!("bad $ref {}", reference)),
+            }
+            return;
+        }
+
+        if let Some(types) = schema.get("type") {
+            let types: Vec<&str> = match types {
+                Value::Array(types) => {
+                    types.iter().filter_map(Value::as_str).collect()
+                }
+                typ => typ.as_str().into_iter().collect(),
+            };
+            if !types.iter().any(|typ| has_type(value, typ)) {
+                self.error(
+                    path,
+                    format!(
+                        "expected {}, found {}",
+                        types.join(" or "),
+                        type_name(value)
+                    ),
+                );
+                return;
+            }
+        }
+
+        if let Some(expected) = schema.get("const") {
+            if value != expected {
+                self.error(
+                    path,
+                    format!("expected {}, found {}", expected, value),
+                );
+            }
+        }
+
+        if let (Some(minimum), Some(n)) = (
+            schema.get("minimum").and_then(Value::as_f64),
+            value.as_f64(),
+        ) {
+            if n < minimum {
+                self.error(path, format!("{} is less than {}", n, minimum));
+            }
+        }
+
+        if let Value::Object(object) = value {
+            let properties =
+                schema.get("properties").and_then(Value::as_object);
+            for field in schema
+                .get("required")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+            {
+                if !object.contains_key(field) {
+                    self.error(path, format!("missing field {:?}", field));
+                }
+            }
+            for (field, value) in object {
+                let field_path = if path.is_empty() {
+                    field.clone()
+                } else {
+                    format!("{}.{}", path, field)
+                };
+                match properties.and_then(|properties| properties.get(field)) {
+                    Some(schema) => self.check(schema, value, &field_path),
+                    None => {
+                        if let Some(schema) = schema.get("additionalProperties")
+                        {
+                            self.check(schema, value, &field_path);
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Value::Array(items) = value {
+            if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+                if (items.len() as u64) < min {
+                    self.error(
+                        path,
+                        format!(
+                            "expected at least {} items, found {}",
+                            min,
+                            items.len()
+                        ),
+                    );
+                }
+            }
+            if let Some(schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    self.check(schema, item, &format!("{}[{}]", path, i));
+                }
+            }
+        }
+    }
+}
+
+fn has_type(value: &Value, typ: &str) -> bool {
+    match typ {
+        "integer" => value.is_i64() || value.is_u64(),
+        typ => {
+            type_name(value) == typ || (typ == "number" && value.is_number())
+        }
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::interchange::Json;
+    use crate::models::Metablock;
+
+    fn layout_error(document: &Value) -> String {
+        validate(document, MetadataType::Layout)
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn schemas_accept_fixtures() {
+        for (path, typ) in [
+            ("tests/test_metadata/demo.layout", MetadataType::Layout),
+            ("tests/test_metadata/demo.link", MetadataType::Link),
+            ("tests/interop/python/root.layout", MetadataType::Layout),
+            (
+                "tests/interop/python/clone.776a00e2.link",
+                MetadataType::Link,
+            ),
+            ("tests/interop/sslib/root.layout", MetadataType::Layout),
+        ] {
+            let document: Value =
+                serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+            validate(&document, typ)
+                .unwrap_or_else(|e| panic!("{}: {}", path, e));
+        }
+    }
+
+    #[test]
+    fn malformed_layouts() {
+        let raw = std::fs::read("tests/test_metadata/demo.layout").unwrap();
+        let layout: Value = serde_json::from_slice(&raw).unwrap();
+
+        let mut missing = layout.clone();
+        missing["signed"]["steps"][1]
+            .as_object_mut()
+            .unwrap()
+            .remove("expected_command");
+        assert_eq!(
+            layout_error(&missing),
+            "encoding: layout metadata does not match its schema: \
+             signed.steps[1]: missing field \"expected_command\""
+        );
+
+        let mut wrong_type = layout.clone();
+        wrong_type["signed"]["steps"][0]["threshold"] = json!("1");
+        wrong_type["signed"]["inspect"][0]["run"] = json!("tar xzf foo");
+        let err = layout_error(&wrong_type);
+        assert!(
+            err.contains(
+                "signed.steps[0].threshold: expected integer, found string"
+            ),
+            "{}",
+            err
+        );
+        assert!(
+            err.contains("signed.inspect[0].run: expected array, found string"),
+            "{}",
+            err
+        );
+
+        let mut short_rule = layout.clone();
+        short_rule["signed"]["steps"][0]["expected_products"][0] =
+            json!(["CREATE"]);
+        assert!(layout_error(&short_rule).contains(
+            "signed.steps[0].expected_products[0]: expected at least 2 items"
+        ));
+
+        let mut key = layout;
+        let key_id = key["signed"]["keys"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .next()
+            .unwrap()
+            .clone();
+        key["signed"]["keys"][&key_id]["keyval"]["public"] = json!(null);
+        assert!(layout_error(&key).contains(&format!(
+            "signed.keys.{}.keyval.public: expected string, found null",
+            key_id
+        )));
+    }
+
+    #[test]
+    fn malformed_link() {
+        let raw = std::fs::read("tests/test_metadata/demo.link").unwrap();
+        let mut link: Value = serde_json::from_slice(&raw).unwrap();
+        link["signed"]["products"]["foo.tar.gz"] = json!("0123");
+        link["signed"].as_object_mut().unwrap().remove("byproducts");
+        let err = validate(&link, MetadataType::Link).unwrap_err().to_string();
+        assert!(
+            err.contains("signed: missing field \"byproducts\""),
+            "{}",
+            err
+        );
+        assert!(
+            err.contains(
+                "signed.products.foo.tar.gz: expected object, found string"
+            ),
+            "{}",
+            err
+        );
+
+        // A link is not a layout.
+        assert!(layout_error(&link)
+            .contains("signed._type: expected \"layout\", found \"link\""));
+    }
+
+    #[test]
+    fn parse_typed_checks_schema() {
+        let raw = std::fs::read("tests/test_metadata/demo.layout").unwrap();
+        let mut layout: Value = serde_json::from_slice(&raw).unwrap();
+        layout["signed"]["readme"] = json!(1);
+        let raw = serde_json::to_vec(&layout).unwrap();
+        let err = Metablock::parse_typed::<Json>(&raw, MetadataType::Layout)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("signed.readme: expected string, found integer"),
+            "{}",
+            err
+        );
+    }
+}
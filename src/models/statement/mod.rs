@@ -1,8 +1,10 @@
 pub mod state_naive;
 pub mod state_v01;
+pub mod state_v1;
 use serde_json::Value;
 pub use state_naive::StateNaive;
 pub use state_v01::StateV01;
+pub use state_v1::{Statement, STATEMENT_TYPE_V1};
 
 use std::convert::TryFrom;
 use std::fmt::{Display, Formatter, Result as FmtResult};
@@ -3,6 +3,7 @@ use std::{collections::BTreeMap, fmt::Debug};
 use serde_derive::{Deserialize, Serialize};
 
 use super::{FromMerge, StateLayout, StatementVer, StatementWrapper};
+use crate::models::helpers::serialize_artifacts;
 use crate::models::{LinkMetadata, TargetDescription, VirtualTargetPath};
 use crate::{
     interchange::{DataInterchange, Json},
@@ -19,7 +20,9 @@ pub struct StateNaive {
     #[serde(rename = "_type")]
     typ: String,
     name: String,
+    #[serde(serialize_with = "serialize_artifacts")]
     materials: BTreeMap<VirtualTargetPath, TargetDescription>,
+    #[serde(serialize_with = "serialize_artifacts")]
     products: BTreeMap<VirtualTargetPath, TargetDescription>,
     env: Option<BTreeMap<String, String>>,
     command: Command,
@@ -57,7 +60,10 @@ impl FromMerge for StateNaive {
             name: meta.name,
             materials: meta.materials,
             products: meta.products,
-            env: meta.env,
+            // Only the variables of a structured environment are kept.
+            env: meta.env.or_else(|| {
+                meta.environment.and_then(|env| env.variables().cloned())
+            }),
             command: meta.command,
             byproducts: meta.byproducts,
         })
@@ -5,8 +5,8 @@ use serde_derive::{Deserialize, Serialize};
 use crate::{
     interchange::{DataInterchange, Json},
     models::{
-        LinkMetadata, PredicateLayout, PredicateVer, PredicateWrapper,
-        TargetDescription, VirtualTargetPath,
+        helpers::serialize_artifacts, LinkMetadata, PredicateLayout,
+        PredicateVer, PredicateWrapper, TargetDescription, VirtualTargetPath,
     },
     Error,
 };
@@ -22,6 +22,7 @@ use crate::Result;
 pub struct StateV01 {
     #[serde(rename = "_type")]
     typ: String,
+    #[serde(serialize_with = "serialize_artifacts")]
     subject: BTreeMap<VirtualTargetPath, TargetDescription>,
     #[serde(rename = "predicateType")]
     predicate_type: PredicateVer,
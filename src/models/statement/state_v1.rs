@@ -0,0 +1,346 @@
+use serde::de::{Deserialize, Deserializer, Error as DeserializeError};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::models::{
+    CycloneDxPredicate, Predicate, ResourceDescriptor, SpdxPredicate,
+};
+use crate::Result;
+
+/// The `_type` of a [`Statement`].
+pub const STATEMENT_TYPE_V1: &str = "https://in-toto.io/Statement/v1";
+
+/// Statement `v1` of the in-toto attestation framework, binding a typed
+/// predicate to the artifacts in `subject`.
+///
+/// The `predicateType` is taken from the predicate itself, and checked
+/// against it when deserializing. The JSON returned by
+/// [`Statement::to_bytes`] can be signed in a DSSE
+/// [`Envelope`](crate::models::Envelope).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Statement<P> {
+    #[serde(rename = "_type")]
+    typ: String,
+    subject: Vec<ResourceDescriptor>,
+    #[serde(rename = "predicateType")]
+    predicate_type: String,
+    predicate: P,
+}
+
+impl<P: Predicate> Statement<P> {
+    /// Create a new `Statement` about `subject`.
+    pub fn new(subject: Vec<ResourceDescriptor>, predicate: P) -> Self {
+        Self {
+            typ: STATEMENT_TYPE_V1.to_string(),
+            subject,
+            predicate_type: predicate.predicate_type(),
+            predicate,
+        }
+    }
+
+    /// The artifacts this statement is about.
+    pub fn subject(&self) -> &[ResourceDescriptor] {
+        &self.subject
+    }
+
+    /// The URI identifying the type of the predicate.
+    pub fn predicate_type(&self) -> &str {
+        &self.predicate_type
+    }
+
+    /// The predicate of this statement.
+    pub fn predicate(&self) -> &P {
+        &self.predicate
+    }
+
+    /// Serialize this `Statement` as JSON. DSSE signs the payload bytes as
+    /// they are, so the JSON is not canonicalized and predicates may hold
+    /// floating point numbers.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+}
+
+impl Statement<CycloneDxPredicate> {
+    /// Create a new `Statement` carrying the CycloneDX SBOM `sbom` about
+    /// `subject`.
+    pub fn with_cyclonedx(
+        subject: Vec<ResourceDescriptor>,
+        sbom: Value,
+    ) -> Self {
+        Statement::new(subject, CycloneDxPredicate::new(sbom))
+    }
+}
+
+impl Statement<SpdxPredicate> {
+    /// Create a new `Statement` carrying the SPDX SBOM `sbom` about
+    /// `subject`, failing if `sbom` is not an SPDX document.
+    pub fn with_spdx(
+        subject: Vec<ResourceDescriptor>,
+        sbom: Value,
+    ) -> Result<Self> {
+        Ok(Statement::new(subject, SpdxPredicate::new(sbom)?))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawStatement<P> {
+    #[serde(rename = "_type")]
+    typ: String,
+    subject: Vec<ResourceDescriptor>,
+    #[serde(rename = "predicateType")]
+    predicate_type: String,
+    predicate: P,
+}
+
+impl<'de, P: Predicate> Deserialize<'de> for Statement<P> {
+    fn deserialize<D: Deserializer<'de>>(
+        de: D,
+    ) -> ::std::result::Result<Self, D::Error> {
+        let raw: RawStatement<P> = RawStatement::deserialize(de)?;
+        if raw.typ != STATEMENT_TYPE_V1 {
+            return Err(DeserializeError::custom(format!(
+                "unexpected statement type {:?}, expected {:?}",
+                raw.typ, STATEMENT_TYPE_V1
+            )));
+        }
+        let expected = raw.predicate.predicate_type();
+        if raw.predicate_type != expected {
+            return Err(DeserializeError::custom(format!(
+                "unexpected predicate type {:?}, expected {:?}",
+                raw.predicate_type, expected
+            )));
+        }
+        Ok(Statement {
+            typ: raw.typ,
+            subject: raw.subject,
+            predicate_type: raw.predicate_type,
+            predicate: raw.predicate,
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::collections::BTreeMap;
+
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::json;
+
+    use super::{Statement, STATEMENT_TYPE_V1};
+    use crate::{
+        crypto::PrivateKey,
+        models::{
+            CycloneDxPredicate, Envelope, Predicate, ResourceDescriptor,
+            ResourceDescriptorBuilder, SpdxPredicate, PAYLOAD_TYPE_IN_TOTO,
+            PREDICATE_TYPE_CYCLONEDX, PREDICATE_TYPE_SPDX,
+        },
+    };
+
+    const ALICE_PRIVATE_KEY: &[u8] =
+        include_bytes!("../../../tests/ed25519/ed25519-1");
+
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    struct TestResult {
+        passed: bool,
+    }
+
+    impl Predicate for TestResult {
+        fn predicate_type(&self) -> String {
+            "https://example.com/TestResult/v1".to_string()
+        }
+    }
+
+    fn subject() -> ResourceDescriptor {
+        let digest = BTreeMap::from([(
+            "sha256".to_string(),
+            "52947cb78b91ad01fe81cd6aef42d1f6817e92b9e6936c1e5aabb7c98514f355"
+                .to_string(),
+        )]);
+        ResourceDescriptorBuilder::new()
+            .name("foo.tar.gz".to_string())
+            .digest(digest)
+            .build()
+            .unwrap()
+    }
+
+    fn statement() -> Statement<TestResult> {
+        Statement::new(vec![subject()], TestResult { passed: true })
+    }
+
+    #[test]
+    fn serialize_statement() {
+        let json = serde_json::to_value(statement()).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "_type": STATEMENT_TYPE_V1,
+                "subject": [{
+                    "name": "foo.tar.gz",
+                    "digest": {
+                        "sha256": "52947cb78b91ad01fe81cd6aef42d1f6817e92b9e6936c1e5aabb7c98514f355"
+                    }
+                }],
+                "predicateType": "https://example.com/TestResult/v1",
+                "predicate": { "passed": true }
+            })
+        );
+
+        let decoded: Statement<TestResult> =
+            serde_json::from_value(json).unwrap();
+        assert_eq!(decoded, statement());
+    }
+
+    #[test]
+    fn deserialize_mismatched_type() {
+        let mut json = serde_json::to_value(statement()).unwrap();
+        json["predicateType"] = "https://example.com/Other/v1".into();
+        assert!(serde_json::from_value::<Statement<TestResult>>(json).is_err());
+
+        let mut json = serde_json::to_value(statement()).unwrap();
+        json["_type"] = "https://in-toto.io/Statement/v0.1".into();
+        assert!(serde_json::from_value::<Statement<TestResult>>(json).is_err());
+    }
+
+    #[test]
+    fn sign_statement() {
+        let key = PrivateKey::from_ed25519(ALICE_PRIVATE_KEY).unwrap();
+        let envelope = Envelope::new(
+            statement().to_bytes().unwrap(),
+            PAYLOAD_TYPE_IN_TOTO.to_string(),
+            &[&key],
+        )
+        .unwrap();
+
+        let payload = envelope.verify(1, [key.public()]).unwrap();
+        let decoded: Statement<TestResult> =
+            serde_json::from_slice(payload).unwrap();
+        assert_eq!(
+            decoded.predicate_type(),
+            decoded.predicate().predicate_type()
+        );
+        assert_eq!(decoded, statement());
+    }
+
+    #[test]
+    fn sign_cyclonedx_statement() {
+        let sbom = json!({
+            "bomFormat": "CycloneDX",
+            "specVersion": "1.5",
+            "serialNumber": "urn:uuid:3e671687-395b-41f5-a30f-a58921a69b79",
+            "version": 1,
+            "components": [{
+                "type": "library",
+                "name": "serde",
+                "version": "1.0.152",
+                "purl": "pkg:cargo/serde@1.0.152",
+                "x-vendor-extension": { "unknown": [null, true, "kept"] }
+            }]
+        });
+        let statement =
+            Statement::with_cyclonedx(vec![subject()], sbom.clone());
+        assert_eq!(statement.predicate_type(), PREDICATE_TYPE_CYCLONEDX);
+
+        let key = PrivateKey::from_ed25519(ALICE_PRIVATE_KEY).unwrap();
+        let envelope = Envelope::new(
+            statement.to_bytes().unwrap(),
+            PAYLOAD_TYPE_IN_TOTO.to_string(),
+            &[&key],
+        )
+        .unwrap();
+
+        // The signed payload holds the SBOM document itself.
+        let payload = envelope.verify(1, [key.public()]).unwrap();
+        let sbom_bytes = serde_json::to_vec(&sbom).unwrap();
+        assert!(payload
+            .windows(sbom_bytes.len())
+            .any(|window| window == sbom_bytes.as_slice()));
+
+        let decoded: Statement<CycloneDxPredicate> =
+            serde_json::from_slice(payload).unwrap();
+        assert_eq!(decoded, statement);
+        assert_eq!(decoded.predicate().sbom(), &sbom);
+    }
+
+    #[test]
+    fn sign_cyclonedx_statement_with_floats() {
+        let sbom = json!({
+            "bomFormat": "CycloneDX",
+            "specVersion": "1.5",
+            "version": 1,
+            "vulnerabilities": [{
+                "id": "CVE-2023-0001",
+                "ratings": [{
+                    "score": 9.8,
+                    "severity": "critical",
+                    "method": "CVSSv31"
+                }]
+            }]
+        });
+        let statement =
+            Statement::with_cyclonedx(vec![subject()], sbom.clone());
+
+        let key = PrivateKey::from_ed25519(ALICE_PRIVATE_KEY).unwrap();
+        let envelope = Envelope::new(
+            statement.to_bytes().unwrap(),
+            PAYLOAD_TYPE_IN_TOTO.to_string(),
+            &[&key],
+        )
+        .unwrap();
+
+        let payload = envelope.verify(1, [key.public()]).unwrap();
+        let decoded: Statement<CycloneDxPredicate> =
+            serde_json::from_slice(payload).unwrap();
+        assert_eq!(decoded, statement);
+        assert_eq!(
+            decoded.predicate().sbom()["vulnerabilities"][0]["ratings"][0]
+                ["score"],
+            9.8
+        );
+    }
+
+    #[test]
+    fn sign_spdx_statement() {
+        let sbom = json!({
+            "spdxVersion": "SPDX-2.3",
+            "dataLicense": "CC0-1.0",
+            "SPDXID": "SPDXRef-DOCUMENT",
+            "name": "foo",
+            "documentNamespace": "https://example.com/spdx/foo-1.0",
+            "creationInfo": {
+                "created": "2023-01-01T00:00:00Z",
+                "creators": ["Tool: in-toto-rs"]
+            },
+            "packages": [{
+                "SPDXID": "SPDXRef-Package-foo",
+                "name": "foo",
+                "downloadLocation": "NOASSERTION"
+            }]
+        });
+        let statement =
+            Statement::with_spdx(vec![subject()], sbom.clone()).unwrap();
+        assert_eq!(statement.predicate_type(), PREDICATE_TYPE_SPDX);
+        assert!(Statement::with_spdx(vec![subject()], json!({})).is_err());
+
+        let key = PrivateKey::from_ed25519(ALICE_PRIVATE_KEY).unwrap();
+        let envelope = Envelope::new(
+            statement.to_bytes().unwrap(),
+            PAYLOAD_TYPE_IN_TOTO.to_string(),
+            &[&key],
+        )
+        .unwrap();
+
+        // The signed payload holds the SBOM document itself.
+        let payload = envelope.verify(1, [key.public()]).unwrap();
+        let sbom_bytes = serde_json::to_vec(&sbom).unwrap();
+        assert!(payload
+            .windows(sbom_bytes.len())
+            .any(|window| window == sbom_bytes.as_slice()));
+
+        let decoded: Statement<SpdxPredicate> =
+            serde_json::from_slice(payload).unwrap();
+        assert_eq!(decoded, statement);
+        assert_eq!(decoded.predicate().sbom(), &sbom);
+    }
+}
@@ -1,14 +1,15 @@
 //! Helper for ArtifactRule to apply on LinkMetadata
 
 use std::collections::{BTreeMap, BTreeSet, HashMap};
-use std::path::PathBuf;
 
 use log::warn;
 
 use crate::models::rule::Artifact;
 use crate::models::supply_chain_item::SupplyChainItem;
 use crate::models::{rule::ArtifactRule, LinkMetadata};
-use crate::models::{TargetDescription, VirtualTargetPath};
+use crate::models::{
+    MatchResult, TargetDescription, TargetDescriptionExt, VirtualTargetPath,
+};
 use crate::{Error, Result};
 
 /// Canonicalize a given [`VirtualTargetPath`]. For example
@@ -19,21 +20,54 @@ fn canonicalize_path(path: &VirtualTargetPath) -> Option<VirtualTargetPath> {
     VirtualTargetPath::new(path.into_os_string().into_string().unwrap()).ok()
 }
 
+/// The error for two artifacts which [`TargetDescriptionExt::matches`]
+/// cannot compare, or which agree on some hash algorithms only.
+fn unmatchable_hashes(
+    a: &VirtualTargetPath,
+    b: &VirtualTargetPath,
+    context: &str,
+    result: MatchResult,
+) -> Error {
+    let reason = match result {
+        MatchResult::Conflict(algorithms) => format!(
+            "they disagree on {} only, which indicates a hash collision",
+            algorithms
+                .iter()
+                .map(|alg| alg.name())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        _ => "they share no supported hash algorithm".to_string(),
+    };
+    Error::ArtifactRuleError(format!(
+        "artifact verification failed in {}, because {:?} and {:?}: {}",
+        context, a, b, reason,
+    ))
+}
+
 /// Apply match rule. The parameters:
 /// * `rule`: MATCH rule to be applied (if not a MATCH rule, will be paniced)
 /// * `src_artifacts`: artifacts of a given link (either Products or Materials)
 /// * `src_artifact_queue`: artifact paths (canonicalized) of the same link (either Products or Materials)
 /// * `items_metadata`: a <name> to <link> hashmap
+///
 /// This function will match the artifact paths of `src_artifact_queue`
 /// and the `dst_artifacts`. Here `dst_artifacts` can be calculated
 /// by indexing the step name from `items_metadata`. Return value is
 /// the matched artifact paths.
+///
+/// Only queued artifacts under `in_src` whose path matches `pattern` are
+/// considered. Such an artifact is consumed if the destination has an
+/// artifact at the same path under `in_dst` with equal hashes; otherwise
+/// it stays in the queue for the following rules. If no source artifact
+/// matches the pattern the rule is a no-op. It is an error if there is
+/// no link metadata for the destination step.
 fn verify_match_rule(
     rule: &ArtifactRule,
     src_artifacts: &BTreeMap<VirtualTargetPath, TargetDescription>,
     src_artifact_queue: &BTreeSet<VirtualTargetPath>,
     items_metadata: &HashMap<String, LinkMetadata>,
-) -> BTreeSet<VirtualTargetPath> {
+) -> Result<BTreeSet<VirtualTargetPath>> {
     let mut consumed = BTreeSet::new();
 
     match rule {
@@ -44,13 +78,12 @@ fn verify_match_rule(
             in_dst,
             from,
         } => {
-            let dst_link = match items_metadata.get(from) {
-                Some(lm) => lm,
-                None => {
-                    warn!("no link metadata {} found.", from);
-                    return consumed;
-                }
-            };
+            let dst_link = items_metadata.get(from).ok_or_else(|| {
+                Error::ArtifactRuleError(format!(
+                    "artifact verification failed for {:?}, no link metadata found for step {}",
+                    rule, from
+                ))
+            })?;
 
             let dst_artifact = match with {
                 Artifact::Materials => &dst_link.materials,
@@ -81,68 +114,69 @@ fn verify_match_rule(
                     })
                     .collect();
 
-            let dst_prefix = {
-                match in_dst {
-                    None => String::new(),
-                    Some(dst_dir) => {
-                        let mut res = PathBuf::new();
-                        res.push(dst_dir);
-                        let mut res = res.to_string_lossy().to_string();
-                        res.push('/');
-                        res
-                    }
-                }
-            };
-
-            let src_prefix = {
-                match in_src {
-                    None => String::new(),
-                    Some(src_dir) => {
-                        let mut res = PathBuf::new();
-                        res.push(src_dir);
-                        let mut res = res.to_string_lossy().to_string();
-                        res.push('/');
-                        res
-                    }
-                }
-            };
-
             for src_path in src_artifact_queue {
-                let src_base_path = src_path
-                    .value()
-                    .strip_prefix(&src_prefix)
-                    .unwrap_or_else(|| src_path.value());
-                let src_base_path =
-                    VirtualTargetPath::new(src_base_path.to_string())
-                        .expect("Unexpected VirtualTargetPath creation failed");
-
-                if let Err(e) = src_base_path.matches(pattern.value()) {
-                    warn!("match failed: {}", e.to_string());
+                let src_base_path = match in_src {
+                    None => src_path.clone(),
+                    Some(src_dir) => match src_path.strip_prefix(src_dir) {
+                        Some(base_path) => base_path,
+                        None => continue,
+                    },
+                };
+
+                if !src_base_path.matches(pattern.value()) {
                     continue;
                 }
 
-                let dst_path = {
-                    let mut res = PathBuf::new();
-                    res.push(&dst_prefix);
-                    res.push(src_base_path.value());
-                    VirtualTargetPath::new(res.to_string_lossy().to_string())
-                        .expect("Unexpected VirtualTargetPath creation failed")
+                let dst_path = match in_dst {
+                    None => src_base_path,
+                    Some(dst_dir) => match src_base_path.with_prefix(dst_dir) {
+                        Ok(dst_path) => dst_path,
+                        Err(e) => {
+                            warn!(
+                                "Cannot put {} under {}: {}",
+                                src_path, dst_dir, e
+                            );
+                            continue;
+                        }
+                    },
                 };
 
-                if let Some(dst_artifact) = dst_artifacts.get(&dst_path) {
-                    if src_artifacts[src_path] == *dst_artifact {
-                        consumed.insert(src_path.clone());
+                match dst_artifacts.get(&dst_path) {
+                    Some(dst_artifact) => {
+                        match src_artifacts[src_path].matches(dst_artifact) {
+                            MatchResult::Match => {
+                                consumed.insert(src_path.clone());
+                            }
+                            MatchResult::Mismatch => warn!(
+                                "{:?} does not have the same hashes as {:?} in {}",
+                                src_path, dst_path, from
+                            ),
+                            result => {
+                                return Err(unmatchable_hashes(
+                                    src_path, &dst_path, from, result,
+                                ))
+                            }
+                        }
                     }
+                    None => warn!(
+                        "{:?} has no counterpart {:?} in {}",
+                        src_path, dst_path, from
+                    ),
                 }
             }
         }
         _ => panic!("Unexpected rule type"),
     }
 
-    consumed
+    Ok(consumed)
 }
 
-/// Apply rules of the given [`SupplyChainItem`] onto the [`LinkMetadata`]
+/// Apply rules of the given [`SupplyChainItem`] onto the [`LinkMetadata`].
+///
+/// The materials and products of the item's link are put into a queue
+/// each, and the rules are applied in order. Every rule filters the queue
+/// by its pattern and removes the artifacts it consumes. Artifacts left
+/// in the queue after the last rule are rejected (implicit `DISALLOW *`).
 pub(crate) fn apply_rules_on_link(
     item: &Box<dyn SupplyChainItem>,
     reduced_link_files: &HashMap<String, LinkMetadata>,
@@ -161,15 +195,15 @@ pub(crate) fn apply_rules_on_link(
     // materials of this link
     let material_paths: BTreeSet<VirtualTargetPath> = src_link
         .materials
-        .iter()
-        .filter_map(|(path, _)| canonicalize_path(path))
+        .keys()
+        .filter_map(canonicalize_path)
         .collect();
 
     // products of this link
     let product_paths: BTreeSet<VirtualTargetPath> = src_link
         .products
-        .iter()
-        .filter_map(|(path, _)| canonicalize_path(path))
+        .keys()
+        .filter_map(canonicalize_path)
         .collect();
 
     // prepare sets of artifacts for `create`, `delete` and `modify` rules.
@@ -178,17 +212,18 @@ pub(crate) fn apply_rules_on_link(
         product_paths.difference(&material_paths).cloned().collect();
     let deleted: BTreeSet<_> =
         material_paths.difference(&product_paths).cloned().collect();
-    let modified: BTreeSet<_> = material_paths
-        .intersection(&product_paths)
-        .cloned()
-        .filter_map(|name| {
-            if src_link.materials[&name] != src_link.products[&name] {
-                Some(name)
-            } else {
-                None
+    let mut modified = BTreeSet::new();
+    for name in material_paths.intersection(&product_paths) {
+        match src_link.materials[name].matches(&src_link.products[name]) {
+            MatchResult::Match => (),
+            MatchResult::Mismatch => {
+                modified.insert(name.clone());
             }
-        })
-        .collect();
+            result => {
+                return Err(unmatchable_hashes(name, name, item_name, result))
+            }
+        }
+    }
 
     #[derive(Debug)]
     struct VerificationDataList<'a> {
@@ -228,12 +263,21 @@ pub(crate) fn apply_rules_on_link(
         // items in `queue` using rule CREATE, DELETE, MODIFY, ALLOW, REQUIRE and DISALLOW.
         // besides, use MATCH rule to filter other items.
         for rule in rules {
+            // an `IN <prefix>` rule only sees the artifacts under it, by
+            // their path relative to it
+            let (unscoped, prefix) = rule.unscoped();
+            let pattern = rule.pattern().value();
             let filtered: BTreeSet<_> = queue
                 .iter()
-                .filter(|p| p.matches(rule.pattern().value()).unwrap_or(false))
+                .filter(|p| match prefix {
+                    None => p.matches(pattern),
+                    Some(prefix) => p
+                        .strip_prefix(prefix)
+                        .is_some_and(|base| base.matches(pattern)),
+                })
                 .cloned()
                 .collect();
-            let consumed = match rule {
+            let consumed = match unscoped {
                 ArtifactRule::Create(_) => {
                     filtered.intersection(&created).cloned().collect()
                 }
@@ -244,14 +288,16 @@ pub(crate) fn apply_rules_on_link(
                     filtered.intersection(&modified).cloned().collect()
                 }
                 ArtifactRule::Allow(_) => filtered,
+                // REQUIRE is about the presence of an artifact, it does
+                // not consume anything
                 ArtifactRule::Require(_) => {
-                    if !queue.contains(rule.pattern()) {
+                    if filtered.is_empty() {
                         return Err(Error::ArtifactRuleError(format!(
-                            r#"artifact verification failed for {:?} in REQUIRE '{:?}',
-                        because {:?} is not in {:?}"#,
+                            r#"artifact verification failed for {:?} in REQUIRE '{}' in {}, because no artifact matching '{}' is in {:?}"#,
                             verification_data.src_type,
-                            rule.pattern(),
-                            rule.pattern(),
+                            rule.pattern().value(),
+                            item_name,
+                            rule.pattern().value(),
                             queue
                         )));
                     } else {
@@ -271,16 +317,33 @@ pub(crate) fn apply_rules_on_link(
                         BTreeSet::new()
                     }
                 }
-                ArtifactRule::Match { .. } => verify_match_rule(
-                    rule,
-                    artifacts,
-                    &queue,
-                    reduced_link_files,
-                ),
+                ArtifactRule::Match { .. } if prefix.is_none() => {
+                    verify_match_rule(
+                        rule,
+                        artifacts,
+                        &queue,
+                        reduced_link_files,
+                    )?
+                }
+                ArtifactRule::Match { .. } | ArtifactRule::In { .. } => {
+                    return Err(Error::ArtifactRuleError(format!(
+                        "artifact rule {:?} in {} cannot be restricted to a path prefix",
+                        rule, item_name,
+                    )))
+                }
             };
 
             queue = queue.difference(&consumed).cloned().collect();
         }
+
+        // every rule list ends with an implicit `DISALLOW *`, so anything
+        // left in the queue has not been authorized by any rule
+        if !queue.is_empty() {
+            return Err(Error::ArtifactRuleError(format!(
+                r#"artifact verification failed for {:?} in {}, because {:?} are not consumed by any rule"#,
+                verification_data.src_type, item_name, queue,
+            )));
+        }
     }
 
     Ok(())
@@ -288,10 +351,14 @@ pub(crate) fn apply_rules_on_link(
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
 
     use rstest::rstest;
 
-    use crate::models::VirtualTargetPath;
+    use crate::crypto::HashAlgorithm;
+    use crate::models::step::Step;
+    use crate::models::supply_chain_item::SupplyChainItem;
+    use crate::models::{LinkMetadata, VirtualTargetPath};
 
     #[rstest]
     #[case("test/../1/1/2", "1/1/2")]
@@ -335,6 +402,24 @@ mod tests {
             r#"["dir1/test1"]"#,
         )
     ]
+    #[
+        case(
+            r#"["MATCH", "*.py", "WITH", "PRODUCTS", "FROM", "package"]"#,
+            r#"{"demo-project.tar.gz": {"sha256": "2989659e6836c941e9015bf38af3cb045365520dbf80460d8a44b2c5b6677fd9"}}"#,
+            r#"["demo-project.tar.gz"]"#,
+            r#"{"package":{"_type":"link","byproducts":{},"command":[""],"environment":{},"materials":{},"name":"package","products":{"demo-project.tar.gz":{"sha256":"2989659e6836c941e9015bf38af3cb045365520dbf80460d8a44b2c5b6677fd9"}}}}"#,
+            r#"[]"#,
+        )
+    ]
+    #[
+        case(
+            r#"["MATCH", "*", "IN", "dir1", "WITH", "PRODUCTS", "FROM", "package"]"#,
+            r#"{"test1": {"sha256": "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"}, "dir1/test2": {"sha256": "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"}}"#,
+            r#"["test1", "dir1/test2"]"#,
+            r#"{"package":{"_type":"link","byproducts":{},"command":[""],"environment":{},"materials":{},"name":"package","products":{"test1":{"sha256":"ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"}, "test2":{"sha256":"eeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee"}}}}"#,
+            r#"[]"#,
+        )
+    ]
     fn verify_match_rule(
         #[case] rule: &str,
         #[case] src_artifacts: &str,
@@ -356,7 +441,293 @@ mod tests {
             &src_artifacts,
             &src_artifact_queue,
             &items_metadata,
-        );
+        )
+        .expect("Match rule failed");
         assert_eq!(got, expected);
     }
+
+    #[test]
+    fn verify_match_rule_missing_destination_link() {
+        let rule = serde_json::from_str(
+            r#"["MATCH", "*", "WITH", "PRODUCTS", "FROM", "package"]"#,
+        )
+        .expect("Parse artifact rule failed");
+        let src_artifacts = serde_json::from_str(
+            r#"{"test1": {"sha256": "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"}}"#,
+        )
+        .expect("Parse Source Artifacts failed");
+        let src_artifact_queue = serde_json::from_str(r#"["test1"]"#)
+            .expect("Parse Source Artifact Queue failed");
+        let got = super::verify_match_rule(
+            &rule,
+            &src_artifacts,
+            &src_artifact_queue,
+            &HashMap::new(),
+        );
+        assert!(got.is_err());
+    }
+
+    fn apply_rules(
+        materials: &str,
+        products: &str,
+        links: &str,
+    ) -> crate::Result<()> {
+        let step: Box<dyn SupplyChainItem> = Box::new(
+            Step::new("package")
+                .expected_materials(
+                    serde_json::from_str(materials)
+                        .expect("Parse material rules failed"),
+                )
+                .expected_products(
+                    serde_json::from_str(products)
+                        .expect("Parse product rules failed"),
+                ),
+        );
+        let links =
+            serde_json::from_str(links).expect("Parse Metadata HashMap failed");
+        super::apply_rules_on_link(&step, &links)
+    }
+
+    const LINKS: &str = r#"{
+        "package": {"_type":"link","byproducts":{},"command":[],"environment":{},"name":"package",
+            "materials":{"foo.py":{"sha256":"c2c0ea54fa94fac3a4e1575d6ed3bbd1b01a6d0b8deb39196bdc31c457ef731b"}},
+            "products":{"foo.py":{"sha256":"c2c0ea54fa94fac3a4e1575d6ed3bbd1b01a6d0b8deb39196bdc31c457ef731b"},
+                "foo.tar.gz":{"sha256":"2989659e6836c941e9015bf38af3cb045365520dbf80460d8a44b2c5b6677fd9"}}},
+        "write-code": {"_type":"link","byproducts":{},"command":[],"environment":{},"name":"write-code",
+            "materials":{},
+            "products":{"foo.py":{"sha256":"c2c0ea54fa94fac3a4e1575d6ed3bbd1b01a6d0b8deb39196bdc31c457ef731b"}}}
+    }"#;
+
+    #[rstest]
+    #[case(r#"[["ALLOW", "*"]]"#, r#"[["ALLOW", "*"]]"#, true)]
+    #[case(r#"[]"#, r#"[["ALLOW", "*"]]"#, false)]
+    #[case(r#"[["ALLOW", "*"]]"#, r#"[["ALLOW", "*.tar.gz"]]"#, false)]
+    #[case(
+        r#"[["MATCH", "foo.py", "WITH", "PRODUCTS", "FROM", "write-code"]]"#,
+        r#"[["CREATE", "foo.tar.gz"], ["ALLOW", "foo.py"]]"#,
+        true
+    )]
+    #[case(
+        r#"[["ALLOW", "*"]]"#,
+        r#"[["CREATE", "foo.py"], ["ALLOW", "*"]]"#,
+        true
+    )]
+    #[case(
+        r#"[["ALLOW", "*"]]"#,
+        r#"[["DISALLOW", "foo.py"], ["ALLOW", "*"]]"#,
+        false
+    )]
+    #[case(
+        r#"[["ALLOW", "*"]]"#,
+        r#"[["REQUIRE", "foo.tar.gz"], ["ALLOW", "*"]]"#,
+        true
+    )]
+    #[case(
+        r#"[["REQUIRE", "bar.py"], ["ALLOW", "*"]]"#,
+        r#"[["ALLOW", "*"]]"#,
+        false
+    )]
+    fn apply_rules_on_link(
+        #[case] materials: &str,
+        #[case] products: &str,
+        #[case] ok: bool,
+    ) {
+        let res = apply_rules(materials, products, LINKS);
+        assert_eq!(res.is_ok(), ok, "{:?}", res);
+    }
+
+    const SOURCES: &str = r#"{
+        "package": {"_type":"link","byproducts":{},"command":[],"environment":{},"name":"package",
+            "materials":{},
+            "products":{"src/a.py":{"sha256":"aa"},"tools/b.py":{"sha256":"bb"}}}
+    }"#;
+
+    #[rstest]
+    // tools/b.py is outside src/, so the rule does not consume it
+    #[case(r#"[["ALLOW", "*.py", "IN", "src/"]]"#, false)]
+    #[case(
+        r#"[["ALLOW", "*.py", "IN", "src/"], ["ALLOW", "b.py", "IN", "tools"]]"#,
+        true
+    )]
+    #[case(r#"[["CREATE", "a.py", "IN", "src"], ["ALLOW", "tools/*"]]"#, true)]
+    #[case(r#"[["DISALLOW", "*", "IN", "tools"], ["ALLOW", "**"]]"#, false)]
+    #[case(r#"[["DISALLOW", "*", "IN", "docs"], ["ALLOW", "**"]]"#, true)]
+    // the prefix is a directory, not a string prefix
+    #[case(r#"[["ALLOW", "*.py", "IN", "sr"], ["ALLOW", "tools/*"]]"#, false)]
+    #[case(r#"[["REQUIRE", "b.py", "IN", "src"], ["ALLOW", "**"]]"#, false)]
+    fn apply_rules_in_prefix(#[case] products: &str, #[case] ok: bool) {
+        let res = apply_rules("[]", products, SOURCES);
+        assert_eq!(res.is_ok(), ok, "{:?}", res);
+    }
+
+    const CHANGES: &str = r#"{
+        "package": {"_type":"link","byproducts":{},"command":[],"environment":{},"name":"package",
+            "materials":{"removed.txt":{"sha256":"aa"},"edited.txt":{"sha256":"aa"},"same.txt":{"sha256":"aa"}},
+            "products":{"generated.txt":{"sha256":"bb"},"edited.txt":{"sha256":"bb"},"same.txt":{"sha256":"aa"}}}
+    }"#;
+
+    #[rstest]
+    #[case(r#"[["ALLOW", "*"]]"#, r#"[["CREATE", "generated.txt"]]"#, false)]
+    #[case(
+        r#"[["ALLOW", "*"]]"#,
+        r#"[["CREATE", "generated.txt"], ["ALLOW", "*"]]"#,
+        true
+    )]
+    #[case(
+        r#"[["ALLOW", "*"]]"#,
+        r#"[["CREATE", "*"], ["MODIFY", "edited.txt"], ["ALLOW", "same.txt"]]"#,
+        true
+    )]
+    #[case(
+        r#"[["ALLOW", "*"]]"#,
+        r#"[["CREATE", "*"], ["ALLOW", "same.txt"]]"#,
+        false
+    )]
+    #[case(
+        r#"[["ALLOW", "*"]]"#,
+        r#"[["MODIFY", "*"], ["DISALLOW", "edited.txt"], ["ALLOW", "*"]]"#,
+        true
+    )]
+    #[case(
+        r#"[["ALLOW", "*"]]"#,
+        r#"[["MODIFY", "*"], ["DISALLOW", "same.txt"], ["ALLOW", "*"]]"#,
+        false
+    )]
+    #[case(
+        r#"[["DELETE", "*"], ["DISALLOW", "removed.txt"], ["ALLOW", "*"]]"#,
+        r#"[["ALLOW", "*"]]"#,
+        true
+    )]
+    #[case(
+        r#"[["DELETE", "*"], ["DISALLOW", "edited.txt"], ["ALLOW", "*"]]"#,
+        r#"[["ALLOW", "*"]]"#,
+        false
+    )]
+    #[case(
+        r#"[["DELETE", "removed.txt"], ["ALLOW", "*"]]"#,
+        r#"[["DELETE", "generated.txt"], ["DISALLOW", "generated.txt"], ["ALLOW", "*"]]"#,
+        false
+    )]
+    fn apply_change_rules_on_link(
+        #[case] materials: &str,
+        #[case] products: &str,
+        #[case] ok: bool,
+    ) {
+        let res = apply_rules(materials, products, CHANGES);
+        assert_eq!(res.is_ok(), ok, "{:?}", res);
+    }
+
+    #[test]
+    fn allow_directory_consumes_nested_paths() {
+        let links = r#"{
+            "package": {"_type":"link","byproducts":{},"command":[],"environment":{},"name":"package",
+                "materials":{},
+                "products":{"demo-project/.git/objects/ab/cdef":{"sha256":"aa"},"demo-project/foo.py":{"sha256":"bb"}}}
+        }"#;
+        let res = apply_rules(
+            "[]",
+            r#"[["ALLOW", "demo-project/.git/*"], ["ALLOW", "demo-project/foo.py"], ["DISALLOW", "*"]]"#,
+            links,
+        );
+        assert!(res.is_ok(), "{:?}", res);
+
+        let res = apply_rules(
+            "[]",
+            r#"[["ALLOW", "demo-project/foo.py"], ["DISALLOW", "*"]]"#,
+            links,
+        );
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn implicit_disallow_rejects_unconsumed_artifacts() {
+        let links = r#"{
+            "package": {"_type":"link","byproducts":{},"command":[],"environment":{},"name":"package",
+                "materials":{"Makefile":{"sha256":"aa"},"foo.c":{"sha256":"bb"}},
+                "products":{}}
+        }"#;
+        let products = r#"[]"#;
+        assert!(apply_rules(
+            r#"[["ALLOW", "*.c"], ["ALLOW", "Makefile"]]"#,
+            products,
+            links,
+        )
+        .is_ok());
+
+        let err = apply_rules(r#"[["ALLOW", "*.c"]]"#, products, links)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Makefile"), "{}", err);
+        assert!(!err.contains("foo.c"), "{}", err);
+    }
+
+    #[test]
+    fn unsupported_hash_algorithms() {
+        let links = r#"{
+            "package": {"_type":"link","byproducts":{},"command":[],"environment":{},"name":"package",
+                "materials":{"foo.c":{"sha256":"aa","future-hash":"bb"},"bar.c":{"future-hash":"aa"}},
+                "products":{}},
+            "write-code": {"_type":"link","byproducts":{},"command":[],"environment":{},"name":"write-code",
+                "materials":{},
+                "products":{"foo.c":{"sha256":"aa","future-hash":"cc"},"bar.c":{"future-hash":"aa"}}}
+        }"#;
+        let products = r#"[]"#;
+
+        // the unknown algorithm is kept but skipped when comparing
+        let link: LinkMetadata =
+            serde_json::from_str::<HashMap<String, LinkMetadata>>(links)
+                .unwrap()
+                .remove("package")
+                .unwrap();
+        assert!(link.materials
+            [&VirtualTargetPath::new("foo.c".into()).unwrap()]
+            .contains_key(&HashAlgorithm::Unknown("future-hash".into())));
+        assert!(apply_rules(
+            r#"[["MATCH", "foo.c", "WITH", "PRODUCTS", "FROM", "write-code"], ["ALLOW", "bar.c"]]"#,
+            products,
+            links,
+        )
+        .is_ok());
+
+        // bar.c only carries an unsupported algorithm
+        let err = apply_rules(
+            r#"[["MATCH", "*.c", "WITH", "PRODUCTS", "FROM", "write-code"]]"#,
+            products,
+            links,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("bar.c"), "{}", err);
+        assert!(err.contains("share no supported hash algorithm"), "{}", err);
+    }
+
+    #[test]
+    fn require_artifact_presence() {
+        let links = r#"{
+            "package": {"_type":"link","byproducts":{},"command":[],"environment":{},"name":"package",
+                "materials":{},
+                "products":{"foo.tar.gz":{"sha256":"aa"}}}
+        }"#;
+        let materials = r#"[]"#;
+        assert!(apply_rules(
+            materials,
+            r#"[["REQUIRE", "*.tar.gz"], ["ALLOW", "foo.tar.gz"]]"#,
+            links,
+        )
+        .is_ok());
+        // REQUIRE does not consume the artifact
+        assert!(
+            apply_rules(materials, r#"[["REQUIRE", "*.tar.gz"]]"#, links)
+                .is_err()
+        );
+
+        let err = apply_rules(
+            materials,
+            r#"[["REQUIRE", "foo.whl"], ["ALLOW", "*"]]"#,
+            links,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("REQUIRE 'foo.whl'"), "{}", err);
+    }
 }
@@ -1,15 +1,23 @@
 //! A tool that functionaries can use to create link metadata about a step.
 
+use glob::{MatchOptions, Pattern};
 use path_clean::clean;
-use std::collections::{BTreeMap, HashSet};
-use std::fs::{canonicalize as canonicalize_path, symlink_metadata, File};
-use std::io::{self, BufReader, Write};
-use std::process::Command;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{
+    canonicalize as canonicalize_path, metadata, read_link, symlink_metadata,
+    File,
+};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
 use walkdir::WalkDir;
 
-use crate::crypto::HashAlgorithm;
-use crate::interchange::Json;
+use crate::crypto::{HashAlgorithm, HashValue, KeyId, HASH_CHUNK_SIZE};
+use crate::interchange::{DataInterchange, Json};
 use crate::models::byproducts::ByProducts;
+use crate::models::step::Command;
 use crate::models::{Metablock, TargetDescription};
 use crate::{
     crypto,
@@ -25,14 +33,101 @@ pub fn record_artifact(
     hash_algorithms: &[HashAlgorithm],
     lstrip_paths: Option<&[&str]>,
 ) -> Result<(VirtualTargetPath, TargetDescription)> {
-    let file = File::open(path)?;
-    let mut reader = BufReader::new(file);
-    let (_length, hashes) =
-        crypto::calculate_hashes(&mut reader, hash_algorithms)?;
+    record_file(path, hash_algorithms, lstrip_paths, false)
+}
+
+/// [`record_artifact`], optionally normalizing the line endings of text
+/// files.
+fn record_file(
+    path: &str,
+    hash_algorithms: &[HashAlgorithm],
+    lstrip_paths: Option<&[&str]>,
+    normalize_line_endings: bool,
+) -> Result<(VirtualTargetPath, TargetDescription)> {
+    let file = File::open(path)
+        .map_err(|e| Error::io(format!("Path {:?}", Path::new(&path)), e))?;
+    let hashes =
+        hash_contents(path, file, hash_algorithms, normalize_line_endings)?;
     let lstripped_path = apply_left_strip(path, lstrip_paths)?;
     Ok((VirtualTargetPath::new(lstripped_path)?, hashes))
 }
 
+/// Hash the contents of the artifact at `path` read from `reader`,
+/// optionally normalizing the line endings of text.
+fn hash_contents<R: Read>(
+    path: &str,
+    reader: R,
+    hash_algorithms: &[HashAlgorithm],
+    normalize_line_endings: bool,
+) -> Result<TargetDescription> {
+    if !normalize_line_endings {
+        return record_artifact_reader(reader, hash_algorithms);
+    }
+    let mut reader = BufReader::with_capacity(HASH_CHUNK_SIZE, reader);
+    if is_binary(&mut reader)
+        .map_err(|e| Error::io(format!("Path {:?}", Path::new(&path)), e))?
+    {
+        record_artifact_reader(reader, hash_algorithms)
+    } else {
+        record_artifact_reader(NormalizeLineEndings(reader), hash_algorithms)
+    }
+}
+
+/// Number of leading bytes searched for a NUL byte to tell binary files
+/// from text files, as git does.
+const BINARY_PROBE_SIZE: usize = 8000;
+
+/// Guess whether the file behind `reader` is binary, i.e. has a NUL byte
+/// close to its start.
+fn is_binary<R: BufRead>(reader: &mut R) -> io::Result<bool> {
+    let head = reader.fill_buf()?;
+    Ok(head[..head.len().min(BINARY_PROBE_SIZE)].contains(&0))
+}
+
+/// Reader converting CRLF line endings to LF. Lone CRs are kept.
+struct NormalizeLineEndings<R>(R);
+
+impl<R: BufRead> Read for NormalizeLineEndings<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut written = 0;
+        while written < buf.len() {
+            let available = self.0.fill_buf()?;
+            if available.is_empty() {
+                break;
+            }
+            if available[0] == b'\r' {
+                // Drop the CR if it is followed by an LF
+                self.0.consume(1);
+                if self.0.fill_buf()?.first() != Some(&b'\n') {
+                    buf[written] = b'\r';
+                    written += 1;
+                }
+                continue;
+            }
+            let len = available.len().min(buf.len() - written);
+            let len = available[..len]
+                .iter()
+                .position(|b| *b == b'\r')
+                .unwrap_or(len);
+            buf[written..written + len].copy_from_slice(&available[..len]);
+            self.0.consume(len);
+            written += len;
+        }
+        Ok(written)
+    }
+}
+
+/// Hashes an artifact read from `reader`, e.g. a network stream, returning
+/// its `TargetDescription`. The input is hashed in fixed-size chunks, so
+/// memory use stays bounded regardless of its size.
+pub fn record_artifact_reader<R: Read>(
+    reader: R,
+    hash_algorithms: &[HashAlgorithm],
+) -> Result<TargetDescription> {
+    let (_length, hashes) = crypto::calculate_hashes(reader, hash_algorithms)?;
+    Ok(hashes)
+}
+
 /// Given an artifact path in `&str` format, left strip path for given artifact based an optional array of `lstrip_paths` provided,
 /// returning the stripped file path in String format wrapped in `Result`.
 fn apply_left_strip(
@@ -56,44 +151,540 @@ fn apply_left_strip(
             continue;
         }
         stripped_path = path.strip_prefix(l_path).ok_or_else(|| {
-            Error::from(io::Error::new(
-                std::io::ErrorKind::Other,
-                format!(
-                    "Lstrip Error: error stripping {} from path {}",
-                    l_path, path
-                ),
-            ))
+            Error::from(io::Error::other(format!(
+                "Lstrip Error: error stripping {} from path {}",
+                l_path, path
+            )))
         })?;
         find_prefix = l_path;
     }
     Ok(String::from(stripped_path))
 }
 
+/// Check whether the given (left stripped) artifact path matches any of the
+/// exclude patterns. Patterns without a `/` also match the file name alone.
+fn is_excluded(path: &str, exclude_patterns: &[Pattern]) -> bool {
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+    let file_name = Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(path);
+    exclude_patterns.iter().any(|pattern| {
+        pattern.matches_with(path, options)
+            || (!pattern.as_str().contains('/')
+                && pattern.matches_with(file_name, options))
+    })
+}
+
+/// How [`record_artifacts`] reads files and walks directories. By default,
+/// symbolic links are followed, files are hashed as they are on disk and
+/// files ignored by git are recorded.
+///
+/// ```
+/// # use in_toto::runlib::RecordOptions;
+/// let options = RecordOptions::new()
+///     .respect_gitignore(true)
+///     .follow_symlinks(false);
+/// # let _ = options;
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordOptions {
+    normalize_line_endings: bool,
+    respect_gitignore: bool,
+    follow_symlinks: bool,
+}
+
+impl Default for RecordOptions {
+    fn default() -> Self {
+        RecordOptions::new()
+    }
+}
+
+impl RecordOptions {
+    /// Create the default `RecordOptions`.
+    pub fn new() -> Self {
+        RecordOptions {
+            normalize_line_endings: false,
+            respect_gitignore: false,
+            follow_symlinks: true,
+        }
+    }
+
+    /// Convert CRLF line endings of text files to LF before hashing, so
+    /// that the same text file checked out on Windows and on Unix is
+    /// recorded with the same hashes. Files with a NUL byte in their first
+    /// 8000 bytes are considered binary and hashed unchanged.
+    ///
+    /// Note that this changes the recorded hashes of text files with CRLF
+    /// line endings, which then no longer match the hashes of the files on
+    /// disk.
+    pub fn normalize_line_endings(
+        mut self,
+        normalize_line_endings: bool,
+    ) -> Self {
+        self.normalize_line_endings = normalize_line_endings;
+        self
+    }
+
+    /// Skip files and directories ignored by git. The `.gitignore` files of
+    /// each traversed directory and its parents up to the root of the git
+    /// repository are honored, as well as `.git/info/exclude`; the `.git`
+    /// directory itself is always skipped.
+    pub fn respect_gitignore(mut self, respect_gitignore: bool) -> Self {
+        self.respect_gitignore = respect_gitignore;
+        self
+    }
+
+    /// How symbolic links, including those given as paths to record, are
+    /// recorded:
+    /// - If `true`, they are followed: a link to a file is recorded under
+    ///   the link's own path with the hash of the file it points to, and a
+    ///   link to a directory is traversed like a directory. A link to a
+    ///   directory containing it is skipped, as traversing it would never
+    ///   end, while dangling links and links that never resolve to a file,
+    ///   e.g. two links pointing to each other, return `Error::Io`.
+    /// - If `false`, they are not followed: every link is recorded under its
+    ///   own path with the hash of the path it points to, as returned by
+    ///   `readlink`, whether that is a file, a directory or nothing at all.
+    ///   A link whose target changes is then recorded as modified.
+    pub fn follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+}
+
 /// Traverses through the passed array of paths, hashes the content of files
 /// encountered, and returns the path and hashed content in `BTreeMap` format, wrapped in `Result`.
 /// If a step in record_artifact fails, the error is returned.
 /// # Arguments
 ///
-/// * `paths` - An array of string slices (`&str`) that holds the paths to be traversed. If a symbolic link cycle is detected in the `paths` during traversal, it is skipped.
+/// * `paths` - An array of string slices (`&str`) that holds the paths to be traversed. Unreadable files return `Error::Io`.
 /// * `hash_algorithms` - An array of string slice (`&str`) wrapped in an `Option` that holds the hash algorithms to be used. If `None` is provided, Sha256 is assumed as default.
 /// * `lstrip_paths` - An array of string slice (`&str`) wrapped in an `Option` that is left stripped from the path of every artifact that contains it.
+///   The longest matching prefix is stripped. If two artifacts end up with the same stripped path, `Error::IllegalArgument` is returned.
+/// * `exclude_patterns` - An array of gitignore-style glob patterns (`&str`) wrapped in an `Option`. Artifacts whose left stripped path matches any pattern are not recorded,
+///   and excluded directories are not traversed. A pattern without a `/` is also matched against the file name alone.
+/// * `options` - How files are read and directories walked, see [`RecordOptions`].
 ///
 /// # Examples
 ///
 /// ```
 /// // You can have rust code between fences inside the comments
 /// // If you pass --test to `rustdoc`, it will even test it for you!
-/// # use in_toto::runlib::{record_artifacts};
-/// let materials = record_artifacts(&["tests/test_runlib"], None, None).unwrap();
+/// # use in_toto::runlib::{record_artifacts, RecordOptions};
+/// let options = RecordOptions::new().normalize_line_endings(true);
+/// let materials = record_artifacts(&["tests/test_runlib"], None, None, Some(&["*.pyc"]), &options).unwrap();
 /// ```
 pub fn record_artifacts(
     paths: &[&str],
     hash_algorithms: Option<&[&str]>,
     lstrip_paths: Option<&[&str]>,
+    exclude_patterns: Option<&[&str]>,
+    options: &RecordOptions,
+) -> Result<BTreeMap<VirtualTargetPath, TargetDescription>> {
+    let hash_algorithms = parse_hash_algorithms(hash_algorithms)?;
+    let files = collect_artifact_paths(
+        paths,
+        lstrip_paths,
+        exclude_patterns,
+        options.respect_gitignore,
+        options.follow_symlinks,
+    )?;
+    let recorded = files.iter().map(|path| {
+        record_entry(
+            path,
+            &hash_algorithms,
+            lstrip_paths,
+            options.normalize_line_endings,
+            options.follow_symlinks,
+        )
+        .map(|artifact| (path, artifact))
+    });
+    merge_artifacts(recorded)
+}
+
+/// Record a path collected by [`collect_artifact_paths`], which is a
+/// symbolic link to record as such unless `follow_symlinks` is set.
+fn record_entry(
+    path: &str,
+    hash_algorithms: &[HashAlgorithm],
+    lstrip_paths: Option<&[&str]>,
+    normalize_line_endings: bool,
+    follow_symlinks: bool,
+) -> Result<(VirtualTargetPath, TargetDescription)> {
+    if !follow_symlinks {
+        let file_type = symlink_metadata(path)
+            .map_err(|e| Error::io(format!("Path {:?}", Path::new(&path)), e))?
+            .file_type();
+        if file_type.is_symlink() {
+            let target = read_link(path).map_err(|e| {
+                Error::io(format!("Path {:?}", Path::new(&path)), e)
+            })?;
+            let hashes = record_artifact_reader(
+                target.as_os_str().as_encoded_bytes(),
+                hash_algorithms,
+            )?;
+            let lstripped_path = apply_left_strip(path, lstrip_paths)?;
+            return Ok((VirtualTargetPath::new(lstripped_path)?, hashes));
+        }
+    }
+    record_file(path, hash_algorithms, lstrip_paths, normalize_line_endings)
+}
+
+/// Where [`record_artifacts_from`] finds the artifacts to record, e.g. a
+/// remote host or an object store, so that they are hashed as they are
+/// read instead of being downloaded to disk first. [`FileSystemSource`]
+/// reads the local file system.
+pub trait ArtifactSource {
+    /// List the artifacts at `path`: `path` itself if it is a file, or the
+    /// files under it, recursively, if it is a directory. The listed paths
+    /// are opened with [`ArtifactSource::open`] and recorded as they are
+    /// listed, before left stripping.
+    fn list(&self, path: &str) -> Result<Vec<String>>;
+
+    /// Open the artifact at `path`, as listed by [`ArtifactSource::list`],
+    /// to read its contents.
+    fn open(&self, path: &str) -> Result<Box<dyn Read + '_>>;
+}
+
+/// The local file system as an [`ArtifactSource`], walked and read like
+/// [`record_artifacts`] does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileSystemSource {
+    respect_gitignore: bool,
+    follow_symlinks: bool,
+}
+
+impl Default for FileSystemSource {
+    fn default() -> Self {
+        FileSystemSource::new()
+    }
+}
+
+impl FileSystemSource {
+    /// Create a `FileSystemSource` following symbolic links and recording
+    /// files ignored by git.
+    pub fn new() -> Self {
+        FileSystemSource {
+            respect_gitignore: false,
+            follow_symlinks: true,
+        }
+    }
+
+    /// Skip files and directories ignored by git, see
+    /// [`RecordOptions::respect_gitignore`].
+    pub fn respect_gitignore(mut self, respect_gitignore: bool) -> Self {
+        self.respect_gitignore = respect_gitignore;
+        self
+    }
+
+    /// Whether symbolic links are followed, see
+    /// [`RecordOptions::follow_symlinks`].
+    pub fn follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+}
+
+impl ArtifactSource for FileSystemSource {
+    fn list(&self, path: &str) -> Result<Vec<String>> {
+        collect_artifact_paths(
+            &[path],
+            None,
+            None,
+            self.respect_gitignore,
+            self.follow_symlinks,
+        )
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn Read + '_>> {
+        let io_error = |e| Error::io(format!("Path {:?}", Path::new(&path)), e);
+        if !self.follow_symlinks
+            && symlink_metadata(path).map_err(io_error)?.is_symlink()
+        {
+            let target = read_link(path).map_err(io_error)?;
+            let target = target.into_os_string().into_encoded_bytes();
+            return Ok(Box::new(io::Cursor::new(target)));
+        }
+        Ok(Box::new(File::open(path).map_err(io_error)?))
+    }
+}
+
+/// Same as [`record_artifacts`], but lists and reads the artifacts with
+/// `source` instead of the local file system.
+///
+/// `exclude_patterns` are matched against the left stripped path of every
+/// listed artifact, and since listing is up to `source`, excluded
+/// directories are listed too. Walking the file system, including git
+/// ignore rules and symbolic links, is configured on [`FileSystemSource`].
+///
+/// ```
+/// # use in_toto::runlib::{record_artifacts, record_artifacts_from, FileSystemSource, RecordOptions};
+/// let source = FileSystemSource::new();
+/// let materials = record_artifacts_from(&source, &["tests/test_runlib"], None, None, None, false).unwrap();
+/// assert_eq!(materials, record_artifacts(&["tests/test_runlib"], None, None, None, &RecordOptions::new()).unwrap());
+/// ```
+pub fn record_artifacts_from<S: ArtifactSource + ?Sized>(
+    source: &S,
+    paths: &[&str],
+    hash_algorithms: Option<&[&str]>,
+    lstrip_paths: Option<&[&str]>,
+    exclude_patterns: Option<&[&str]>,
+    normalize_line_endings: bool,
+) -> Result<BTreeMap<VirtualTargetPath, TargetDescription>> {
+    let hash_algorithms = parse_hash_algorithms(hash_algorithms)?;
+    let exclude_patterns = compile_exclude_patterns(exclude_patterns)?;
+    let mut files = Vec::new();
+    for path in paths {
+        for file in source.list(path)? {
+            let lstripped_path = apply_left_strip(&file, lstrip_paths)?;
+            if !is_excluded(&lstripped_path, &exclude_patterns) {
+                files.push((file, lstripped_path));
+            }
+        }
+    }
+    let recorded = files.iter().map(|(path, lstripped_path)| {
+        let hashes = hash_contents(
+            path,
+            source.open(path)?,
+            &hash_algorithms,
+            normalize_line_endings,
+        )?;
+        Ok((
+            path,
+            (VirtualTargetPath::new(lstripped_path.clone())?, hashes),
+        ))
+    });
+    merge_artifacts(recorded)
+}
+
+/// Same as [`record_artifacts`], but hashes the files on the `rayon` global
+/// thread pool. The tree is walked first and the results are merged in walk
+/// order, so the output (and any error reported) is identical to the serial
+/// version regardless of the number of threads.
+///
+/// Only the hashing is spread across threads; the directory walk stays
+/// serial.
+///
+/// Requires the `rayon` feature.
+#[cfg(feature = "rayon")]
+pub fn record_artifacts_parallel(
+    paths: &[&str],
+    hash_algorithms: Option<&[&str]>,
+    lstrip_paths: Option<&[&str]>,
+    exclude_patterns: Option<&[&str]>,
+    options: &RecordOptions,
+) -> Result<BTreeMap<VirtualTargetPath, TargetDescription>> {
+    use rayon::prelude::*;
+
+    let hash_algorithms = parse_hash_algorithms(hash_algorithms)?;
+    let files = collect_artifact_paths(
+        paths,
+        lstrip_paths,
+        exclude_patterns,
+        options.respect_gitignore,
+        options.follow_symlinks,
+    )?;
+    let recorded = files
+        .par_iter()
+        .map(|path| {
+            record_entry(
+                path,
+                &hash_algorithms,
+                lstrip_paths,
+                options.normalize_line_endings,
+                options.follow_symlinks,
+            )
+            .map(|artifact| (path, artifact))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    merge_artifacts(recorded.into_iter().map(Ok))
+}
+
+/// Record the whole tree under `dir` as a single digest, e.g. to record a
+/// large directory as one artifact that a `MATCH` rule compares at once.
+///
+/// The digest is computed as follows, so that other tools can reproduce it:
+///
+/// 1. Every file under `dir` is hashed with `hash_algorithm`, following
+///    symbolic links like [`record_artifacts`] does.
+/// 2. For each file, in the byte order of its path relative to `dir` with
+///    `/` separators, the line `<hex digest>  <path>\n` is formed, with the
+///    lowercase hex digest and two spaces, as printed by `sha256sum`.
+/// 3. The digest is the hash with `hash_algorithm` of all lines
+///    concatenated.
+///
+/// An empty directory therefore has the hash of the empty string. Paths
+/// containing a newline are rejected with `Error::IllegalArgument`, as
+/// they would make the lines ambiguous.
+pub fn record_directory_hash(
+    dir: &str,
+    hash_algorithm: &HashAlgorithm,
+) -> Result<HashValue> {
+    let dir = clean(dir).to_string_lossy().into_owned();
+    let prefix = format!("{}/", dir.trim_end_matches('/'));
+    let hash_algorithms = std::slice::from_ref(hash_algorithm);
+    let files = collect_artifact_paths(&[&dir], None, None, false, true)?;
+    let recorded = files.iter().map(|path| {
+        record_file(path, hash_algorithms, Some(&[&prefix]), false)
+            .map(|artifact| (path, artifact))
+    });
+
+    let mut context = hash_algorithm.digest_context()?;
+    for (path, hashes) in merge_artifacts(recorded)? {
+        if path.value().contains('\n') {
+            return Err(Error::IllegalArgument(format!(
+                "Cannot hash directory {}: path {:?} contains a newline",
+                dir,
+                path.value()
+            )));
+        }
+        let line =
+            format!("{}  {}\n", hashes[hash_algorithm].to_hex(), path.value());
+        context.update(line.as_bytes());
+    }
+    Ok(HashValue::new(context.finish().as_ref().to_vec()))
+}
+
+/// Same as [`record_artifacts`], but records the files committed in the git
+/// repository at `repo` as of `rev` (any tree-ish, e.g. a commit hash, tag or
+/// `HEAD`), instead of the working directory. Uncommitted changes and
+/// untracked files are therefore never recorded.
+///
+/// `paths` are relative to the root of the repository; if empty, the whole
+/// tree is recorded. Recorded paths are relative to the root as well, before
+/// `lstrip_paths` and `exclude_patterns` are applied. Symbolic links are
+/// recorded as stored by git, i.e. with the hash of the link target path,
+/// and submodules are skipped.
+///
+/// This runs the `git` executable, which must be on the `PATH`.
+///
+/// Requires the `git` feature.
+#[cfg(feature = "git")]
+pub fn record_artifacts_from_git(
+    repo: &Path,
+    rev: &str,
+    paths: &[&str],
+    hash_algorithms: Option<&[&str]>,
+    lstrip_paths: Option<&[&str]>,
+    exclude_patterns: Option<&[&str]>,
 ) -> Result<BTreeMap<VirtualTargetPath, TargetDescription>> {
-    // Verify hash_algorithms inputs are valid
+    let hash_algorithms = parse_hash_algorithms(hash_algorithms)?;
+    let exclude_patterns = compile_exclude_patterns(exclude_patterns)?;
+
+    // List the blobs of the tree: "<mode> <type> <object>\t<path>\0"
+    let tree = git_output(
+        process::Command::new("git")
+            .arg("-C")
+            .arg(repo)
+            .args(["ls-tree", "-r", "-z", "--full-tree", rev, "--"])
+            .args(paths),
+    )?;
+    let mut blobs = Vec::new();
+    for entry in tree.split(|b| *b == 0).filter(|entry| !entry.is_empty()) {
+        let entry = String::from_utf8_lossy(entry);
+        let (info, path) = entry.split_once('\t').ok_or_else(|| {
+            Error::Programming(format!(
+                "unexpected git ls-tree entry {}",
+                entry
+            ))
+        })?;
+        let mut info = info.split(' ');
+        if info.nth(1) != Some("blob") {
+            continue;
+        }
+        let object = info.next().unwrap_or_default().to_string();
+        if is_excluded(
+            &apply_left_strip(path, lstrip_paths)?,
+            &exclude_patterns,
+        ) {
+            continue;
+        }
+        blobs.push((path.to_string(), object));
+    }
+
+    // Read all blobs from a single process, one at a time: "<object>\n" is
+    // answered with "<object> blob <size>\n<contents>\n"
+    let mut child = process::Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["cat-file", "--batch"])
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::null())
+        .spawn()
+        .map_err(|e| Error::io("cannot run git", e))?;
+    let mut requests = child.stdin.take().expect("stdin is piped");
+    let mut responses =
+        BufReader::new(child.stdout.take().expect("stdout is piped"));
+    let git_error = |e: io::Error| Error::io("git cat-file", e);
+    let mut record_blob = |path: &str, object: &str| -> Result<_> {
+        writeln!(requests, "{}", object).map_err(git_error)?;
+        requests.flush().map_err(git_error)?;
+        let mut header = String::new();
+        responses.read_line(&mut header).map_err(git_error)?;
+        let size = match header.trim_end().split(' ').collect::<Vec<_>>()[..] {
+            [_, "blob", size] => size.parse::<u64>().ok(),
+            _ => None,
+        }
+        .ok_or_else(|| {
+            Error::IllegalArgument(format!(
+                "git cat-file failed for {}: {}",
+                path,
+                header.trim_end()
+            ))
+        })?;
+        let hashes = record_artifact_reader(
+            responses.by_ref().take(size),
+            &hash_algorithms,
+        )?;
+        responses.read_exact(&mut [0; 1]).map_err(git_error)?;
+        let lstripped_path = apply_left_strip(path, lstrip_paths)?;
+        Ok((VirtualTargetPath::new(lstripped_path)?, hashes))
+    };
+    let recorded = blobs
+        .iter()
+        .map(|(path, object)| Ok((path, record_blob(path, object)?)))
+        .collect::<Result<Vec<_>>>();
+
+    // Closing both pipes ends the process, also after a failed read
+    drop(requests);
+    drop(responses);
+    let status = child.wait().map_err(git_error)?;
+    let recorded = recorded?;
+    if !status.success() {
+        return Err(Error::IllegalArgument(format!(
+            "git cat-file failed: {}",
+            status
+        )));
+    }
+    merge_artifacts(recorded.into_iter().map(Ok))
+}
+
+/// Run a git command, returning its stdout or an error with its stderr.
+#[cfg(feature = "git")]
+fn git_output(command: &mut process::Command) -> Result<Vec<u8>> {
+    let output = command
+        .output()
+        .map_err(|e| Error::io("cannot run git", e))?;
+    if !output.status.success() {
+        return Err(Error::IllegalArgument(format!(
+            "git failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+/// Verify hash_algorithms inputs are valid, defaulting to sha256.
+fn parse_hash_algorithms(
+    hash_algorithms: Option<&[&str]>,
+) -> Result<Vec<HashAlgorithm>> {
     let available_algorithms = HashAlgorithm::return_all();
-    let hash_algorithms = match hash_algorithms {
+    match hash_algorithms {
         Some(hashes) => {
             let mut map = vec![];
             for hash in hashes {
@@ -105,71 +696,423 @@ pub fn record_artifacts(
                 let value = available_algorithms.get(*hash).unwrap();
                 map.push(value.clone());
             }
-            map
+            Ok(map)
         }
-        None => vec![HashAlgorithm::Sha256],
+        None => Ok(vec![HashAlgorithm::Sha256]),
+    }
+}
+
+/// Compile the exclude patterns given to [`record_artifacts`].
+fn compile_exclude_patterns(
+    exclude_patterns: Option<&[&str]>,
+) -> Result<Vec<Pattern>> {
+    exclude_patterns
+        .unwrap_or_default()
+        .iter()
+        .map(|pattern| {
+            Pattern::new(pattern).map_err(|e| {
+                Error::IllegalArgument(format!(
+                    "Invalid exclude pattern {}: {}",
+                    pattern, e
+                ))
+            })
+        })
+        .collect()
+}
+
+/// Walk `paths` and return the files to record, in walk order, skipping
+/// excluded entries and already visited symbolic links.
+fn collect_artifact_paths(
+    paths: &[&str],
+    lstrip_paths: Option<&[&str]>,
+    exclude_patterns: Option<&[&str]>,
+    respect_gitignore: bool,
+    follow_symlinks: bool,
+) -> Result<Vec<String>> {
+    let exclude_patterns = compile_exclude_patterns(exclude_patterns)?;
+    // Whether `path` is a directory the walk descends into
+    let is_dir = |path: &str| {
+        let metadata = if follow_symlinks {
+            metadata(path)
+        } else {
+            symlink_metadata(path)
+        };
+        metadata.map(|m| m.is_dir()).unwrap_or(false)
     };
-    let hash_algorithms = &hash_algorithms[..];
 
-    // Initialize artifacts
-    let mut artifacts: BTreeMap<VirtualTargetPath, TargetDescription> =
-        BTreeMap::new();
-    // For each path provided, walk the directory and add all files to artifacts
+    let mut files = Vec::new();
+    // For each path provided, walk the directory and add all files
     for path in paths {
         // Normalize path
         let path = clean(path);
-        let mut walker = WalkDir::new(path).follow_links(true).into_iter();
+        let mut gitignore = if respect_gitignore {
+            Some(Gitignore::new(&path).map_err(|e| {
+                Error::io(format!("Path {:?}", Path::new(&path)), e)
+            })?)
+        } else {
+            None
+        };
+        let mut walker = WalkDir::new(path)
+            .follow_links(follow_symlinks)
+            .follow_root_links(follow_symlinks)
+            .into_iter();
         let mut visited_sym_links = HashSet::new();
         while let Some(entry) = walker.next() {
             let path = dir_entry_to_path(entry)?;
-            let file_type = std::fs::symlink_metadata(&path)?.file_type();
-            // If entry is a symlink, check it's unvisited. If so, continue.
-            if file_type.is_symlink() {
+            let is_ignored = match gitignore.as_mut() {
+                Some(gitignore) => {
+                    let is_dir = is_dir(&path);
+                    gitignore.is_ignored(Path::new(&path), is_dir).map_err(
+                        |e| {
+                            Error::io(format!("Path {:?}", Path::new(&path)), e)
+                        },
+                    )?
+                }
+                None => false,
+            };
+            // If entry is excluded, skip it and everything below it
+            if is_ignored
+                || is_excluded(
+                    &apply_left_strip(&path, lstrip_paths)?,
+                    &exclude_patterns,
+                )
+            {
+                if is_dir(&path) {
+                    walker.skip_current_dir();
+                }
+                continue;
+            }
+            let file_type = symlink_metadata(&path)
+                .map_err(|e| {
+                    Error::io(format!("Path {:?}", Path::new(&path)), e)
+                })?
+                .file_type();
+            let mut is_file = file_type.is_file();
+            // If entry is a symlink, record it as such when not following
+            // it, or else check it's unvisited. If so, continue.
+            if file_type.is_symlink() && !follow_symlinks {
+                is_file = true;
+            } else if file_type.is_symlink() {
                 if visited_sym_links.contains(&path) {
                     walker.skip_current_dir();
                 } else {
                     visited_sym_links.insert(String::from(&path));
-                    // s_path: the actual path the symbolic link is pointing to
-                    let s_path =
-                        match std::fs::read_link(&path)?.as_path().to_str() {
-                            Some(str) => String::from(str),
-                            None => break,
-                        };
-                    if symlink_metadata(s_path)?.file_type().is_file() {
-                        let (virtual_target_path, hashes) = record_artifact(
-                            &path,
-                            hash_algorithms,
-                            lstrip_paths,
-                        )?;
-                        if artifacts.contains_key(&virtual_target_path) {
-                            return Err(Error::LinkGatheringError(format!(
-                                "non unique stripped path {}",
-                                virtual_target_path.to_string()
-                            )));
-                        }
-                        artifacts.insert(virtual_target_path, hashes);
-                    }
+                    // check what the symbolic link is pointing to
+                    let target_metadata = metadata(&path).map_err(|e| {
+                        Error::io(format!("Path {:?}", Path::new(&path)), e)
+                    })?;
+                    is_file = target_metadata.is_file();
+                }
+            }
+            if is_file {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// The `.gitignore` rules that apply below a directory: those of the
+/// `.gitignore` files of the directory and its parents up to the root of the
+/// enclosing git repository, and the repository's `.git/info/exclude`.
+/// Outside of a repository only the `.gitignore` files below the directory
+/// are used.
+struct Gitignore {
+    /// Root of the repository, or the walked directory outside of one
+    base: PathBuf,
+    /// Rules of each directory, in increasing order of precedence
+    rules: HashMap<PathBuf, Vec<IgnoreRule>>,
+    /// Whether each directory checked so far is ignored
+    ignored_dirs: HashMap<PathBuf, bool>,
+}
+
+impl Gitignore {
+    fn new(root: &Path) -> io::Result<Self> {
+        let root = absolute_path(root)?;
+        let base = root
+            .ancestors()
+            .find(|dir| dir.join(".git").exists())
+            .unwrap_or(&root)
+            .to_path_buf();
+        Ok(Gitignore {
+            base,
+            rules: HashMap::new(),
+            ignored_dirs: HashMap::new(),
+        })
+    }
+
+    /// Whether `path` is ignored, either by a rule or because one of its
+    /// parent directories is. The `.git` directory is always ignored.
+    fn is_ignored(&mut self, path: &Path, is_dir: bool) -> io::Result<bool> {
+        let path = absolute_path(path)?;
+        self.check(&path, is_dir)
+    }
+
+    fn check(&mut self, path: &Path, is_dir: bool) -> io::Result<bool> {
+        if path == self.base || !path.starts_with(&self.base) {
+            return Ok(false);
+        }
+        if path.file_name() == Some(".git".as_ref()) {
+            return Ok(true);
+        }
+        let parent = match path.parent() {
+            Some(parent) => parent,
+            None => return Ok(false),
+        };
+        let parent_ignored = match self.ignored_dirs.get(parent) {
+            Some(ignored) => *ignored,
+            None => {
+                let ignored = self.check(parent, true)?;
+                self.ignored_dirs.insert(parent.to_path_buf(), ignored);
+                ignored
+            }
+        };
+        if parent_ignored {
+            return Ok(true);
+        }
+
+        // The last matching rule wins, and deeper files take precedence
+        let mut dirs: Vec<&Path> = parent
+            .ancestors()
+            .take_while(|dir| dir.starts_with(&self.base))
+            .collect();
+        dirs.reverse();
+        let mut ignored = false;
+        for dir in dirs {
+            if !self.rules.contains_key(dir) {
+                let rules = self.load_rules(dir)?;
+                self.rules.insert(dir.to_path_buf(), rules);
+            }
+            let relative = path
+                .strip_prefix(dir)
+                .unwrap_or(path)
+                .to_string_lossy()
+                .replace(std::path::MAIN_SEPARATOR, "/");
+            for rule in &self.rules[dir] {
+                if rule.matches(&relative, is_dir) {
+                    ignored = !rule.negated;
                 }
             }
-            // If entry is a file, open and hash the file
-            if file_type.is_file() {
-                let (virtual_target_path, hashes) =
-                    record_artifact(&path, hash_algorithms, lstrip_paths)?;
-                if artifacts.contains_key(&virtual_target_path) {
-                    return Err(Error::LinkGatheringError(format!(
-                        "non unique stripped path {}",
-                        virtual_target_path.to_string()
-                    )));
+        }
+        Ok(ignored)
+    }
+
+    fn load_rules(&self, dir: &Path) -> io::Result<Vec<IgnoreRule>> {
+        let mut rules = Vec::new();
+        let mut files = vec![dir.join(".gitignore")];
+        if dir == self.base {
+            files.insert(0, dir.join(".git/info/exclude"));
+        }
+        for file in files {
+            match std::fs::read_to_string(&file) {
+                Ok(content) => {
+                    rules.extend(content.lines().filter_map(IgnoreRule::parse))
                 }
-                artifacts.insert(virtual_target_path, hashes);
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
             }
         }
+        Ok(rules)
+    }
+}
+
+/// A single line of a `.gitignore` file.
+struct IgnoreRule {
+    pattern: Pattern,
+    negated: bool,
+    dir_only: bool,
+    /// Whether the pattern is matched against the path relative to the
+    /// `.gitignore` file, rather than against the file name alone
+    anchored: bool,
+}
+
+impl IgnoreRule {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(line) => (true, line),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        Some(IgnoreRule {
+            pattern: Pattern::new(line).ok()?,
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        if self.anchored {
+            self.pattern.matches_with(relative, options)
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            self.pattern.matches_with(name, options)
+        }
+    }
+}
+
+/// `path` made absolute against the current directory, without resolving
+/// symbolic links.
+fn absolute_path(path: &Path) -> io::Result<PathBuf> {
+    Ok(clean(std::env::current_dir()?.join(path)))
+}
+
+/// Collect recorded artifacts into a map, rejecting paths that collide
+/// after left stripping.
+fn merge_artifacts<'a, I>(
+    recorded: I,
+) -> Result<BTreeMap<VirtualTargetPath, TargetDescription>>
+where
+    I: IntoIterator<
+        Item = Result<(&'a String, (VirtualTargetPath, TargetDescription))>,
+    >,
+{
+    let mut artifacts: BTreeMap<VirtualTargetPath, TargetDescription> =
+        BTreeMap::new();
+    // Source path of every recorded artifact, to report lstrip collisions
+    let mut sources: HashMap<VirtualTargetPath, &String> = HashMap::new();
+    for result in recorded {
+        let (path, (virtual_target_path, hashes)) = result?;
+        if let Some(source) = sources.get(&virtual_target_path) {
+            return Err(Error::IllegalArgument(format!(
+                "non unique stripped path {}: both {} and {} are recorded as it",
+                virtual_target_path, source, path
+            )));
+        }
+        sources.insert(virtual_target_path.clone(), path);
+        artifacts.insert(virtual_target_path, hashes);
     }
     Ok(artifacts)
 }
 
+/// The environment a command is executed in by [`run_command_with_env`] and
+/// [`in_toto_run`], and the limits it is run with. By default the command
+/// inherits the whole environment of the current process and is not
+/// limited. Inspections are run on the verifier's machine, so `clear_env`
+/// can be used to only pass the explicitly set variables.
+///
+/// ```
+/// # use in_toto::runlib::{run_command_with_env, CommandEnvironment};
+/// let env = CommandEnvironment::new()
+///     .clear_env(true)
+///     .set_var("GREETING", "hello");
+/// let byproducts =
+///     run_command_with_env(&["/usr/bin/env"], None, &env).unwrap();
+/// assert_eq!(byproducts.stdout().as_deref(), Some("GREETING=hello\n"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandEnvironment {
+    clear_env: bool,
+    inherit_path: Option<bool>,
+    vars: BTreeMap<String, String>,
+    command_timeout: Option<Duration>,
+    max_output_bytes: Option<usize>,
+}
+
+impl CommandEnvironment {
+    /// Create a `CommandEnvironment` inheriting the environment of the
+    /// current process.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start the command with an empty environment, holding only the
+    /// variables set with `set_var`.
+    pub fn clear_env(mut self, clear_env: bool) -> Self {
+        self.clear_env = clear_env;
+        self
+    }
+
+    /// Whether the command inherits the `PATH` of the current process, also
+    /// with `clear_env`. By default `PATH` is inherited like any other
+    /// variable. A `PATH` set with `set_var` takes precedence.
+    pub fn inherit_path(mut self, inherit_path: bool) -> Self {
+        self.inherit_path = Some(inherit_path);
+        self
+    }
+
+    fn inherits_path(&self) -> bool {
+        self.inherit_path.unwrap_or(!self.clear_env)
+    }
+
+    /// Set the variable `name` to `value` for the command.
+    pub fn set_var(mut self, name: &str, value: &str) -> Self {
+        self.vars.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Kill the command if it runs longer than `timeout`, failing with
+    /// `Error::Timeout`. On Unix the command is started in its own process
+    /// group, which is killed as a whole, so that processes it started in
+    /// the background are killed as well. It then no longer receives
+    /// signals sent to the terminal's foreground process group, e.g. on
+    /// Ctrl-C. Elsewhere only the command itself is killed, and a process
+    /// it started that keeps its output open delays the return until it
+    /// exits.
+    pub fn command_timeout(mut self, timeout: Duration) -> Self {
+        self.command_timeout = Some(timeout);
+        self
+    }
+
+    /// Keep at most `max` bytes of each of `stdout` and `stderr` in the
+    /// byproducts. Output cut at the limit ends with
+    /// [`OUTPUT_TRUNCATED_MARKER`].
+    pub fn max_output_bytes(mut self, max: usize) -> Self {
+        self.max_output_bytes = Some(max);
+        self
+    }
+
+    /// The value of the variable `name` as seen by the command.
+    pub fn var(&self, name: &str) -> Option<String> {
+        if let Some(value) = self.vars.get(name) {
+            return Some(value.clone());
+        }
+        let inherited = if name == "PATH" {
+            self.inherits_path()
+        } else {
+            !self.clear_env
+        };
+        if inherited {
+            std::env::var(name).ok()
+        } else {
+            None
+        }
+    }
+
+    fn apply(&self, cmd: &mut process::Command) {
+        if self.clear_env {
+            cmd.env_clear();
+            if self.inherits_path() {
+                if let Some(path) = std::env::var_os("PATH") {
+                    cmd.env("PATH", path);
+                }
+            }
+        } else if !self.inherits_path() {
+            cmd.env_remove("PATH");
+        }
+        cmd.envs(&self.vars);
+    }
+}
+
 /// Given command arguments, executes commands on a software supply chain step
 /// and returns the `stdout`, `stderr`, and `return-value` as `byproducts` in `Result<ByProducts>` format.
+/// Output that is not valid UTF-8 is decoded lossily, replacing invalid sequences with `U+FFFD`.
 /// If a commands in run_command fails to execute, `Error` is returned.
 /// # Arguments
 ///
@@ -187,6 +1130,15 @@ pub fn record_artifacts(
 pub fn run_command(
     cmd_args: &[&str],
     run_dir: Option<&str>,
+) -> Result<ByProducts> {
+    run_command_with_env(cmd_args, run_dir, &CommandEnvironment::default())
+}
+
+/// [`run_command`] in the environment described by `env`.
+pub fn run_command_with_env(
+    cmd_args: &[&str],
+    run_dir: Option<&str>,
+    env: &CommandEnvironment,
 ) -> Result<ByProducts> {
     // Format output into Byproduct
 
@@ -214,15 +1166,24 @@ pub fn run_command(
 
     // TODO: Validate executable
 
-    let mut cmd = Command::new(executable);
+    let mut cmd = process::Command::new(executable);
     let mut cmd = cmd.args(args);
 
     if let Some(dir) = run_dir {
         cmd = cmd.current_dir(dir)
     }
+    env.apply(cmd);
 
-    let output = match cmd.output() {
-        Ok(out) => out,
+    cmd.stdin(process::Stdio::null())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped());
+    #[cfg(unix)]
+    if env.command_timeout.is_some() {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
         Err(err) => {
             return Err(Error::IllegalArgument(format!(
                 "Something went wrong with run_command inside in_toto_run. Error: {:?}",
@@ -231,32 +1192,39 @@ pub fn run_command(
         }
     };
 
-    // Emit stdout, stderror
-    io::stdout().write_all(&output.stdout)?;
-    io::stderr().write_all(&output.stderr)?;
-
-    // Write to byproducts
-    let stdout = match String::from_utf8(output.stdout) {
-        Ok(output) => output,
-        Err(error) => {
-            return Err(Error::from(io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Utf8Error: {}", error),
-            )))
-        }
+    // Read both pipes while waiting, so that a command filling one of them
+    // does not block forever.
+    let stdout = capture_output(child.stdout.take(), env.max_output_bytes);
+    let stderr = capture_output(child.stderr.take(), env.max_output_bytes);
+    let status = match env.command_timeout {
+        Some(timeout) => wait_with_timeout(&mut child, timeout),
+        None => child.wait().map_err(Error::from),
     };
-    let stderr = match String::from_utf8(output.stderr) {
-        Ok(output) => output,
-        Err(error) => {
-            return Err(Error::from(io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Utf8Error: {}", error),
-            )))
-        }
+    // The pipes are closed once the command and everything killed with it
+    // have exited, also after a timeout, so the readers are always joined.
+    let join = |reader: thread::JoinHandle<io::Result<(Vec<u8>, bool)>>| {
+        let output = reader.join().map_err(|_| {
+            Error::RunLibError("Reading command output panicked".to_string())
+        })?;
+        Ok::<_, Error>(output?)
     };
-    let status = output.status.code().ok_or_else(|| {
-        Error::RunLibError("Process terminated by signal".to_string())
-    })?;
+    let stdout = join(stdout);
+    let stderr = join(stderr);
+    let status = status?;
+    let (stdout, stdout_truncated) = stdout?;
+    let (stderr, stderr_truncated) = stderr?;
+
+    // Emit stdout, stderror
+    io::stdout().write_all(&stdout)?;
+    io::stderr().write_all(&stderr)?;
+
+    // Write to byproducts. Invalid UTF-8 sequences are replaced with U+FFFD
+    // REPLACEMENT CHARACTER.
+    let stdout = output_to_string(&stdout, stdout_truncated);
+    let stderr = output_to_string(&stderr, stderr_truncated);
+    let status = status.code().ok_or_else(|| {
+        Error::RunLibError("Process terminated by signal".to_string())
+    })?;
 
     let byproducts = ByProducts::new()
         .set_stdout(stdout)
@@ -266,7 +1234,189 @@ pub fn run_command(
     Ok(byproducts)
 }
 
-// TODO: implement default trait for in_toto_run's parameters
+/// Appended to the `stdout` or `stderr` byproduct of a command whose output
+/// was cut to `CommandEnvironment::max_output_bytes`.
+pub const OUTPUT_TRUNCATED_MARKER: &str = "\n[output truncated by in-toto]";
+
+/// Read `pipe` to its end on a separate thread, keeping at most `limit`
+/// bytes. Returns the kept bytes and whether any were dropped.
+fn capture_output<R: Read + Send + 'static>(
+    pipe: Option<R>,
+    limit: Option<usize>,
+) -> thread::JoinHandle<io::Result<(Vec<u8>, bool)>> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        let mut truncated = false;
+        let mut pipe = match pipe {
+            Some(pipe) => pipe,
+            None => return Ok((output, truncated)),
+        };
+        let mut buf = [0; 8192];
+        loop {
+            let read = match pipe.read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            // keep draining past the limit, so the command is not blocked
+            let keep = match limit {
+                Some(limit) => read.min(limit - output.len()),
+                None => read,
+            };
+            truncated |= keep < read;
+            output.extend_from_slice(&buf[..keep]);
+        }
+        Ok((output, truncated))
+    })
+}
+
+/// Wait for `child` to exit, killing it if it runs longer than `timeout`,
+/// together with its process group on Unix.
+fn wait_with_timeout(
+    child: &mut process::Child,
+    timeout: Duration,
+) -> Result<process::ExitStatus> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            // the child may have exited in the meantime
+            kill_process_group(child);
+            child.wait()?;
+            return Err(Error::Timeout(timeout));
+        }
+        thread::sleep((deadline - now).min(Duration::from_millis(10)));
+    }
+}
+
+/// Kill the process group `child` leads, as started with a timeout.
+#[cfg(unix)]
+fn kill_process_group(child: &mut process::Child) {
+    // SAFETY: kill has no memory safety requirements. The group still
+    // exists as its leader has not been waited on.
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_process_group(child: &mut process::Child) {
+    let _ = child.kill();
+}
+
+fn output_to_string(output: &[u8], truncated: bool) -> String {
+    let mut output = String::from_utf8_lossy(output).into_owned();
+    if truncated {
+        output.push_str(OUTPUT_TRUNCATED_MARKER);
+    }
+    output
+}
+
+/// How [`in_toto_run`] runs the command and records its artifacts. By
+/// default the command is run in the current directory with the current
+/// environment, artifacts are hashed with sha256 as [`record_artifacts`]
+/// does with the default [`RecordOptions`], no environment is recorded and
+/// the link is not signed.
+///
+/// ```
+/// # use in_toto::runlib::{CommandEnvironment, RunOptions};
+/// let options = RunOptions::new()
+///     .run_dir("tests")
+///     .hash_algorithms(&["sha512", "sha256"])
+///     .lstrip_paths(&["tests/"])
+///     .command_env(CommandEnvironment::new().clear_env(true));
+/// # let _ = options;
+/// ```
+#[derive(Clone, Default)]
+pub struct RunOptions<'a> {
+    run_dir: Option<String>,
+    key: Option<&'a PrivateKey>,
+    hash_algorithms: Option<Vec<String>>,
+    lstrip_paths: Option<Vec<String>>,
+    exclude_patterns: Option<Vec<String>>,
+    env_capture: Option<Vec<String>>,
+    command_env: CommandEnvironment,
+    record_options: RecordOptions,
+}
+
+impl<'a> RunOptions<'a> {
+    /// Create the default `RunOptions`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run the command in `run_dir` instead of the current directory.
+    pub fn run_dir(mut self, run_dir: &str) -> Self {
+        self.run_dir = Some(run_dir.to_string());
+        self
+    }
+
+    /// Sign the link with `key`. Without a key the link is returned without
+    /// signatures, e.g. to be signed later in a job holding the key with
+    /// `MetablockBuilder::from_raw_metadata`.
+    pub fn key(mut self, key: &'a PrivateKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// Hash artifacts with `hash_algorithms` instead of sha256.
+    pub fn hash_algorithms(mut self, hash_algorithms: &[&str]) -> Self {
+        self.hash_algorithms = Some(to_strings(hash_algorithms));
+        self
+    }
+
+    /// Left strip the longest of `lstrip_paths` that prefixes the path of an
+    /// artifact, see [`record_artifacts`].
+    pub fn lstrip_paths(mut self, lstrip_paths: &[&str]) -> Self {
+        self.lstrip_paths = Some(to_strings(lstrip_paths));
+        self
+    }
+
+    /// Do not record materials and products matching any of the
+    /// gitignore-style glob patterns `exclude_patterns`, see
+    /// [`record_artifacts`].
+    pub fn exclude_patterns(mut self, exclude_patterns: &[&str]) -> Self {
+        self.exclude_patterns = Some(to_strings(exclude_patterns));
+        self
+    }
+
+    /// Record the values of the environment variables `names` in the link's
+    /// `environment`, together with the `workdir` the command is run in.
+    /// Unset variables are omitted.
+    pub fn env_capture(mut self, names: &[&str]) -> Self {
+        self.env_capture = Some(to_strings(names));
+        self
+    }
+
+    /// Run the command in `command_env`, e.g. to start it with an empty
+    /// environment, and limit its run time and captured output. The
+    /// recorded `environment` holds the values the command sees.
+    pub fn command_env(mut self, command_env: CommandEnvironment) -> Self {
+        self.command_env = command_env;
+        self
+    }
+
+    /// Read files and walk directories as set in `record_options` when
+    /// recording materials and products.
+    pub fn record_options(mut self, record_options: RecordOptions) -> Self {
+        self.record_options = record_options;
+        self
+    }
+}
+
+fn to_strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+fn as_strs(values: &Option<Vec<String>>) -> Option<Vec<&str>> {
+    values
+        .as_ref()
+        .map(|values| values.iter().map(String::as_str).collect())
+}
 
 /// Executes commands on a software supply chain step, then generates and returns its corresponding `LinkMetadata`
 /// as a `Metablock` component, wrapped in `Result`.
@@ -274,64 +1424,249 @@ pub fn run_command(
 /// # Arguments
 ///
 /// * `name` - The unique string used to associate link metadata with a step or inspection.
-/// * `run_dir` - A string slice (`&str`) wrapped in an `Option` that holds the directory the commands are to be run in. If `None` is provided, the current directory is assumed as default.
 /// * `material_paths` - A string slice (`&str`) of artifact paths to be recorded before command execution. Directories are traversed recursively.
 /// * `product_paths` - A string slice (`&str`) of artifact paths to be recorded after command execution. Directories are traversed recursively.
 /// * `cmd_args` - A string slice (`&str`) where the first element is a command and the remaining elements are arguments passed to that command.
-/// * `key` -  A key used to sign the resulting link metadata.
-/// * `hash_algorithms` - An array of string slice (`&str`) wrapped in an `Option` that holds the hash algorithms to be used. If `None` is provided, Sha256 is assumed as default.
-/// * `lstrip_paths` - An array of string slice (`&str`) wrapped in an `Option` that is left stripped from the path of every artifact that contains it.
+/// * `options` - Where and how the command is run, how artifacts are recorded and the key signing the link, see [`RunOptions`].
 ///
 /// # Examples
 ///
 /// ```
 /// // You can have rust code between fences inside the comments
 /// // If you pass --test to `rustdoc`, it will even test it for you!
-/// # use in_toto::runlib::{in_toto_run};
+/// # use in_toto::runlib::{in_toto_run, RunOptions};
 /// # use in_toto::crypto::PrivateKey;
 /// const ED25519_1_PRIVATE_KEY: &'static [u8] = include_bytes!("../tests/ed25519/ed25519-1");
 /// let key = PrivateKey::from_ed25519(ED25519_1_PRIVATE_KEY).unwrap();
-/// let link = in_toto_run("example", Some("tests"), &["tests/test_runlib"], &["tests/test_runlib"],  &["sh", "-c", "echo 'in_toto says hi' >> hello_intoto"], Some(&key), Some(&["sha512", "sha256"]), Some(&["tests/test_runlib/"])).unwrap();
+/// let options = RunOptions::new()
+///     .run_dir("tests")
+///     .key(&key)
+///     .hash_algorithms(&["sha512", "sha256"])
+///     .lstrip_paths(&["tests/test_runlib/"]);
+/// let link = in_toto_run("example", &["tests/test_runlib"], &["tests/test_runlib"],  &["sh", "-c", "echo 'in_toto says hi' >> hello_intoto"], &options).unwrap();
 /// let json = serde_json::to_value(&link).unwrap();
 /// println!("Generated link: {}", json);
 /// ```
 pub fn in_toto_run(
     name: &str,
-    run_dir: Option<&str>,
     material_paths: &[&str],
     product_paths: &[&str],
     cmd_args: &[&str],
-    key: Option<&PrivateKey>,
-    hash_algorithms: Option<&[&str]>,
-    lstrip_paths: Option<&[&str]>,
-    // env: Option<BTreeMap<String, String>>
+    options: &RunOptions,
 ) -> Result<Metablock> {
+    let run_dir = options.run_dir.as_deref();
+    let command_env = &options.command_env;
+    let hash_algorithms = as_strs(&options.hash_algorithms);
+    let lstrip_paths = as_strs(&options.lstrip_paths);
+    let exclude_patterns = as_strs(&options.exclude_patterns);
+
+    // Capture the whitelisted environment before running anything
+    let env = match as_strs(&options.env_capture) {
+        Some(names) => Some(capture_environment(&names, run_dir, command_env)?),
+        None => None,
+    };
+
     // Record Materials: Given the material_paths, recursively traverse and record files in given path(s)
-    let materials =
-        record_artifacts(material_paths, hash_algorithms, lstrip_paths)?;
+    let materials = record_artifacts(
+        material_paths,
+        hash_algorithms.as_deref(),
+        lstrip_paths.as_deref(),
+        exclude_patterns.as_deref(),
+        &options.record_options,
+    )?;
 
     // Execute commands provided in cmd_args
-    let byproducts = run_command(cmd_args, run_dir)?;
+    let byproducts = run_command_with_env(cmd_args, run_dir, command_env)?;
 
     // Record Products: Given the product_paths, recursively traverse and record files in given path(s)
-    let products =
-        record_artifacts(product_paths, hash_algorithms, lstrip_paths)?;
+    let products = record_artifacts(
+        product_paths,
+        hash_algorithms.as_deref(),
+        lstrip_paths.as_deref(),
+        exclude_patterns.as_deref(),
+        &options.record_options,
+    )?;
 
     // Create link based on values collected above
+    let command: Vec<String> =
+        cmd_args.iter().map(|arg| arg.to_string()).collect();
     let link_metadata_builder = LinkMetadataBuilder::new()
         .name(name.to_string())
         .materials(materials)
         .byproducts(byproducts)
-        .products(products);
+        .products(products)
+        .env(env)
+        .command(Command::from(command));
 
     // Sign the link with key param supplied. If no key is found, return Metablock with
     // no signatures (for inspection purposes)
-    match key {
+    match options.key {
         Some(k) => link_metadata_builder.signed::<Json>(k),
         None => link_metadata_builder.unsigned::<Json>(),
     }
 }
 
+/// Record a step the way the `in-toto-run` command line tool does, from plain
+/// arguments, so that a command line tool can be built on it in a few lines.
+/// Returns the name of the file the link is to be written to, following
+/// [`link_filename`], and the link as JSON.
+///
+/// * `key_path` - The PKCS#8 private key signing the link, PEM encoded or
+///   DER, used with `scheme`.
+/// * `base_path` - The directory the command is run in. Material and product
+///   paths are relative to it and recorded as such. If `None` is provided,
+///   the current directory is used.
+///
+/// The other arguments are those of [`in_toto_run`] and [`RunOptions`].
+///
+/// ```
+/// # use std::path::Path;
+/// # use in_toto::crypto::SignatureScheme;
+/// # use in_toto::runlib::run;
+/// let (file_name, link) = run(
+///     "example",
+///     &["test_runlib"],
+///     &["test_runlib"],
+///     &["true"],
+///     Path::new("tests/ed25519/ed25519-1.pk8.der"),
+///     SignatureScheme::Ed25519,
+///     Some(&["sha256"]),
+///     Some("tests"),
+/// )
+/// .unwrap();
+/// assert!(file_name.starts_with("example."));
+/// # let _ = link;
+/// ```
+pub fn run(
+    name: &str,
+    material_paths: &[&str],
+    product_paths: &[&str],
+    cmd_args: &[&str],
+    key_path: &Path,
+    scheme: crypto::SignatureScheme,
+    hash_algorithms: Option<&[&str]>,
+    base_path: Option<&str>,
+) -> Result<(String, Vec<u8>)> {
+    let key = load_signing_key(key_path, scheme)?;
+    let (material_paths, product_paths, lstrip_path) = match base_path {
+        Some(base) => {
+            let rebase = |paths: &[&str]| -> Vec<String> {
+                paths
+                    .iter()
+                    .map(|path| {
+                        clean(Path::new(base).join(path))
+                            .to_string_lossy()
+                            .into_owned()
+                    })
+                    .collect()
+            };
+            let base = clean(base).to_string_lossy().into_owned();
+            (
+                rebase(material_paths),
+                rebase(product_paths),
+                Some(format!("{}/", base.trim_end_matches('/'))),
+            )
+        }
+        None => (
+            material_paths.iter().map(|p| p.to_string()).collect(),
+            product_paths.iter().map(|p| p.to_string()).collect(),
+            None,
+        ),
+    };
+    let material_paths: Vec<&str> =
+        material_paths.iter().map(String::as_str).collect();
+    let product_paths: Vec<&str> =
+        product_paths.iter().map(String::as_str).collect();
+    let mut options = RunOptions::new().key(&key);
+    if let Some(base) = base_path {
+        options = options.run_dir(base);
+    }
+    if let Some(hash_algorithms) = hash_algorithms {
+        options = options.hash_algorithms(hash_algorithms);
+    }
+    if let Some(lstrip_path) = &lstrip_path {
+        options = options.lstrip_paths(&[lstrip_path]);
+    }
+
+    let link =
+        in_toto_run(name, &material_paths, &product_paths, cmd_args, &options)?;
+    let mut bytes = Vec::new();
+    Json::to_writer(&mut bytes, &link)?;
+    Ok((link_filename(name, key.key_id()), bytes))
+}
+
+/// Load a PKCS#8 private key from `path`, PEM encoded or DER.
+fn load_signing_key(
+    path: &Path,
+    scheme: crypto::SignatureScheme,
+) -> Result<PrivateKey> {
+    let bytes = std::fs::read(path)?;
+    match std::str::from_utf8(&bytes) {
+        Ok(pem) if pem.starts_with("-----BEGIN") => {
+            PrivateKey::from_pem(pem, scheme)
+        }
+        _ => PrivateKey::from_pkcs8(&bytes, scheme),
+    }
+}
+
+/// Name of the file holding the link of `step_name` signed by `key_id`,
+/// following the `<step_name>.<8-char-keyid>.link` convention.
+///
+/// ```
+/// # use std::str::FromStr;
+/// # use in_toto::crypto::KeyId;
+/// # use in_toto::runlib::link_filename;
+/// let key_id = KeyId::from_str(
+///     "e0294a3f17cc8563c3ed5fceb3bd8d3f6bfeeaca499b5c9572729ae015566554",
+/// )
+/// .unwrap();
+/// assert_eq!(link_filename("build", &key_id), "build.e0294a3f.link");
+/// ```
+pub fn link_filename(step_name: &str, key_id: &KeyId) -> String {
+    format!("{}.{}.link", step_name, key_id.prefix())
+}
+
+/// Split a link file name produced by [`link_filename`] into the step name
+/// and the 8-char key id prefix. Step names may themselves contain dots, so
+/// the key id is taken from the last component. Returns `None` if the name
+/// does not follow the convention.
+pub fn parse_link_filename(filename: &str) -> Option<(String, String)> {
+    let (step_name, key_id) =
+        filename.strip_suffix(".link")?.rsplit_once('.')?;
+    let is_prefix =
+        key_id.len() == 8 && key_id.bytes().all(|b| b.is_ascii_hexdigit());
+    if step_name.is_empty() || !is_prefix {
+        return None;
+    }
+    Some((step_name.to_string(), key_id.to_string()))
+}
+
+/// Record the values of the given environment variables as the command sees
+/// them in `command_env`, omitting unset ones, and the directory the command
+/// is run in as `workdir`.
+fn capture_environment(
+    names: &[&str],
+    run_dir: Option<&str>,
+    command_env: &CommandEnvironment,
+) -> Result<BTreeMap<String, String>> {
+    let mut env: BTreeMap<String, String> = names
+        .iter()
+        .filter_map(|name| {
+            command_env.var(name).map(|value| (name.to_string(), value))
+        })
+        .collect();
+
+    let workdir = match run_dir {
+        Some(dir) => canonicalize_path(dir)?,
+        None => std::env::current_dir()?,
+    };
+    env.insert(
+        "workdir".to_string(),
+        workdir.to_string_lossy().replace('\\', "/"),
+    );
+    Ok(env)
+}
+
 /// A private helper function that, given a `DirEntry`, return the entry's path as a `String`
 /// wrapped in `Result`. If the entry's path is invalid, `Error` is returned.
 fn dir_entry_to_path(
@@ -361,10 +1696,10 @@ fn dir_entry_to_path(
             if error.loop_ancestor().is_some() {
                 match error.path() {
                     None => {
-                        return Err(Error::from(io::Error::new(
-                            std::io::ErrorKind::Other,
-                            format!("Walkdir Error: {}", error),
-                        )))
+                        return Err(Error::from(io::Error::other(format!(
+                            "Walkdir Error: {}",
+                            error
+                        ))))
                     }
                     Some(error_path) => {
                         let sym_path = match error_path.to_str() {
@@ -382,10 +1717,15 @@ fn dir_entry_to_path(
                     }
                 }
             } else {
-                return Err(Error::from(io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("Walkdir Error: {}", error),
-                )));
+                return Err(match (error.io_error(), error.path()) {
+                    (Some(io_error), Some(path)) => {
+                        Error::from_io(io_error, path)
+                    }
+                    _ => Error::io(
+                        "Walkdir Error",
+                        io::Error::other(error.to_string()),
+                    ),
+                });
             }
         }
     };
@@ -398,6 +1738,7 @@ mod test {
     use std::collections::HashMap;
 
     use super::*;
+    use std::str::FromStr;
 
     fn create_target_description(
         hash_algorithm: crypto::HashAlgorithm,
@@ -437,14 +1778,174 @@ mod test {
             ),
         );
         assert_eq!(
-            record_artifacts(&["tests/test_runlib"], None, None).unwrap(),
+            record_artifacts(
+                &["tests/test_runlib"],
+                None,
+                None,
+                None,
+                &RecordOptions::new()
+            )
+            .unwrap(),
             expected
         );
-        assert_eq!(record_artifacts(&["tests"], None, None).is_ok(), true);
+        assert!(record_artifacts(
+            &["tests"],
+            None,
+            None,
+            None,
+            &RecordOptions::new()
+        )
+        .is_ok());
+        assert!(record_artifacts(
+            &["file-does-not-exist"],
+            None,
+            None,
+            None,
+            &RecordOptions::new()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_record_artifact_large_file() {
+        use std::io::{Seek, SeekFrom};
+
+        // A sparse file spanning many hashing chunks, with data at the
+        // start, across a chunk boundary and at the end.
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"start").unwrap();
+        file.seek(SeekFrom::Start(64 * 1024 - 2)).unwrap();
+        file.write_all(b"boundary").unwrap();
+        file.as_file().set_len(16 * 1024 * 1024).unwrap();
+        file.seek(SeekFrom::End(0)).unwrap();
+        file.write_all(b"end").unwrap();
+        file.flush().unwrap();
+
+        let contents = std::fs::read(file.path()).unwrap();
+        let algorithms = [HashAlgorithm::Sha256, HashAlgorithm::Sha512];
+        let path = file.path().to_str().unwrap();
+        let (_, streamed) = record_artifact(path, &algorithms, None).unwrap();
+
+        assert_eq!(
+            streamed[&HashAlgorithm::Sha256].value(),
+            ring::digest::digest(&ring::digest::SHA256, &contents).as_ref()
+        );
+        assert_eq!(
+            streamed[&HashAlgorithm::Sha512].value(),
+            ring::digest::digest(&ring::digest::SHA512, &contents).as_ref()
+        );
+        assert_eq!(
+            record_artifact_reader(&contents[..], &algorithms).unwrap(),
+            streamed
+        );
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_record_artifacts_parallel() {
+        use crate::interchange::DataInterchange;
+
+        let serial = record_artifacts(
+            &["tests"],
+            Some(&["sha256", "sha512"]),
+            None,
+            Some(&["hello_intoto"]),
+            &RecordOptions::new(),
+        )
+        .unwrap();
+        let parallel = record_artifacts_parallel(
+            &["tests"],
+            Some(&["sha256", "sha512"]),
+            None,
+            Some(&["hello_intoto"]),
+            &RecordOptions::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            Json::canonicalize(&Json::serialize(&parallel).unwrap()).unwrap(),
+            Json::canonicalize(&Json::serialize(&serial).unwrap()).unwrap()
+        );
+
+        // Errors from the workers are reported, not swallowed.
+        assert!(record_artifacts_parallel(
+            &["file-does-not-exist"],
+            None,
+            None,
+            None,
+            &RecordOptions::new()
+        )
+        .is_err());
+        // Both agree on a tree recorded with stripped prefixes.
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..32 {
+            let sub = dir.path().join(format!("dir{}", i % 4));
+            std::fs::create_dir_all(&sub).unwrap();
+            std::fs::write(sub.join(format!("file{}", i)), i.to_string())
+                .unwrap();
+        }
+        let root = dir.path().to_str().unwrap();
+        let prefix = format!("{}/", root);
+        let record = |parallel: bool| {
+            let record = if parallel {
+                record_artifacts_parallel
+            } else {
+                record_artifacts
+            };
+            record(&[root], None, Some(&[&prefix]), None, &RecordOptions::new())
+        };
+        let serial = record(false).unwrap();
+        assert_eq!(serial.len(), 32);
+        assert!(serial.contains_key(
+            &VirtualTargetPath::new("dir1/file5".to_string()).unwrap()
+        ));
+        assert_eq!(record(true).unwrap(), serial);
+
+        // Paths colliding once stripped fail either way.
+        let paths = &["tests/test_runlib/hello./world", "tests/test_runlib"];
+        for record in [record_artifacts_parallel, record_artifacts] {
+            assert!(record(
+                paths,
+                None,
+                Some(&["tests/"]),
+                None,
+                &RecordOptions::new()
+            )
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn test_record_artifacts_multiple_hashes() {
+        let path = VirtualTargetPath::new(
+            "tests/test_runlib/hello./world".to_string(),
+        )
+        .unwrap();
+        let artifacts = record_artifacts(
+            &["tests/test_runlib/hello./world"],
+            Some(&["sha256", "sha512"]),
+            None,
+            None,
+            &RecordOptions::new(),
+        )
+        .unwrap();
+
+        let description = &artifacts[&path];
+        assert_eq!(description.len(), 2);
         assert_eq!(
-            record_artifacts(&["file-does-not-exist"], None, None).is_err(),
-            true
+            description[&crypto::HashAlgorithm::Sha256].to_string(),
+            "25623b53e0984428da972f4c635706d32d01ec92dcd2ab39066082e0b9488c9d"
         );
+        assert!(description.contains_key(&crypto::HashAlgorithm::Sha512));
+
+        let artifacts = record_artifacts(
+            &["tests/test_runlib/hello./world"],
+            Some(&["sha384"]),
+            None,
+            None,
+            &RecordOptions::new(),
+        )
+        .unwrap();
+        assert!(artifacts[&path].contains_key(&crypto::HashAlgorithm::Sha384));
     }
 
     #[test]
@@ -462,21 +1963,22 @@ mod test {
             record_artifacts(
                 &["tests/test_prefix/left"],
                 None,
-                Some(&["tests/test_prefix/left/"])
+                Some(&["tests/test_prefix/left/"]),
+                None,
+                &RecordOptions::new()
             )
             .unwrap(),
             expected
         );
         // conflict of file "left/world" and "right/world"
-        assert_eq!(
-            record_artifacts(
-                &["tests/test_prefix"],
-                None,
-                Some(&["tests/test_prefix/left/", "tests/test_prefix/right/"])
-            )
-            .is_err(),
-            true
-        );
+        assert!(record_artifacts(
+            &["tests/test_prefix"],
+            None,
+            Some(&["tests/test_prefix/left/", "tests/test_prefix/right/"]),
+            None,
+            &RecordOptions::new()
+        )
+        .is_err());
     }
 
     #[test]
@@ -537,9 +2039,857 @@ mod test {
 
         assert_eq!(byproducts, expected);
 
+        assert!(run_command(&["command-does-not-exist", "true"], None).is_err());
+    }
+
+    #[test]
+    fn test_run_command_byproducts() {
+        let byproducts = run_command(&["echo", "hello"], None).unwrap();
+        assert_eq!(byproducts.stdout(), &Some("hello\n".to_string()));
+        assert_eq!(byproducts.return_value(), Some(0));
+
+        let byproducts =
+            run_command(&["sh", "-c", "printf 'a\\377b' >&2; exit 3"], None)
+                .unwrap();
+        assert_eq!(byproducts.stderr(), &Some("a\u{FFFD}b".to_string()));
+        assert_eq!(byproducts.return_value(), Some(3));
+    }
+
+    #[test]
+    fn test_run_command_with_env() {
+        let env_lines = |env: &CommandEnvironment| -> Vec<String> {
+            let byproducts =
+                run_command_with_env(&["/usr/bin/env"], None, env).unwrap();
+            let mut lines: Vec<String> = byproducts
+                .stdout()
+                .as_ref()
+                .unwrap()
+                .lines()
+                .map(String::from)
+                .collect();
+            lines.sort();
+            lines
+        };
+        let path = std::env::var("PATH").unwrap();
+
+        // only the provided variables
+        let env = CommandEnvironment::new()
+            .clear_env(true)
+            .set_var("B", "2")
+            .set_var("A", "1");
+        assert_eq!(env_lines(&env), ["A=1", "B=2"]);
+        assert_eq!(env.var("A").as_deref(), Some("1"));
+        assert_eq!(env.var("PATH"), None);
+
+        // and the PATH of the parent
+        let env = CommandEnvironment::new()
+            .clear_env(true)
+            .inherit_path(true)
+            .set_var("A", "1");
+        assert_eq!(
+            env_lines(&env),
+            ["A=1".to_string(), format!("PATH={}", path)]
+        );
+
+        let env = CommandEnvironment::new()
+            .clear_env(true)
+            .set_var("PATH", "/bin");
+        assert_eq!(env_lines(&env), ["PATH=/bin"]);
+
+        // the inherited environment without PATH
+        let env = CommandEnvironment::new().inherit_path(false);
+        let lines = env_lines(&env);
+        assert!(!lines.iter().any(|line| line.starts_with("PATH=")));
+        let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        assert!(lines.contains(&format!("CARGO_MANIFEST_DIR={}", manifest_dir)));
+        assert_eq!(env.var("CARGO_MANIFEST_DIR"), Some(manifest_dir));
+    }
+
+    #[test]
+    fn test_run_command_timeout() {
+        let timeout = std::time::Duration::from_millis(100);
+        let env = CommandEnvironment::new().command_timeout(timeout);
+        let start = std::time::Instant::now();
         assert_eq!(
-            run_command(&["command-does-not-exist", "true"], None).is_err(),
-            true
+            run_command_with_env(&["sleep", "5"], None, &env),
+            Err(Error::Timeout(timeout))
         );
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+
+        let byproducts = run_command_with_env(&["true"], None, &env).unwrap();
+        assert_eq!(byproducts.return_value(), Some(0));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_command_timeout_kills_process_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let timeout = std::time::Duration::from_millis(100);
+        let env = CommandEnvironment::new().command_timeout(timeout);
+        let start = std::time::Instant::now();
+        // the background sleep holds on to the output pipes, so this only
+        // returns once it is killed too
+        assert_eq!(
+            run_command_with_env(
+                &["sh", "-c", "sleep 100 & echo $! > pid; sleep 100"],
+                dir.path().to_str(),
+                &env
+            ),
+            Err(Error::Timeout(timeout))
+        );
+        assert!(start.elapsed() < std::time::Duration::from_secs(10));
+
+        // and it is gone, or at most a zombie waiting to be reaped
+        let pid = std::fs::read_to_string(dir.path().join("pid")).unwrap();
+        let ps = process::Command::new("ps")
+            .args(["-o", "stat=", "-p", pid.trim()])
+            .output()
+            .unwrap();
+        let stat = String::from_utf8(ps.stdout).unwrap();
+        assert!(stat.trim().is_empty() || stat.starts_with('Z'), "{}", stat);
+    }
+
+    #[test]
+    fn test_run_command_max_output_bytes() {
+        // both pipes get more than fits into their buffers
+        let cmd = [
+            "sh",
+            "-c",
+            "head -c 200000 /dev/zero | tr '\\0' a; \
+             head -c 200000 /dev/zero | tr '\\0' b >&2",
+        ];
+        let byproducts = run_command(&cmd, None).unwrap();
+        assert_eq!(byproducts.stdout().as_ref().unwrap().len(), 200000);
+        assert_eq!(byproducts.stderr().as_ref().unwrap().len(), 200000);
+
+        let env = CommandEnvironment::new().max_output_bytes(1000);
+        let byproducts = run_command_with_env(&cmd, None, &env).unwrap();
+        assert_eq!(
+            byproducts.stdout().as_deref(),
+            Some(format!("{}{}", "a".repeat(1000), OUTPUT_TRUNCATED_MARKER))
+                .as_deref()
+        );
+        assert_eq!(
+            byproducts.stderr().as_deref(),
+            Some(format!("{}{}", "b".repeat(1000), OUTPUT_TRUNCATED_MARKER))
+                .as_deref()
+        );
+        assert_eq!(byproducts.return_value(), Some(0));
+
+        // output within the limit is kept unchanged
+        let byproducts =
+            run_command_with_env(&["echo", "hello"], None, &env).unwrap();
+        assert_eq!(byproducts.stdout(), &Some("hello\n".to_string()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_record_artifacts_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir_path = dir.path().to_str().unwrap();
+        std::fs::write(dir.path().join("foo"), "foo\n").unwrap();
+        std::os::unix::fs::symlink("foo", dir.path().join("link")).unwrap();
+
+        let artifacts = record_artifacts(
+            &[dir_path],
+            None,
+            None,
+            None,
+            &RecordOptions::new(),
+        )
+        .unwrap();
+        let foo = VirtualTargetPath::new(format!("{}/foo", dir_path)).unwrap();
+        let link =
+            VirtualTargetPath::new(format!("{}/link", dir_path)).unwrap();
+        assert_eq!(artifacts.len(), 2);
+        assert_eq!(artifacts[&foo], artifacts[&link]);
+
+        std::os::unix::fs::symlink("missing", dir.path().join("dangling"))
+            .unwrap();
+        assert!(matches!(
+            record_artifacts(
+                &[dir_path],
+                None,
+                None,
+                None,
+                &RecordOptions::new()
+            ),
+            Err(Error::Io { .. })
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_record_artifacts_symlinks_not_followed() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir_path = dir.path().to_str().unwrap();
+        let lstrip = format!("{}/", dir_path);
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("sub/foo"), "foo\n").unwrap();
+        std::os::unix::fs::symlink("sub/foo", dir.path().join("link")).unwrap();
+        std::os::unix::fs::symlink("sub", dir.path().join("dir_link")).unwrap();
+        std::os::unix::fs::symlink("missing", dir.path().join("dangling"))
+            .unwrap();
+
+        let record = |follow_symlinks| {
+            record_artifacts(
+                &[dir_path],
+                None,
+                Some(&[&lstrip]),
+                None,
+                &RecordOptions::new().follow_symlinks(follow_symlinks),
+            )
+        };
+        let artifacts = record(false).unwrap();
+        let paths: Vec<_> = artifacts.keys().map(|p| p.value()).collect();
+        assert_eq!(paths, ["dangling", "dir_link", "link", "sub/foo"]);
+        let link = VirtualTargetPath::new("link".into()).unwrap();
+        let target =
+            record_artifact_reader(&b"sub/foo"[..], &[HashAlgorithm::Sha256])
+                .unwrap();
+        assert_eq!(artifacts[&link], target);
+
+        // Followed, the dangling link cannot be recorded.
+        assert!(matches!(record(true), Err(Error::Io { .. })));
+        std::fs::remove_file(dir.path().join("dangling")).unwrap();
+        let artifacts = record(true).unwrap();
+        let paths: Vec<_> = artifacts.keys().map(|p| p.value()).collect();
+        assert_eq!(paths, ["dir_link/foo", "link", "sub/foo"]);
+        let foo = VirtualTargetPath::new("sub/foo".into()).unwrap();
+        assert_eq!(artifacts[&foo], artifacts[&link]);
+    }
+
+    /// An object store held in memory.
+    struct MemorySource(BTreeMap<&'static str, &'static [u8]>);
+
+    impl ArtifactSource for MemorySource {
+        fn list(&self, path: &str) -> Result<Vec<String>> {
+            let dir = format!("{}/", path.trim_end_matches('/'));
+            let files: Vec<String> = self
+                .0
+                .keys()
+                .filter(|file| **file == path || file.starts_with(&dir))
+                .map(|file| file.to_string())
+                .collect();
+            if files.is_empty() {
+                return Err(Error::NotFound);
+            }
+            Ok(files)
+        }
+
+        fn open(&self, path: &str) -> Result<Box<dyn Read + '_>> {
+            Ok(Box::new(self.0[path]))
+        }
+    }
+
+    #[test]
+    fn test_record_artifacts_from_source() {
+        let source = MemorySource(BTreeMap::from([
+            ("bucket/app.bin", &b"\x7fELF\0\r\n"[..]),
+            ("bucket/lib/libapp.so", &b"\x7fELF"[..]),
+            ("bucket/README", &b"hello\r\n"[..]),
+            ("other/README", &b"other"[..]),
+        ]));
+        let sha256 = |contents: &[u8]| {
+            record_artifact_reader(contents, &[HashAlgorithm::Sha256]).unwrap()
+        };
+
+        let recorded = record_artifacts_from(
+            &source,
+            &["bucket"],
+            None,
+            Some(&["bucket/"]),
+            Some(&["*.so"]),
+            true,
+        )
+        .unwrap();
+        let expected = BTreeMap::from([
+            // binary files are hashed unchanged
+            (VirtualTargetPath::from("app.bin"), sha256(b"\x7fELF\0\r\n")),
+            (VirtualTargetPath::from("README"), sha256(b"hello\n")),
+        ]);
+        assert_eq!(recorded, expected);
+
+        assert!(matches!(
+            record_artifacts_from(
+                &source,
+                &["missing"],
+                None,
+                None,
+                None,
+                false
+            ),
+            Err(Error::NotFound)
+        ));
+        // lstrip collisions are reported like for files on disk
+        assert!(matches!(
+            record_artifacts_from(
+                &source,
+                &["bucket/README", "other/README"],
+                None,
+                Some(&["bucket/", "other/"]),
+                None,
+                false,
+            ),
+            Err(Error::IllegalArgument(_))
+        ));
+
+        // the file system source records like record_artifacts
+        for follow_symlinks in [true, false] {
+            let source =
+                FileSystemSource::new().follow_symlinks(follow_symlinks);
+            assert_eq!(
+                record_artifacts_from(
+                    &source,
+                    &["tests/test_runlib"],
+                    Some(&["sha256", "sha512"]),
+                    Some(&["tests/"]),
+                    Some(&[".bar"]),
+                    false,
+                )
+                .unwrap(),
+                record_artifacts(
+                    &["tests/test_runlib"],
+                    Some(&["sha256", "sha512"]),
+                    Some(&["tests/"]),
+                    Some(&[".bar"]),
+                    &RecordOptions::new().follow_symlinks(follow_symlinks)
+                )
+                .unwrap()
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_record_artifacts_symlink_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir_path = dir.path().to_str().unwrap();
+        std::os::unix::fs::symlink("b", dir.path().join("a")).unwrap();
+        std::os::unix::fs::symlink("a", dir.path().join("b")).unwrap();
+
+        let err = record_artifacts(
+            &[dir_path],
+            None,
+            None,
+            None,
+            &RecordOptions::new(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::Io { .. }), "{:?}", err);
+
+        // Not followed, the links are recorded like any other.
+        let artifacts = record_artifacts(
+            &[dir_path],
+            None,
+            None,
+            None,
+            &RecordOptions::new().follow_symlinks(false),
+        )
+        .unwrap();
+        assert_eq!(artifacts.len(), 2);
+
+        // A link to a directory containing it is not traversed again.
+        std::fs::remove_file(dir.path().join("a")).unwrap();
+        std::fs::remove_file(dir.path().join("b")).unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("sub/foo"), "foo\n").unwrap();
+        std::os::unix::fs::symlink("..", dir.path().join("sub/parent"))
+            .unwrap();
+        let artifacts = record_artifacts(
+            &[dir_path],
+            None,
+            None,
+            None,
+            &RecordOptions::new(),
+        )
+        .unwrap();
+        assert_eq!(artifacts.len(), 1);
+    }
+
+    #[test]
+    fn test_record_io_errors() {
+        struct Failing;
+
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("disk on fire"))
+            }
+        }
+
+        let err = record_artifact_reader(Failing, &[HashAlgorithm::Sha256])
+            .unwrap_err();
+        assert_eq!(err, Error::from(io::Error::other("disk on fire")));
+
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing");
+        let missing = missing.to_str().unwrap();
+        assert!(matches!(
+            record_artifact(missing, &[HashAlgorithm::Sha256], None),
+            Err(Error::Io { .. })
+        ));
+        assert!(matches!(
+            record_artifacts(
+                &[missing],
+                None,
+                None,
+                None,
+                &RecordOptions::new()
+            ),
+            Err(Error::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn test_record_directory_hash() {
+        let write_tree = |files: &[(&str, &str)]| {
+            let dir = tempfile::tempdir().unwrap();
+            for (path, contents) in files {
+                let path = dir.path().join(path);
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(path, contents).unwrap();
+            }
+            dir
+        };
+        let hash = |dir: &tempfile::TempDir| {
+            record_directory_hash(
+                dir.path().to_str().unwrap(),
+                &HashAlgorithm::Sha256,
+            )
+            .unwrap()
+        };
+        let files = [("a", "one\n"), ("b/c/d", "two\n"), ("b/e f", "three")];
+        let tree = write_tree(&files);
+        let reversed: Vec<_> = files.iter().rev().copied().collect();
+        let same_tree = write_tree(&reversed);
+
+        // As computed with `sha256sum`, see the documentation.
+        assert_eq!(
+            hash(&tree).to_hex(),
+            "6e6c24d2b41a2483b8e4157dad76fed8e54fc5301589639eeafc22c3157beb52"
+        );
+        assert_eq!(hash(&tree), hash(&same_tree));
+        let with_slash = format!("{}/", tree.path().to_str().unwrap());
+        assert_eq!(
+            record_directory_hash(&with_slash, &HashAlgorithm::Sha256).unwrap(),
+            hash(&tree)
+        );
+
+        std::fs::write(same_tree.path().join("b/c/d"), "changed\n").unwrap();
+        assert_ne!(hash(&tree), hash(&same_tree));
+        let renamed = write_tree(&[("a", "one\n"), ("b/c/e", "two\n")]);
+        assert_ne!(hash(&tree), hash(&renamed));
+
+        let empty = tempfile::tempdir().unwrap();
+        assert_eq!(
+            hash(&empty).to_hex(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert!(record_directory_hash(
+            empty.path().to_str().unwrap(),
+            &HashAlgorithm::Unknown("md4".into())
+        )
+        .is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exclude_record_artifacts() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir_path = dir.path().to_str().unwrap();
+        let lstrip = format!("{}/", dir_path);
+        std::fs::create_dir_all(dir.path().join("src")).unwrap();
+        std::fs::create_dir_all(dir.path().join("target/debug")).unwrap();
+        std::fs::write(dir.path().join("src/main.py"), "main").unwrap();
+        std::fs::write(dir.path().join("src/main.pyc"), "main").unwrap();
+        std::fs::write(dir.path().join("target/debug/out"), "out").unwrap();
+        // would fail to be recorded if the excluded directory was traversed
+        std::os::unix::fs::symlink(
+            "missing",
+            dir.path().join("target/debug/dangling"),
+        )
+        .unwrap();
+
+        let artifacts = record_artifacts(
+            &[dir_path],
+            None,
+            Some(&[&lstrip]),
+            Some(&["*.pyc", "target/**"]),
+            &RecordOptions::new(),
+        )
+        .unwrap();
+        let paths: Vec<_> = artifacts.keys().map(|p| p.value()).collect();
+        assert_eq!(paths, vec!["src/main.py"]);
+
+        assert!(record_artifacts(
+            &[dir_path],
+            None,
+            Some(&[&lstrip]),
+            Some(&["target"]),
+            &RecordOptions::new()
+        )
+        .is_ok());
+        assert!(record_artifacts(
+            &[dir_path],
+            None,
+            None,
+            None,
+            &RecordOptions::new()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_lstrip_collision() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir_path = dir.path().to_str().unwrap();
+        std::fs::create_dir_all(dir.path().join("src")).unwrap();
+        std::fs::create_dir_all(dir.path().join("build/src")).unwrap();
+        std::fs::write(dir.path().join("src/a.txt"), "a").unwrap();
+        std::fs::write(dir.path().join("build/src/a.txt"), "b").unwrap();
+
+        let root = format!("{}/", dir_path);
+        let build = format!("{}/build/", dir_path);
+        let res = record_artifacts(
+            &[dir_path],
+            None,
+            Some(&[&root, &build]),
+            None,
+            &RecordOptions::new(),
+        );
+        match res {
+            Err(Error::IllegalArgument(msg)) => {
+                assert!(msg.contains("src/a.txt"), "{}", msg)
+            }
+            res => panic!("expected a collision, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_link_filename() {
+        let key_id = KeyId::from_str(
+            "e0294a3f17cc8563c3ed5fceb3bd8d3f6bfeeaca499b5c9572729ae015566554",
+        )
+        .unwrap();
+        let filename = link_filename("build.step", &key_id);
+        assert_eq!(filename, "build.step.e0294a3f.link");
+        assert_eq!(
+            parse_link_filename(&filename),
+            Some(("build.step".to_string(), "e0294a3f".to_string()))
+        );
+        assert_eq!(
+            parse_link_filename("write-code.776a00e2.link"),
+            Some(("write-code".to_string(), "776a00e2".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_link_filename_invalid() {
+        for filename in [
+            "build.776a00e2",
+            "build.776a00e.link",
+            "build.776a00eg.link",
+            ".776a00e2.link",
+            "776a00e2.link",
+            "build.layout",
+        ] {
+            assert_eq!(parse_link_filename(filename), None, "{}", filename);
+        }
+    }
+
+    #[cfg(feature = "git")]
+    #[test]
+    fn test_record_artifacts_from_git() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = |args: &[&str]| {
+            let status = process::Command::new("git")
+                .arg("-C")
+                .arg(dir.path())
+                .args(["-c", "user.name=in-toto", "-c", "user.email=in@to.to"])
+                .args(args)
+                .stdout(process::Stdio::null())
+                .status()
+                .unwrap();
+            assert!(status.success(), "git {:?}", args);
+        };
+        git(&["init", "-q"]);
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/main.rs"), "fn main() {}\n")
+            .unwrap();
+        std::fs::write(dir.path().join("README"), "committed\n").unwrap();
+        // Larger than the pipe and read buffers
+        let big = vec![b'x'; 200 * 1024];
+        std::fs::write(dir.path().join("big.bin"), &big).unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "initial"]);
+
+        // Local edits and untracked files are not part of the commit
+        std::fs::write(dir.path().join("README"), "edited\n").unwrap();
+        std::fs::write(dir.path().join("src/new.rs"), "").unwrap();
+
+        let artifacts = record_artifacts_from_git(
+            dir.path(),
+            "HEAD",
+            &[],
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        let paths: Vec<&str> = artifacts.keys().map(|p| p.value()).collect();
+        assert_eq!(paths, ["README", "big.bin", "src/main.rs"]);
+        let expected = record_artifact_reader(
+            &b"committed\n"[..],
+            &[HashAlgorithm::Sha256],
+        )
+        .unwrap();
+        assert_eq!(artifacts[&VirtualTargetPath::from("README")], expected);
+        let expected =
+            record_artifact_reader(&big[..], &[HashAlgorithm::Sha256]).unwrap();
+        assert_eq!(artifacts[&VirtualTargetPath::from("big.bin")], expected);
+
+        let artifacts = record_artifacts_from_git(
+            dir.path(),
+            "HEAD",
+            &["src"],
+            None,
+            Some(&["src/"]),
+            None,
+        )
+        .unwrap();
+        let paths: Vec<&str> = artifacts.keys().map(|p| p.value()).collect();
+        assert_eq!(paths, ["main.rs"]);
+
+        assert!(record_artifacts_from_git(
+            dir.path(),
+            "no-such-rev",
+            &[],
+            None,
+            None,
+            None
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_normalize_line_endings() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("unix")).unwrap();
+        std::fs::create_dir(dir.path().join("windows")).unwrap();
+        let text = "first line\nsecond line\n\nlast line";
+        let binary = b"\x00\x01\r\n\x02";
+        std::fs::write(dir.path().join("unix/a.txt"), text).unwrap();
+        std::fs::write(dir.path().join("unix/b.bin"), binary).unwrap();
+        std::fs::write(
+            dir.path().join("windows/a.txt"),
+            text.replace('\n', "\r\n"),
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("windows/b.bin"), binary).unwrap();
+
+        let unix = format!("{}/unix/", dir.path().to_str().unwrap());
+        let windows = format!("{}/windows/", dir.path().to_str().unwrap());
+        let record = |path: &str, normalize| {
+            record_artifacts(
+                &[path],
+                None,
+                Some(&[path]),
+                None,
+                &RecordOptions::new().normalize_line_endings(normalize),
+            )
+            .unwrap()
+        };
+
+        assert_ne!(record(&unix, false), record(&windows, false));
+        assert_eq!(record(&unix, true), record(&windows, true));
+        assert_eq!(record(&unix, true), record(&unix, false));
+
+        // Binary files are hashed as they are
+        let binary_hashes =
+            record_artifact_reader(&binary[..], &[HashAlgorithm::Sha256])
+                .unwrap();
+        assert_eq!(
+            record(&windows, true)[&VirtualTargetPath::from("b.bin")],
+            binary_hashes
+        );
+    }
+
+    #[test]
+    fn test_normalize_line_endings_reader() {
+        for (input, expected) in [
+            (&b"a\r\nb\r\n"[..], &b"a\nb\n"[..]),
+            (b"a\rb\r", b"a\rb\r"),
+            (b"\r\r\n\n", b"\r\n\n"),
+            (b"", b""),
+        ] {
+            // A one byte buffer puts every CR at a buffer boundary
+            for capacity in [1, 2, 64] {
+                let mut normalized = Vec::new();
+                NormalizeLineEndings(BufReader::with_capacity(capacity, input))
+                    .read_to_end(&mut normalized)
+                    .unwrap();
+                assert_eq!(normalized, expected, "{:?}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_record_artifacts_respect_gitignore() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, content: &str| {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        write(".git/HEAD", "ref: refs/heads/main\n");
+        write(".git/info/exclude", "# local excludes\n*.swp\n");
+        write(".gitignore", "*.log\n!keep.log\nbuild/\n");
+        write("sub/.gitignore", "/local.txt\nsecret\n");
+        for file in [
+            "main.rs",
+            "debug.log",
+            "keep.log",
+            "main.rs.swp",
+            "build/out.bin",
+            "sub/local.txt",
+            "sub/secret",
+            "sub/nested/local.txt",
+            "sub/nested/secret",
+            "sub/lib.rs",
+        ] {
+            write(file, file);
+        }
+
+        let root = format!("{}/", dir.path().to_str().unwrap());
+        let record = |path: &str, respect_gitignore| {
+            let artifacts = record_artifacts(
+                &[path],
+                None,
+                Some(&[&root]),
+                None,
+                &RecordOptions::new().respect_gitignore(respect_gitignore),
+            )
+            .unwrap();
+            artifacts
+                .keys()
+                .map(|path| path.value().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            record(&root, true),
+            [
+                ".gitignore",
+                "keep.log",
+                "main.rs",
+                "sub/.gitignore",
+                "sub/lib.rs",
+                "sub/nested/local.txt",
+            ]
+        );
+        // Rules of parent directories apply when recording a subdirectory
+        assert_eq!(
+            record(&format!("{}sub/nested", root), true),
+            ["sub/nested/local.txt"]
+        );
+        assert!(record(&root, false).contains(&"debug.log".to_string()));
+    }
+
+    /// Compare `Gitignore` against `git check-ignore` on rules exercising
+    /// negation, anchoring, directory-only patterns and `**`.
+    #[cfg(feature = "git")]
+    #[test]
+    fn test_gitignore_matches_git_check_ignore() {
+        let dir = tempfile::tempdir().unwrap();
+        let status = process::Command::new("git")
+            .args(["init", "-q"])
+            .current_dir(dir.path())
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let write = |path: &str, content: &str| {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        write(
+            ".gitignore",
+            concat!(
+                "# negation\n",
+                "*.log\n",
+                "!keep.log\n",
+                "logs/\n",
+                "!logs/keep.log\n",
+                "# anchoring\n",
+                "/root.txt\n",
+                "doc/*.txt\n",
+                "name.txt\n",
+                "# **\n",
+                "**/cache\n",
+                "a/**/z.txt\n",
+                "out/**\n",
+                "!out/**/*.md\n",
+                "\\#hash\n",
+            ),
+        );
+        write("sub/.gitignore", "/local.txt\n!debug.log\n");
+        let files = [
+            "debug.log",
+            "keep.log",
+            "sub/debug.log",
+            "sub/deeper/debug.log",
+            "logs/keep.log",
+            "logs/other.txt",
+            "root.txt",
+            "sub/root.txt",
+            "doc/a.txt",
+            "doc/nested/a.txt",
+            "sub/doc/a.txt",
+            "name.txt",
+            "sub/name.txt",
+            "cache",
+            "x/cache/file",
+            "x/y/cache",
+            "a/z.txt",
+            "a/b/z.txt",
+            "a/b/c/z.txt",
+            "b/a/z.txt",
+            "out/bin",
+            "out/sub/bin",
+            "out/sub/readme.md",
+            "#hash",
+            "sub/local.txt",
+            "sub/deeper/local.txt",
+            "src/main.rs",
+        ];
+        for file in files {
+            write(file, file);
+        }
+
+        let mut gitignore = Gitignore::new(dir.path()).unwrap();
+        let mut paths: Vec<String> =
+            files.iter().map(|f| f.to_string()).collect();
+        for file in files {
+            let mut parent = Path::new(file).parent();
+            while let Some(dir) = parent.filter(|p| !p.as_os_str().is_empty()) {
+                paths.push(dir.to_string_lossy().into_owned());
+                parent = dir.parent();
+            }
+        }
+        paths.sort();
+        paths.dedup();
+        for path in paths {
+            let full_path = dir.path().join(&path);
+            let expected = process::Command::new("git")
+                .args(["check-ignore", "-q", "--no-index", &path])
+                .current_dir(dir.path())
+                .status()
+                .unwrap()
+                .success();
+            let ignored = gitignore
+                .is_ignored(&full_path, full_path.is_dir())
+                .unwrap();
+            assert_eq!(ignored, expected, "{}", path);
+        }
     }
 }
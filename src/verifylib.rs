@@ -1,25 +1,37 @@
 //! A tool to be used by the client to perform verification on the final product.
 
 use std::{
-    collections::HashMap,
-    fs,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt, fs,
+    io::BufReader,
     path::{Path, PathBuf},
 };
 
+use chrono::{DateTime, Utc};
 use glob::glob;
 use log::{debug, info, warn};
+use serde_derive::{Deserialize, Serialize};
 
 use crate::{
     crypto::{KeyId, PublicKey},
+    interchange::{DataInterchange, Json},
     models::{
-        step::Step, supply_chain_item::SupplyChainItem, LayoutMetadata,
-        LinkMetadata, LinkMetadataBuilder, Metablock, MetadataWrapper,
+        inspection::Inspection, rule::ArtifactRule, step::Step,
+        supply_chain_item::SupplyChainItem, LayoutMetadata, LinkMetadata,
+        LinkMetadataBuilder, Metablock, MetadataType, MetadataWrapper,
+        TargetDescription, VirtualTargetPath,
     },
     rulelib::apply_rules_on_link,
-    runlib::in_toto_run,
+    runlib::{
+        in_toto_run, link_filename, parse_link_filename, CommandEnvironment,
+        RunOptions,
+    },
 };
 use crate::{Error, Result};
 
+/// Maximum nesting depth of sublayouts, to guard against unbounded recursion.
+const MAX_SUBLAYOUT_DEPTH: usize = 16;
+
 /// verify_layout_signatures can verify the layout wrapped in a Metablock with given
 /// set of public keys. If verification fails, an error occurs.
 fn verify_layout_signatures(
@@ -30,11 +42,13 @@ fn verify_layout_signatures(
 }
 
 /// verify_layout_expiration will verify whether the layout has expired
-fn verify_layout_expiration(layout: &LayoutMetadata) -> Result<()> {
-    let time = layout.expires;
-    let now = chrono::Utc::now();
-    if time < now {
-        return Err(Error::VerificationFailure("layout expired".to_string()));
+/// at the given time
+fn verify_layout_expiration(
+    layout: &LayoutMetadata,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    if layout.is_expired(now) {
+        return Err(Error::ExpiredMetadata(layout.expires));
     }
 
     Ok(())
@@ -42,9 +56,7 @@ fn verify_layout_expiration(layout: &LayoutMetadata) -> Result<()> {
 
 /// load content from path to a Metablock
 fn load_linkfile(path: &PathBuf) -> Result<Metablock> {
-    let content = fs::read_to_string(path)?;
-    let meta = serde_json::from_str(&content)?;
-    Ok(meta)
+    Json::from_reader(BufReader::new(fs::File::open(path)?))
 }
 
 /// Match signer's key id and metablock's signatures, if one of the
@@ -63,60 +75,134 @@ fn match_signatures(
     }
 }
 
-/// load_links_for_layout will load Metablock from disk,
+/// Where the link metadata files of a layout are loaded from.
+enum LinkSource<'a> {
+    /// Link files in a directory, with the links of sublayouts in its
+    /// subdirectories.
+    Dir(PathBuf),
+    /// The links of a [`Bundle`] under `prefix`, the relative path of the
+    /// directory they would be in.
+    Bundle {
+        links: &'a BTreeMap<String, Metablock>,
+        prefix: String,
+    },
+    /// Link metadata in memory, each for the step it is named after and
+    /// signed by every key it has a signature of.
+    Links(&'a [Metablock]),
+}
+
+impl LinkSource<'_> {
+    /// The link files of the step `step_name`, each with the key id prefix
+    /// from its file name.
+    fn step_links(&self, step_name: &str) -> Result<Vec<(String, Metablock)>> {
+        match self {
+            LinkSource::Dir(link_dir) => {
+                let pattern = format!("{}.????????.link", step_name);
+                let path_pattern = link_dir.join(pattern);
+                let path_pattern = path_pattern.to_str().ok_or_else(|| {
+                    Error::VerificationFailure(format!(
+                        "Pathbuf convert to str failed: {:?}",
+                        path_pattern
+                    ))
+                })?;
+                let matched_files = glob(path_pattern).map_err(|e| {
+                    Error::VerificationFailure(format!(
+                        "Path glob error: {}",
+                        e
+                    ))
+                })?;
+                let mut step_links = Vec::new();
+                for link_path in matched_files.flatten() {
+                    // Get the key-id that signed this link file
+                    let signer_short_key_id = match link_path
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .and_then(parse_link_filename)
+                    {
+                        Some((name, key_id)) if name == step_name => key_id,
+                        _ => {
+                            warn!(
+                                "Skipping unexpected link file {:?}",
+                                link_path
+                            );
+                            continue;
+                        }
+                    };
+
+                    // load link from the disk, canbe either a linkfile or a layout file
+                    step_links.push((
+                        signer_short_key_id,
+                        load_linkfile(&link_path)?,
+                    ));
+                }
+                Ok(step_links)
+            }
+            LinkSource::Bundle { links, prefix } => Ok(links
+                .iter()
+                .filter_map(|(path, link)| {
+                    let file_name = path.strip_prefix(prefix.as_str())?;
+                    if file_name.contains('/') {
+                        return None;
+                    }
+                    match parse_link_filename(file_name) {
+                        Some((name, key_id)) if name == step_name => {
+                            Some((key_id, link.clone()))
+                        }
+                        _ => None,
+                    }
+                })
+                .collect()),
+            LinkSource::Links(links) => Ok(links
+                .iter()
+                .filter(|link| match &link.metadata {
+                    MetadataWrapper::Link(inner) => inner.name == step_name,
+                    MetadataWrapper::Layout(_) => false,
+                })
+                .flat_map(|link| {
+                    link.signatures.iter().map(move |sig| {
+                        (sig.key_id().prefix().to_string(), link.clone())
+                    })
+                })
+                .collect()),
+        }
+    }
+
+    /// The source of the links of a sublayout, which are in the
+    /// subdirectory `dir_name`.
+    fn sublayout(&self, dir_name: &str) -> LinkSource<'_> {
+        match self {
+            LinkSource::Dir(link_dir) => {
+                LinkSource::Dir(link_dir.join(dir_name))
+            }
+            LinkSource::Bundle { links, prefix } => LinkSource::Bundle {
+                links,
+                prefix: format!("{prefix}{dir_name}/"),
+            },
+            // only links are given, never sublayouts
+            LinkSource::Links(_) => LinkSource::Links(&[]),
+        }
+    }
+}
+
+/// load_links_for_layout will load Metablock from the link source,
 /// return a map containing the Metablocks.
 /// The returned value is a nested HashMap
 /// * step-name => (key-id => Metablock)
 fn load_links_for_layout(
     layout: &LayoutMetadata,
-    link_dir: &str,
+    links: &LinkSource,
 ) -> Result<HashMap<String, HashMap<KeyId, Metablock>>> {
     let mut steps_links_metadata = HashMap::new();
 
     for step in &layout.steps {
         let mut links_per_step = HashMap::new();
 
-        let pattern = format!("{}.????????.link", step.name);
-        let mut path_pattern = PathBuf::from(link_dir);
-        path_pattern.push(pattern);
-        let path_pattern = path_pattern.to_str().ok_or_else(|| {
-            Error::VerificationFailure(format!(
-                "Pathbuf convert to str failed: {:?}",
-                path_pattern
-            ))
-        })?;
-        let matched_files = glob(path_pattern).map_err(|e| {
-            Error::VerificationFailure(format!("Path glob error: {}", e))
-        })?;
-        for link_path in matched_files.flatten() {
-            // load link from the disk, canbe either a linkfile or a layout file
-            let link_metablock = load_linkfile(&link_path)?;
-
-            // Get the key-id that signed this link file
-            let signer_short_key_id = link_path
-                .file_name()
-                .ok_or_else(|| {
-                    Error::VerificationFailure(
-                        "link_file name get failed.".into(),
-                    )
-                })?
-                .to_str()
-                .ok_or_else(|| {
-                    Error::VerificationFailure(
-                        "link_file name get failed.".into(),
-                    )
-                })?
-                .to_string();
-
-            // by trim filename's start <step-name>." and end ".link"
-            let signer_short_key_id = signer_short_key_id
-                .trim_end_matches(".link")
-                .trim_start_matches(&step.name)
-                .trim_start_matches('.');
-
+        for (signer_short_key_id, link_metablock) in
+            links.step_links(&step.name)?
+        {
             match_signatures(
                 link_metablock,
-                signer_short_key_id,
+                &signer_short_key_id,
                 &mut links_per_step,
             );
         }
@@ -149,9 +235,11 @@ fn verify_link_signature_thresholds_step(
     // links in the HashMap.
     for (signer_key_id, link_metablock) in links {
         // For each link corresponding to a step, check that the signer key was
-        // authorized by checking whether it's included in the layout.
-        // Only good links are stored, to verify thresholds.
-        // The sign key of the link is not authorized in the layout
+        // authorized by checking whether it's one of the step's functionary
+        // keys. Only good links are stored, to verify thresholds.
+        if !step.pub_keys.contains(signer_key_id) {
+            continue;
+        }
         if let Some(authorized_key) = pubkeys.get(signer_key_id) {
             let authorized_key = vec![authorized_key];
             if link_metablock.verify(1, authorized_key).is_ok() {
@@ -206,11 +294,13 @@ fn verify_link_signature_thresholds(
 /// verify_sublayouts will check if any step has been
 /// delegated by the functionary, recurses into the delegation and
 /// replaces the layout object in the chain_link_dict by an
-/// equivalent link object.
+/// equivalent link object. `depth` is the nesting depth of `layout`.
 fn verify_sublayouts(
     layout: &LayoutMetadata,
     chain_link_dict: HashMap<String, HashMap<KeyId, Metablock>>,
-    link_dir: &str,
+    links: &LinkSource,
+    now: DateTime<Utc>,
+    depth: usize,
 ) -> Result<HashMap<String, HashMap<KeyId, LinkMetadata>>> {
     let mut steps_link_metadata = HashMap::new();
     for (step_name, key_link_dict) in chain_link_dict {
@@ -232,27 +322,20 @@ fn verify_sublayouts(
                     let sub_link_dir =
                         format!("{step_name}.{}", keyid.prefix());
 
-                    let sublayout_link_dir_path =
-                        Path::new(link_dir).join(&sub_link_dir);
-
-                    let sublayout_link_dir_path =
-                        sublayout_link_dir_path.to_str().ok_or_else(|| {
-                            Error::VerificationFailure(format!(
-                                "failed to convert dir {} in {}",
-                                &sub_link_dir, link_dir
-                            ))
-                        })?;
-
-                    let summary_link = in_toto_verify(
+                    let summary_link = verify_at(
                         link,
                         layout_key_dict,
-                        sublayout_link_dir_path,
+                        &links.sublayout(&sub_link_dir),
                         Some(&step_name),
+                        now,
+                        depth + 1,
                     )?;
 
                     match summary_link.metadata {
                         MetadataWrapper::Layout(_) => {
-                            panic!("unexpected layout")
+                            return Err(Error::Programming(
+                                "sublayout summary is not a link".into(),
+                            ))
                         }
                         MetadataWrapper::Link(inner) => inner,
                     }
@@ -268,6 +351,120 @@ fn verify_sublayouts(
     Ok(steps_link_metadata)
 }
 
+/// A difference between the expected command of a step and the command
+/// recorded in its link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandWarning {
+    /// The commands run different binaries.
+    Binary { expected: String, run: String },
+    /// The argument at `position` differs, or is missing from one of the
+    /// commands.
+    Argument {
+        position: usize,
+        expected: Option<String>,
+        run: Option<String>,
+    },
+}
+
+impl fmt::Display for CommandWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandWarning::Binary { expected, run } => {
+                write!(f, "ran {:?} instead of {:?}", run, expected)
+            }
+            CommandWarning::Argument {
+                position,
+                expected,
+                run,
+            } => match (expected, run) {
+                (Some(expected), Some(run)) => write!(
+                    f,
+                    "argument {} is {:?} instead of {:?}",
+                    position, run, expected
+                ),
+                (Some(expected), None) => {
+                    write!(f, "argument {} {:?} is missing", position, expected)
+                }
+                (None, Some(run)) => {
+                    write!(f, "unexpected argument {} {:?}", position, run)
+                }
+                (None, None) => write!(f, "argument {} differs", position),
+            },
+        }
+    }
+}
+
+/// The differences found by [`verify_command_alignment`]. Like the
+/// reference implementation, verification only warns about them by
+/// default; [`CommandWarnings::strict`] makes them an error instead.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandWarnings(Vec<CommandWarning>);
+
+impl CommandWarnings {
+    /// Whether the commands align.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The differences, in the order of the arguments.
+    pub fn warnings(&self) -> &[CommandWarning] {
+        &self.0
+    }
+
+    /// Fail with `Error::VerificationFailure` if the commands do not align.
+    pub fn strict(self) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        Err(Error::VerificationFailure(format!(
+            "command does not align with the expected command: {}",
+            self
+        )))
+    }
+}
+
+impl fmt::Display for CommandWarnings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, warning) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare the command a link recorded, `run`, with the `expected`
+/// command of its step, argument by argument. An empty expected command
+/// accepts any command.
+pub fn verify_command_alignment(
+    expected: &[String],
+    run: &[String],
+) -> CommandWarnings {
+    let mut warnings = Vec::new();
+    if expected.is_empty() {
+        return CommandWarnings(warnings);
+    }
+    if expected.first() != run.first() {
+        warnings.push(CommandWarning::Binary {
+            expected: expected[0].clone(),
+            run: run.first().cloned().unwrap_or_default(),
+        });
+    }
+    for position in 1..expected.len().max(run.len()) {
+        let (expected, run) = (expected.get(position), run.get(position));
+        if expected != run {
+            warnings.push(CommandWarning::Argument {
+                position,
+                expected: expected.cloned(),
+                run: run.cloned(),
+            });
+        }
+    }
+    CommandWarnings(warnings)
+}
+
 /// verify_all_steps_command_alignment will iteratively check if all
 /// expected commands as defined in the Steps of a Layout align with
 /// the actual commands as recorded in the Link metadata.
@@ -285,10 +482,14 @@ fn verify_all_steps_command_alignment(
         })?;
         for link in key_link_dict.values() {
             let command = &link.command;
-            if *command != *expected_command {
+            let warnings = verify_command_alignment(
+                expected_command.as_ref(),
+                command.as_ref(),
+            );
+            if !warnings.is_empty() {
                 warn!(
-                    "Run command {:?} different from expected command {:?}",
-                    command, expected_command
+                    "Run command {:?} different from expected command {:?}: {}",
+                    command, expected_command, warnings
                 );
             }
         }
@@ -297,13 +498,14 @@ fn verify_all_steps_command_alignment(
     Ok(())
 }
 
-/// verify_threshold_constraints will verify that all links
-/// corresponding to a given step report the same materials
-/// and products.
+/// verify_threshold_constraints will verify that at least `threshold`
+/// links corresponding to a given step report the same materials and
+/// products. Links that disagree with that group are dropped, so that
+/// the returned map only holds links with identical artifacts.
 fn verify_threshold_constraints(
     layout: &LayoutMetadata,
-    link_files: &HashMap<String, HashMap<KeyId, LinkMetadata>>,
-) -> Result<()> {
+    mut link_files: HashMap<String, HashMap<KeyId, LinkMetadata>>,
+) -> Result<HashMap<String, HashMap<KeyId, LinkMetadata>>> {
     for step in &layout.steps {
         if step.threshold <= 1 {
             info!(
@@ -314,43 +516,92 @@ fn verify_threshold_constraints(
         }
 
         let key_link_per_step =
-            link_files.get(&step.name).ok_or_else(|| {
+            link_files.get_mut(&step.name).ok_or_else(|| {
                 Error::VerificationFailure(format!(
                     "step {} does not have validated links.",
                     step.name
                 ))
             })?;
-        if key_link_per_step.len() < step.threshold as usize {
-            return Err(Error::VerificationFailure(format!(
-                "step {} does not be performed by enough functionaries.",
-                step.name
-            )));
+        verify_threshold_constraints_step(step, key_link_per_step)?;
+    }
+
+    Ok(link_files)
+}
+
+/// Check that `threshold` of the links of `step` report the same materials
+/// and products, and drop the links that disagree with them.
+fn verify_threshold_constraints_step(
+    step: &Step,
+    key_link_per_step: &mut HashMap<KeyId, LinkMetadata>,
+) -> Result<()> {
+    if key_link_per_step.len() < step.threshold as usize {
+        return Err(Error::VerificationFailure(format!(
+            "step {} does not be performed by enough functionaries.",
+            step.name
+        )));
+    }
+
+    // Group the links by the artifacts they report
+    let mut groups: Vec<Vec<&KeyId>> = Vec::new();
+    let mut keyids: Vec<&KeyId> = key_link_per_step.keys().collect();
+    keyids.sort();
+    for keyid in keyids {
+        let link = &key_link_per_step[keyid];
+        match groups.iter_mut().find(|group| {
+            let reference = &key_link_per_step[group[0]];
+            link.materials == reference.materials
+                && link.products == reference.products
+        }) {
+            Some(group) => group.push(keyid),
+            None => groups.push(vec![keyid]),
         }
+    }
 
-        let reference_keyid =
-            key_link_per_step.keys().next().ok_or_else(|| {
-                Error::VerificationFailure(format!(
-                    "step {} does not have enough key ids.",
-                    step.name
-                ))
-            })?;
-        let reference_link = &key_link_per_step[reference_keyid];
-
-        for link in key_link_per_step.values() {
-            if link.materials != reference_link.materials
-                || link.products != reference_link.products
-            {
-                return Err(Error::VerificationFailure(format!(
-                    "Links {} have different artifacts.",
-                    link.name
-                )));
+    let mut agreeing = groups
+        .iter()
+        .filter(|group| group.len() >= step.threshold as usize);
+    let group = match (agreeing.next(), agreeing.next()) {
+        (Some(group), None) => group,
+        _ => {
+            let reference = &key_link_per_step[groups[0][0]];
+            let mut conflicting = BTreeSet::new();
+            for group in &groups[1..] {
+                let link = &key_link_per_step[group[0]];
+                conflicting.extend(differing_artifacts(
+                    &reference.materials,
+                    &link.materials,
+                ));
+                conflicting.extend(differing_artifacts(
+                    &reference.products,
+                    &link.products,
+                ));
             }
+            return Err(Error::VerificationFailure(format!(
+                "step {} requires {} links with identical artifacts, but the links disagree on {:?}",
+                step.name,
+                step.threshold,
+                conflicting.iter().map(|p| p.value()).collect::<Vec<_>>(),
+            )));
         }
-    }
+    };
+
+    let group: HashSet<KeyId> = group.iter().map(|k| (*k).clone()).collect();
+    key_link_per_step.retain(|keyid, _| group.contains(keyid));
 
     Ok(())
 }
 
+/// Return the artifact paths that are missing from either map or that have
+/// different hashes.
+fn differing_artifacts<'a>(
+    a: &'a BTreeMap<VirtualTargetPath, TargetDescription>,
+    b: &'a BTreeMap<VirtualTargetPath, TargetDescription>,
+) -> impl Iterator<Item = &'a VirtualTargetPath> {
+    a.keys()
+        .chain(b.keys())
+        .filter(move |path| a.get(*path) != b.get(*path))
+}
+
 /// reduce_chain_links will iterates through the passed
 /// chain_link_dict and builds a dict with step-name as
 /// keys and link objects as values. We already check if
@@ -391,42 +642,76 @@ fn verify_all_item_rules(
     Ok(())
 }
 
+/// Run `inspection` in `run_dir` and return the resulting unsigned link,
+/// named after the inspection. The files under `run_dir` are recorded as
+/// materials before and as products after the inspection's `run` command,
+/// with paths relative to `run_dir`, and the output of the command is kept
+/// as byproducts. The inspection's artifact rules can then be applied to
+/// this link like to the link of a step.
+pub fn run_inspection(
+    inspection: &Inspection,
+    run_dir: &Path,
+) -> Result<LinkMetadata> {
+    run_inspection_with_env(inspection, run_dir, &CommandEnvironment::new())
+}
+
+/// [`run_inspection`] with the command run in the environment described by
+/// `env`, e.g. an empty one, since inspections run on the verifier's
+/// machine.
+pub fn run_inspection_with_env(
+    inspection: &Inspection,
+    run_dir: &Path,
+    env: &CommandEnvironment,
+) -> Result<LinkMetadata> {
+    let dir = run_dir.to_str().ok_or_else(|| {
+        Error::IllegalArgument(format!(
+            "run directory {} is not valid UTF-8",
+            run_dir.display()
+        ))
+    })?;
+    // artifact paths are recorded relative to the run directory
+    let prefix = format!("{}/", path_clean::clean(dir).display());
+    let cmd_args: Vec<&str> =
+        inspection.run.as_ref().iter().map(|arg| &arg[..]).collect();
+
+    let options = RunOptions::new()
+        .run_dir(dir)
+        .lstrip_paths(&[&prefix])
+        .command_env(env.clone());
+    let metablock =
+        in_toto_run(inspection.name(), &[dir], &[dir], &cmd_args, &options)
+            .map_err(|e| {
+                Error::VerificationFailure(format!(
+                    "inspection {} failed to run: {}",
+                    inspection.name(),
+                    e
+                ))
+            })?;
+
+    match metablock.metadata {
+        MetadataWrapper::Link(link) => Ok(link),
+        MetadataWrapper::Layout(_) => panic!("Unexpected layout."),
+    }
+}
+
 /// run_all_inspections will extracts all inspections from a passed
-/// Layout's inspect field and iteratively run each command defined
-/// in the Inspection's `run` field using `runlib::in_toto_run`, which
-/// returns a Metablock object containing a Link object.
+/// Layout's inspect field and iteratively run each of them in the current
+/// directory using [`run_inspection`], dumping the resulting links.
 fn run_all_inspections(
     layout: &LayoutMetadata,
 ) -> Result<HashMap<String, LinkMetadata>> {
-    let material_paths = ["."];
-    let product_paths = ["."];
     let mut inspection_links = HashMap::new();
 
-    for inspect in &layout.inspect {
-        let cmd_args: Vec<&str> =
-            inspect.run.as_ref().iter().map(|arg| &arg[..]).collect();
-
-        let metablock = in_toto_run(
-            inspect.name(),
-            Some("."),
-            &material_paths,
-            &product_paths,
-            &cmd_args,
-            None,
-            None,
-            None,
-        )?;
+    for inspect in layout.inspect() {
+        let link_metadata = run_inspection(inspect, Path::new("."))?;
 
         // dump the metadata
+        let metablock =
+            Metablock::new(MetadataWrapper::Link(link_metadata.clone()), &[])?;
         let filename = format!("{}.link", inspect.name());
         std::fs::write(filename, serde_json::to_string_pretty(&metablock)?)?;
 
         // record in the hashmap
-        let link_metadata = match metablock.metadata {
-            MetadataWrapper::Layout(_) => panic!("Unexpected layout."),
-            MetadataWrapper::Link(inner) => inner,
-        };
-
         inspection_links.insert(inspect.name().to_string(), link_metadata);
     }
 
@@ -467,6 +752,116 @@ fn get_summary_link(
     Metablock::new(MetadataWrapper::Link(link_metadata), &[])
 }
 
+/// What [`in_toto_verify`] would check for a layout, as returned by [`plan`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerificationPlan {
+    /// The steps, in the order their rules are applied.
+    pub steps: Vec<StepPlan>,
+    /// The inspections, in the order they are run.
+    pub inspections: Vec<InspectionPlan>,
+}
+
+/// How a step of a layout is verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepPlan {
+    pub name: String,
+    /// The number of link files needed from distinct functionaries.
+    pub threshold: u32,
+    /// The link file of every functionary authorized to perform the step.
+    pub link_files: Vec<String>,
+    /// The command the links are expected to record (only warns).
+    pub expected_command: Vec<String>,
+    pub material_rules: Vec<ArtifactRule>,
+    pub product_rules: Vec<ArtifactRule>,
+}
+
+/// How an inspection of a layout is run and verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InspectionPlan {
+    pub name: String,
+    /// The command run in the current directory.
+    pub run: Vec<String>,
+    /// The link file the inspection writes to the current directory.
+    pub link_file: String,
+    pub material_rules: Vec<ArtifactRule>,
+    pub product_rules: Vec<ArtifactRule>,
+}
+
+impl fmt::Display for VerificationPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn rules(rules: &[ArtifactRule]) -> String {
+            rules
+                .iter()
+                .map(|rule| rule.to_tokens().join(" "))
+                .collect::<Vec<_>>()
+                .join("; ")
+        }
+        for (i, step) in self.steps.iter().enumerate() {
+            writeln!(
+                f,
+                "{}. step {}: {} of [{}]",
+                i + 1,
+                step.name,
+                step.threshold,
+                step.link_files.join(", ")
+            )?;
+            writeln!(f, "   command: {}", step.expected_command.join(" "))?;
+            writeln!(f, "   materials: {}", rules(&step.material_rules))?;
+            writeln!(f, "   products: {}", rules(&step.product_rules))?;
+        }
+        for (i, inspection) in self.inspections.iter().enumerate() {
+            writeln!(
+                f,
+                "{}. inspection {}: writes {}",
+                self.steps.len() + i + 1,
+                inspection.name,
+                inspection.link_file
+            )?;
+            writeln!(f, "   run: {}", inspection.run.join(" "))?;
+            writeln!(f, "   materials: {}", rules(&inspection.material_rules))?;
+            writeln!(f, "   products: {}", rules(&inspection.product_rules))?;
+        }
+        Ok(())
+    }
+}
+
+/// plan lists what [`in_toto_verify`] would do for `layout` without doing
+/// any of it: the steps whose links it loads and whose rules it applies,
+/// and the inspections it runs, in order. Nothing is read or executed, and
+/// the layout is taken as is, without checking its signatures or
+/// expiration. Whether a step is delegated to a sublayout depends on its
+/// links, so sublayouts are not planned.
+pub fn plan(layout: &LayoutMetadata) -> VerificationPlan {
+    let steps = layout
+        .steps
+        .iter()
+        .map(|step| StepPlan {
+            name: step.name.clone(),
+            threshold: step.threshold,
+            link_files: step
+                .pub_keys
+                .iter()
+                .map(|key_id| link_filename(&step.name, key_id))
+                .collect(),
+            expected_command: step.expected_command.as_ref().to_vec(),
+            material_rules: step.expected_materials.clone(),
+            product_rules: step.expected_products.clone(),
+        })
+        .collect();
+    let inspections = layout
+        .inspect
+        .iter()
+        .map(|inspection| InspectionPlan {
+            name: inspection.name.clone(),
+            run: inspection.run.as_ref().to_vec(),
+            link_file: format!("{}.link", inspection.name),
+            material_rules: inspection.expected_materials.clone(),
+            product_rules: inspection.expected_products.clone(),
+        })
+        .collect();
+    VerificationPlan { steps, inspections }
+}
+
 /// in_toto_verify can be used to verify an entire software supply chain according to
 /// the in-toto specification v0.9. It requires the metadata of the root layout, a map
 /// that contains public keys to verify the root layout signatures, a path to a
@@ -478,15 +873,18 @@ fn get_summary_link(
 ///
 /// 1. Verify layout signature(s) using passed key(s)
 /// 2. Verify layout expiration date
-/// // 3. Substitute parameters in layout
 /// 3. Load link metadata files for steps of layout
 /// 4. Verify signatures and signature thresholds for steps of layout
-/// 5. Verify sublayouts recursively
+/// 5. Verify sublayouts recursively, up to a fixed nesting depth
 /// 6. Verify command alignment for steps of layout (only warns)
 /// 7. Verify artifact rules for steps of layout
 /// 8. Execute inspection commands (generates link metadata for each inspection)
 /// 9. Verify artifact rules for inspections of layout
 ///
+/// The links of inspections are added to those of the steps before the
+/// rules of any inspection are verified, so an inspection's `MATCH` rules
+/// can refer to a step or to another inspection with `FROM <name>`.
+///
 /// in_toto_verify returns a summary link wrapped in a Metablock object or an error.
 /// If any of the verification routines fail, verification is aborted and error is
 /// returned.
@@ -494,9 +892,10 @@ fn get_summary_link(
 /// # Parameters
 /// * `layout`: The LayoutMetadata wrapped in a Metablock.
 /// * `layout_keys`: A `key_id` to `Pubkey` map defined in layout.
-/// * `link_dir`: The directory where link files are stored.
+/// * `link_dir`: The directory where link files are stored, as a `&str`,
+///   `&Path` or anything else that converts to a `Path`.
 /// * `step_name`(Optional): A name assigned to the returned link. This is mostly
-/// useful during recursive sublayout verification.
+///   useful during recursive sublayout verification.
 ///
 /// # Side-Effects
 /// * I/O: Read link files from the disk.
@@ -504,13 +903,239 @@ fn get_summary_link(
 ///
 /// # Return Value
 /// * A LinkMetadata which summarizes the materials
-/// and products of the whole software supply chain.
-pub fn in_toto_verify(
+///   and products of the whole software supply chain.
+pub fn in_toto_verify<P: AsRef<Path>>(
+    layout: &Metablock,
+    layout_keys: HashMap<KeyId, PublicKey>,
+    link_dir: P,
+    step_name: Option<&str>,
+) -> Result<Metablock> {
+    in_toto_verify_at(layout, layout_keys, link_dir, step_name, Utc::now())
+}
+
+/// Verify the supply chain as [`in_toto_verify`] does, with `now` as the
+/// current time for the expiration checks of the layout and any
+/// sublayouts, e.g. to verify a supply chain as of the time it was
+/// released, or to pin the time in tests.
+pub fn in_toto_verify_at<P: AsRef<Path>>(
+    layout: &Metablock,
+    layout_keys: HashMap<KeyId, PublicKey>,
+    link_dir: P,
+    step_name: Option<&str>,
+    now: DateTime<Utc>,
+) -> Result<Metablock> {
+    verify_at(
+        layout,
+        layout_keys,
+        &LinkSource::Dir(link_dir.as_ref().to_path_buf()),
+        step_name,
+        now,
+        0,
+    )
+}
+
+/// Verify a supply chain the way the `in-toto-verify` command line tool
+/// does, from plain arguments, so that a command line tool can be built on
+/// it in a few lines. Returns the summary link of [`in_toto_verify`] as
+/// JSON.
+///
+/// * `layout_path` - The signed layout, as JSON.
+/// * `layout_key_paths` - The public keys of the layout owners, as PEM or
+///   DER encoded SPKI. The layout must be signed by all of them.
+/// * `link_dir` - The directory holding the link files.
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use in_toto::verifylib::verify;
+/// let summary = verify(
+///     Path::new("root.layout"),
+///     &[Path::new("alice.pub")],
+///     Path::new("."),
+/// )
+/// .unwrap();
+/// ```
+///
+/// # Side-Effects
+/// * I/O: Read the layout, key and link files from the disk.
+/// * Process: Run commands of inspections using subprocess.
+pub fn verify(
+    layout_path: &Path,
+    layout_key_paths: &[&Path],
+    link_dir: &Path,
+) -> Result<Vec<u8>> {
+    let layout = Metablock::parse_typed::<Json>(
+        &fs::read(layout_path)?,
+        MetadataType::Layout,
+    )?;
+    let layout_keys = layout_key_paths
+        .iter()
+        .map(|path| {
+            let key = load_public_key(path)?;
+            Ok((key.key_id().clone(), key))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+
+    let summary = in_toto_verify(&layout, layout_keys, link_dir, None)?;
+    let mut bytes = Vec::new();
+    Json::to_writer(&mut bytes, &summary)?;
+    Ok(bytes)
+}
+
+/// Load an SPKI public key from `path`, PEM encoded or DER.
+fn load_public_key(path: &Path) -> Result<PublicKey> {
+    let bytes = fs::read(path)?;
+    match std::str::from_utf8(&bytes) {
+        Ok(pem) if pem.starts_with("-----BEGIN") => PublicKey::from_pem(pem),
+        _ => PublicKey::from_spki_inferring_scheme(&bytes),
+    }
+}
+
+/// in_toto_verify_from_metadata verifies the supply chain as
+/// [`in_toto_verify`] does, with link metadata that is already in memory,
+/// e.g. uploaded to a server, instead of link files in a directory. Each
+/// link counts for the step it is named after, signed by the keys it has
+/// signatures of. Layouts cannot be given as links, so steps cannot be
+/// delegated to sublayouts: use a [`Bundle`] for those.
+///
+/// ```
+/// # use std::collections::HashMap;
+/// # use in_toto::crypto::{KeyId, PublicKey};
+/// # use in_toto::models::Metablock;
+/// # use in_toto::verifylib::in_toto_verify_from_metadata;
+/// # fn verify(layout: &[u8], link: &[u8], keys: HashMap<KeyId, PublicKey>) {
+/// let layout: Metablock = serde_json::from_slice(layout).unwrap();
+/// let links: Vec<Metablock> = vec![serde_json::from_slice(link).unwrap()];
+/// let summary = in_toto_verify_from_metadata(&layout, keys, &links, None);
+/// # }
+/// ```
+///
+/// # Side-Effects
+/// * Process: Run commands of inspections using subprocess.
+pub fn in_toto_verify_from_metadata(
+    layout: &Metablock,
+    layout_keys: HashMap<KeyId, PublicKey>,
+    links: &[Metablock],
+    step_name: Option<&str>,
+) -> Result<Metablock> {
+    in_toto_verify_from_metadata_at(
+        layout,
+        layout_keys,
+        links,
+        step_name,
+        Utc::now(),
+    )
+}
+
+/// Verify the supply chain as [`in_toto_verify_from_metadata`] does, with
+/// `now` as the current time for the expiration check of the layout.
+pub fn in_toto_verify_from_metadata_at(
+    layout: &Metablock,
+    layout_keys: HashMap<KeyId, PublicKey>,
+    links: &[Metablock],
+    step_name: Option<&str>,
+    now: DateTime<Utc>,
+) -> Result<Metablock> {
+    if links
+        .iter()
+        .any(|link| matches!(link.metadata, MetadataWrapper::Layout(_)))
+    {
+        return Err(Error::IllegalArgument(
+            "sublayouts cannot be verified from in-memory links".to_string(),
+        ));
+    }
+    verify_at(
+        layout,
+        layout_keys,
+        &LinkSource::Links(links),
+        step_name,
+        now,
+        0,
+    )
+}
+
+/// verify_step verifies the links of a single step in isolation, e.g. each
+/// time a functionary submits one, instead of the whole supply chain at the
+/// end. It checks that `threshold` of the links named after `step` are
+/// signed by distinct functionaries of the step whose keys are in `keys`,
+/// usually [`LayoutMetadata::keys`], and that those links report the same
+/// materials and products. Returns that agreed link, i.e. the one of the
+/// lowest key id among them.
+///
+/// The artifact rules of the step are not applied, as they refer to other
+/// steps, and links of other steps are ignored. A step delegated to a
+/// sublayout cannot be verified this way, as the links of the sublayout are
+/// not at hand, so `links` holding any layout is an
+/// [`Error::IllegalArgument`].
+pub fn verify_step(
+    step: &Step,
+    links: &[Metablock],
+    keys: &BTreeMap<KeyId, PublicKey>,
+) -> Result<LinkMetadata> {
+    if links
+        .iter()
+        .any(|link| matches!(link.metadata, MetadataWrapper::Layout(_)))
+    {
+        return Err(Error::IllegalArgument(format!(
+            "step {} cannot be verified from a sublayout",
+            step.name
+        )));
+    }
+    let keys: HashMap<KeyId, PublicKey> = keys
+        .iter()
+        .map(|(key_id, key)| (key_id.clone(), key.clone()))
+        .collect();
+    let mut links_per_step = HashMap::new();
+    for (signer_short_key_id, link_metablock) in
+        LinkSource::Links(links).step_links(&step.name)?
+    {
+        match_signatures(
+            link_metablock,
+            &signer_short_key_id,
+            &mut links_per_step,
+        );
+    }
+    let mut links_per_step =
+        verify_link_signature_thresholds_step(step, &links_per_step, &keys)?
+            .into_iter()
+            .filter_map(|(key_id, link)| match link.metadata {
+                MetadataWrapper::Link(link) => Some((key_id, link)),
+                // Rejected above.
+                MetadataWrapper::Layout(_) => None,
+            })
+            .collect();
+    if step.threshold > 1 {
+        verify_threshold_constraints_step(step, &mut links_per_step)?;
+    }
+    links_per_step
+        .into_iter()
+        .min_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, link)| link)
+        .ok_or_else(|| {
+            Error::VerificationFailure(format!(
+                "step {} has no link with a valid signature",
+                step.name
+            ))
+        })
+}
+
+/// Verify the supply chain as [`in_toto_verify`] does, with `now` as the
+/// current time for expiration checks of the layout and any sublayouts.
+/// `depth` is the sublayout nesting depth of `layout`, 0 for the root layout.
+fn verify_at(
     layout: &Metablock,
     layout_keys: HashMap<KeyId, PublicKey>,
-    link_dir: &str,
+    links: &LinkSource,
     step_name: Option<&str>,
+    now: DateTime<Utc>,
+    depth: usize,
 ) -> Result<Metablock> {
+    if depth > MAX_SUBLAYOUT_DEPTH {
+        return Err(Error::VerificationFailure(format!(
+            "sublayouts are nested deeper than {} levels",
+            MAX_SUBLAYOUT_DEPTH
+        )));
+    }
+
     // Verify layout signature(s) using passed key(s) and
     // judge whether the Metablock has layout inside
     let layout = match verify_layout_signatures(layout, &layout_keys)? {
@@ -523,23 +1148,23 @@ pub fn in_toto_verify(
     };
 
     // Verify layout expiration date
-    verify_layout_expiration(&layout)?;
+    verify_layout_expiration(&layout, now)?;
 
     // Load metadata files for steps of layout
-    let steps_links_metadata = load_links_for_layout(&layout, link_dir)?;
+    let steps_links_metadata = load_links_for_layout(&layout, links)?;
 
     // Verify signatures and signature thresholds for steps of layout
     let link_files =
         verify_link_signature_thresholds(&layout, steps_links_metadata)?;
 
     // Verify sublayouts recursively
-    let link_files = verify_sublayouts(&layout, link_files, link_dir)?;
+    let link_files = verify_sublayouts(&layout, link_files, links, now, depth)?;
 
     // Verify command alignment for steps of layout (only warns)
     verify_all_steps_command_alignment(&layout, &link_files)?;
 
     // Verify threshold
-    verify_threshold_constraints(&layout, &link_files)?;
+    let link_files = verify_threshold_constraints(&layout, link_files)?;
 
     // Reduce link files
     let mut reduced_link_files = reduce_chain_links(link_files)?;
@@ -554,6 +1179,7 @@ pub fn in_toto_verify(
 
     // Execute inspection commands (generates link metadata for each inspection)
     let inspection_link_files = run_all_inspections(&layout)?;
+    // so that MATCH rules of inspections resolve `FROM <inspection>`
     reduced_link_files.extend(inspection_link_files);
 
     let inspects = layout
@@ -568,18 +1194,781 @@ pub fn in_toto_verify(
     get_summary_link(&layout, &reduced_link_files, step_name.unwrap_or(""))
 }
 
+/// A layout and all the link files needed to verify it, in a single
+/// document: the whole supply chain record ships as one file, e.g. to an
+/// air-gapped verifier. It serializes to JSON as
+///
+/// ```json
+/// {
+///   "layout": { "signatures": [...], "signed": { "_type": "layout", ... } },
+///   "links": { "build.776a00e2.link": { "signatures": [...], ... }, ... }
+/// }
+/// ```
+///
+/// where `links` maps the path of every link file, relative to the link
+/// directory, to its contents. The links of a sublayout are under its
+/// subdirectory, e.g. `build.776a00e2/compile.0c6c50a1.link`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bundle {
+    layout: Metablock,
+    links: BTreeMap<String, Metablock>,
+}
+
+impl Bundle {
+    /// Bundle the layout with the link files in `link_dir` that
+    /// [`in_toto_verify`] would load for it, including those of sublayouts.
+    /// Neither the layout nor the links are verified.
+    pub fn from_dir<P: AsRef<Path>>(
+        layout: Metablock,
+        link_dir: P,
+    ) -> Result<Self> {
+        let mut links = BTreeMap::new();
+        match &layout.metadata {
+            MetadataWrapper::Layout(inner) => {
+                collect_links(inner, link_dir.as_ref(), "", 0, &mut links)?
+            }
+            MetadataWrapper::Link(_) => {
+                return Err(Error::IllegalArgument(
+                    "The input Metablock is not a layout.".to_string(),
+                ))
+            }
+        }
+        Ok(Bundle { layout, links })
+    }
+
+    /// The root layout.
+    pub fn layout(&self) -> &Metablock {
+        &self.layout
+    }
+
+    /// The link files, by path relative to the link directory.
+    pub fn links(&self) -> &BTreeMap<String, Metablock> {
+        &self.links
+    }
+
+    /// Verify the supply chain as [`in_toto_verify`] does, loading the link
+    /// metadata from the bundle instead of the disk. Nothing is read from or
+    /// written to the disk, so layouts with inspections, which run commands
+    /// on the verifier's files, are refused.
+    pub fn verify(
+        &self,
+        layout_keys: HashMap<KeyId, PublicKey>,
+    ) -> Result<Metablock> {
+        self.verify_at(layout_keys, Utc::now())
+    }
+
+    /// Verify the bundle as [`Bundle::verify`] does, with `now` as the
+    /// current time for the expiration checks of the layout and any
+    /// sublayouts.
+    pub fn verify_at(
+        &self,
+        layout_keys: HashMap<KeyId, PublicKey>,
+        now: DateTime<Utc>,
+    ) -> Result<Metablock> {
+        let layouts = std::iter::once(&self.layout)
+            .chain(self.links.values())
+            .filter_map(|metablock| match &metablock.metadata {
+                MetadataWrapper::Layout(layout) => Some(layout),
+                MetadataWrapper::Link(_) => None,
+            });
+        for layout in layouts {
+            if let Some(inspection) = layout.inspect.first() {
+                return Err(Error::IllegalArgument(format!(
+                    "inspection {} cannot be run from a bundle",
+                    inspection.name
+                )));
+            }
+        }
+        let links = LinkSource::Bundle {
+            links: &self.links,
+            prefix: String::new(),
+        };
+        verify_at(&self.layout, layout_keys, &links, None, now, 0)
+    }
+}
+
+/// Insert the link files in `link_dir` for the steps of `layout` into
+/// `links`, with their path prefixed by `prefix`, and recurse into the
+/// subdirectories of sublayouts. `depth` is the sublayout nesting depth of
+/// `layout`.
+fn collect_links(
+    layout: &LayoutMetadata,
+    link_dir: &Path,
+    prefix: &str,
+    depth: usize,
+    links: &mut BTreeMap<String, Metablock>,
+) -> Result<()> {
+    if depth > MAX_SUBLAYOUT_DEPTH {
+        return Err(Error::VerificationFailure(format!(
+            "sublayouts are nested deeper than {} levels",
+            MAX_SUBLAYOUT_DEPTH
+        )));
+    }
+
+    let source = LinkSource::Dir(link_dir.to_path_buf());
+    for step in &layout.steps {
+        for (key_id, link) in source.step_links(&step.name)? {
+            let file_name = format!("{}.{}", step.name, key_id);
+            if let MetadataWrapper::Layout(sublayout) = &link.metadata {
+                collect_links(
+                    sublayout,
+                    &link_dir.join(&file_name),
+                    &format!("{prefix}{file_name}/"),
+                    depth + 1,
+                    links,
+                )?;
+            }
+            links.insert(format!("{prefix}{file_name}.link"), link);
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use std::{collections::HashMap, fs, str::FromStr};
+    use std::{
+        collections::{BTreeMap, HashMap},
+        fs,
+        str::FromStr,
+    };
 
     use crate::{
-        crypto::{KeyId, PublicKey, SignatureScheme},
-        error::Error::VerificationFailure,
-        models::Metablock,
+        crypto::{KeyId, PrivateKey, PublicKey, SignatureScheme},
+        error::Error::{self, ExpiredMetadata},
+        models::{
+            inspection::Inspection,
+            rule::ArtifactRule,
+            step::{Command, Step},
+            supply_chain_item::SupplyChainItem,
+            LayoutMetadata, LinkMetadata, LinkMetadataBuilder, Metablock,
+            MetadataWrapper, VirtualTargetPath,
+        },
+        rulelib::apply_rules_on_link,
+        runlib::{in_toto_run, link_filename, RunOptions},
     };
     use std::path::Path;
 
-    use super::in_toto_verify;
+    use chrono::{DateTime, Duration, Utc};
+
+    use crate::models::LayoutMetadataBuilder;
+
+    use super::{
+        in_toto_verify, in_toto_verify_at, in_toto_verify_from_metadata, plan,
+        run_inspection, verify_at, verify_command_alignment,
+        verify_layout_expiration, verify_link_signature_thresholds,
+        verify_step, verify_threshold_constraints, Bundle, CommandWarning,
+        LinkSource, MAX_SUBLAYOUT_DEPTH,
+    };
+
+    /// Write a two-level supply chain to `dir`: the root layout's `build`
+    /// step is delegated to a sublayout with a single `compile` step.
+    /// Returns the root layout and the keys to verify it.
+    fn write_nested_supply_chain(
+        dir: &Path,
+    ) -> (Metablock, HashMap<KeyId, PublicKey>) {
+        let owner = PrivateKey::from_ed25519(include_bytes!(
+            "../tests/test_metadata/owner.der"
+        ))
+        .unwrap();
+        let alice = PrivateKey::from_ed25519(include_bytes!(
+            "../tests/ed25519/ed25519-1"
+        ))
+        .unwrap();
+        let bob = PrivateKey::from_pkcs8(
+            include_bytes!("../tests/ed25519/ed25519-2.pk8.der"),
+            SignatureScheme::Ed25519,
+        )
+        .unwrap();
+        let expires = Utc::now() + Duration::days(1);
+        let product = "tests/test_link/foo.tar.gz";
+
+        let link = LinkMetadataBuilder::new()
+            .name("compile".into())
+            .add_product(VirtualTargetPath::new(product.into()).unwrap())
+            .build()
+            .unwrap();
+        let link =
+            Metablock::new(MetadataWrapper::Link(link), &[&bob]).unwrap();
+
+        let sublayout = LayoutMetadataBuilder::new()
+            .expires(expires)
+            .add_key(bob.public().clone())
+            .add_step(
+                Step::new("compile")
+                    .threshold(1)
+                    .add_key(bob.public().key_id().clone())
+                    .add_expected_product(ArtifactRule::Create(product.into())),
+            )
+            .build()
+            .unwrap();
+        let sublayout =
+            Metablock::new(MetadataWrapper::Layout(sublayout), &[&alice])
+                .unwrap();
+
+        let layout = LayoutMetadataBuilder::new()
+            .expires(expires)
+            .add_key(alice.public().clone())
+            .add_step(
+                Step::new("build")
+                    .threshold(1)
+                    .add_key(alice.public().key_id().clone())
+                    .add_expected_product(ArtifactRule::Create(product.into())),
+            )
+            .build()
+            .unwrap();
+        let layout =
+            Metablock::new(MetadataWrapper::Layout(layout), &[&owner]).unwrap();
+
+        let sub_dir =
+            dir.join(format!("build.{}", alice.public().key_id().prefix()));
+        fs::create_dir(&sub_dir).unwrap();
+        fs::write(
+            dir.join(link_filename("build", alice.public().key_id())),
+            serde_json::to_vec(&sublayout).unwrap(),
+        )
+        .unwrap();
+        fs::write(
+            sub_dir.join(link_filename("compile", bob.public().key_id())),
+            serde_json::to_vec(&link).unwrap(),
+        )
+        .unwrap();
+
+        let keys = HashMap::from([(
+            owner.public().key_id().clone(),
+            owner.public().clone(),
+        )]);
+        (layout, keys)
+    }
+
+    /// A layout with a single `build` step with threshold 2, and links for
+    /// it from three functionaries, recording the given products.
+    fn threshold_links(
+        products: &[&str],
+    ) -> (
+        LayoutMetadata,
+        HashMap<String, HashMap<KeyId, LinkMetadata>>,
+    ) {
+        let keys: Vec<PublicKey> = [
+            &include_bytes!("../tests/ed25519/ed25519-1.pk8.der")[..],
+            &include_bytes!("../tests/ed25519/ed25519-2.pk8.der")[..],
+            &include_bytes!("../tests/ed25519/ed25519-3.pk8.der")[..],
+        ]
+        .iter()
+        .map(|der| {
+            PrivateKey::from_pkcs8(der, SignatureScheme::Ed25519)
+                .unwrap()
+                .public()
+                .clone()
+        })
+        .collect();
+
+        let mut step = Step::new("build").threshold(2);
+        for key in &keys {
+            step = step.add_key(key.key_id().clone());
+        }
+        let mut builder = LayoutMetadataBuilder::new().add_step(step);
+        for key in &keys {
+            builder = builder.add_key(key.clone());
+        }
+
+        let links = keys
+            .iter()
+            .zip(products)
+            .map(|(key, product)| {
+                let link = LinkMetadataBuilder::new()
+                    .name("build".into())
+                    .add_product(
+                        VirtualTargetPath::new(product.to_string()).unwrap(),
+                    )
+                    .build()
+                    .unwrap();
+                (key.key_id().clone(), link)
+            })
+            .collect();
+        (
+            builder.build().unwrap(),
+            HashMap::from([("build".to_string(), links)]),
+        )
+    }
+
+    #[test]
+    fn threshold_links_agree() {
+        let (layout, links) = threshold_links(&[
+            "tests/test_link/foo.tar.gz",
+            "tests/test_link/foo.tar.gz",
+            "tests/test_runlib/hello./world",
+        ]);
+        let verified = verify_threshold_constraints(&layout, links).unwrap();
+        assert_eq!(verified["build"].len(), 2);
+        for link in verified["build"].values() {
+            assert!(link.products.contains_key(
+                &VirtualTargetPath::new("tests/test_link/foo.tar.gz".into())
+                    .unwrap()
+            ));
+        }
+    }
+
+    #[test]
+    fn threshold_links_disagree() {
+        let (layout, links) = threshold_links(&[
+            "tests/test_link/foo.tar.gz",
+            "tests/test_runlib/hello./world",
+        ]);
+        match verify_threshold_constraints(&layout, links) {
+            Err(Error::VerificationFailure(msg)) => {
+                assert!(msg.contains("tests/test_link/foo.tar.gz"), "{}", msg);
+                assert!(
+                    msg.contains("tests/test_runlib/hello./world"),
+                    "{}",
+                    msg
+                );
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn threshold_links_below_threshold() {
+        let (layout, links) = threshold_links(&["tests/test_link/foo.tar.gz"]);
+        assert!(matches!(
+            verify_threshold_constraints(&layout, links),
+            Err(Error::VerificationFailure(_))
+        ));
+    }
+
+    #[test]
+    fn verify_single_step() {
+        let keys: Vec<PrivateKey> = [
+            &include_bytes!("../tests/ed25519/ed25519-1.pk8.der")[..],
+            &include_bytes!("../tests/ed25519/ed25519-2.pk8.der")[..],
+            &include_bytes!("../tests/ed25519/ed25519-3.pk8.der")[..],
+        ]
+        .iter()
+        .map(|der| {
+            PrivateKey::from_pkcs8(der, SignatureScheme::Ed25519).unwrap()
+        })
+        .collect();
+        // Only the first two keys are functionaries of the step.
+        let step = Step::new("build")
+            .threshold(2)
+            .add_key(keys[0].public().key_id().clone())
+            .add_key(keys[1].public().key_id().clone());
+        let layout_keys: BTreeMap<KeyId, PublicKey> = keys
+            .iter()
+            .map(|key| (key.key_id().clone(), key.public().clone()))
+            .collect();
+        let link = |name: &str, product: &str, key: &PrivateKey| {
+            let link = LinkMetadataBuilder::new()
+                .name(name.into())
+                .add_product(VirtualTargetPath::new(product.into()).unwrap())
+                .build()
+                .unwrap();
+            Metablock::new(MetadataWrapper::Link(link), &[key]).unwrap()
+        };
+        let product = "tests/test_link/foo.tar.gz";
+
+        let links = [
+            link("build", product, &keys[0]),
+            link("build", product, &keys[1]),
+            link("package", product, &keys[2]),
+        ];
+        let verified = verify_step(&step, &links, &layout_keys).unwrap();
+        assert_eq!(verified.name, "build");
+        assert!(verified
+            .products
+            .contains_key(&VirtualTargetPath::new(product.into()).unwrap()));
+
+        // The agreed link does not depend on the order of the links.
+        let mut reversed = links.clone();
+        reversed.reverse();
+        assert_eq!(
+            verify_step(&step, &reversed, &layout_keys).unwrap(),
+            verified
+        );
+
+        // The third key is in the layout, but not a functionary of the
+        // step, and one link is not enough.
+        let links = [
+            link("build", product, &keys[0]),
+            link("build", product, &keys[2]),
+        ];
+        assert!(matches!(
+            verify_step(&step, &links, &layout_keys),
+            Err(Error::VerificationFailure(_))
+        ));
+
+        // The links must agree on their artifacts.
+        let links = [
+            link("build", product, &keys[0]),
+            link("build", "tests/test_runlib/hello./world", &keys[1]),
+        ];
+        assert!(matches!(
+            verify_step(&step, &links, &layout_keys),
+            Err(Error::VerificationFailure(_))
+        ));
+
+        // A step delegated to a sublayout is refused instead of being
+        // reported as missing links.
+        let sublayout = LayoutMetadataBuilder::new()
+            .expires(Utc::now() + Duration::days(1))
+            .build()
+            .unwrap();
+        let links = [
+            link("build", product, &keys[0]),
+            Metablock::new(MetadataWrapper::Layout(sublayout), &[&keys[1]])
+                .unwrap(),
+        ];
+        assert!(matches!(
+            verify_step(&step, &links, &layout_keys),
+            Err(Error::IllegalArgument(_))
+        ));
+    }
+
+    #[test]
+    fn link_signed_by_other_step_functionary() {
+        let keys: Vec<PrivateKey> = [
+            &include_bytes!("../tests/ed25519/ed25519-1.pk8.der")[..],
+            &include_bytes!("../tests/ed25519/ed25519-2.pk8.der")[..],
+        ]
+        .iter()
+        .map(|der| {
+            PrivateKey::from_pkcs8(der, SignatureScheme::Ed25519).unwrap()
+        })
+        .collect();
+        let layout = LayoutMetadataBuilder::new()
+            .add_key(keys[0].public().clone())
+            .add_key(keys[1].public().clone())
+            .add_step(
+                Step::new("a")
+                    .threshold(1)
+                    .add_key(keys[0].public().key_id().clone()),
+            )
+            .add_step(
+                Step::new("b")
+                    .threshold(1)
+                    .add_key(keys[1].public().key_id().clone()),
+            )
+            .build()
+            .unwrap();
+        let link = |name: &str, key: &PrivateKey| {
+            let link = LinkMetadataBuilder::new()
+                .name(name.into())
+                .build()
+                .unwrap();
+            let link =
+                Metablock::new(MetadataWrapper::Link(link), &[key]).unwrap();
+            HashMap::from([(key.key_id().clone(), link)])
+        };
+
+        let links = HashMap::from([
+            ("a".to_string(), link("a", &keys[0])),
+            ("b".to_string(), link("b", &keys[1])),
+        ]);
+        assert!(verify_link_signature_thresholds(&layout, links).is_ok());
+
+        // The key of step b is in the layout, but may not sign for step a.
+        let links = HashMap::from([
+            ("a".to_string(), link("a", &keys[1])),
+            ("b".to_string(), link("b", &keys[1])),
+        ]);
+        assert!(matches!(
+            verify_link_signature_thresholds(&layout, links),
+            Err(Error::VerificationFailure(_))
+        ));
+    }
+
+    #[test]
+    fn verify_nested_sublayout() {
+        let dir = tempfile::tempdir().unwrap();
+        let (layout, keys) = write_nested_supply_chain(dir.path());
+
+        let summary = in_toto_verify(&layout, keys, dir.path(), None).unwrap();
+        match summary.metadata {
+            MetadataWrapper::Link(link) => {
+                let products: Vec<_> =
+                    link.products.keys().map(|p| p.value()).collect();
+                assert_eq!(products, ["tests/test_link/foo.tar.gz"]);
+            }
+            MetadataWrapper::Layout(_) => panic!("summary is not a link"),
+        }
+    }
+
+    #[test]
+    fn plan_demo_layout() {
+        let layout: Metablock = serde_json::from_slice(include_bytes!(
+            "../tests/test_metadata/demo.layout"
+        ))
+        .unwrap();
+        let layout = match layout.metadata {
+            MetadataWrapper::Layout(layout) => layout,
+            MetadataWrapper::Link(_) => panic!("demo.layout is a link"),
+        };
+
+        let plan = plan(&layout);
+        let steps: Vec<_> = plan
+            .steps
+            .iter()
+            .map(|step| (step.name.as_str(), step.link_files.clone()))
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                ("write-code", vec!["write-code.e0294a3f.link".to_string()]),
+                ("package", vec!["package.59d12f31.link".to_string()]),
+            ]
+        );
+        assert_eq!(
+            plan.steps[1].expected_command,
+            ["tar", "zcvf", "foo.tar.gz", "foo.py"]
+        );
+        assert_eq!(plan.steps[1].material_rules.len(), 1);
+        let inspections: Vec<_> = plan
+            .inspections
+            .iter()
+            .map(|inspection| inspection.link_file.as_str())
+            .collect();
+        assert_eq!(inspections, ["inspect_tarball.link"]);
+        assert_eq!(
+            plan.inspections[0].run,
+            ["inspect_tarball.sh", "foo.tar.gz"]
+        );
+
+        let shown = plan.to_string();
+        let order: Vec<_> = [
+            "step write-code",
+            "step package",
+            "inspection inspect_tarball",
+        ]
+        .iter()
+        .map(|item| shown.find(item).unwrap())
+        .collect();
+        assert!(order.windows(2).all(|w| w[0] < w[1]), "{}", shown);
+        assert!(shown.contains("MATCH foo.py WITH PRODUCTS FROM write-code"));
+    }
+
+    #[test]
+    fn verify_from_metadata() {
+        let owner = PrivateKey::from_ed25519(include_bytes!(
+            "../tests/test_metadata/owner.der"
+        ))
+        .unwrap();
+        let bob = PrivateKey::from_pkcs8(
+            include_bytes!("../tests/ed25519/ed25519-2.pk8.der"),
+            SignatureScheme::Ed25519,
+        )
+        .unwrap();
+        let product = "tests/test_link/foo.tar.gz";
+        let layout = LayoutMetadataBuilder::new()
+            .expires(Utc::now() + Duration::days(1))
+            .add_key(bob.public().clone())
+            .add_step(
+                Step::new("build")
+                    .threshold(1)
+                    .add_key(bob.public().key_id().clone())
+                    .add_expected_product(ArtifactRule::Create(product.into())),
+            )
+            .build()
+            .unwrap();
+        let layout = serde_json::to_vec(
+            &Metablock::new(MetadataWrapper::Layout(layout), &[&owner])
+                .unwrap(),
+        )
+        .unwrap();
+        let link = |name: &str| {
+            let link = LinkMetadataBuilder::new()
+                .name(name.into())
+                .add_product(VirtualTargetPath::new(product.into()).unwrap())
+                .build()
+                .unwrap();
+            serde_json::to_vec(
+                &Metablock::new(MetadataWrapper::Link(link), &[&bob]).unwrap(),
+            )
+            .unwrap()
+        };
+        let keys = HashMap::from([(
+            owner.public().key_id().clone(),
+            owner.public().clone(),
+        )]);
+
+        let layout: Metablock = serde_json::from_slice(&layout).unwrap();
+        let links: Vec<Metablock> = [link("build"), link("unrelated")]
+            .iter()
+            .map(|raw| serde_json::from_slice(raw).unwrap())
+            .collect();
+        let summary =
+            in_toto_verify_from_metadata(&layout, keys.clone(), &links, None)
+                .unwrap();
+        match summary.metadata {
+            MetadataWrapper::Link(link) => {
+                let products: Vec<_> =
+                    link.products.keys().map(|p| p.value()).collect();
+                assert_eq!(products, [product]);
+            }
+            MetadataWrapper::Layout(_) => panic!("summary is not a link"),
+        }
+
+        // without the link of the build step
+        assert!(matches!(
+            in_toto_verify_from_metadata(
+                &layout,
+                keys.clone(),
+                &links[1..],
+                None
+            ),
+            Err(Error::VerificationFailure(_))
+        ));
+
+        // layouts are rejected as links
+        assert!(matches!(
+            in_toto_verify_from_metadata(
+                &layout,
+                keys,
+                &[links[0].clone(), layout.clone()],
+                None
+            ),
+            Err(Error::IllegalArgument(_))
+        ));
+    }
+
+    #[test]
+    fn verify_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let (layout, keys) = write_nested_supply_chain(dir.path());
+        let bundle = Bundle::from_dir(layout, dir.path()).unwrap();
+        assert_eq!(bundle.links().len(), 2);
+        let serialized = serde_json::to_vec(&bundle).unwrap();
+        dir.close().unwrap();
+
+        let bundle: Bundle = serde_json::from_slice(&serialized).unwrap();
+        let summary = bundle.verify(keys.clone()).unwrap();
+        // the layouts expire in a day
+        assert!(bundle.verify_at(keys.clone(), Utc::now()).is_ok());
+        assert!(matches!(
+            bundle.verify_at(keys.clone(), Utc::now() + Duration::days(2)),
+            Err(ExpiredMetadata(_))
+        ));
+        match summary.metadata {
+            MetadataWrapper::Link(link) => {
+                let products: Vec<_> =
+                    link.products.keys().map(|p| p.value()).collect();
+                assert_eq!(products, ["tests/test_link/foo.tar.gz"]);
+            }
+            MetadataWrapper::Layout(_) => panic!("summary is not a link"),
+        }
+
+        // without the link of its step, the sublayout fails
+        let mut links = bundle.links().clone();
+        links.retain(|path, _| !path.contains('/'));
+        let bundle = Bundle {
+            layout: bundle.layout().clone(),
+            links,
+        };
+        assert!(bundle.verify(keys).is_err());
+
+        // inspections would run commands on the verifier's machine
+        let owner = PrivateKey::from_ed25519(include_bytes!(
+            "../tests/test_metadata/owner.der"
+        ))
+        .unwrap();
+        let layout = LayoutMetadataBuilder::new()
+            .expires(Utc::now() + Duration::days(1))
+            .add_inspect(
+                Inspection::new("touch").run(Command::from("touch touched")),
+            )
+            .build()
+            .unwrap();
+        let layout =
+            Metablock::new(MetadataWrapper::Layout(layout), &[&owner]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = Bundle::from_dir(layout, dir.path()).unwrap();
+        let keys =
+            HashMap::from([(owner.key_id().clone(), owner.public().clone())]);
+        assert!(matches!(
+            bundle.verify(keys),
+            Err(Error::IllegalArgument(msg)) if msg.contains("touch")
+        ));
+        assert!(!Path::new("touched").exists());
+        assert!(!Path::new("touch.link").exists());
+    }
+
+    #[test]
+    fn verify_sublayout_depth_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let (layout, keys) = write_nested_supply_chain(dir.path());
+
+        // The root layout itself is allowed, its sublayout is one too deep.
+        let result = verify_at(
+            &layout,
+            keys,
+            &LinkSource::Dir(dir.path().to_path_buf()),
+            None,
+            Utc::now(),
+            MAX_SUBLAYOUT_DEPTH,
+        );
+        assert!(matches!(
+            result,
+            Err(Error::VerificationFailure(msg)) if msg.contains("nested")
+        ));
+    }
+
+    #[test]
+    fn run_untar_inspection() {
+        let dir = tempfile::tempdir().unwrap();
+        let run_dir = dir.path().to_str().unwrap();
+        let package = |contents: &str| {
+            fs::write(dir.path().join("foo.txt"), contents).unwrap();
+            let options = RunOptions::new()
+                .run_dir(run_dir)
+                .lstrip_paths(&[&format!("{}/", run_dir)]);
+            let link = in_toto_run(
+                "package",
+                &[run_dir],
+                &[run_dir],
+                &["tar", "-cf", "foo.tar", "foo.txt"],
+                &options,
+            )
+            .unwrap();
+            fs::remove_file(dir.path().join("foo.txt")).unwrap();
+            match link.metadata {
+                MetadataWrapper::Link(link) => link,
+                MetadataWrapper::Layout(_) => panic!("not a link"),
+            }
+        };
+        let rules = |json: &str| -> Vec<ArtifactRule> {
+            serde_json::from_str(json).unwrap()
+        };
+        let inspection = Inspection::new("untar")
+            .run(Command::from("tar -xf foo.tar"))
+            .expected_materials(rules(
+                r#"[["MATCH", "foo.tar", "WITH", "PRODUCTS", "FROM", "package"]]"#,
+            ))
+            .expected_products(rules(
+                r#"[["MATCH", "foo.txt", "WITH", "MATERIALS", "FROM", "package"], ["ALLOW", "foo.tar"]]"#,
+            ));
+        let item: Box<dyn SupplyChainItem> = Box::new(inspection.clone());
+
+        let mut links =
+            HashMap::from([("package".to_string(), package("hello"))]);
+        let untar = run_inspection(&inspection, dir.path()).unwrap();
+        assert_eq!(untar.name, "untar");
+        assert_eq!(untar.byproducts.return_value(), Some(0));
+        let materials: Vec<_> =
+            untar.materials.keys().map(|p| p.value()).collect();
+        assert_eq!(materials, ["foo.tar"]);
+        let products: Vec<_> =
+            untar.products.keys().map(|p| p.value()).collect();
+        assert_eq!(products, ["foo.tar", "foo.txt"]);
+        links.insert("untar".to_string(), untar);
+        apply_rules_on_link(&item, &links).unwrap();
+
+        // a tarball replaced after packaging is rejected
+        let recorded = links.remove("package").unwrap();
+        package("tampered");
+        links.insert("package".to_string(), recorded);
+        let untar = run_inspection(&inspection, dir.path()).unwrap();
+        links.insert("untar".to_string(), untar);
+        assert!(apply_rules_on_link(&item, &links).is_err());
+    }
 
     #[test]
     fn verify_demo() {
@@ -610,8 +1999,108 @@ mod tests {
         let result = in_toto_verify(&layout, layout_keys, "../links", None);
         match result {
             Ok(_) => {}
-            Err(VerificationFailure(msg)) => assert!(msg == "layout expired"),
+            Err(ExpiredMetadata(_)) => {}
             Err(error) => panic!("{}", error),
         }
     }
+
+    #[test]
+    fn verify_expiration() {
+        let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+
+        let expired = LayoutMetadataBuilder::new()
+            .expires(now - Duration::seconds(1))
+            .build()
+            .unwrap();
+        assert!(expired.is_expired(now));
+        assert_eq!(
+            verify_layout_expiration(&expired, now),
+            Err(ExpiredMetadata(now - Duration::seconds(1)))
+        );
+
+        let valid = LayoutMetadataBuilder::new()
+            .expires(now + Duration::seconds(1))
+            .build()
+            .unwrap();
+        assert!(!valid.is_expired(now));
+        assert!(verify_layout_expiration(&valid, now).is_ok());
+    }
+
+    #[test]
+    fn verify_at_given_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let (layout, keys) = write_nested_supply_chain(dir.path());
+        let now = Utc::now();
+
+        assert!(in_toto_verify_at(
+            &layout,
+            keys.clone(),
+            dir.path(),
+            None,
+            now
+        )
+        .is_ok());
+        assert!(matches!(
+            in_toto_verify_at(
+                &layout,
+                keys,
+                dir.path(),
+                None,
+                now + Duration::days(2)
+            ),
+            Err(ExpiredMetadata(_))
+        ));
+    }
+
+    fn argv(command: &str) -> Vec<String> {
+        command.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn command_alignment() {
+        let expected = argv("tar -z -c -f foo.tar.gz foo.py");
+
+        let identical = verify_command_alignment(&expected, &expected);
+        assert!(identical.is_empty());
+        assert!(identical.strict().is_ok());
+
+        let swapped = verify_command_alignment(
+            &expected,
+            &argv("tar -c -z -f foo.tar.gz foo.py"),
+        );
+        assert_eq!(
+            swapped.warnings(),
+            [
+                CommandWarning::Argument {
+                    position: 1,
+                    expected: Some("-z".into()),
+                    run: Some("-c".into()),
+                },
+                CommandWarning::Argument {
+                    position: 2,
+                    expected: Some("-c".into()),
+                    run: Some("-z".into()),
+                },
+            ]
+        );
+        assert!(matches!(
+            swapped.strict(),
+            Err(Error::VerificationFailure(_))
+        ));
+
+        let different = verify_command_alignment(&expected, &argv("curl"));
+        assert_eq!(
+            different.warnings()[0],
+            CommandWarning::Binary {
+                expected: "tar".into(),
+                run: "curl".into(),
+            }
+        );
+        assert_eq!(different.warnings().len(), expected.len());
+        let err = different.strict().unwrap_err().to_string();
+        assert!(err.contains("ran \"curl\" instead of \"tar\""), "{}", err);
+
+        // A step without an expected command accepts any command.
+        assert!(verify_command_alignment(&[], &argv("make")).is_empty());
+    }
 }
@@ -0,0 +1,109 @@
+//! Run a step and verify the supply chain through `runlib::run` and
+//! `verifylib::verify`, the way a command line tool built on them would.
+
+use in_toto::{
+    crypto::{PrivateKey, SignatureScheme},
+    models::{
+        rule::ArtifactRule,
+        step::{Command, Step},
+        LayoutMetadataBuilder, Metablock, MetadataWrapper,
+    },
+    runlib::run,
+    verifylib::verify,
+};
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+const FUNCTIONARY_KEY: &str = "tests/ed25519/ed25519-1.pk8.der";
+const OWNER_KEY: &str = "tests/ed25519/ed25519-2.pk8.der";
+
+fn load_key(path: &str) -> PrivateKey {
+    PrivateKey::from_pkcs8(&fs::read(path).unwrap(), SignatureScheme::Ed25519)
+        .unwrap()
+}
+
+#[test]
+fn run_then_verify() {
+    let dir = tempdir().unwrap();
+    let work = dir.path().join("work");
+    let links = dir.path().join("links");
+    fs::create_dir(&work).unwrap();
+    fs::create_dir(&links).unwrap();
+    fs::write(work.join("foo.c"), "int main() {}\n").unwrap();
+
+    let functionary = load_key(FUNCTIONARY_KEY);
+    let owner = load_key(OWNER_KEY);
+    let command = ["sh", "-c", "cp foo.c foo.o"];
+    let layout = LayoutMetadataBuilder::new()
+        .add_key(functionary.public().clone())
+        .add_step(
+            Step::new("compile")
+                .threshold(1)
+                .add_key(functionary.public().key_id().clone())
+                .expected_command(Command::from(
+                    command.map(String::from).to_vec(),
+                ))
+                .add_expected_material(ArtifactRule::Allow("foo.c".into()))
+                .add_expected_material(ArtifactRule::Disallow("*".into()))
+                .add_expected_product(ArtifactRule::Create("foo.o".into()))
+                .add_expected_product(ArtifactRule::Allow("foo.c".into()))
+                .add_expected_product(ArtifactRule::Disallow("*".into())),
+        )
+        .build()
+        .unwrap();
+    let layout =
+        Metablock::new(MetadataWrapper::Layout(layout), &[&owner]).unwrap();
+    let layout_path = dir.path().join("root.layout");
+    fs::write(&layout_path, serde_json::to_vec(&layout).unwrap()).unwrap();
+    let owner_pub = dir.path().join("owner.pub");
+    fs::write(&owner_pub, owner.public().as_spki().unwrap()).unwrap();
+
+    let (file_name, link) = run(
+        "compile",
+        &["."],
+        &["."],
+        &command,
+        Path::new(FUNCTIONARY_KEY),
+        SignatureScheme::Ed25519,
+        Some(&["sha256"]),
+        Some(work.to_str().unwrap()),
+    )
+    .unwrap();
+    assert_eq!(
+        file_name,
+        format!("compile.{}.link", functionary.public().key_id().prefix())
+    );
+    fs::write(links.join(&file_name), link).unwrap();
+
+    let summary = verify(&layout_path, &[&owner_pub], &links).unwrap();
+    let summary: Metablock = serde_json::from_slice(&summary).unwrap();
+    let summary = match summary.metadata {
+        MetadataWrapper::Link(link) => link,
+        MetadataWrapper::Layout(_) => panic!("the summary is a layout"),
+    };
+    let products: Vec<&str> =
+        summary.products.keys().map(|path| path.value()).collect();
+    assert_eq!(products, ["foo.c", "foo.o"]);
+
+    // The layout is not signed by the functionary.
+    let functionary_pub = dir.path().join("functionary.pub");
+    fs::write(&functionary_pub, functionary.public().as_spki().unwrap())
+        .unwrap();
+    assert!(verify(&layout_path, &[&functionary_pub], &links).is_err());
+
+    // A run that breaks the artifact rules of the step.
+    let (_, tampered) = run(
+        "compile",
+        &["."],
+        &["."],
+        &["sh", "-c", "echo tampered > foo.o"],
+        Path::new(FUNCTIONARY_KEY),
+        SignatureScheme::Ed25519,
+        Some(&["sha256"]),
+        Some(work.to_str().unwrap()),
+    )
+    .unwrap();
+    fs::write(links.join(&file_name), tampered).unwrap();
+    assert!(verify(&layout_path, &[&owner_pub], &links).is_err());
+}
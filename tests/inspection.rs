@@ -0,0 +1,145 @@
+//! Verify a supply chain whose inspection unpacks the final product and
+//! matches the unpacked files with the products of earlier steps and
+//! inspections.
+//!
+//! Inspections run in the current directory, so this is the only test of
+//! its binary.
+
+use chrono::{Duration, Utc};
+use in_toto::{
+    crypto::{PrivateKey, SignatureScheme},
+    models::{
+        inspection::Inspection, rule::ArtifactRule, step::Step,
+        LayoutMetadataBuilder, Metablock, MetadataWrapper,
+    },
+    runlib::{in_toto_run, link_filename, RunOptions},
+    verifylib::in_toto_verify,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn load_key(path: &str) -> PrivateKey {
+    PrivateKey::from_pkcs8(&fs::read(path).unwrap(), SignatureScheme::Ed25519)
+        .unwrap()
+}
+
+fn rules(json: &str) -> Vec<ArtifactRule> {
+    serde_json::from_str(json).unwrap()
+}
+
+/// Run the step `name` in `work`, writing its link to `links`.
+fn run(name: &str, cmd: &[&str], work: &Path, links: &Path, key: &PrivateKey) {
+    let work = work.to_str().unwrap();
+    let prefix = format!("{}/", work);
+    let options = RunOptions::new()
+        .run_dir(work)
+        .key(key)
+        .hash_algorithms(&["sha256"])
+        .lstrip_paths(&[&prefix]);
+    let link = in_toto_run(name, &[work], &[work], cmd, &options).unwrap();
+    fs::write(
+        links.join(link_filename(name, key.key_id())),
+        serde_json::to_vec(&link).unwrap(),
+    )
+    .unwrap();
+}
+
+#[test]
+fn inspection_matches_unpacked_product() {
+    let dir = tempdir().unwrap();
+    let work = dir.path().join("work");
+    let links = dir.path().join("links");
+    fs::create_dir(&work).unwrap();
+    fs::create_dir(&links).unwrap();
+
+    let functionary = load_key("tests/ed25519/ed25519-1.pk8.der");
+    let owner = load_key("tests/ed25519/ed25519-2.pk8.der");
+    let layout = LayoutMetadataBuilder::new()
+        .expires(Utc::now() + Duration::days(1))
+        .add_key(functionary.public().clone())
+        .add_step(
+            Step::new("build")
+                .threshold(1)
+                .add_key(functionary.key_id().clone())
+                .add_expected_product(ArtifactRule::Create("app.bin".into()))
+                .add_expected_product(ArtifactRule::Disallow("*".into())),
+        )
+        .add_step(
+            Step::new("package")
+                .threshold(1)
+                .add_key(functionary.key_id().clone())
+                .add_expected_material(ArtifactRule::Allow("*".into()))
+                .add_expected_product(ArtifactRule::Create("app.tar".into()))
+                .add_expected_product(ArtifactRule::Allow("*".into())),
+        )
+        .add_inspect(
+            Inspection::new("untar")
+                .run("tar -xf app.tar".into())
+                .expected_materials(rules(
+                    r#"[["MATCH", "app.tar", "WITH", "PRODUCTS", "FROM", "package"],
+                        ["DISALLOW", "*"]]"#,
+                ))
+                .expected_products(rules(
+                    r#"[["MATCH", "app.bin", "WITH", "PRODUCTS", "FROM", "build"],
+                        ["ALLOW", "app.tar"],
+                        ["DISALLOW", "*"]]"#,
+                )),
+        )
+        .add_inspect(
+            Inspection::new("check")
+                .run("true".into())
+                .expected_materials(rules(
+                    r#"[["MATCH", "app.bin", "WITH", "PRODUCTS", "FROM", "untar"],
+                        ["ALLOW", "*"]]"#,
+                ))
+                .expected_products(rules(r#"[["ALLOW", "*"]]"#)),
+        )
+        .build()
+        .unwrap();
+    let layout =
+        Metablock::new(MetadataWrapper::Layout(layout), &[&owner]).unwrap();
+    let keys =
+        HashMap::from([(owner.key_id().clone(), owner.public().clone())]);
+
+    std::env::set_current_dir(&work).unwrap();
+    let verify = || in_toto_verify(&layout, keys.clone(), &links, None);
+
+    run(
+        "build",
+        &["sh", "-c", "echo app > app.bin"],
+        &work,
+        &links,
+        &functionary,
+    );
+    run(
+        "package",
+        &["tar", "-cf", "app.tar", "app.bin"],
+        &work,
+        &links,
+        &functionary,
+    );
+    // The inspection checks the unpacked binary, not the one left behind.
+    fs::remove_file(work.join("app.bin")).unwrap();
+    verify().unwrap();
+    let untar = fs::read_to_string(work.join("untar.link")).unwrap();
+    assert!(untar.contains("app.bin"));
+    for inspection in ["untar", "check"] {
+        fs::remove_file(work.join(format!("{}.link", inspection))).unwrap();
+    }
+
+    // A binary swapped between the build and the package steps is only
+    // caught by the inspection: the package step allows any material.
+    fs::remove_file(work.join("app.tar")).unwrap();
+    run(
+        "package",
+        &["sh", "-c", "echo evil > app.bin && tar -cf app.tar app.bin"],
+        &work,
+        &links,
+        &functionary,
+    );
+    fs::remove_file(work.join("app.bin")).unwrap();
+    let error = verify().unwrap_err().to_string();
+    assert!(error.contains("in untar"), "{}", error);
+}
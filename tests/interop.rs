@@ -0,0 +1,86 @@
+//! Verify metadata signed by other in-toto implementations.
+//!
+//! - `tests/interop/python` is the demo supply chain signed by python
+//!   in-toto, with RSA keys.
+//! - `tests/interop/sslib` is written by `gen.py` there in the key format
+//!   of python in-toto and securesystemslib, with ECDSA and Ed25519 keys.
+
+use in_toto::{
+    crypto::{KeyId, PublicKey},
+    models::{Metablock, MetadataWrapper},
+    verifylib::in_toto_verify,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn fixtures(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("interop")
+        .join(name)
+}
+
+fn read_metablock(path: &Path) -> Metablock {
+    let raw = fs::read(path).unwrap();
+    serde_json::from_slice(&raw)
+        .unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+fn owner_key(dir: &Path) -> PublicKey {
+    let pem = fs::read_to_string(dir.join("owner.pub")).unwrap();
+    PublicKey::from_pem(&pem).unwrap()
+}
+
+/// Verify the layout in `dir` with `owner`, and the link of every
+/// functionary of every step with the key the layout lists for it.
+fn verify_signatures(dir: &Path, owner: &PublicKey) {
+    let layout = match read_metablock(&dir.join("root.layout"))
+        .verify(1, [owner])
+        .unwrap()
+    {
+        MetadataWrapper::Layout(layout) => layout,
+        MetadataWrapper::Link(_) => panic!("root.layout is a link"),
+    };
+
+    for (key_id, key) in layout.keys() {
+        assert_eq!(key.key_id(), key_id);
+    }
+    for step in layout.steps() {
+        for key_id in step.pub_keys() {
+            let file = format!("{}.{}.link", step.name, &key_id.as_str()[..8]);
+            let link = read_metablock(&dir.join(&file));
+            let key = &layout.keys()[key_id];
+            link.verify(1, [key])
+                .unwrap_or_else(|e| panic!("{}: {}", file, e));
+        }
+    }
+}
+
+#[test]
+fn python_rsa_signatures() {
+    let dir = fixtures("python");
+    let pem = fs::read_to_string(dir.join("alice.pub")).unwrap();
+    let owner = PublicKey::from_pem(&pem).unwrap();
+    assert_eq!(
+        owner.key_id().as_str(),
+        "556caebdc0877eed53d419b60eddb1e57fa773e4e31d70698b588f3e9cc48b35"
+    );
+    verify_signatures(&dir, &owner);
+}
+
+#[test]
+fn sslib_ecdsa_and_ed25519_signatures() {
+    let dir = fixtures("sslib");
+    verify_signatures(&dir, &owner_key(&dir));
+}
+
+#[test]
+fn sslib_supply_chain_verifies() {
+    let dir = fixtures("sslib");
+    let owner = owner_key(&dir);
+    let layout = read_metablock(&dir.join("root.layout"));
+    let layout_keys: HashMap<KeyId, PublicKey> =
+        HashMap::from([(owner.key_id().clone(), owner)]);
+    in_toto_verify(&layout, layout_keys, &dir, None).unwrap();
+}
@@ -0,0 +1,174 @@
+#!/usr/bin/env python3
+# Generate an ECDSA-owned layout and two links the way in-toto 1.x and
+# securesystemslib 0.x write them: their key dictionaries, key IDs,
+# canonical JSON and signatures, using pyca/cryptography directly.
+#
+# The keys are generated anew on each run.
+
+import hashlib
+import json
+import os
+import re
+
+from cryptography.hazmat.primitives import hashes, serialization
+from cryptography.hazmat.primitives.asymmetric import ec, ed25519
+
+HASH_ALGORITHMS = ["sha256", "sha512"]
+
+
+def encode_canonical(obj):
+    # securesystemslib.formats.encode_canonical only escapes `\` and `"`.
+    if isinstance(obj, bool):
+        return "true" if obj else "false"
+    if obj is None:
+        return "null"
+    if isinstance(obj, int):
+        return str(obj)
+    if isinstance(obj, str):
+        return '"' + re.sub(r'(["\\])', r"\\\1", obj) + '"'
+    if isinstance(obj, list):
+        return "[" + ",".join(encode_canonical(x) for x in obj) + "]"
+    if isinstance(obj, dict):
+        return (
+            "{"
+            + ",".join(
+                encode_canonical(k) + ":" + encode_canonical(obj[k])
+                for k in sorted(obj)
+            )
+            + "}"
+        )
+    raise TypeError(obj)
+
+
+def key_dict(keytype, scheme, public):
+    key = {
+        "keytype": keytype,
+        "scheme": scheme,
+        "keyid_hash_algorithms": HASH_ALGORITHMS,
+        "keyval": {"public": public},
+    }
+    keyid = hashlib.sha256(encode_canonical(key).encode()).hexdigest()
+    key["keyval"]["private"] = ""
+    key["keyid"] = keyid
+    return key
+
+
+def ecdsa_key():
+    private = ec.generate_private_key(ec.SECP256R1())
+    public = (
+        private.public_key()
+        .public_bytes(
+            serialization.Encoding.PEM,
+            serialization.PublicFormat.SubjectPublicKeyInfo,
+        )
+        .decode()
+        .strip()
+    )
+    key = key_dict("ecdsa", "ecdsa-sha2-nistp256", public)
+    return key, lambda data: private.sign(data, ec.ECDSA(hashes.SHA256()))
+
+
+def ed25519_key():
+    private = ed25519.Ed25519PrivateKey.generate()
+    public = (
+        private.public_key()
+        .public_bytes(
+            serialization.Encoding.Raw, serialization.PublicFormat.Raw
+        )
+        .hex()
+    )
+    key = key_dict("ed25519", "ed25519", public)
+    return key, private.sign
+
+
+def metablock(signed, key, sign):
+    sig = sign(encode_canonical(signed).encode()).hex()
+    return {
+        "signatures": [{"keyid": key["keyid"], "sig": sig}],
+        "signed": signed,
+    }
+
+
+def write(path, obj):
+    with open(path, "w") as f:
+        json.dump(obj, f, indent=1, sort_keys=True)
+        f.write("\n")
+
+
+def main():
+    os.chdir(os.path.dirname(os.path.abspath(__file__)))
+
+    owner, owner_sign = ecdsa_key()
+    builder, builder_sign = ecdsa_key()
+    packager, packager_sign = ed25519_key()
+
+    foo = {"sha256": hashlib.sha256(b"print('foo')\n").hexdigest()}
+    tarball = {"sha256": hashlib.sha256(b"foo.tar.gz").hexdigest()}
+
+    layout = {
+        "_type": "layout",
+        "expires": "2030-01-01T00:00:00Z",
+        "readme": "",
+        "keys": {k["keyid"]: k for k in (builder, packager)},
+        "steps": [
+            {
+                "_type": "step",
+                "name": "build",
+                "threshold": 1,
+                "expected_materials": [],
+                "expected_products": [["CREATE", "foo.py"], ["DISALLOW", "*"]],
+                "pubkeys": [builder["keyid"]],
+                "expected_command": ["make"],
+            },
+            {
+                "_type": "step",
+                "name": "package",
+                "threshold": 1,
+                "expected_materials": [
+                    ["MATCH", "foo.py", "WITH", "PRODUCTS", "FROM", "build"],
+                    ["DISALLOW", "*"],
+                ],
+                "expected_products": [
+                    ["CREATE", "foo.tar.gz"],
+                    ["MATCH", "foo.py", "WITH", "PRODUCTS", "FROM", "build"],
+                ],
+                "pubkeys": [packager["keyid"]],
+                "expected_command": ["tar", "zcvf", "foo.tar.gz", "foo.py"],
+            },
+        ],
+        "inspect": [],
+    }
+    write("root.layout", metablock(layout, owner, owner_sign))
+    with open("owner.pub", "w") as f:
+        f.write(owner["keyval"]["public"] + "\n")
+
+    def link(name, command, materials, products):
+        return {
+            "_type": "link",
+            "name": name,
+            "command": command,
+            "materials": materials,
+            "products": products,
+            "byproducts": {"return-value": 0, "stderr": "", "stdout": ""},
+            "environment": {},
+        }
+
+    build = link("build", ["make"], {}, {"foo.py": foo})
+    write(
+        "build.%s.link" % builder["keyid"][:8],
+        metablock(build, builder, builder_sign),
+    )
+    package = link(
+        "package",
+        ["tar", "zcvf", "foo.tar.gz", "foo.py"],
+        {"foo.py": foo},
+        {"foo.py": foo, "foo.tar.gz": tarball},
+    )
+    write(
+        "package.%s.link" % packager["keyid"][:8],
+        metablock(package, packager, packager_sign),
+    )
+
+
+if __name__ == "__main__":
+    main()
@@ -1,8 +1,11 @@
 use in_toto::{
     crypto::{KeyType, PrivateKey, SignatureScheme},
     interchange::Json,
-    models::{byproducts::ByProducts, LinkMetadataBuilder, VirtualTargetPath},
-    runlib::in_toto_run,
+    models::{
+        byproducts::ByProducts, step::Command, LinkMetadataBuilder, Metablock,
+        MetablockBuilder, MetadataWrapper, VirtualTargetPath,
+    },
+    runlib::{in_toto_run, CommandEnvironment, RunOptions},
 };
 use std::fs::{canonicalize, write};
 use std::os::unix::fs;
@@ -49,7 +52,7 @@ fn in_toto_run_record_file() {
 
     // Create file
     write(format!("{}/foo.txt", dir_path), "lorem ipsum").unwrap();
-    print!("Path: {}\n", dir_path);
+    println!("Path: {}", dir_path);
 
     // Expected value
     let byproducts = ByProducts::new()
@@ -65,19 +68,22 @@ fn in_toto_run_record_file() {
         .add_product(
             VirtualTargetPath::new(format!("{}/foo.txt", dir_path)).unwrap(),
         )
+        .command(Command::from(vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            "echo 'in_toto says hi'".to_string(),
+        ]))
         .signed::<Json>(&TEST_PRIVATE_KEY)
         .unwrap();
 
     // Result value
+    let options = RunOptions::new().key(&TEST_PRIVATE_KEY);
     let result = in_toto_run(
         "test",
-        None,
-        &vec![dir_path],
-        &vec![dir_path],
+        &[dir_path],
+        &[dir_path],
         &["sh", "-c", "echo 'in_toto says hi'"],
-        Some(&TEST_PRIVATE_KEY),
-        None,
-        None,
+        &options,
     )
     .unwrap();
 
@@ -96,22 +102,20 @@ fn in_toto_run_record_new_file() {
 
     // Create file
     write(format!("{}/foo.txt", dir_path), "lorem ipsum").unwrap();
-    print!("Path: {}\n", dir_path);
+    println!("Path: {}", dir_path);
 
     // Result Value
+    let options = RunOptions::new().key(&TEST_PRIVATE_KEY);
     let result = in_toto_run(
         "test",
-        None,
-        &vec![dir_path],
-        &vec![dir_path],
+        &[dir_path],
+        &[dir_path],
         &[
             "sh",
             "-c",
             &format!("echo 'in_toto says hi' >> {}/bar.txt", dir_path),
         ],
-        Some(&TEST_PRIVATE_KEY),
-        None,
-        None,
+        &options,
     )
     .unwrap();
 
@@ -133,6 +137,11 @@ fn in_toto_run_record_new_file() {
             VirtualTargetPath::new(format!("{}/bar.txt", dir_path)).unwrap(),
         )
         .byproducts(byproducts)
+        .command(Command::from(vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            format!("echo 'in_toto says hi' >> {}/bar.txt", dir_path),
+        ]))
         .signed::<Json>(&TEST_PRIVATE_KEY)
         .unwrap();
 
@@ -195,7 +204,7 @@ fn in_toto_run_record_symlink_file() {
     )
     .unwrap();
 
-    print!("Path: {}\n", dir_path);
+    println!("Path: {}", dir_path);
 
     let byproducts = ByProducts::new()
         .set_return_value(0)
@@ -220,19 +229,22 @@ fn in_toto_run_record_symlink_file() {
                 .unwrap(),
         )
         .byproducts(byproducts)
+        .command(Command::from(vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            "echo 'in_toto says hi'".to_string(),
+        ]))
         .signed::<Json>(&TEST_PRIVATE_KEY)
         .unwrap();
 
     // Result Value
+    let options = RunOptions::new().key(&TEST_PRIVATE_KEY);
     let result = in_toto_run(
         "test",
-        None,
-        &vec![dir_path],
-        &vec![dir_path],
+        &[dir_path],
+        &[dir_path],
         &["sh", "-c", "echo 'in_toto says hi'"],
-        Some(&TEST_PRIVATE_KEY),
-        None,
-        None,
+        &options,
     )
     .unwrap();
 
@@ -246,3 +258,122 @@ fn in_toto_run_record_symlink_file() {
 fn in_toto_run_record_symlink_cycle() {
     // TODO
 }
+
+#[test]
+fn in_toto_run_capture_environment() {
+    // Initialization
+    let dir = tempdir().unwrap();
+    let dir_canonical = canonicalize(dir.path()).unwrap();
+    let dir_path = dir_canonical.to_str().unwrap();
+    std::env::set_var("IN_TOTO_TEST_CAPTURED", "captured");
+    std::env::set_var("IN_TOTO_TEST_SECRET", "secret");
+    std::env::remove_var("IN_TOTO_TEST_UNSET");
+
+    let options = RunOptions::new()
+        .run_dir(dir_path)
+        .key(&TEST_PRIVATE_KEY)
+        .env_capture(&["IN_TOTO_TEST_CAPTURED", "IN_TOTO_TEST_UNSET"]);
+    let result =
+        in_toto_run("test", &[dir_path], &[dir_path], &["true"], &options)
+            .unwrap();
+
+    let env = match result.metadata {
+        MetadataWrapper::Link(link) => link.env.unwrap(),
+        MetadataWrapper::Layout(_) => panic!("Unexpected layout."),
+    };
+    assert_eq!(env.len(), 2);
+    assert_eq!(env["IN_TOTO_TEST_CAPTURED"], "captured");
+    assert_eq!(env["workdir"], dir_path);
+    assert!(!env.values().any(|value| value == "secret"));
+
+    // Clean-up work
+    dir.close().unwrap();
+}
+
+#[test]
+fn in_toto_run_clear_env() {
+    // Initialization
+    let dir = tempdir().unwrap();
+    let dir_canonical = canonicalize(dir.path()).unwrap();
+    let dir_path = dir_canonical.to_str().unwrap();
+    std::env::set_var("IN_TOTO_TEST_INHERITED", "inherited");
+    let command_env = CommandEnvironment::new()
+        .clear_env(true)
+        .set_var("IN_TOTO_TEST_PROVIDED", "provided");
+
+    let options = RunOptions::new()
+        .run_dir(dir_path)
+        .key(&TEST_PRIVATE_KEY)
+        .env_capture(&["IN_TOTO_TEST_INHERITED", "IN_TOTO_TEST_PROVIDED"])
+        .command_env(command_env);
+    let result = in_toto_run(
+        "test",
+        &[dir_path],
+        &[dir_path],
+        &["/usr/bin/env"],
+        &options,
+    )
+    .unwrap();
+
+    let link = match result.metadata {
+        MetadataWrapper::Link(link) => link,
+        MetadataWrapper::Layout(_) => panic!("Unexpected layout."),
+    };
+    // The child only sees the provided variable, and so does the link
+    assert_eq!(
+        link.byproducts.stdout().as_deref(),
+        Some("IN_TOTO_TEST_PROVIDED=provided\n")
+    );
+    let env = link.env.unwrap();
+    assert_eq!(env.len(), 2);
+    assert_eq!(env["IN_TOTO_TEST_PROVIDED"], "provided");
+    assert_eq!(env["workdir"], dir_path);
+
+    // Clean-up work
+    dir.close().unwrap();
+}
+
+#[test]
+fn in_toto_run_record_then_sign() {
+    // Initialization
+    let dir = tempdir().unwrap();
+    let dir_canonical = canonicalize(dir.path()).unwrap();
+    let dir_path = dir_canonical.to_str().unwrap();
+    write(format!("{}/foo.txt", dir_path), "lorem ipsum").unwrap();
+
+    // Record the link without a key, and store it for a later job
+    let options = RunOptions::new().run_dir(dir_path);
+    let unsigned = in_toto_run(
+        "test",
+        &[dir_path],
+        &[dir_path],
+        &["sh", "-c", "printf 'line one\\nline \"two\"\\n'"],
+        &options,
+    )
+    .unwrap();
+    assert!(unsigned.signatures.is_empty());
+    unsigned.write_to_dir::<Json>(dir.path(), "test").unwrap();
+
+    // Sign the stored link in the job holding the key
+    let stored = Metablock::read_from_dir::<Json>(dir.path(), "test").unwrap();
+    assert_eq!(stored, unsigned);
+    let raw = serde_json::to_vec(&stored.metadata).unwrap();
+    let builder = MetablockBuilder::from_raw_metadata(&raw).unwrap();
+    assert_eq!(
+        builder.signable_bytes().unwrap(),
+        MetablockBuilder::from_metadata(unsigned.metadata.clone().into_trait())
+            .signable_bytes()
+            .unwrap()
+    );
+    let signed = builder.sign(&[&TEST_PRIVATE_KEY]).unwrap().build();
+    assert_eq!(signed.metadata, unsigned.metadata);
+
+    // The signature holds once the signed link is stored and loaded again
+    signed.write_to_dir::<Json>(dir.path(), "test").unwrap();
+    let loaded = Metablock::read_from_dir::<Json>(dir.path(), "test").unwrap();
+    assert_eq!(loaded.metadata, unsigned.metadata);
+    loaded.verify(1, [TEST_PRIVATE_KEY.public()]).unwrap();
+
+    // Clean-up work
+    dir.close().unwrap();
+}
@@ -0,0 +1,137 @@
+#!/bin/bash
+# Generate a test CA: a P-384 root, a P-256 intermediate and a P-256 leaf
+# issued to alice@example.com, with the OIDC issuer extension Fulcio adds.
+set -eux
+
+cd "$(dirname "$0")"
+
+cat > ext.cnf <<'CNF'
+[root]
+basicConstraints = critical, CA:TRUE
+keyUsage = critical, keyCertSign, cRLSign
+subjectKeyIdentifier = hash
+
+[intermediate]
+basicConstraints = critical, CA:TRUE, pathlen:0
+keyUsage = critical, keyCertSign, cRLSign
+subjectKeyIdentifier = hash
+authorityKeyIdentifier = keyid
+
+[leaf]
+basicConstraints = critical, CA:FALSE
+keyUsage = critical, digitalSignature
+extendedKeyUsage = codeSigning
+subjectAltName = critical, email:alice@example.com, URI:https://example.com/alice
+1.3.6.1.4.1.57264.1.1 = DER:68:74:74:70:73:3a:2f:2f:69:73:73:75:65:72:2e:65:78:61:6d:70:6c:65:2e:63:6f:6d
+1.3.6.1.4.1.57264.1.8 = ASN1:UTF8String:https://issuer.example.com
+
+[leaf_ca]
+basicConstraints = critical, CA:TRUE
+keyUsage = critical, digitalSignature, keyCertSign
+extendedKeyUsage = codeSigning
+subjectAltName = critical, email:alice@example.com
+
+[leaf_no_eku]
+basicConstraints = critical, CA:FALSE
+keyUsage = critical, digitalSignature
+subjectAltName = critical, email:alice@example.com
+
+[leaf_server_auth]
+basicConstraints = critical, CA:FALSE
+keyUsage = critical, digitalSignature
+extendedKeyUsage = serverAuth
+subjectAltName = critical, email:alice@example.com
+
+[leaf_key_encipherment]
+basicConstraints = critical, CA:FALSE
+keyUsage = critical, keyEncipherment
+extendedKeyUsage = codeSigning
+subjectAltName = critical, email:alice@example.com
+
+[ca_no_cert_sign]
+basicConstraints = critical, CA:TRUE
+keyUsage = critical, digitalSignature
+
+[sub_ca]
+basicConstraints = critical, CA:TRUE
+keyUsage = critical, keyCertSign, cRLSign
+
+[name_constrained_ca]
+basicConstraints = critical, CA:TRUE
+keyUsage = critical, keyCertSign, cRLSign
+nameConstraints = critical, permitted;email:example.com
+CNF
+
+openssl genpkey -algorithm EC -pkeyopt ec_paramgen_curve:P-384 \
+    -out root.pem
+openssl pkcs8 -topk8 -nocrypt -in root.pem \
+    -outform der -out root.pk8.der
+openssl req -new -x509 -key root.pk8.der -keyform der -sha384 \
+    -subj "/O=in-toto test/CN=Test Root CA" -days 36500 \
+    -set_serial 1 -extensions root -config ext.cnf \
+    -outform der -out root.der
+
+openssl genpkey -algorithm EC -pkeyopt ec_paramgen_curve:P-256 \
+    -out intermediate.pem
+openssl pkcs8 -topk8 -nocrypt -in intermediate.pem \
+    -outform der -out intermediate.pk8.der
+openssl req -new -key intermediate.pk8.der -keyform der \
+    -subj "/O=in-toto test/CN=Test Intermediate CA" -out intermediate.csr
+openssl x509 -req -in intermediate.csr -CA root.der -CAform der \
+    -CAkey root.pk8.der -CAkeyform der -sha384 -days 36500 -set_serial 2 \
+    -extensions intermediate -extfile ext.cnf -outform der \
+    -out intermediate.der
+
+openssl genpkey -algorithm EC -pkeyopt ec_paramgen_curve:P-256 \
+    -out leaf.pem
+openssl pkcs8 -topk8 -nocrypt -in leaf.pem \
+    -outform der -out leaf.pk8.der
+openssl req -new -key leaf.pk8.der -keyform der -subj "/" -out leaf.csr
+openssl x509 -req -in leaf.csr -CA intermediate.der -CAform der \
+    -CAkey intermediate.pk8.der -CAkeyform der -sha256 -days 36500 \
+    -set_serial 3 -extensions leaf -extfile ext.cnf -outform der \
+    -out leaf.der
+
+# Certificates breaking one of the rules checked when verifying a chain,
+# reusing the keys above. Issue <out> for <csr> with the <extensions>,
+# signed by the <ca> named <ca>.der and <ca>.pk8.der.
+issue() {
+    openssl x509 -req -in "$2" -CA "$4.der" -CAform der \
+        -CAkey "$4.pk8.der" -CAkeyform der -sha256 -days 36500 \
+        -set_serial "$5" -extensions "$3" -extfile ext.cnf -outform der \
+        -out "$1.der"
+}
+
+issue leaf-ca leaf.csr leaf_ca intermediate 10
+issue leaf-no-eku leaf.csr leaf_no_eku intermediate 11
+issue leaf-server-auth leaf.csr leaf_server_auth intermediate 12
+issue leaf-key-encipherment leaf.csr leaf_key_encipherment intermediate 13
+
+# A leaf whose P-384 key cannot sign DSSE envelopes here
+openssl genpkey -algorithm EC -pkeyopt ec_paramgen_curve:P-384 \
+    -out leaf-p384.pem
+openssl req -new -key leaf-p384.pem -subj "/" -out leaf-p384.csr
+issue leaf-p384 leaf-p384.csr leaf intermediate 19
+
+openssl req -new -key intermediate.pk8.der -keyform der \
+    -subj "/O=in-toto test/CN=Test No Cert Sign CA" -out no-cert-sign.csr
+issue ca-no-cert-sign no-cert-sign.csr ca_no_cert_sign root 14
+cp intermediate.pk8.der ca-no-cert-sign.pk8.der
+issue leaf-no-cert-sign leaf.csr leaf ca-no-cert-sign 15
+
+# The intermediate has a path length of 0, so it may not issue a CA
+openssl req -new -key intermediate.pk8.der -keyform der \
+    -subj "/O=in-toto test/CN=Test Sub CA" -out sub-ca.csr
+issue sub-ca sub-ca.csr sub_ca intermediate 16
+cp intermediate.pk8.der sub-ca.pk8.der
+issue leaf-sub-ca leaf.csr leaf sub-ca 17
+
+openssl req -new -key intermediate.pk8.der -keyform der \
+    -subj "/O=in-toto test/CN=Test Name Constrained CA" \
+    -out name-constrained-ca.csr
+issue name-constrained-ca name-constrained-ca.csr name_constrained_ca root 18
+
+rm ext.cnf root.pem intermediate.pem leaf.pem intermediate.csr leaf.csr \
+    leaf-p384.pem leaf-p384.csr \
+    no-cert-sign.csr sub-ca.csr name-constrained-ca.csr \
+    ca-no-cert-sign.pk8.der sub-ca.pk8.der